@@ -0,0 +1,775 @@
+//! LDR decoder for ASTC blocks.
+//!
+//! Blocks using HDR endpoint modes, and blocks that are malformed, decode to the error color
+//! (opaque magenta) like hardware decoders do.
+
+const BLOCK_BYTES: usize = 16;
+const CHANNELS: usize = 4;
+const ERROR_COLOR: [u8; CHANNELS] = [0xff, 0, 0xff, 0xff];
+const MAX_WEIGHTS: usize = 64;
+
+/// Number of trits, quints and plain bits of each integer sequence encoding range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Range {
+    trits: bool,
+    quints: bool,
+    bits: u32,
+}
+
+impl Range {
+    const fn new(trits: bool, quints: bool, bits: u32) -> Self {
+        Self {
+            trits,
+            quints,
+            bits,
+        }
+    }
+
+    fn bit_count(self, count: usize) -> usize {
+        let bits = self.bits as usize * count;
+        if self.trits {
+            bits + (8 * count).div_ceil(5)
+        } else if self.quints {
+            bits + (7 * count).div_ceil(3)
+        } else {
+            bits
+        }
+    }
+}
+
+/// Ranges usable for color endpoints, ordered by increasing number of levels.
+const COLOR_RANGES: [Range; 21] = [
+    Range::new(false, false, 1),
+    Range::new(true, false, 0),
+    Range::new(false, false, 2),
+    Range::new(false, true, 0),
+    Range::new(true, false, 1),
+    Range::new(false, false, 3),
+    Range::new(false, true, 1),
+    Range::new(true, false, 2),
+    Range::new(false, false, 4),
+    Range::new(false, true, 2),
+    Range::new(true, false, 3),
+    Range::new(false, false, 5),
+    Range::new(false, true, 3),
+    Range::new(true, false, 4),
+    Range::new(false, false, 6),
+    Range::new(false, true, 4),
+    Range::new(true, false, 5),
+    Range::new(false, false, 7),
+    Range::new(false, true, 5),
+    Range::new(true, false, 6),
+    Range::new(false, false, 8),
+];
+
+/// Index of the first color range (0..5) allowed for endpoints.
+const MIN_COLOR_RANGE: usize = 4;
+
+/// Weight ranges indexed by the `R` bits of the block mode, without and with high precision.
+const WEIGHT_RANGES: [[Option<Range>; 8]; 2] = [
+    [
+        None,
+        None,
+        Some(Range::new(false, false, 1)),
+        Some(Range::new(true, false, 0)),
+        Some(Range::new(false, false, 2)),
+        Some(Range::new(false, true, 0)),
+        Some(Range::new(true, false, 1)),
+        Some(Range::new(false, false, 3)),
+    ],
+    [
+        None,
+        None,
+        Some(Range::new(false, true, 1)),
+        Some(Range::new(true, false, 2)),
+        Some(Range::new(false, false, 4)),
+        Some(Range::new(false, true, 2)),
+        Some(Range::new(true, false, 3)),
+        Some(Range::new(false, false, 5)),
+    ],
+];
+
+#[inline]
+fn bits(block: u128, offset: u32, count: u32) -> u32 {
+    ((block >> offset) & ((1u128 << count) - 1)) as u32
+}
+
+struct BlockMode {
+    grid_width: usize,
+    grid_height: usize,
+    dual_plane: bool,
+    weight_range: Range,
+}
+
+impl BlockMode {
+    fn weight_count(&self) -> usize {
+        self.grid_width * self.grid_height * if self.dual_plane { 2 } else { 1 }
+    }
+
+    fn decode(mode: u32) -> Option<Self> {
+        let bit = |n: u32| (mode >> n) & 1;
+        let a = ((mode >> 5) & 3) as usize;
+        let (r, width, height, high, dual) = if mode & 3 != 0 {
+            let r = bit(4) | ((mode & 3) << 1);
+            let b = ((mode >> 7) & 3) as usize;
+            let (width, height) = match (mode >> 2) & 3 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 0 => (a + 2, (b & 1) + 6),
+                _ => ((b & 1) + 2, a + 2),
+            };
+            (r, width, height, bit(9), bit(10))
+        } else {
+            let r = bit(4) | (((mode >> 2) & 3) << 1);
+            if (mode >> 2) & 3 == 0 {
+                return None;
+            }
+            let b = ((mode >> 9) & 3) as usize;
+            match (mode >> 7) & 3 {
+                0 => (r, 12, a + 2, bit(9), bit(10)),
+                1 => (r, a + 2, 12, bit(9), bit(10)),
+                2 => (r, a + 6, b + 6, 0, 0),
+                _ => match a {
+                    0 => (r, 6, 10, bit(9), bit(10)),
+                    1 => (r, 10, 6, bit(9), bit(10)),
+                    _ => return None,
+                },
+            }
+        };
+        let weight_range = WEIGHT_RANGES[high as usize][r as usize]?;
+        let ret = Self {
+            grid_width: width,
+            grid_height: height,
+            dual_plane: dual != 0,
+            weight_range,
+        };
+        let weight_bits = weight_range.bit_count(ret.weight_count());
+        if ret.weight_count() > MAX_WEIGHTS || !(24..=96).contains(&weight_bits) {
+            return None;
+        }
+        Some(ret)
+    }
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |n: u32| (t >> n) & 1;
+    let (c, t4, t3) = if (t >> 2) & 7 == 7 {
+        (((t >> 5) & 7) << 2 | (t & 3), 2, 2)
+    } else {
+        let c = t & 0x1f;
+        if (t >> 5) & 3 == 3 {
+            (c, 2, bit(7))
+        } else {
+            (c, bit(7), (t >> 5) & 3)
+        }
+    };
+    let cbit = |n: u32| (c >> n) & 1;
+    let (t2, t1, t0) = if c & 3 == 3 {
+        (2, cbit(4), (cbit(3) << 1) | (cbit(2) & !cbit(3) & 1))
+    } else if (c >> 2) & 3 == 3 {
+        (2, 2, c & 3)
+    } else {
+        (cbit(4), (c >> 2) & 3, (cbit(1) << 1) | (cbit(0) & !cbit(1) & 1))
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |n: u32| (q >> n) & 1;
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let q2 = (bit(0) << 2) | ((bit(4) & !bit(0) & 1) << 1) | (bit(3) & !bit(0) & 1);
+        return [4, 4, q2];
+    }
+    let (q2, c) = if (q >> 1) & 3 == 3 {
+        (4, (((q >> 3) & 3) << 3) | ((!(q >> 5) & 3) << 1) | bit(0))
+    } else {
+        ((q >> 5) & 3, q & 0x1f)
+    };
+    let (q1, q0) = if c & 7 == 5 {
+        (4, (c >> 3) & 3)
+    } else {
+        ((c >> 3) & 3, c & 7)
+    };
+    [q0, q1, q2]
+}
+
+/// A decoded value of an integer sequence: plain bits plus an optional trit or quint.
+#[derive(Copy, Clone, Default)]
+struct IseValue {
+    bits: u32,
+    tq: u32,
+}
+
+fn decode_ise(block: u128, offset: u32, range: Range, count: usize) -> Vec<IseValue> {
+    let read = |pos: u32, n: u32| -> u32 {
+        if n == 0 || pos >= 128 {
+            0
+        } else {
+            bits(block, pos, std::cmp::min(n, 128 - pos))
+        }
+    };
+    let mut ret = Vec::with_capacity(count);
+    let mut pos = offset;
+    let b = range.bits;
+    if range.trits {
+        const TRIT_BITS: [u32; 5] = [2, 2, 1, 2, 1];
+        while ret.len() < count {
+            let mut m = [0u32; 5];
+            let mut t = 0u32;
+            let mut t_pos = 0;
+            for (m, &tb) in m.iter_mut().zip(TRIT_BITS.iter()) {
+                *m = read(pos, b);
+                pos += b;
+                t |= read(pos, tb) << t_pos;
+                pos += tb;
+                t_pos += tb;
+            }
+            let trits = decode_trits(t);
+            for (&bits, &tq) in m.iter().zip(trits.iter()) {
+                if ret.len() < count {
+                    ret.push(IseValue { bits, tq });
+                }
+            }
+        }
+    } else if range.quints {
+        const QUINT_BITS: [u32; 3] = [3, 2, 2];
+        while ret.len() < count {
+            let mut m = [0u32; 3];
+            let mut q = 0u32;
+            let mut q_pos = 0;
+            for (m, &qb) in m.iter_mut().zip(QUINT_BITS.iter()) {
+                *m = read(pos, b);
+                pos += b;
+                q |= read(pos, qb) << q_pos;
+                pos += qb;
+                q_pos += qb;
+            }
+            let quints = decode_quints(q);
+            for (&bits, &tq) in m.iter().zip(quints.iter()) {
+                if ret.len() < count {
+                    ret.push(IseValue { bits, tq });
+                }
+            }
+        }
+    } else {
+        for _ in 0..count {
+            ret.push(IseValue {
+                bits: read(pos, b),
+                tq: 0,
+            });
+            pos += b;
+        }
+    }
+    ret
+}
+
+/// Repeats the `from` low bits of `v` until `to` bits are filled.
+fn replicate(v: u32, from: u32, to: u32) -> u32 {
+    if from == 0 {
+        return 0;
+    }
+    let mut ret = 0;
+    let mut filled = 0;
+    while filled < to {
+        ret = (ret << from) | v;
+        filled += from;
+    }
+    ret >> (filled - to)
+}
+
+fn unquantize_color(v: IseValue, range: Range) -> u8 {
+    if !range.trits && !range.quints {
+        return replicate(v.bits, range.bits, 8) as u8;
+    }
+    let m = v.bits;
+    let a = if m & 1 != 0 { 0x1ff } else { 0 };
+    let x = m >> 1;
+    let (b, c) = if range.trits {
+        match range.bits {
+            1 => (0, 204),
+            2 => (x * 0x116, 93),
+            3 => ((x >> 1) * 0x10a + (x & 1) * 0x85, 44),
+            4 => ((x << 6) | x, 22),
+            5 => ((x << 5) | (x >> 2), 11),
+            _ => ((x << 4) | (x >> 4), 5),
+        }
+    } else {
+        match range.bits {
+            1 => (0, 113),
+            2 => (x * 0x10c, 54),
+            3 => ((x >> 1) * 0x105 + (x & 1) * 0x82, 26),
+            4 => ((x << 6) | (x >> 1), 13),
+            _ => ((x << 5) | (x >> 3), 6),
+        }
+    };
+    let t = (v.tq * c + b) ^ a;
+    ((a & 0x80) | (t >> 2)) as u8
+}
+
+fn unquantize_weight(v: IseValue, range: Range) -> u32 {
+    let t = if !range.trits && !range.quints {
+        replicate(v.bits, range.bits, 6)
+    } else if range.bits == 0 {
+        if range.trits {
+            [0, 32, 63][v.tq as usize]
+        } else {
+            [0, 16, 32, 47, 63][v.tq as usize]
+        }
+    } else {
+        let m = v.bits;
+        let a = if m & 1 != 0 { 0x7f } else { 0 };
+        let x = m >> 1;
+        let (b, c) = if range.trits {
+            match range.bits {
+                1 => (0, 50),
+                2 => (x * 0x45, 23),
+                _ => ((x << 5) | x, 11),
+            }
+        } else {
+            match range.bits {
+                1 => (0, 28),
+                _ => (x * 0x42, 13),
+            }
+        };
+        let t = (v.tq * c + b) ^ a;
+        (a & 0x20) | (t >> 2)
+    };
+    if t > 32 {
+        t + 1
+    } else {
+        t
+    }
+}
+
+fn hash52(mut v: u32) -> u32 {
+    v ^= v >> 15;
+    v = v.wrapping_mul(0xeede0891);
+    v ^= v >> 5;
+    v = v.wrapping_add(v << 16);
+    v ^= v >> 7;
+    v ^= v >> 3;
+    v ^= v << 6;
+    v ^= v >> 17;
+    v
+}
+
+fn select_partition(seed: u32, x: u32, y: u32, partition_count: u32, small_block: bool) -> usize {
+    let (x, y) = if small_block { (x << 1, y << 1) } else { (x, y) };
+    let seed = seed + (partition_count - 1) * 1024;
+    let rnum = hash52(seed);
+    let mut s = [
+        rnum & 0xf,
+        (rnum >> 4) & 0xf,
+        (rnum >> 8) & 0xf,
+        (rnum >> 12) & 0xf,
+        (rnum >> 16) & 0xf,
+        (rnum >> 20) & 0xf,
+        (rnum >> 24) & 0xf,
+        (rnum >> 28) & 0xf,
+    ];
+    for s in s.iter_mut() {
+        *s *= *s;
+    }
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partition_count == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partition_count == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    for (i, s) in s.iter_mut().enumerate() {
+        *s >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+    // The z coordinate is always zero for 2D blocks, so seeds 9 to 12 don't contribute.
+    let a = (s[0] * x + s[1] * y + (rnum >> 14)) & 0x3f;
+    let b = (s[2] * x + s[3] * y + (rnum >> 10)) & 0x3f;
+    let c = if partition_count >= 3 {
+        (s[4] * x + s[5] * y + (rnum >> 6)) & 0x3f
+    } else {
+        0
+    };
+    let d = if partition_count >= 4 {
+        (s[6] * x + s[7] * y + (rnum >> 2)) & 0x3f
+    } else {
+        0
+    };
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3f;
+    let a = if a & 0x20 != 0 { a - 0x40 } else { a };
+    (a, b)
+}
+
+fn clamp(v: i32) -> i32 {
+    v.clamp(0, 0xff)
+}
+
+fn blue_contract(r: i32, g: i32, b: i32, a: i32) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+type Endpoints = [[i32; CHANNELS]; 2];
+
+fn decode_endpoints(cem: u32, v: &[u8]) -> Option<Endpoints> {
+    let v = v.iter().map(|&v| i32::from(v)).collect::<Vec<_>>();
+    Some(match cem {
+        0 => [[v[0], v[0], v[0], 0xff], [v[1], v[1], v[1], 0xff]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = std::cmp::min(l0 + (v[1] & 0x3f), 0xff);
+            [[l0, l0, l0, 0xff], [l1, l1, l1, 0xff]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (v1, v0) = bit_transfer_signed(v[1], v[0]);
+            let (v3, v2) = bit_transfer_signed(v[3], v[2]);
+            let l1 = clamp(v0 + v1);
+            [[v0, v0, v0, v2], [l1, l1, l1, clamp(v2 + v3)]]
+        }
+        6 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                0xff,
+            ],
+            [v[0], v[1], v[2], 0xff],
+        ],
+        8 | 12 => {
+            let (a0, a1) = if cem == 12 { (v[6], v[7]) } else { (0xff, 0xff) };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]]
+            } else {
+                [
+                    blue_contract(v[1], v[3], v[5], a1),
+                    blue_contract(v[0], v[2], v[4], a0),
+                ]
+            }
+        }
+        9 | 13 => {
+            let (v1, v0) = bit_transfer_signed(v[1], v[0]);
+            let (v3, v2) = bit_transfer_signed(v[3], v[2]);
+            let (v5, v4) = bit_transfer_signed(v[5], v[4]);
+            let (v7, v6) = if cem == 13 {
+                bit_transfer_signed(v[7], v[6])
+            } else {
+                (0, 0xff)
+            };
+            let ret = if v1 + v3 + v5 >= 0 {
+                [[v0, v2, v4, v6], [v0 + v1, v2 + v3, v4 + v5, v6 + v7]]
+            } else {
+                [
+                    blue_contract(v0 + v1, v2 + v3, v4 + v5, v6 + v7),
+                    blue_contract(v0, v2, v4, v6),
+                ]
+            };
+            let mut ret = ret;
+            for e in ret.iter_mut() {
+                for c in e.iter_mut() {
+                    *c = clamp(*c);
+                }
+            }
+            ret
+        }
+        10 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                v[4],
+            ],
+            [v[0], v[1], v[2], v[5]],
+        ],
+        // HDR endpoint modes
+        _ => return None,
+    })
+}
+
+/// Bilinearly resamples the weight grid to the texel grid.
+fn infill_weights(
+    weights: &[u32],
+    mode: &BlockMode,
+    plane: usize,
+    block_width: usize,
+    block_height: usize,
+) -> Vec<u32> {
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let get = |idx: usize| -> u32 { weights.get(idx * planes + plane).copied().unwrap_or(0) };
+    if mode.grid_width == block_width && mode.grid_height == block_height {
+        return (0..block_width * block_height).map(get).collect();
+    }
+    let ds = (1024 + block_width / 2) / (block_width - 1).max(1);
+    let dt = (1024 + block_height / 2) / (block_height - 1).max(1);
+    let mut ret = Vec::with_capacity(block_width * block_height);
+    for t in 0..block_height {
+        for s in 0..block_width {
+            let gs = (ds * s * (mode.grid_width - 1) + 32) >> 6;
+            let gt = (dt * t * (mode.grid_height - 1) + 32) >> 6;
+            let (js, fs) = (gs >> 4, (gs & 0xf) as u32);
+            let (jt, ft) = (gt >> 4, (gt & 0xf) as u32);
+            let v0 = js + jt * mode.grid_width;
+            let w11 = (fs * ft + 8) >> 4;
+            let w10 = ft - w11;
+            let w01 = fs - w11;
+            let w00 = 16 + w11 - fs - ft;
+            let p00 = get(v0);
+            let p01 = get(v0 + 1);
+            let p10 = get(v0 + mode.grid_width);
+            let p11 = get(v0 + mode.grid_width + 1);
+            ret.push((p00 * w00 + p01 * w01 + p10 * w10 + p11 * w11 + 8) >> 4);
+        }
+    }
+    ret
+}
+
+/// Decodes a block into pixels, or `None` if the block is an error block.
+fn decode_block(block: u128, block_width: usize, block_height: usize) -> Option<Vec<u8>> {
+    let texel_count = block_width * block_height;
+    let mode = bits(block, 0, 11);
+
+    if mode & 0x1ff == 0x1fc {
+        // Void-extent block: a constant color, stored as UNORM16 for LDR.
+        if mode & 0x200 != 0 {
+            return None;
+        }
+        let color = [
+            (bits(block, 64, 16) >> 8) as u8,
+            (bits(block, 80, 16) >> 8) as u8,
+            (bits(block, 96, 16) >> 8) as u8,
+            (bits(block, 112, 16) >> 8) as u8,
+        ];
+        return Some(color.repeat(texel_count));
+    }
+
+    let mode = BlockMode::decode(mode)?;
+    if mode.grid_width > block_width || mode.grid_height > block_height {
+        return None;
+    }
+    let partition_count = bits(block, 11, 2) + 1;
+    let weight_bits = mode.weight_range.bit_count(mode.weight_count()) as u32;
+    let mut below_weights = 128 - weight_bits;
+
+    let (cems, color_offset, seed) = if partition_count == 1 {
+        (vec![bits(block, 13, 4)], 17, 0)
+    } else {
+        let seed = bits(block, 13, 10);
+        let mut encoded = bits(block, 23, 6);
+        let cems = if encoded & 3 == 0 {
+            vec![encoded >> 2; partition_count as usize]
+        } else {
+            let extra = 3 * partition_count - 4;
+            below_weights -= extra;
+            encoded |= bits(block, below_weights, extra) << 6;
+            let base = (encoded & 3) - 1;
+            (0..partition_count)
+                .map(|i| {
+                    let class = (encoded >> (2 + i)) & 1;
+                    let m = (encoded >> (2 + partition_count + 2 * i)) & 3;
+                    ((base + class) << 2) | m
+                })
+                .collect()
+        };
+        (cems, 29, seed)
+    };
+    if mode.dual_plane && partition_count == 4 {
+        return None;
+    }
+    let ccs = if mode.dual_plane {
+        below_weights -= 2;
+        Some(bits(block, below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    let color_count = cems
+        .iter()
+        .map(|cem| (((cem >> 2) + 1) * 2) as usize)
+        .sum::<usize>();
+    if color_count > 18 || below_weights < color_offset {
+        return None;
+    }
+    let color_bits = (below_weights - color_offset) as usize;
+    let color_range_idx = COLOR_RANGES
+        .iter()
+        .rposition(|range| range.bit_count(color_count) <= color_bits)?;
+    if color_range_idx < MIN_COLOR_RANGE {
+        return None;
+    }
+    let color_range = COLOR_RANGES[color_range_idx];
+    let colors = decode_ise(block, color_offset, color_range, color_count)
+        .into_iter()
+        .map(|v| unquantize_color(v, color_range))
+        .collect::<Vec<_>>();
+    let mut endpoints = Vec::with_capacity(cems.len());
+    let mut colors_left = &colors[..];
+    for &cem in &cems {
+        let (v, left) = colors_left.split_at((((cem >> 2) + 1) * 2) as usize);
+        colors_left = left;
+        endpoints.push(decode_endpoints(cem, v)?);
+    }
+
+    let weights = decode_ise(
+        block.reverse_bits(),
+        0,
+        mode.weight_range,
+        mode.weight_count(),
+    )
+    .into_iter()
+    .map(|v| unquantize_weight(v, mode.weight_range))
+    .collect::<Vec<_>>();
+    let plane0 = infill_weights(&weights, &mode, 0, block_width, block_height);
+    let plane1 = if ccs.is_some() {
+        infill_weights(&weights, &mode, 1, block_width, block_height)
+    } else {
+        Vec::new()
+    };
+
+    let small_block = texel_count < 31;
+    let mut ret = vec![0u8; texel_count * CHANNELS];
+    for y in 0..block_height {
+        for x in 0..block_width {
+            let idx = y * block_width + x;
+            let partition = if partition_count == 1 {
+                0
+            } else {
+                select_partition(seed, x as u32, y as u32, partition_count, small_block)
+            };
+            let [e0, e1] = endpoints[partition];
+            for c in 0..CHANNELS {
+                let w = if ccs == Some(c) { plane1[idx] } else { plane0[idx] } as i32;
+                let c0 = (e0[c] << 8) | e0[c];
+                let c1 = (e1[c] << 8) | e1[c];
+                let v = (c0 * (64 - w) + c1 * w + 32) >> 6;
+                ret[idx * CHANNELS + c] = (v >> 8) as u8;
+            }
+        }
+    }
+    Some(ret)
+}
+
+/// Reads and decodes a single block, returning `block_height` rows of `block_width` RGBA8 pixels.
+pub fn decode_single_block<R: std::io::Read>(
+    input: &mut R,
+    block_width: usize,
+    block_height: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut buf = [0u8; BLOCK_BYTES];
+    input.read_exact(&mut buf)?;
+    let block = u128::from_le_bytes(buf);
+    let ret = decode_block(block, block_width, block_height)
+        .unwrap_or_else(|| ERROR_COLOR.repeat(block_width * block_height));
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(block: &str, block_width: usize, block_height: usize) -> Vec<u8> {
+        let block = (0..BLOCK_BYTES)
+            .map(|i| u8::from_str_radix(&block[2 * i..2 * i + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        decode_single_block(&mut &block[..], block_width, block_height).unwrap()
+    }
+
+    /// Expands rows of luminance and alpha values to RGBA.
+    fn gray(luminance: &[u8], alpha: &[u8]) -> Vec<u8> {
+        luminance
+            .iter()
+            .zip(alpha)
+            .flat_map(|(&l, &a)| vec![l, l, l, a])
+            .collect()
+    }
+
+    #[test]
+    fn void_extent() {
+        // RGBA 0x1234, 0x5678, 0x9abc, 0xffff with every extent coordinate set.
+        let block = "fcfdffffffffffff34127856bc9affff";
+        for &(w, h) in &[(4, 4), (6, 6), (8, 8), (12, 10)] {
+            assert_eq!(decode(block, w, h), [0x12, 0x56, 0x9a, 0xff].repeat(w * h));
+        }
+        // The HDR flag makes it an error block.
+        let block = "fcffffffffffffff34127856bc9affff";
+        assert_eq!(decode(block, 4, 4), ERROR_COLOR.repeat(16));
+    }
+
+    #[test]
+    fn rgb_4x4() {
+        // Direct RGB endpoints and a full-size grid of 2-bit weights 0, 1, 2, 3 along each row.
+        let pixels = decode("420015f429903d2c0100000027272727", 4, 4);
+        let row = [
+            [10, 20, 30, 255],
+            [89, 79, 69, 255],
+            [171, 141, 111, 255],
+            [250, 200, 150, 255],
+        ];
+        assert_eq!(pixels, row.concat().repeat(4));
+    }
+
+    #[test]
+    fn luminance_6x6() {
+        // Luminance endpoints 0 and 255, with a 3x3 grid of 4-bit weights to infill.
+        let pixels = decode("ae0300fe0100000000000000f113f201", 6, 6);
+        #[rustfmt::skip]
+        let luminance = [
+            0, 52, 112, 159, 211, 255,
+            24, 68, 120, 159, 199, 239,
+            56, 88, 124, 155, 187, 215,
+            104, 120, 139, 139, 155, 167,
+            183, 163, 151, 128, 104, 80,
+            255, 211, 159, 112, 52, 0,
+        ];
+        assert_eq!(pixels, gray(&luminance, &[255; 36]));
+    }
+
+    #[test]
+    fn dual_plane_8x8() {
+        // Luminance 40 to 200 and alpha 255 to 0, with alpha weighted by the second plane of a
+        // 4x4 grid of 2-bit weights.
+        let pixels = decode("42845090ff0100c0edfcce5ffca55f0a", 8, 8);
+        #[rustfmt::skip]
+        let luminance = [
+            40, 62, 85, 110, 130, 155, 178, 200,
+            62, 87, 110, 133, 153, 175, 188, 200,
+            85, 110, 135, 158, 178, 193, 198, 200,
+            110, 133, 158, 175, 190, 200, 200, 200,
+            130, 153, 178, 190, 193, 200, 200, 200,
+            155, 175, 193, 200, 200, 200, 200, 200,
+            178, 188, 198, 200, 200, 200, 200, 200,
+            200, 200, 200, 200, 200, 200, 200, 200,
+        ];
+        #[rustfmt::skip]
+        let alpha = [
+            255, 219, 183, 143, 112, 72, 36, 0,
+            219, 179, 143, 108, 76, 56, 84, 112,
+            183, 143, 104, 68, 36, 44, 131, 223,
+            143, 108, 68, 72, 64, 92, 159, 227,
+            112, 76, 36, 64, 139, 187, 191, 199,
+            72, 56, 44, 92, 187, 235, 195, 159,
+            36, 84, 131, 159, 191, 195, 159, 124,
+            0, 112, 223, 227, 199, 159, 124, 84,
+        ];
+        assert_eq!(pixels, gray(&luminance, &alpha));
+    }
+
+    #[test]
+    fn reserved_block_mode() {
+        assert_eq!(decode(&"00".repeat(16), 4, 4), ERROR_COLOR.repeat(16));
+    }
+}
@@ -0,0 +1,959 @@
+//! Decoder for BC6H and BC7 blocks.
+//!
+//! BC6H blocks hold HDR colors as half floats. There is no tonemapping; each channel is clamped
+//! to `[0, 1]` and quantized to eight bits, so anything brighter than white is lost.
+
+const BLOCK_BYTES: usize = 16;
+const BLOCK_WIDTH: usize = 4;
+const BLOCK_HEIGHT: usize = 4;
+const CHANNELS: usize = 4;
+const PIXELS: usize = BLOCK_WIDTH * BLOCK_HEIGHT;
+
+/// Decoded block, as rows of RGBA8 pixels from the top.
+pub type Block = [[u8; BLOCK_WIDTH * CHANNELS]; BLOCK_HEIGHT];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeFormat {
+    Bc6hUnsigned,
+    Bc6hSigned,
+    Bc7,
+}
+
+const WEIGHTS_2: [i32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Subset of each pixel in two-subset partitions, one bit per pixel.
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Subset of each pixel in three-subset partitions, two bits per pixel.
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// Anchor pixel of the second subset in two-subset partitions.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor pixels of the second and third subsets in three-subset partitions.
+const ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5,
+    15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5,
+    10, 8, 13, 15, 12, 3, 3,
+];
+
+const ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6,
+    10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+/// Reads a block from the least significant bit up.
+struct BitReader {
+    block: u128,
+    offset: u32,
+}
+
+impl BitReader {
+    fn new(block: u128) -> Self {
+        Self { block, offset: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let v = ((self.block >> self.offset) & ((1u128 << count) - 1)) as u32;
+        self.offset += count;
+        v
+    }
+}
+
+fn weights(index_bits: u32) -> &'static [i32] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+fn subset_of(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        2 => ((PARTITIONS_2[partition] >> pixel) & 1) as usize,
+        3 => ((PARTITIONS_3[partition] >> (2 * pixel)) & 3) as usize,
+        _ => 0,
+    }
+}
+
+/// Whether the index of a pixel is stored with its most significant bit omitted.
+fn is_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            2 => pixel == ANCHORS_2[partition] as usize,
+            3 => {
+                pixel == ANCHORS_3_SECOND[partition] as usize
+                    || pixel == ANCHORS_3_THIRD[partition] as usize
+            }
+            _ => false,
+        }
+}
+
+fn interpolate(e0: i32, e1: i32, weight: i32) -> i32 {
+    (e0 * (64 - weight) + e1 * weight + 32) >> 6
+}
+
+fn to_block(pixels: &[[u8; CHANNELS]; PIXELS]) -> Block {
+    let mut ret = [[0u8; BLOCK_WIDTH * CHANNELS]; BLOCK_HEIGHT];
+    for (idx, pixel) in pixels.iter().enumerate() {
+        let x = idx % BLOCK_WIDTH;
+        ret[idx / BLOCK_WIDTH][(x * CHANNELS)..((x + 1) * CHANNELS)].copy_from_slice(pixel);
+    }
+    ret
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+impl Bc7Mode {
+    #[allow(clippy::too_many_arguments)]
+    const fn new(
+        subsets: usize,
+        partition_bits: u32,
+        rotation_bits: u32,
+        index_selection_bits: u32,
+        color_bits: u32,
+        alpha_bits: u32,
+        endpoint_pbits: bool,
+        shared_pbits: bool,
+        index_bits: u32,
+        secondary_index_bits: u32,
+    ) -> Self {
+        Self {
+            subsets,
+            partition_bits,
+            rotation_bits,
+            index_selection_bits,
+            color_bits,
+            alpha_bits,
+            endpoint_pbits,
+            shared_pbits,
+            index_bits,
+            secondary_index_bits,
+        }
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode::new(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    Bc7Mode::new(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    Bc7Mode::new(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    Bc7Mode::new(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    Bc7Mode::new(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    Bc7Mode::new(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    Bc7Mode::new(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    Bc7Mode::new(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+/// Expands a `bits`-bit value to eight bits by replicating its high bits.
+fn expand(v: u32, bits: u32) -> i32 {
+    let v = v << (8 - bits);
+    (v | (v >> bits)) as i32
+}
+
+fn decode_bc7(block: u128) -> Block {
+    // The mode is the number of zero bits before the first one; reserved modes decode to zero.
+    let mode_index = (block as u8).trailing_zeros();
+    let mode = match BC7_MODES.get(mode_index as usize) {
+        Some(mode) => mode,
+        None => return [[0u8; BLOCK_WIDTH * CHANNELS]; BLOCK_HEIGHT],
+    };
+    let mut reader = BitReader::new(block);
+    reader.read(mode_index + 1);
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; CHANNELS]; 6];
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    if mode.alpha_bits > 0 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[3] = reader.read(mode.alpha_bits);
+        }
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbit = 0;
+        for (idx, endpoint) in endpoints[..endpoint_count].iter_mut().enumerate() {
+            if mode.endpoint_pbits || idx % 2 == 0 {
+                pbit = reader.read(1);
+            }
+            for v in endpoint.iter_mut() {
+                *v = (*v << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    let mut expanded = [[0i32; CHANNELS]; 6];
+    for (target, endpoint) in expanded.iter_mut().zip(&endpoints[..endpoint_count]) {
+        for channel in 0..3 {
+            target[channel] = expand(endpoint[channel], color_bits);
+        }
+        target[3] = if alpha_bits > 0 {
+            expand(endpoint[3], alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let mut indices = [0u32; PIXELS];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.subsets, partition, pixel);
+        *index = reader.read(mode.index_bits - anchor as u32);
+    }
+    let mut secondary_indices = [0u32; PIXELS];
+    if mode.secondary_index_bits > 0 {
+        for (pixel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = reader.read(mode.secondary_index_bits - (pixel == 0) as u32);
+        }
+    }
+
+    let mut pixels = [[0u8; CHANNELS]; PIXELS];
+    for (idx, pixel) in pixels.iter_mut().enumerate() {
+        let subset = subset_of(mode.subsets, partition, idx);
+        let e0 = expanded[subset * 2];
+        let e1 = expanded[subset * 2 + 1];
+        let (color_weight, alpha_weight) = if mode.secondary_index_bits == 0 {
+            let weight = weights(mode.index_bits)[indices[idx] as usize];
+            (weight, weight)
+        } else {
+            let primary = weights(mode.index_bits)[indices[idx] as usize];
+            let secondary = weights(mode.secondary_index_bits)[secondary_indices[idx] as usize];
+            if index_selection == 0 {
+                (primary, secondary)
+            } else {
+                (secondary, primary)
+            }
+        };
+        for channel in 0..3 {
+            pixel[channel] = interpolate(e0[channel], e1[channel], color_weight) as u8;
+        }
+        pixel[3] = interpolate(e0[3], e1[3], alpha_weight) as u8;
+        match rotation {
+            1 => pixel.swap(0, 3),
+            2 => pixel.swap(1, 3),
+            3 => pixel.swap(2, 3),
+            _ => {}
+        }
+    }
+    to_block(&pixels)
+}
+
+const R0: u8 = 0;
+const G0: u8 = 1;
+const B0: u8 = 2;
+const R1: u8 = 3;
+const G1: u8 = 4;
+const B1: u8 = 5;
+const R2: u8 = 6;
+const G2: u8 = 7;
+const B2: u8 = 8;
+const R3: u8 = 9;
+const G3: u8 = 10;
+const B3: u8 = 11;
+
+/// Endpoint bits of each BC6H mode, in stream order.
+///
+/// `(field, a, b)` stands for `field[a:b]` in the specification: bits `b` to `a` of an endpoint
+/// channel, read starting from `b`.
+const BC6H_LAYOUTS: [&[(u8, u8, u8)]; 14] = [
+    // Mode 1
+    &[
+        (G2, 4, 4),
+        (B2, 4, 4),
+        (B3, 4, 4),
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 4, 0),
+        (G3, 4, 4),
+        (G2, 3, 0),
+        (G1, 4, 0),
+        (B3, 0, 0),
+        (G3, 3, 0),
+        (B1, 4, 0),
+        (B3, 1, 1),
+        (B2, 3, 0),
+        (R2, 4, 0),
+        (B3, 2, 2),
+        (R3, 4, 0),
+        (B3, 3, 3),
+    ],
+    // Mode 2
+    &[
+        (G2, 5, 5),
+        (G3, 4, 4),
+        (G3, 5, 5),
+        (R0, 6, 0),
+        (B3, 0, 0),
+        (B3, 1, 1),
+        (B2, 4, 4),
+        (G0, 6, 0),
+        (B2, 5, 5),
+        (B3, 2, 2),
+        (G2, 4, 4),
+        (B0, 6, 0),
+        (B3, 3, 3),
+        (B3, 5, 5),
+        (B3, 4, 4),
+        (R1, 5, 0),
+        (G2, 3, 0),
+        (G1, 5, 0),
+        (G3, 3, 0),
+        (B1, 5, 0),
+        (B2, 3, 0),
+        (R2, 5, 0),
+        (R3, 5, 0),
+    ],
+    // Mode 3
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 4, 0),
+        (R0, 10, 10),
+        (G2, 3, 0),
+        (G1, 3, 0),
+        (G0, 10, 10),
+        (B3, 0, 0),
+        (G3, 3, 0),
+        (B1, 3, 0),
+        (B0, 10, 10),
+        (B3, 1, 1),
+        (B2, 3, 0),
+        (R2, 4, 0),
+        (B3, 2, 2),
+        (R3, 4, 0),
+        (B3, 3, 3),
+    ],
+    // Mode 4
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 3, 0),
+        (R0, 10, 10),
+        (G3, 4, 4),
+        (G2, 3, 0),
+        (G1, 4, 0),
+        (G0, 10, 10),
+        (G3, 3, 0),
+        (B1, 3, 0),
+        (B0, 10, 10),
+        (B3, 1, 1),
+        (B2, 3, 0),
+        (R2, 3, 0),
+        (B3, 0, 0),
+        (B3, 2, 2),
+        (R3, 3, 0),
+        (G2, 4, 4),
+        (B3, 3, 3),
+    ],
+    // Mode 5
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 3, 0),
+        (R0, 10, 10),
+        (B2, 4, 4),
+        (G2, 3, 0),
+        (G1, 3, 0),
+        (G0, 10, 10),
+        (B3, 0, 0),
+        (G3, 3, 0),
+        (B1, 4, 0),
+        (B0, 10, 10),
+        (B2, 3, 0),
+        (R2, 3, 0),
+        (B3, 1, 1),
+        (B3, 2, 2),
+        (R3, 3, 0),
+        (B3, 4, 4),
+        (B3, 3, 3),
+    ],
+    // Mode 6
+    &[
+        (R0, 8, 0),
+        (B2, 4, 4),
+        (G0, 8, 0),
+        (G2, 4, 4),
+        (B0, 8, 0),
+        (B3, 4, 4),
+        (R1, 4, 0),
+        (G3, 4, 4),
+        (G2, 3, 0),
+        (G1, 4, 0),
+        (B3, 0, 0),
+        (G3, 3, 0),
+        (B1, 4, 0),
+        (B3, 1, 1),
+        (B2, 3, 0),
+        (R2, 4, 0),
+        (B3, 2, 2),
+        (R3, 4, 0),
+        (B3, 3, 3),
+    ],
+    // Mode 7
+    &[
+        (R0, 7, 0),
+        (G3, 4, 4),
+        (B2, 4, 4),
+        (G0, 7, 0),
+        (B3, 2, 2),
+        (G2, 4, 4),
+        (B0, 7, 0),
+        (B3, 3, 3),
+        (B3, 4, 4),
+        (R1, 5, 0),
+        (G2, 3, 0),
+        (G1, 4, 0),
+        (B3, 0, 0),
+        (G3, 3, 0),
+        (B1, 4, 0),
+        (B3, 1, 1),
+        (B2, 3, 0),
+        (R2, 5, 0),
+        (R3, 5, 0),
+    ],
+    // Mode 8
+    &[
+        (R0, 7, 0),
+        (B3, 0, 0),
+        (B2, 4, 4),
+        (G0, 7, 0),
+        (G2, 5, 5),
+        (G2, 4, 4),
+        (B0, 7, 0),
+        (G3, 5, 5),
+        (B3, 4, 4),
+        (R1, 4, 0),
+        (G3, 4, 4),
+        (G2, 3, 0),
+        (G1, 5, 0),
+        (G3, 3, 0),
+        (B1, 4, 0),
+        (B3, 1, 1),
+        (B2, 3, 0),
+        (R2, 4, 0),
+        (B3, 2, 2),
+        (R3, 4, 0),
+        (B3, 3, 3),
+    ],
+    // Mode 9
+    &[
+        (R0, 7, 0),
+        (B3, 1, 1),
+        (B2, 4, 4),
+        (G0, 7, 0),
+        (B2, 5, 5),
+        (G2, 4, 4),
+        (B0, 7, 0),
+        (B3, 5, 5),
+        (B3, 4, 4),
+        (R1, 4, 0),
+        (G3, 4, 4),
+        (G2, 3, 0),
+        (G1, 4, 0),
+        (B3, 0, 0),
+        (G3, 3, 0),
+        (B1, 5, 0),
+        (B2, 3, 0),
+        (R2, 4, 0),
+        (B3, 2, 2),
+        (R3, 4, 0),
+        (B3, 3, 3),
+    ],
+    // Mode 10
+    &[
+        (R0, 5, 0),
+        (G3, 4, 4),
+        (B3, 0, 0),
+        (B3, 1, 1),
+        (B2, 4, 4),
+        (G0, 5, 0),
+        (G2, 5, 5),
+        (B2, 5, 5),
+        (B3, 2, 2),
+        (G2, 4, 4),
+        (B0, 5, 0),
+        (G3, 5, 5),
+        (B3, 3, 3),
+        (B3, 5, 5),
+        (B3, 4, 4),
+        (R1, 5, 0),
+        (G2, 3, 0),
+        (G1, 5, 0),
+        (G3, 3, 0),
+        (B1, 5, 0),
+        (B2, 3, 0),
+        (R2, 5, 0),
+        (R3, 5, 0),
+    ],
+    // Mode 11
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 9, 0),
+        (G1, 9, 0),
+        (B1, 9, 0),
+    ],
+    // Mode 12
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 8, 0),
+        (R0, 10, 10),
+        (G1, 8, 0),
+        (G0, 10, 10),
+        (B1, 8, 0),
+        (B0, 10, 10),
+    ],
+    // Mode 13
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 7, 0),
+        (R0, 10, 11),
+        (G1, 7, 0),
+        (G0, 10, 11),
+        (B1, 7, 0),
+        (B0, 10, 11),
+    ],
+    // Mode 14
+    &[
+        (R0, 9, 0),
+        (G0, 9, 0),
+        (B0, 9, 0),
+        (R1, 3, 0),
+        (R0, 10, 15),
+        (G1, 3, 0),
+        (G0, 10, 15),
+        (B1, 3, 0),
+        (B0, 10, 15),
+    ],
+];
+
+struct Bc6hMode {
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    transformed: bool,
+}
+
+impl Bc6hMode {
+    const fn new(endpoint_bits: u32, delta_bits: [u32; 3], transformed: bool) -> Self {
+        Self {
+            endpoint_bits,
+            delta_bits,
+            transformed,
+        }
+    }
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode::new(10, [5, 5, 5], true),
+    Bc6hMode::new(7, [6, 6, 6], true),
+    Bc6hMode::new(11, [5, 4, 4], true),
+    Bc6hMode::new(11, [4, 5, 4], true),
+    Bc6hMode::new(11, [4, 4, 5], true),
+    Bc6hMode::new(9, [5, 5, 5], true),
+    Bc6hMode::new(8, [6, 5, 5], true),
+    Bc6hMode::new(8, [5, 6, 5], true),
+    Bc6hMode::new(8, [5, 5, 6], true),
+    Bc6hMode::new(6, [6, 6, 6], false),
+    Bc6hMode::new(10, [10, 10, 10], false),
+    Bc6hMode::new(11, [9, 9, 9], true),
+    Bc6hMode::new(12, [8, 8, 8], true),
+    Bc6hMode::new(16, [4, 4, 4], true),
+];
+
+/// Maps a BC6H mode field to its index in [`BC6H_MODES`] and the length of the field.
+fn bc6h_mode(block: u128) -> Option<(usize, u32)> {
+    match block & 3 {
+        0 => return Some((0, 2)),
+        1 => return Some((1, 2)),
+        _ => {}
+    }
+    let index = match block & 0x1f {
+        0x02 => 2,
+        0x06 => 3,
+        0x0a => 4,
+        0x0e => 5,
+        0x12 => 6,
+        0x16 => 7,
+        0x1a => 8,
+        0x1e => 9,
+        0x03 => 10,
+        0x07 => 11,
+        0x0b => 12,
+        0x0f => 13,
+        _ => return None,
+    };
+    Some((index, 5))
+}
+
+fn sign_extend(v: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (v << shift) >> shift
+}
+
+fn unquantize(v: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 {
+            v
+        } else if v == 0 {
+            0
+        } else if v == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((v << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 {
+        v
+    } else {
+        let magnitude = v.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if v < 0 {
+            -unquantized
+        } else {
+            unquantized
+        }
+    }
+}
+
+/// Scales an interpolated value to the bits of a half float.
+fn finish_unquantize(v: i32, signed: bool) -> u16 {
+    if !signed {
+        ((v * 31) >> 6) as u16
+    } else if v < 0 {
+        (((-v * 31) >> 5) as u16) | 0x8000
+    } else {
+        ((v * 31) >> 5) as u16
+    }
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half >> 15) << 31;
+    let exponent = u32::from((half >> 10) & 0x1f);
+    let mantissa = u32::from(half & 0x3ff);
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal; normalize it.
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((mantissa << shift) & 0x3ff) << 13)
+        }
+        (0x1f, _) => sign | 0x7f800000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+fn decode_bc6h(block: u128, signed: bool) -> Block {
+    // Reserved modes decode to zero; BC6H has no alpha, so it stays opaque.
+    let (mode_index, mode_bits) = match bc6h_mode(block) {
+        Some(mode) => mode,
+        None => return to_block(&[[0, 0, 0, 0xff]; PIXELS]),
+    };
+    let mode = &BC6H_MODES[mode_index];
+    // The last four modes have a single region.
+    let subsets = if mode_index >= 10 { 1 } else { 2 };
+    let mut reader = BitReader::new(block);
+    reader.read(mode_bits);
+
+    let mut endpoints = [[0i32; 3]; 4];
+    for &(field, a, b) in BC6H_LAYOUTS[mode_index] {
+        let target = &mut endpoints[(field / 3) as usize][(field % 3) as usize];
+        if a >= b {
+            for bit in b..=a {
+                *target |= (reader.read(1) as i32) << bit;
+            }
+        } else {
+            for bit in (a..=b).rev() {
+                *target |= (reader.read(1) as i32) << bit;
+            }
+        }
+    }
+    let partition = if subsets == 2 {
+        reader.read(5) as usize
+    } else {
+        0
+    };
+
+    let endpoint_count = subsets * 2;
+    let mask = (1 << mode.endpoint_bits) - 1;
+    for channel in 0..3 {
+        let base = endpoints[0][channel];
+        if signed {
+            endpoints[0][channel] = sign_extend(base, mode.endpoint_bits);
+        }
+        for endpoint in &mut endpoints[1..endpoint_count] {
+            if mode.transformed {
+                let delta = sign_extend(endpoint[channel], mode.delta_bits[channel]);
+                endpoint[channel] = (base + delta) & mask;
+                if signed {
+                    endpoint[channel] = sign_extend(endpoint[channel], mode.endpoint_bits);
+                }
+            } else if signed {
+                endpoint[channel] = sign_extend(endpoint[channel], mode.endpoint_bits);
+            }
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        for v in endpoint.iter_mut() {
+            *v = unquantize(*v, mode.endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if subsets == 2 { 3 } else { 4 };
+    let mut pixels = [[0u8; CHANNELS]; PIXELS];
+    for (idx, pixel) in pixels.iter_mut().enumerate() {
+        let anchor = is_anchor(subsets, partition, idx);
+        let weight = weights(index_bits)[reader.read(index_bits - anchor as u32) as usize];
+        let subset = subset_of(subsets, partition, idx);
+        let e0 = endpoints[subset * 2];
+        let e1 = endpoints[subset * 2 + 1];
+        for channel in 0..3 {
+            let half = finish_unquantize(interpolate(e0[channel], e1[channel], weight), signed);
+            let v = f16_to_f32(half).clamp(0.0, 1.0);
+            pixel[channel] = (v * 255.0 + 0.5) as u8;
+        }
+        pixel[3] = 0xff;
+    }
+    to_block(&pixels)
+}
+
+/// Reads and decodes a single block.
+pub fn decode_single_block<R: std::io::Read>(
+    input: &mut R,
+    format: DecodeFormat,
+) -> std::io::Result<Block> {
+    let mut buf = [0u8; BLOCK_BYTES];
+    input.read_exact(&mut buf)?;
+    let block = u128::from_le_bytes(buf);
+    let ret = match format {
+        DecodeFormat::Bc6hUnsigned => decode_bc6h(block, false),
+        DecodeFormat::Bc6hSigned => decode_bc6h(block, true),
+        DecodeFormat::Bc7 => decode_bc7(block),
+    };
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    /// Packs fields into a block, least significant bit first.
+    #[derive(Default)]
+    struct BitWriter {
+        block: u128,
+        len: u32,
+    }
+
+    impl BitWriter {
+        fn put(&mut self, value: i32, count: u32) -> &mut Self {
+            self.block |= u128::from(value as u32 & ((1 << count) - 1)) << self.len;
+            self.len += count;
+            self
+        }
+
+        fn each(&mut self, values: &[i32], count: u32) -> &mut Self {
+            for &value in values {
+                self.put(value, count);
+            }
+            self
+        }
+
+        /// Writes the index of each pixel, with one bit less for the anchors.
+        fn indices(&mut self, indices: &[i32], bits: u32, anchors: &[usize]) -> &mut Self {
+            for (pixel, &index) in indices.iter().enumerate() {
+                self.put(index, bits - anchors.contains(&pixel) as u32);
+            }
+            self
+        }
+
+        fn decode(&self, format: DecodeFormat) -> Vec<[u8; CHANNELS]> {
+            assert_eq!(self.len, 128);
+            let block = decode_single_block(&mut &self.block.to_le_bytes()[..], format).unwrap();
+            block
+                .iter()
+                .flat_map(|row| row.chunks(CHANNELS))
+                .map(|pixel| pixel.try_into().unwrap())
+                .collect()
+        }
+    }
+
+    const INDICES: [i32; PIXELS] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+    #[test]
+    fn bc7_mode_6() {
+        // One subset of RGBA endpoints (21, 41, 61, 255) and (200, 100, 50, 0), whose p-bits
+        // make the first one odd, and each pixel using its own 4-bit index.
+        let pixels = BitWriter::default()
+            .put(1 << 6, 7)
+            .each(&[10, 100, 20, 50, 30, 25, 127, 0], 7)
+            .each(&[1, 0], 1)
+            .indices(&INDICES, 4, &[0])
+            .decode(DecodeFormat::Bc7);
+        #[rustfmt::skip]
+        let expected = [
+            [21, 41, 61, 255], [32, 45, 60, 239], [46, 49, 59, 219], [57, 53, 59, 203],
+            [69, 57, 58, 187], [80, 60, 57, 171], [94, 65, 57, 151], [105, 69, 56, 135],
+            [116, 72, 55, 120], [127, 76, 54, 104], [141, 81, 54, 84], [152, 84, 53, 68],
+            [164, 88, 52, 52], [175, 92, 52, 36], [189, 96, 51, 16], [200, 100, 50, 0],
+        ];
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn bc7_mode_1() {
+        // Partition 0 puts the right two columns in the second subset, whose anchor is the last
+        // pixel. Each subset shares a p-bit between its 6-bit endpoints.
+        let indices = [0, 5, 2, 7, 4, 1, 6, 3, 0, 5, 2, 7, 4, 1, 6, 3];
+        let pixels = BitWriter::default()
+            .put(1 << 1, 2)
+            .put(0, 6)
+            .each(&[10, 63, 0, 33], 6)
+            .each(&[20, 50, 0, 44], 6)
+            .each(&[30, 40, 0, 55], 6)
+            .each(&[1, 0], 1)
+            .indices(&indices, 3, &[0, 15])
+            .decode(DecodeFormat::Bc7);
+        let row = [
+            [42, 82, 122, 255],
+            [195, 169, 151, 255],
+            [37, 50, 62, 255],
+            [133, 177, 221, 255],
+            [165, 152, 146, 255],
+            [72, 99, 128, 255],
+            [114, 152, 190, 255],
+            [56, 75, 93, 255],
+        ];
+        assert_eq!(pixels, [row, row].concat());
+    }
+
+    #[test]
+    fn bc7_mode_5_rotation() {
+        // Separate colour and alpha indices, then red and alpha swapped by rotation 1.
+        let colour_indices = [0, 1, 2, 3].repeat(4);
+        let alpha_indices = [[0; 4], [1; 4], [2; 4], [3; 4]].concat();
+        let pixels = BitWriter::default()
+            .put(1 << 5, 6)
+            .put(1, 2)
+            .each(&[10, 127, 64, 0, 127, 64], 7)
+            .each(&[0, 255], 8)
+            .indices(&colour_indices, 2, &[0])
+            .indices(&alpha_indices, 2, &[0])
+            .decode(DecodeFormat::Bc7);
+        let colours = [[129, 255, 20], [87, 214, 97], [42, 170, 178], [0, 129, 255]];
+        for (idx, pixel) in pixels.iter().enumerate() {
+            let [g, b, r] = colours[idx % 4];
+            let a = [0, 84, 171, 255][idx / 4];
+            assert_eq!(*pixel, [a, g, b, r], "pixel {}", idx);
+        }
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        let pixels = BitWriter::default()
+            .each(&[0; 8], 16)
+            .decode(DecodeFormat::Bc7);
+        assert_eq!(pixels, [[0; CHANNELS]; PIXELS]);
+    }
+
+    /// Mode 11 of BC6H: one region of plain 10-bit endpoints and 4-bit indices.
+    fn bc6h_mode_11(e0: [i32; 3], e1: [i32; 3], format: DecodeFormat) -> Vec<[u8; CHANNELS]> {
+        BitWriter::default()
+            .put(0x03, 5)
+            .each(&e0, 10)
+            .each(&e1, 10)
+            .indices(&INDICES, 4, &[0])
+            .decode(format)
+    }
+
+    #[test]
+    fn bc6h_unsigned() {
+        // 495 unquantizes to 1.0, and 1023 to the largest half float, which is clamped.
+        let pixels = bc6h_mode_11([0, 462, 495], [495, 0, 1023], DecodeFormat::Bc6hUnsigned);
+        #[rustfmt::skip]
+        let expected = [
+            [0, 128, 255, 255], [0, 72, 255, 255], [0, 33, 255, 255], [0, 18, 255, 255],
+            [0, 10, 255, 255], [0, 6, 255, 255], [1, 3, 255, 255], [1, 1, 255, 255],
+            [2, 1, 255, 255], [4, 0, 255, 255], [9, 0, 255, 255], [16, 0, 255, 255],
+            [31, 0, 255, 255], [60, 0, 255, 255], [135, 0, 255, 255], [255, 0, 255, 255],
+        ];
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn bc6h_signed() {
+        // Negative values clamp to zero, and 511 is the largest positive endpoint.
+        let pixels = bc6h_mode_11([-100, 0, 200], [300, 247, 511], DecodeFormat::Bc6hSigned);
+        #[rustfmt::skip]
+        let expected = [
+            [0, 0, 36, 255], [0, 0, 84, 255], [0, 0, 228, 255], [0, 0, 255, 255],
+            [0, 0, 255, 255], [0, 0, 255, 255], [0, 1, 255, 255], [0, 1, 255, 255],
+            [1, 2, 255, 255], [3, 4, 255, 255], [10, 9, 255, 255], [28, 16, 255, 255],
+            [80, 31, 255, 255], [227, 60, 255, 255], [255, 134, 255, 255], [255, 253, 255, 255],
+        ];
+        assert_eq!(pixels, expected);
+
+        // Read as unsigned, the same bits make the first endpoint large instead.
+        let pixels = bc6h_mode_11([-100, 0, 200], [300, 247, 511], DecodeFormat::Bc6hUnsigned);
+        assert_eq!(pixels[0][0], 255);
+    }
+}
@@ -0,0 +1,965 @@
+//! Transcoder for crunch (`.crn`) textures.
+//!
+//! Crunch stores DXT or ETC blocks as entropy-coded references into shared endpoint and selector
+//! palettes. Transcoding rebuilds the plain block stream, which can then be handed to a regular
+//! DXT or ETC decoder.
+
+use std::io;
+
+const SIGNATURE: u16 = 0x4878;
+const HEADER_MIN_SIZE: usize = 74;
+
+const MAX_CODE_SIZE: usize = 16;
+const MAX_CODELENGTH_CODES: usize = 21;
+const MOST_PROBABLE_CODELENGTH_CODES: [usize; MAX_CODELENGTH_CODES] = [
+    17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+];
+
+const DXT1_FROM_LINEAR: [u32; 4] = [0, 2, 3, 1];
+const DXT5_FROM_LINEAR: [u32; 8] = [0, 2, 3, 4, 5, 6, 7, 1];
+
+const CHUNK_ENCODING_NUM_TILES: [usize; 8] = [1, 2, 2, 3, 3, 3, 3, 4];
+const CHUNK_ENCODING_TILES: [[usize; 4]; 8] = [
+    [0, 0, 0, 0],
+    [0, 0, 1, 1],
+    [0, 1, 0, 1],
+    [0, 0, 1, 2],
+    [1, 2, 0, 0],
+    [0, 1, 0, 2],
+    [1, 0, 2, 0],
+    [0, 1, 2, 3],
+];
+
+const CRN_FORMAT_DXT1: u8 = 0;
+const CRN_FORMAT_DXT5: u8 = 2;
+const CRN_FORMAT_DXT5_CCXY: u8 = 3;
+const CRN_FORMAT_DXT5_XGXR: u8 = 4;
+const CRN_FORMAT_DXT5_XGBR: u8 = 5;
+const CRN_FORMAT_DXT5_AGBR: u8 = 6;
+const CRN_FORMAT_ETC1: u8 = 10;
+const CRN_FORMAT_ETC2A: u8 = 12;
+const CRN_FORMAT_ETC1S: u8 = 13;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Implementation of crunch that produced a texture.
+///
+/// The two share a container but encode blocks differently, and nothing in the file tells them
+/// apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// The original crnlib, used by Unity before 2017.3.
+    Crnlib,
+    /// Unity's fork of crunch, used by Unity 2017.3 and later.
+    Unity,
+}
+
+/// Block format of a transcoded level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Dxt1,
+    Dxt5,
+    Etc1,
+    /// ETC2 color blocks, each preceded by an EAC alpha block.
+    Etc2a,
+}
+
+impl Format {
+    pub fn block_size(self) -> usize {
+        match self {
+            Format::Dxt1 | Format::Etc1 => 8,
+            Format::Dxt5 | Format::Etc2a => 16,
+        }
+    }
+
+    fn is_etc(self) -> bool {
+        matches!(self, Format::Etc1 | Format::Etc2a)
+    }
+
+    fn has_alpha(self) -> bool {
+        matches!(self, Format::Dxt5 | Format::Etc2a)
+    }
+}
+
+struct Palette {
+    offset: usize,
+    size: usize,
+    count: usize,
+}
+
+struct Codec<'a> {
+    input: &'a [u8],
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl<'a> Codec<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Reads `count` bits MSB first. Reading past the end yields zeros.
+    fn bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        while self.bit_count < count {
+            let (&c, rest) = self.input.split_first().unwrap_or((&0, &[]));
+            self.input = rest;
+            self.bit_buf |= u64::from(c) << (56 - self.bit_count);
+            self.bit_count += 8;
+        }
+        let ret = (self.bit_buf >> (64 - count)) as u32;
+        self.bit_buf <<= count;
+        self.bit_count -= count;
+        ret
+    }
+
+    fn decode(&mut self, model: &HuffmanModel) -> io::Result<u32> {
+        let mut code = 0u32;
+        for len in 1..=MAX_CODE_SIZE {
+            code = (code << 1) | self.bits(1);
+            let idx = code.wrapping_sub(model.first[len]);
+            if idx < model.count[len] {
+                return Ok(model.symbols[(model.offset[len] + idx) as usize].into());
+            }
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+
+    fn receive_model(&mut self) -> io::Result<HuffmanModel> {
+        let total_used_syms = self.bits(14) as usize;
+        if total_used_syms == 0 {
+            return HuffmanModel::from_code_sizes(&[]);
+        }
+        let mut code_sizes = vec![0u8; total_used_syms];
+
+        let num_codelength_codes = self.bits(5) as usize;
+        if !(1..=MAX_CODELENGTH_CODES).contains(&num_codelength_codes) {
+            return Err(invalid_data("invalid code length code count"));
+        }
+        let mut codelength_sizes = [0u8; MAX_CODELENGTH_CODES];
+        for &code in &MOST_PROBABLE_CODELENGTH_CODES[..num_codelength_codes] {
+            codelength_sizes[code] = self.bits(3) as u8;
+        }
+        let codelength_model = HuffmanModel::from_code_sizes(&codelength_sizes)?;
+
+        let mut ofs = 0;
+        while ofs < total_used_syms {
+            let remaining = total_used_syms - ofs;
+            let code = self.decode(&codelength_model)?;
+            let (len, value) = match code {
+                0..=16 => (1, code as u8),
+                17 => (self.bits(3) as usize + 3, 0),
+                18 => (self.bits(7) as usize + 11, 0),
+                19 | 20 => {
+                    let len = if code == 19 {
+                        self.bits(2) as usize + 3
+                    } else {
+                        self.bits(6) as usize + 7
+                    };
+                    let prev = match ofs.checked_sub(1).map(|idx| code_sizes[idx]) {
+                        Some(prev) if prev != 0 => prev,
+                        _ => return Err(invalid_data("invalid code length repeat")),
+                    };
+                    (len, prev)
+                }
+                _ => return Err(invalid_data("invalid code length code")),
+            };
+            if len > remaining {
+                return Err(invalid_data("code length run too long"));
+            }
+            for size in &mut code_sizes[ofs..(ofs + len)] {
+                *size = value;
+            }
+            ofs += len;
+        }
+        HuffmanModel::from_code_sizes(&code_sizes)
+    }
+}
+
+/// Canonical Huffman decoding table.
+struct HuffmanModel {
+    first: [u32; MAX_CODE_SIZE + 1],
+    count: [u32; MAX_CODE_SIZE + 1],
+    offset: [u32; MAX_CODE_SIZE + 1],
+    symbols: Vec<u16>,
+}
+
+impl HuffmanModel {
+    fn from_code_sizes(code_sizes: &[u8]) -> io::Result<Self> {
+        let mut count = [0u32; MAX_CODE_SIZE + 1];
+        for &size in code_sizes {
+            if size as usize > MAX_CODE_SIZE {
+                return Err(invalid_data("invalid code size"));
+            }
+            count[size as usize] += 1;
+        }
+        count[0] = 0;
+
+        let mut first = [0u32; MAX_CODE_SIZE + 1];
+        let mut offset = [0u32; MAX_CODE_SIZE + 1];
+        let mut code = 0u32;
+        let mut total = 0u32;
+        for len in 1..=MAX_CODE_SIZE {
+            first[len] = code;
+            offset[len] = total;
+            code += count[len];
+            total += count[len];
+            if code > (1 << len) {
+                return Err(invalid_data("oversubscribed Huffman code"));
+            }
+            code <<= 1;
+        }
+
+        let mut symbols = vec![0u16; total as usize];
+        let mut next = offset;
+        for (sym, &size) in code_sizes.iter().enumerate() {
+            if size != 0 {
+                symbols[next[size as usize] as usize] = sym as u16;
+                next[size as usize] += 1;
+            }
+        }
+        Ok(Self {
+            first,
+            count,
+            offset,
+            symbols,
+        })
+    }
+}
+
+/// Steps a palette index by `delta`, wrapping around the palette once.
+fn step(index: &mut usize, delta: u32, len: usize) -> io::Result<usize> {
+    *index += delta as usize;
+    if *index >= len {
+        *index -= len;
+    }
+    if *index >= len {
+        return Err(invalid_data("palette index out of range"));
+    }
+    Ok(*index)
+}
+
+fn check_index(index: u32, len: usize) -> io::Result<usize> {
+    if (index as usize) < len {
+        Ok(index as usize)
+    } else {
+        Err(invalid_data("palette index out of range"))
+    }
+}
+
+/// Sets the 3-bit EAC selector `s` of the field ending `end` bits into `bits`, whose fields are
+/// stored most significant bit first.
+fn put_eac_selector(bits: &mut [u8], end: usize, s: u8) {
+    let (byte, bit) = (end >> 3, end & 7);
+    if bit == 0 {
+        bits[byte - 1] |= s;
+    } else {
+        bits[byte] |= s << (8 - bit);
+        if bit < 3 {
+            bits[byte - 1] |= s >> bit;
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct BlockBufferElement {
+    endpoint_reference: u32,
+    color_endpoint_index: usize,
+    alpha_endpoint_index: usize,
+}
+
+/// A parsed crunch texture.
+pub struct Texture<'a> {
+    data: &'a [u8],
+    variant: Variant,
+    width: u32,
+    height: u32,
+    faces: u32,
+    crn_format: u8,
+    format: Format,
+    level_offsets: Vec<usize>,
+    chunk_encoding: HuffmanModel,
+    endpoint_delta: [Option<HuffmanModel>; 2],
+    selector_delta: [Option<HuffmanModel>; 2],
+    color_endpoints: Vec<u32>,
+    color_selectors: Vec<u32>,
+    alpha_endpoints: Vec<u16>,
+    alpha_selectors: Vec<u16>,
+}
+
+fn read_be(input: &[u8], offset: usize, len: usize) -> io::Result<u32> {
+    let bytes = input
+        .get(offset..(offset + len))
+        .ok_or_else(|| invalid_data("truncated header"))?;
+    Ok(bytes.iter().fold(0, |acc, &b| (acc << 8) | u32::from(b)))
+}
+
+fn read_palette(input: &[u8], offset: usize) -> io::Result<Palette> {
+    Ok(Palette {
+        offset: read_be(input, offset, 3)? as usize,
+        size: read_be(input, offset + 3, 3)? as usize,
+        count: read_be(input, offset + 6, 2)? as usize,
+    })
+}
+
+impl<'a> Texture<'a> {
+    pub fn parse(input: &'a [u8], variant: Variant) -> io::Result<Self> {
+        if read_be(input, 0, 2)? as u16 != SIGNATURE {
+            return Err(invalid_data("not a crunch texture"));
+        }
+        let header_size = read_be(input, 2, 2)? as usize;
+        let data_size = read_be(input, 6, 4)? as usize;
+        let width = read_be(input, 12, 2)?;
+        let height = read_be(input, 14, 2)?;
+        let levels = read_be(input, 16, 1)? as usize;
+        let faces = read_be(input, 17, 1)?;
+        let crn_format = read_be(input, 18, 1)? as u8;
+        let color_endpoints = read_palette(input, 33)?;
+        let color_selectors = read_palette(input, 41)?;
+        let alpha_endpoints = read_palette(input, 49)?;
+        let alpha_selectors = read_palette(input, 57)?;
+        let tables_size = read_be(input, 65, 2)? as usize;
+        let tables_offset = read_be(input, 67, 3)? as usize;
+        if header_size < HEADER_MIN_SIZE || levels == 0 || faces == 0 {
+            return Err(invalid_data("invalid header"));
+        }
+        let level_offsets = (0..levels)
+            .map(|level| read_be(input, 70 + 4 * level, 4).map(|v| v as usize))
+            .collect::<io::Result<Vec<_>>>()?;
+        let data = input
+            .get(..data_size)
+            .ok_or_else(|| invalid_data("truncated data"))?;
+
+        let format = match (crn_format, variant) {
+            (CRN_FORMAT_DXT1, _) => Format::Dxt1,
+            (CRN_FORMAT_DXT5, _)
+            | (CRN_FORMAT_DXT5_CCXY, _)
+            | (CRN_FORMAT_DXT5_XGXR, _)
+            | (CRN_FORMAT_DXT5_XGBR, _)
+            | (CRN_FORMAT_DXT5_AGBR, _) => Format::Dxt5,
+            (CRN_FORMAT_ETC1, Variant::Unity) | (CRN_FORMAT_ETC1S, Variant::Unity) => Format::Etc1,
+            (CRN_FORMAT_ETC2A, Variant::Unity) => Format::Etc2a,
+            _ => return Err(invalid_data("unsupported crunch format")),
+        };
+        let has_alpha = format.has_alpha();
+        if color_endpoints.count == 0 || (has_alpha && alpha_endpoints.count == 0) {
+            return Err(invalid_data("missing palette"));
+        }
+
+        let tables = data
+            .get(tables_offset..(tables_offset + tables_size))
+            .ok_or_else(|| invalid_data("truncated tables"))?;
+        let mut codec = Codec::new(tables);
+        let chunk_encoding = codec.receive_model()?;
+        let color_models = (codec.receive_model()?, codec.receive_model()?);
+        let alpha_models = if has_alpha {
+            Some((codec.receive_model()?, codec.receive_model()?))
+        } else {
+            None
+        };
+        let (alpha_endpoint_delta, alpha_selector_delta) = match alpha_models {
+            Some((e, s)) => (Some(e), Some(s)),
+            None => (None, None),
+        };
+
+        let mut texture = Self {
+            data,
+            variant,
+            width,
+            height,
+            faces,
+            crn_format,
+            format,
+            level_offsets,
+            chunk_encoding,
+            endpoint_delta: [Some(color_models.0), alpha_endpoint_delta],
+            selector_delta: [Some(color_models.1), alpha_selector_delta],
+            color_endpoints: Vec::new(),
+            color_selectors: Vec::new(),
+            alpha_endpoints: Vec::new(),
+            alpha_selectors: Vec::new(),
+        };
+        texture.decode_color_endpoints(&color_endpoints)?;
+        texture.decode_color_selectors(&color_selectors)?;
+        if has_alpha {
+            texture.decode_alpha_endpoints(&alpha_endpoints)?;
+            texture.decode_alpha_selectors(&alpha_selectors)?;
+        }
+        Ok(texture)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn levels(&self) -> usize {
+        self.level_offsets.len()
+    }
+
+    pub fn faces(&self) -> u32 {
+        self.faces
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    fn palette_codec(&self, palette: &Palette) -> io::Result<Codec<'a>> {
+        let data = self
+            .data
+            .get(palette.offset..(palette.offset + palette.size))
+            .ok_or_else(|| invalid_data("truncated palette"))?;
+        Ok(Codec::new(data))
+    }
+
+    fn has_subblocks(&self) -> bool {
+        self.crn_format == CRN_FORMAT_ETC1 || self.crn_format == CRN_FORMAT_ETC2A
+    }
+
+    fn decode_color_endpoints(&mut self, palette: &Palette) -> io::Result<()> {
+        let mut codec = self.palette_codec(palette)?;
+        let mut endpoints = Vec::with_capacity(palette.count);
+        if self.format.is_etc() {
+            let dm = codec.receive_model()?;
+            let mut a = 0u32;
+            for _ in 0..palette.count {
+                for shift in (0..32).step_by(8) {
+                    a = a.wrapping_add(codec.decode(&dm)? << shift);
+                }
+                a &= 0x1f1f1f1f;
+                endpoints.push(if self.has_subblocks() {
+                    a
+                } else {
+                    // Bake the endpoint into a differential block with zero deltas.
+                    ((a & 0x07000000) << 5)
+                        | ((a & 0x07000000) << 2)
+                        | 0x02000000
+                        | ((a & 0x001f1f1f) << 3)
+                });
+            }
+        } else {
+            let dm = [codec.receive_model()?, codec.receive_model()?];
+            let (mut a, mut b, mut c, mut d, mut e, mut f) = (0u32, 0u32, 0u32, 0u32, 0u32, 0u32);
+            for _ in 0..palette.count {
+                a = (a + codec.decode(&dm[0])?) & 31;
+                b = (b + codec.decode(&dm[1])?) & 63;
+                c = (c + codec.decode(&dm[0])?) & 31;
+                d = (d + codec.decode(&dm[0])?) & 31;
+                e = (e + codec.decode(&dm[1])?) & 63;
+                f = (f + codec.decode(&dm[0])?) & 31;
+                endpoints.push(c | (b << 5) | (a << 11) | (f << 16) | (e << 21) | (d << 27));
+            }
+        }
+        self.color_endpoints = endpoints;
+        Ok(())
+    }
+
+    fn decode_color_selectors(&mut self, palette: &Palette) -> io::Result<()> {
+        let mut codec = self.palette_codec(palette)?;
+        let dm = codec.receive_model()?;
+        match self.variant {
+            Variant::Crnlib => {
+                let mut cur = [0u32; 16];
+                let mut selectors = Vec::with_capacity(palette.count);
+                for _ in 0..palette.count {
+                    for j in 0..8 {
+                        let sym = codec.decode(&dm)?;
+                        if sym >= 49 {
+                            return Err(invalid_data("invalid selector delta"));
+                        }
+                        let (d0, d1) = (sym % 7, sym / 7);
+                        cur[j * 2] = (cur[j * 2] + d0 + 1) & 3;
+                        cur[j * 2 + 1] = (cur[j * 2 + 1] + d1 + 1) & 3;
+                    }
+                    let selector = cur.iter().enumerate().fold(0, |acc, (i, &v)| {
+                        acc | (DXT1_FROM_LINEAR[v as usize] << (i * 2))
+                    });
+                    selectors.push(selector);
+                }
+                self.color_selectors = selectors;
+            }
+            Variant::Unity => {
+                let is_etc = self.format.is_etc();
+                let has_subblocks = self.has_subblocks();
+                let mut selectors = vec![0u32; palette.count << (has_subblocks as usize)];
+                let mut s = 0u32;
+                for i in 0..palette.count {
+                    for shift in (0..32).step_by(4) {
+                        s ^= codec.decode(&dm)? << shift;
+                    }
+                    if is_etc {
+                        // Convert linear selectors to ETC's sign/magnitude and MSB/LSB planes,
+                        // in column-major pixel order. Blocks with subblocks also get a
+                        // transposed copy for the unflipped layout.
+                        let selector = (!s & 0xaaaaaaaa) | (!(s ^ (s >> 1)) & 0x55555555);
+                        for h in 0..4 {
+                            for w in 0..4 {
+                                let t = (8 + h + 4 * w) & 15;
+                                if has_subblocks {
+                                    let s0 = selector >> ((w << 3) | (h << 1));
+                                    selectors[i << 1] |= (((s0 >> 1) & 1) | ((s0 & 1) << 16)) << t;
+                                }
+                                let s1 = selector >> ((h << 3) | (w << 1));
+                                let idx = if has_subblocks { (i << 1) | 1 } else { i };
+                                selectors[idx] |= (((s1 >> 1) & 1) | ((s1 & 1) << 16)) << t;
+                            }
+                        }
+                    } else {
+                        selectors[i] = ((s ^ (s << 1)) & 0xaaaaaaaa) | ((s >> 1) & 0x55555555);
+                    }
+                }
+                self.color_selectors = selectors;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_alpha_endpoints(&mut self, palette: &Palette) -> io::Result<()> {
+        let mut codec = self.palette_codec(palette)?;
+        let dm = codec.receive_model()?;
+        let mut endpoints = Vec::with_capacity(palette.count);
+        let (mut a, mut b) = (0u32, 0u32);
+        for _ in 0..palette.count {
+            a = (a + codec.decode(&dm)?) & 255;
+            b = (b + codec.decode(&dm)?) & 255;
+            endpoints.push((a | (b << 8)) as u16);
+        }
+        self.alpha_endpoints = endpoints;
+        Ok(())
+    }
+
+    fn decode_alpha_selectors(&mut self, palette: &Palette) -> io::Result<()> {
+        let mut codec = self.palette_codec(palette)?;
+        let dm = codec.receive_model()?;
+        let mut selectors = Vec::with_capacity(palette.count * 3);
+        match self.variant {
+            Variant::Unity if self.format.is_etc() => {
+                // EAC selectors are laid out in column-major pixel order. Like color selectors,
+                // each entry holds a transposed copy for the unflipped layout first, so entries
+                // take six words instead of three.
+                let mut linear = [0u8; 8];
+                for _ in 0..palette.count {
+                    let mut entry = [0u8; 12];
+                    let mut group = 0u8;
+                    for p in 0..16 {
+                        group = if p & 1 == 1 {
+                            group >> 3
+                        } else {
+                            linear[p >> 1] ^= codec.decode(&dm)? as u8;
+                            linear[p >> 1]
+                        };
+                        let s = match group & 7 {
+                            s @ 0..=3 => 3 - s,
+                            s => s,
+                        };
+                        put_eac_selector(&mut entry[..6], 3 * (p + 1), s);
+                        put_eac_selector(&mut entry[6..], 3 * (4 * (p & 3) + (p >> 2) + 1), s);
+                    }
+                    for word in entry.chunks(2) {
+                        selectors.push(u16::from_le_bytes([word[0], word[1]]));
+                    }
+                }
+            }
+            Variant::Crnlib => {
+                let mut cur = [0u32; 16];
+                for _ in 0..palette.count {
+                    for j in 0..8 {
+                        let sym = codec.decode(&dm)?;
+                        if sym >= 225 {
+                            return Err(invalid_data("invalid selector delta"));
+                        }
+                        let (d0, d1) = (sym % 15, sym / 15);
+                        cur[j * 2] = (cur[j * 2] + d0 + 1) & 7;
+                        cur[j * 2 + 1] = (cur[j * 2 + 1] + d1 + 1) & 7;
+                    }
+                    let selector = cur.iter().enumerate().fold(0u64, |acc, (i, &v)| {
+                        acc | (u64::from(DXT5_FROM_LINEAR[v as usize]) << (i * 3))
+                    });
+                    selectors.extend_from_slice(&[
+                        selector as u16,
+                        (selector >> 16) as u16,
+                        (selector >> 32) as u16,
+                    ]);
+                }
+            }
+            Variant::Unity => {
+                let from_linear = |v: u32| -> u32 {
+                    DXT5_FROM_LINEAR[(v & 7) as usize] | (DXT5_FROM_LINEAR[(v >> 3) as usize] << 3)
+                };
+                let (mut s0_linear, mut s1_linear) = (0u32, 0u32);
+                for _ in 0..palette.count {
+                    let (mut s0, mut s1) = (0u32, 0u32);
+                    for shift in (0..24).step_by(6) {
+                        s0_linear ^= codec.decode(&dm)? << shift;
+                        s0 |= from_linear((s0_linear >> shift) & 0x3f) << shift;
+                    }
+                    for shift in (0..24).step_by(6) {
+                        s1_linear ^= codec.decode(&dm)? << shift;
+                        s1 |= from_linear((s1_linear >> shift) & 0x3f) << shift;
+                    }
+                    selectors.extend_from_slice(&[
+                        s0 as u16,
+                        ((s0 >> 16) | (s1 << 8)) as u16,
+                        (s1 >> 8) as u16,
+                    ]);
+                }
+            }
+        }
+        self.alpha_selectors = selectors;
+        Ok(())
+    }
+
+    /// Transcodes a mip level of every face, returning the blocks of each face in row-major order.
+    pub fn unpack_level(&self, level: usize) -> io::Result<Vec<u8>> {
+        let start = *self
+            .level_offsets
+            .get(level)
+            .ok_or_else(|| invalid_data("no such level"))?;
+        let end = self
+            .level_offsets
+            .get(level + 1)
+            .copied()
+            .unwrap_or(self.data.len());
+        let src = self
+            .data
+            .get(start..end)
+            .ok_or_else(|| invalid_data("truncated level"))?;
+
+        let width = std::cmp::max(self.width >> level, 1);
+        let height = std::cmp::max(self.height >> level, 1);
+        let blocks_x = width.div_ceil(4) as usize;
+        let blocks_y = height.div_ceil(4) as usize;
+        let face_size = blocks_x * blocks_y * self.format.block_size();
+        let mut out = vec![0u8; face_size * self.faces as usize];
+        let mut faces = out.chunks_mut(face_size).collect::<Vec<_>>();
+        let mut codec = Codec::new(src);
+        match (self.variant, self.format) {
+            (Variant::Crnlib, _) => {
+                self.unpack_chunks(&mut codec, &mut faces, blocks_x, blocks_y)?
+            }
+            (Variant::Unity, format) if format.is_etc() && self.has_subblocks() => {
+                self.unpack_etc(&mut codec, &mut faces, blocks_x, blocks_y)?
+            }
+            (Variant::Unity, _) => {
+                self.unpack_referenced(&mut codec, &mut faces, blocks_x, blocks_y)?
+            }
+        }
+        Ok(out)
+    }
+
+    fn models(&self, idx: usize) -> (&HuffmanModel, &HuffmanModel) {
+        (
+            self.endpoint_delta[idx].as_ref().unwrap(),
+            self.selector_delta[idx].as_ref().unwrap(),
+        )
+    }
+
+    fn write_alpha(&self, block: &mut [u8], endpoint: usize, selector: usize) {
+        block[0..2].copy_from_slice(&self.alpha_endpoints[endpoint].to_le_bytes());
+        for (i, s) in self.alpha_selectors[(selector * 3)..(selector * 3 + 3)]
+            .iter()
+            .enumerate()
+        {
+            block[(2 + i * 2)..(4 + i * 2)].copy_from_slice(&s.to_le_bytes());
+        }
+    }
+
+    fn write_color(&self, block: &mut [u8], endpoint: usize, selector: usize) {
+        block[0..4].copy_from_slice(&self.color_endpoints[endpoint].to_le_bytes());
+        block[4..8].copy_from_slice(&self.color_selectors[selector].to_le_bytes());
+    }
+
+    /// crnlib groups blocks into 2x2 chunks, walked in serpentine order, whose blocks share up to
+    /// four endpoint tiles.
+    fn unpack_chunks(
+        &self,
+        codec: &mut Codec<'_>,
+        faces: &mut [&mut [u8]],
+        blocks_x: usize,
+        blocks_y: usize,
+    ) -> io::Result<()> {
+        let block_size = self.format.block_size();
+        let has_alpha = self.format == Format::Dxt5;
+        let chunks_x = blocks_x.div_ceil(2);
+        let chunks_y = blocks_y.div_ceil(2);
+        let mut chunk_encoding_bits = 1u32;
+        let mut color_endpoint = 0usize;
+        let mut color_selector = 0usize;
+        let mut alpha_endpoint = 0usize;
+        let mut alpha_selector = 0usize;
+        let num_alpha_selectors = self.alpha_selectors.len() / 3;
+        let (color_endpoint_dm, color_selector_dm) = self.models(0);
+
+        for face in faces.iter_mut() {
+            for y in 0..chunks_y {
+                let xs: Box<dyn Iterator<Item = usize>> = if y & 1 == 1 {
+                    Box::new((0..chunks_x).rev())
+                } else {
+                    Box::new(0..chunks_x)
+                };
+                for x in xs {
+                    if chunk_encoding_bits == 1 {
+                        chunk_encoding_bits = codec.decode(&self.chunk_encoding)? | 512;
+                    }
+                    let encoding = (chunk_encoding_bits & 7) as usize;
+                    chunk_encoding_bits >>= 3;
+                    let num_tiles = CHUNK_ENCODING_NUM_TILES[encoding];
+
+                    let mut color_endpoints = [0usize; 4];
+                    for tile in &mut color_endpoints[..num_tiles] {
+                        let delta = codec.decode(color_endpoint_dm)?;
+                        *tile = step(&mut color_endpoint, delta, self.color_endpoints.len())?;
+                    }
+                    let mut alpha_endpoints = [0usize; 4];
+                    if has_alpha {
+                        let (alpha_endpoint_dm, _) = self.models(1);
+                        for tile in &mut alpha_endpoints[..num_tiles] {
+                            let delta = codec.decode(alpha_endpoint_dm)?;
+                            *tile = step(&mut alpha_endpoint, delta, self.alpha_endpoints.len())?;
+                        }
+                    }
+
+                    let tiles = &CHUNK_ENCODING_TILES[encoding];
+                    for (i, &tile) in tiles.iter().enumerate() {
+                        let bx = x * 2 + (i & 1);
+                        let by = y * 2 + (i >> 1);
+                        let delta = codec.decode(color_selector_dm)?;
+                        let cs = step(&mut color_selector, delta, self.color_selectors.len())?;
+                        let alpha = if has_alpha {
+                            let (_, alpha_selector_dm) = self.models(1);
+                            let delta = codec.decode(alpha_selector_dm)?;
+                            Some(step(&mut alpha_selector, delta, num_alpha_selectors)?)
+                        } else {
+                            None
+                        };
+                        if bx >= blocks_x || by >= blocks_y {
+                            continue;
+                        }
+                        let offset = (by * blocks_x + bx) * block_size;
+                        let block = &mut face[offset..(offset + block_size)];
+                        match alpha {
+                            Some(alpha_selector) => {
+                                self.write_alpha(block, alpha_endpoints[tile], alpha_selector);
+                                self.write_color(&mut block[8..], color_endpoints[tile], cs);
+                            }
+                            None => self.write_color(block, color_endpoints[tile], cs),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unity's crunch codes, for each block, whether its endpoints are new, shared with the block
+    /// to the left, or shared with the block above.
+    fn unpack_referenced(
+        &self,
+        codec: &mut Codec<'_>,
+        faces: &mut [&mut [u8]],
+        blocks_x: usize,
+        blocks_y: usize,
+    ) -> io::Result<()> {
+        let block_size = self.format.block_size();
+        let has_alpha = self.format == Format::Dxt5;
+        let width = (blocks_x + 1) & !1;
+        let height = (blocks_y + 1) & !1;
+        let mut buffer = vec![BlockBufferElement::default(); width];
+        let mut color_endpoint = 0usize;
+        let mut alpha_endpoint = 0usize;
+        let mut reference_group = 0u32;
+        let num_alpha_selectors = self.alpha_selectors.len() / 3;
+        let (color_endpoint_dm, color_selector_dm) = self.models(0);
+
+        for face in faces.iter_mut() {
+            for y in 0..height {
+                for (x, element) in buffer.iter_mut().enumerate() {
+                    if y & 1 == 0 && x & 1 == 0 {
+                        reference_group = codec.decode(&self.chunk_encoding)?;
+                    }
+                    let endpoint_reference = if y & 1 == 1 {
+                        element.endpoint_reference
+                    } else {
+                        let reference = reference_group & 3;
+                        element.endpoint_reference = (reference_group >> 2) & 3;
+                        reference_group >>= 4;
+                        reference
+                    };
+                    match endpoint_reference {
+                        0 => {
+                            let delta = codec.decode(color_endpoint_dm)?;
+                            step(&mut color_endpoint, delta, self.color_endpoints.len())?;
+                            element.color_endpoint_index = color_endpoint;
+                            if has_alpha {
+                                let (alpha_endpoint_dm, _) = self.models(1);
+                                let delta = codec.decode(alpha_endpoint_dm)?;
+                                step(&mut alpha_endpoint, delta, self.alpha_endpoints.len())?;
+                                element.alpha_endpoint_index = alpha_endpoint;
+                            }
+                        }
+                        1 => {
+                            element.color_endpoint_index = color_endpoint;
+                            element.alpha_endpoint_index = alpha_endpoint;
+                        }
+                        _ => {
+                            color_endpoint = element.color_endpoint_index;
+                            alpha_endpoint = element.alpha_endpoint_index;
+                        }
+                    }
+                    let color_selector =
+                        check_index(codec.decode(color_selector_dm)?, self.color_selectors.len())?;
+                    let alpha_selector = if has_alpha {
+                        let (_, alpha_selector_dm) = self.models(1);
+                        Some(check_index(
+                            codec.decode(alpha_selector_dm)?,
+                            num_alpha_selectors,
+                        )?)
+                    } else {
+                        None
+                    };
+                    if x >= blocks_x || y >= blocks_y {
+                        continue;
+                    }
+                    let offset = (y * blocks_x + x) * block_size;
+                    let block = &mut face[offset..(offset + block_size)];
+                    match alpha_selector {
+                        Some(alpha_selector) => {
+                            self.write_alpha(block, alpha_endpoint, alpha_selector);
+                            self.write_color(&mut block[8..], color_endpoint, color_selector);
+                        }
+                        None => self.write_color(block, color_endpoint, color_selector),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// ETC1 blocks carry a second endpoint for their second subblock, which may also come from
+    /// the block diagonally above. ETC2A blocks add an EAC alpha block in front, whose endpoint
+    /// is referenced along with the first color endpoint.
+    fn unpack_etc(
+        &self,
+        codec: &mut Codec<'_>,
+        faces: &mut [&mut [u8]],
+        blocks_x: usize,
+        blocks_y: usize,
+    ) -> io::Result<()> {
+        let block_size = self.format.block_size();
+        let has_alpha = self.format == Format::Etc2a;
+        let width = (blocks_x + 1) & !1;
+        let height = (blocks_y + 1) & !1;
+        let mut buffer = vec![BlockBufferElement::default(); width * 2];
+        let num_endpoints = self.color_endpoints.len();
+        let num_alpha_selectors = self.alpha_selectors.len() / 6;
+        let mut color_endpoint = 0usize;
+        let mut diagonal_color_endpoint = 0usize;
+        let mut alpha_endpoint = 0usize;
+        let mut diagonal_alpha_endpoint = 0usize;
+        let (color_endpoint_dm, color_selector_dm) = self.models(0);
+
+        for face in faces.iter_mut() {
+            for y in 0..height {
+                for x in 0..width {
+                    let primary = &mut buffer[x << 1];
+                    let mut endpoint_reference = if y & 1 == 1 {
+                        primary.endpoint_reference
+                    } else {
+                        let group = codec.decode(&self.chunk_encoding)?;
+                        primary.endpoint_reference = ((group >> 2) & 3) | ((group >> 4) & 12);
+                        (group & 3) | ((group >> 2) & 12)
+                    };
+                    match endpoint_reference & 3 {
+                        0 => {
+                            let delta = codec.decode(color_endpoint_dm)?;
+                            step(&mut color_endpoint, delta, num_endpoints)?;
+                            if has_alpha {
+                                let (alpha_endpoint_dm, _) = self.models(1);
+                                let delta = codec.decode(alpha_endpoint_dm)?;
+                                step(&mut alpha_endpoint, delta, self.alpha_endpoints.len())?;
+                            }
+                        }
+                        1 => {}
+                        3 => {
+                            color_endpoint = diagonal_color_endpoint;
+                            alpha_endpoint = diagonal_alpha_endpoint;
+                        }
+                        _ => {
+                            color_endpoint = primary.color_endpoint_index;
+                            alpha_endpoint = primary.alpha_endpoint_index;
+                        }
+                    }
+                    primary.color_endpoint_index = color_endpoint;
+                    primary.alpha_endpoint_index = alpha_endpoint;
+                    endpoint_reference >>= 2;
+                    let e0 = self.color_endpoints[color_endpoint].to_le_bytes();
+                    let selector = check_index(
+                        codec.decode(color_selector_dm)?,
+                        self.color_selectors.len() / 2,
+                    )?;
+                    if endpoint_reference & 1 != 0 {
+                        let delta = codec.decode(color_endpoint_dm)?;
+                        step(&mut color_endpoint, delta, num_endpoints)?;
+                    }
+                    let secondary = &mut buffer[(x << 1) | 1];
+                    diagonal_color_endpoint = secondary.color_endpoint_index;
+                    diagonal_alpha_endpoint = secondary.alpha_endpoint_index;
+                    secondary.color_endpoint_index = color_endpoint;
+                    secondary.alpha_endpoint_index = alpha_endpoint;
+                    let e1 = self.color_endpoints[color_endpoint].to_le_bytes();
+                    let alpha_selector = if has_alpha {
+                        let (_, alpha_selector_dm) = self.models(1);
+                        Some(check_index(
+                            codec.decode(alpha_selector_dm)?,
+                            num_alpha_selectors,
+                        )?)
+                    } else {
+                        None
+                    };
+                    if x >= blocks_x || y >= blocks_y {
+                        continue;
+                    }
+
+                    let flip = ((endpoint_reference >> 1) ^ 1) & 1;
+                    let diff = (0..3).all(|c| e0[c] + 3 >= e1[c] && e1[c] + 4 >= e0[c]);
+                    let mut block_endpoint = [0u8; 4];
+                    for c in 0..3 {
+                        block_endpoint[c] = if diff {
+                            (e0[c] << 3) | (e1[c].wrapping_sub(e0[c]) & 7)
+                        } else {
+                            ((e0[c] << 3) & 0xf0) | (e1[c] >> 1)
+                        };
+                    }
+                    block_endpoint[3] =
+                        (e0[3] << 5) | (e1[3] << 2) | ((diff as u8) << 1) | flip as u8;
+
+                    let offset = (y * blocks_x + x) * block_size;
+                    let mut block = &mut face[offset..(offset + block_size)];
+                    if let Some(alpha_selector) = alpha_selector {
+                        // Entries come in pairs, the second for flipped blocks.
+                        let selector = (alpha_selector << 1) | flip as usize;
+                        self.write_alpha(block, alpha_endpoint, selector);
+                        block = &mut block[8..];
+                    }
+                    block[0..4].copy_from_slice(&block_endpoint);
+                    block[4..8].copy_from_slice(
+                        &self.color_selectors[(selector << 1) | flip as usize].to_le_bytes(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::needless_range_loop, clippy::identity_op)]
+
 const TABLE_59T: [u8; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
 const TABLE_58H: [u8; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
 const COMPRESS_PARAMS: [[i32; 4]; 16] = [
@@ -105,7 +107,7 @@ fn unstuff_57(mut from: u64) -> u64 {
     let d = extract_and_shift!(from >> 7);
     from >>= 1;
     let e = extract_and_shift!(from >> 7);
-    drop(from);
+    let _ = from;
 
     (e << 57) | (d << 50) | (c << 48) | (b << 40) | (a << 7)
 }
@@ -118,7 +120,7 @@ fn unstuff_58(mut from: u64) -> u64 {
     let c = extract_and_shift!(from >> 2);
     from >>= 3;
     let d = extract_and_shift!(from >> 7);
-    drop(from);
+    let _ = from;
 
     (d << 51) | (c << 49) | (b << 33) | a
 }
@@ -129,7 +131,7 @@ fn unstuff_59(mut from: u64) -> u64 {
     let b = extract_and_shift!(from >> 24);
     from >>= 1;
     let c = extract_and_shift!(from >> 2);
-    drop(from);
+    let _ = from;
 
     (c << 57) | (b << 33) | a
 }
@@ -339,7 +341,7 @@ fn decompress_block_diff_flip(mut block: u64, alpha: bool) -> Block {
             let enc_color = extract_and_shift!(block >> 5) as u8;
             let avg_color = (enc_color << 3) | (enc_color >> 2);
             (colors_table[0].0)[i] = avg_color;
-            let enc_color = enc_color.overflowing_add(diff).0 as u8;
+            let enc_color = enc_color.overflowing_add(diff).0;
             let avg_color = (enc_color << 3) | (enc_color >> 2);
             (colors_table[1].0)[i] = avg_color;
         }
@@ -404,6 +406,52 @@ fn decompress_block_alpha(block: u64) -> SingleChannelBlock {
     ret
 }
 
+/// Decodes an EAC block of 11-bit values, used by the R11 and RG11 formats, keeping the top
+/// 8 bits. Signed values are shifted so that -1 maps to 0 and 1 to 255.
+fn decompress_block_r11(block: u64, signed: bool) -> SingleChannelBlock {
+    let base = ((block & (0xff << 56)) >> 56) as u8;
+    let mul = ((block & (0xf << 52)) >> 52) as i32;
+    let table = ((block & (0xf << 48)) >> 48) as usize;
+    let mut bits = (block & 0xffffffffffff).reverse_bits() >> 16;
+    let mut ret = [[0u8; BLOCK_WIDTH]; BLOCK_HEIGHT];
+    for x in 0..4 {
+        for y in 0..4 {
+            let idx = extract_and_shift!(bits >> 3) as u8;
+            let idx = idx.reverse_bits() >> 5;
+            // Modifiers of the table with a multiplier of 1.
+            let modifier = ALPHA_TABLE[16 + table][idx as usize];
+            let modifier = if mul == 0 {
+                modifier
+            } else {
+                modifier * mul * 8
+            };
+            ret[y][x] = if signed {
+                let base = i32::from((base as i8).max(-127));
+                let value = (base * 8 + modifier).clamp(-1023, 1023);
+                ((value + 1023) * 255 / 2046) as u8
+            } else {
+                let value = (i32::from(base) * 8 + 4 + modifier).clamp(0, 2047);
+                (value >> 3) as u8
+            };
+        }
+    }
+    ret
+}
+
+/// Expands one or two channels into opaque pixels, with the missing channels at zero.
+fn expand_channels(red: SingleChannelBlock, green: Option<SingleChannelBlock>) -> Block {
+    let mut ret = [[0u8; BLOCK_WIDTH * CHANNELS]; BLOCK_HEIGHT];
+    for y in 0..BLOCK_HEIGHT {
+        for x in 0..BLOCK_WIDTH {
+            let pixel = &mut ret[y][CHANNELS * x..][..CHANNELS];
+            pixel[0] = red[y][x];
+            pixel[1] = green.map_or(0, |green| green[y][x]);
+            pixel[3] = 0xff;
+        }
+    }
+    ret
+}
+
 fn combine_color_alpha(color: Block, alpha: SingleChannelBlock) -> Block {
     let mut ret = color;
     for y in 0..BLOCK_HEIGHT {
@@ -420,13 +468,50 @@ pub enum DecodeFormat {
     Etc2Rgb,
     Etc2Rgba8,
     Etc2Rgba1,
+    EacR,
+    EacRSigned,
+    EacRg,
+    EacRgSigned,
+}
+
+impl DecodeFormat {
+    /// Size in bytes of a 4x4 block.
+    pub fn block_size(self) -> usize {
+        match self {
+            DecodeFormat::EtcRgb4
+            | DecodeFormat::Etc2Rgb
+            | DecodeFormat::Etc2Rgba1
+            | DecodeFormat::EacR
+            | DecodeFormat::EacRSigned => 8,
+            DecodeFormat::Etc2Rgba8 | DecodeFormat::EacRg | DecodeFormat::EacRgSigned => 16,
+        }
+    }
 }
 
+/// Decodes a block into RGBA8 pixels. EAC formats fill red, and green for RG11, leaving blue at
+/// zero.
 pub fn decode_single_block<R: std::io::Read>(
     input: &mut R,
     format: DecodeFormat,
 ) -> std::io::Result<Block> {
     let mut buf = [0u8; 8];
+    if let DecodeFormat::EacR
+    | DecodeFormat::EacRSigned
+    | DecodeFormat::EacRg
+    | DecodeFormat::EacRgSigned = format
+    {
+        let signed = matches!(format, DecodeFormat::EacRSigned | DecodeFormat::EacRgSigned);
+        let mut read_channel = || -> std::io::Result<SingleChannelBlock> {
+            input.read_exact(&mut buf)?;
+            Ok(decompress_block_r11(u64::from_be_bytes(buf), signed))
+        };
+        let red = read_channel()?;
+        let green = match format {
+            DecodeFormat::EacRg | DecodeFormat::EacRgSigned => Some(read_channel()?),
+            _ => None,
+        };
+        return Ok(expand_channels(red, green));
+    }
     let alpha_block = if format == DecodeFormat::Etc2Rgba8 {
         input.read_exact(&mut buf)?;
         let block = u64::from_be_bytes(buf);
@@ -0,0 +1,357 @@
+//! Extractor for FMOD FSB5 sound banks.
+//!
+//! Unity stores the payload of an `AudioClip` as an FSB5 bank, usually holding a single subsound.
+//! Only PCM subsounds can be extracted. Vorbis ones leave out the setup header of their streams,
+//! which FMOD looks up by CRC in a table of its own, so they can't be rebuilt from the bank alone.
+
+use std::io;
+
+const SIGNATURE: &[u8; 4] = b"FSB5";
+
+const FREQUENCIES: [u32; 10] = [
+    0, 8000, 11000, 11025, 16000, 22050, 24000, 32000, 44100, 48000,
+];
+
+const CHUNK_CHANNELS: u32 = 1;
+const CHUNK_FREQUENCY: u32 = 2;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(input: &[u8], offset: usize) -> io::Result<u32> {
+    let bytes = input
+        .get(offset..(offset + 4))
+        .ok_or_else(|| invalid_data("unexpected end of data"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn bits(value: u64, offset: u32, count: u32) -> u64 {
+    (value >> offset) & ((1 << count) - 1)
+}
+
+/// Codec of the samples in a bank.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    PcmFloat,
+    GcAdpcm,
+    ImaAdpcm,
+    Vag,
+    HeVag,
+    Xma,
+    Mpeg,
+    Celt,
+    At9,
+    Xwma,
+    Vorbis,
+    Unknown(u32),
+}
+
+impl Codec {
+    fn from_u32(mode: u32) -> Self {
+        match mode {
+            1 => Codec::Pcm8,
+            2 => Codec::Pcm16,
+            3 => Codec::Pcm24,
+            4 => Codec::Pcm32,
+            5 => Codec::PcmFloat,
+            6 => Codec::GcAdpcm,
+            7 => Codec::ImaAdpcm,
+            8 => Codec::Vag,
+            9 => Codec::HeVag,
+            10 => Codec::Xma,
+            11 => Codec::Mpeg,
+            12 => Codec::Celt,
+            13 => Codec::At9,
+            14 => Codec::Xwma,
+            15 => Codec::Vorbis,
+            mode => Codec::Unknown(mode),
+        }
+    }
+
+    /// Whether subsounds of the codec can be extracted with [`Fsb5::to_wav`].
+    pub fn is_pcm(self) -> bool {
+        self.pcm_bits().is_some()
+    }
+
+    /// Bits per sample of a PCM codec.
+    fn pcm_bits(self) -> Option<u16> {
+        match self {
+            Codec::Pcm8 => Some(8),
+            Codec::Pcm16 => Some(16),
+            Codec::Pcm24 => Some(24),
+            Codec::Pcm32 | Codec::PcmFloat => Some(32),
+            _ => None,
+        }
+    }
+}
+
+/// A subsound of a bank.
+pub struct Sample<'a> {
+    frequency: u32,
+    channels: u16,
+    frames: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Sample<'a> {
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Number of frames, i.e. samples per channel.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Encoded data of the subsound.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// A parsed FSB5 bank.
+pub struct Fsb5<'a> {
+    codec: Codec,
+    samples: Vec<Sample<'a>>,
+}
+
+impl<'a> Fsb5<'a> {
+    pub fn parse(input: &'a [u8]) -> io::Result<Self> {
+        if input.get(..4) != Some(&SIGNATURE[..]) {
+            return Err(invalid_data("not an FSB5 bank"));
+        }
+        let version = read_u32(input, 4)?;
+        let num_samples = read_u32(input, 8)? as usize;
+        let sample_headers_size = read_u32(input, 12)? as usize;
+        let name_table_size = read_u32(input, 16)? as usize;
+        let data_size = read_u32(input, 20)? as usize;
+        let codec = Codec::from_u32(read_u32(input, 24)?);
+        let header_size = match version {
+            0 => 64,
+            1 => 60,
+            _ => return Err(invalid_data("unsupported FSB5 version")),
+        };
+
+        let sample_headers = input
+            .get(header_size..)
+            .and_then(|rest| rest.get(..sample_headers_size))
+            .ok_or_else(|| invalid_data("truncated sample headers"))?;
+        let data_offset = header_size + sample_headers_size + name_table_size;
+        let data = input
+            .get(data_offset..)
+            .and_then(|rest| rest.get(..data_size))
+            .ok_or_else(|| invalid_data("truncated sample data"))?;
+
+        // Each header is a bit-packed u64, optionally followed by extra chunks.
+        let mut headers = Vec::with_capacity(std::cmp::min(num_samples, sample_headers_size / 8));
+        let mut offset = 0;
+        for _ in 0..num_samples {
+            let raw = sample_headers
+                .get(offset..(offset + 8))
+                .ok_or_else(|| invalid_data("truncated sample headers"))?;
+            let raw = u64::from_le_bytes([
+                raw[0], raw[1], raw[2], raw[3], raw[4], raw[5], raw[6], raw[7],
+            ]);
+            offset += 8;
+            let mut has_next_chunk = bits(raw, 0, 1) != 0;
+            let mut frequency = FREQUENCIES
+                .get(bits(raw, 1, 4) as usize)
+                .copied()
+                .ok_or_else(|| invalid_data("invalid frequency"))?;
+            let mut channels = bits(raw, 5, 1) as u16 + 1;
+            let sample_offset = bits(raw, 6, 28) as usize * 16;
+            let frames = bits(raw, 34, 30) as u32;
+
+            while has_next_chunk {
+                let chunk = read_u32(sample_headers, offset)?;
+                has_next_chunk = chunk & 1 != 0;
+                let size = ((chunk >> 1) & 0xffffff) as usize;
+                let ty = chunk >> 25;
+                let chunk_data = sample_headers
+                    .get((offset + 4)..(offset + 4 + size))
+                    .ok_or_else(|| invalid_data("truncated sample chunk"))?;
+                match ty {
+                    CHUNK_CHANNELS if size >= 1 => channels = chunk_data[0].into(),
+                    CHUNK_FREQUENCY if size >= 4 => frequency = read_u32(chunk_data, 0)?,
+                    _ => {}
+                }
+                offset += 4 + size;
+            }
+            headers.push((frequency, channels, sample_offset, frames));
+        }
+
+        let mut samples = Vec::with_capacity(headers.len());
+        for (idx, &(frequency, channels, start, frames)) in headers.iter().enumerate() {
+            let end = headers
+                .get(idx + 1)
+                .map(|&(_, _, next, _)| next)
+                .unwrap_or(data.len());
+            let data = data
+                .get(start..end)
+                .ok_or_else(|| invalid_data("sample out of range"))?;
+            samples.push(Sample {
+                frequency,
+                channels,
+                frames,
+                data,
+            });
+        }
+        Ok(Self { codec, samples })
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn samples(&self) -> std::slice::Iter<'_, Sample<'a>> {
+        self.samples.iter()
+    }
+
+    /// Rebuilds a subsound as a WAV file.
+    ///
+    /// Only PCM codecs are supported; others fail with [`io::ErrorKind::Unsupported`].
+    pub fn to_wav(&self, index: usize) -> io::Result<Vec<u8>> {
+        let sample = self
+            .samples
+            .get(index)
+            .ok_or_else(|| invalid_data("no such sample"))?;
+        let bits_per_sample = self.codec.pcm_bits().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported codec {:?}", self.codec),
+            )
+        })?;
+        let block_align = sample.channels * bits_per_sample / 8;
+        let len = std::cmp::min(
+            sample.data.len(),
+            sample.frames as usize * block_align as usize,
+        );
+        let data = &sample.data[..len];
+        let format_tag: u16 = if self.codec == Codec::PcmFloat { 3 } else { 1 };
+
+        let mut wav = Vec::with_capacity(44 + data.len());
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&format_tag.to_le_bytes());
+        wav.extend_from_slice(&sample.channels.to_le_bytes());
+        wav.extend_from_slice(&sample.frequency.to_le_bytes());
+        wav.extend_from_slice(&(sample.frequency.wrapping_mul(block_align.into())).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&bits_per_sample.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        if self.codec == Codec::Pcm8 {
+            // FSB stores signed 8-bit samples, WAV unsigned ones.
+            wav.extend(data.iter().map(|b| b ^ 0x80));
+        } else {
+            wav.extend_from_slice(data);
+        }
+        Ok(wav)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A version 1 bank of one subsound, whose header is followed by `chunks`.
+    fn bank(codec: u32, header: u64, chunks: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bank = SIGNATURE.to_vec();
+        for &field in &[1, 1, 8 + chunks.len(), 0, data.len(), codec as usize] {
+            bank.extend_from_slice(&(field as u32).to_le_bytes());
+        }
+        bank.resize(60, 0);
+        bank.extend_from_slice(&header.to_le_bytes());
+        bank.extend_from_slice(chunks);
+        bank.extend_from_slice(data);
+        bank
+    }
+
+    /// A sample header at offset 0, without a frequency or channel count of its own.
+    fn header(frequency: u64, stereo: bool, frames: u64) -> u64 {
+        (frequency << 1) | ((stereo as u64) << 5) | (frames << 34)
+    }
+
+    #[test]
+    fn pcm16_to_wav() {
+        // Three stereo frames, then padding that isn't part of the sound.
+        let samples = [1i16, -1, 2, -2, 0x1234, -0x1234];
+        let mut data = samples
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect::<Vec<_>>();
+        data.extend_from_slice(&[0xaa; 4]);
+        let bank = bank(2, header(8, true, 3), &[], &data);
+
+        let fsb = Fsb5::parse(&bank).unwrap();
+        assert_eq!(fsb.codec(), Codec::Pcm16);
+        let sample = fsb.samples().next().unwrap();
+        assert_eq!(
+            (sample.frequency(), sample.channels(), sample.frames()),
+            (44100, 2, 3)
+        );
+        assert_eq!(sample.data(), &data[..]);
+
+        let wav = fsb.to_wav(0).unwrap();
+        let mut expected = b"RIFF".to_vec();
+        expected.extend_from_slice(&48u32.to_le_bytes());
+        expected.extend_from_slice(b"WAVEfmt ");
+        expected.extend_from_slice(&16u32.to_le_bytes());
+        expected.extend_from_slice(&[1, 0, 2, 0]);
+        expected.extend_from_slice(&44100u32.to_le_bytes());
+        expected.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+        expected.extend_from_slice(&[4, 0, 16, 0]);
+        expected.extend_from_slice(b"data");
+        expected.extend_from_slice(&12u32.to_le_bytes());
+        expected.extend_from_slice(&data[..12]);
+        assert_eq!(wav, expected);
+        assert!(fsb.to_wav(1).is_err());
+    }
+
+    #[test]
+    fn header_chunks() {
+        // A frequency chunk, then a channel chunk, overriding the packed header.
+        let mut chunks = Vec::new();
+        chunks.extend_from_slice(&(1 | (4 << 1) | (CHUNK_FREQUENCY << 25)).to_le_bytes());
+        chunks.extend_from_slice(&12345u32.to_le_bytes());
+        chunks.extend_from_slice(&((1 << 1) | (CHUNK_CHANNELS << 25)).to_le_bytes());
+        chunks.push(6);
+        let bank = bank(2, header(8, false, 1) | 1, &chunks, &[0; 12]);
+
+        let fsb = Fsb5::parse(&bank).unwrap();
+        let sample = fsb.samples().next().unwrap();
+        assert_eq!((sample.frequency(), sample.channels()), (12345, 6));
+        let wav = fsb.to_wav(0).unwrap();
+        assert_eq!(wav.len(), 44 + 12);
+        assert_eq!(&wav[22..24], &[6, 0]);
+    }
+
+    #[test]
+    fn vorbis_is_unsupported() {
+        let bank = bank(15, header(8, false, 1), &[], &[0; 16]);
+        let fsb = Fsb5::parse(&bank).unwrap();
+        assert_eq!(fsb.codec(), Codec::Vorbis);
+        assert!(!fsb.codec().is_pcm());
+        let err = fsb.to_wav(0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn truncated_banks() {
+        let bank = bank(2, header(8, true, 3), &[], &[0; 12]);
+        for len in 0..bank.len() {
+            assert!(Fsb5::parse(&bank[..len]).is_err(), "{} bytes", len);
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! Decoder for PVRTC 2bpp and 4bpp images.
+//!
+//! Unlike other block formats, a PVRTC pixel is blended from the colors of the four blocks
+//! around it, and blocks are stored in Morton order. The whole image has to be decoded at once.
+
+use std::io;
+
+const BLOCK_BYTES: usize = 8;
+const BLOCK_HEIGHT: usize = 4;
+
+/// Weight of color B for each two-bit modulation value, in eighths.
+const MODULATION_WEIGHTS: [u32; 4] = [0, 3, 5, 8];
+/// Marker for pixels of punch-through blocks that are fully transparent.
+const PUNCH_THROUGH: u32 = 14;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeFormat {
+    Pvrtc2Bpp,
+    Pvrtc4Bpp,
+}
+
+impl DecodeFormat {
+    fn block_width(self) -> usize {
+        match self {
+            DecodeFormat::Pvrtc2Bpp => 8,
+            DecodeFormat::Pvrtc4Bpp => 4,
+        }
+    }
+
+    /// Number of blocks in each direction. Images smaller than two blocks are padded.
+    fn blocks(self, width: u32, height: u32) -> (usize, usize) {
+        let blocks_x = (width as usize / self.block_width()).max(2);
+        let blocks_y = (height as usize / BLOCK_HEIGHT).max(2);
+        (blocks_x, blocks_y)
+    }
+
+    /// Size in bytes of an image.
+    pub fn image_size(self, width: u32, height: u32) -> usize {
+        let (blocks_x, blocks_y) = self.blocks(width, height);
+        blocks_x * blocks_y * BLOCK_BYTES
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Block {
+    modulation: u32,
+    color: u32,
+}
+
+impl Block {
+    /// Punch-through alpha in 4bpp blocks, and interpolated modulation in 2bpp ones.
+    fn modulation_flag(self) -> bool {
+        self.color & 1 != 0
+    }
+
+    /// Color A as five bits per color channel and four bits of alpha.
+    fn color_a(self) -> [u32; 4] {
+        let c = self.color & 0xffff;
+        if c & 0x8000 != 0 {
+            let b = (c >> 1) & 0xf;
+            [(c >> 10) & 0x1f, (c >> 5) & 0x1f, (b << 1) | (b >> 3), 0xf]
+        } else {
+            let r = (c >> 8) & 0xf;
+            let g = (c >> 4) & 0xf;
+            let b = (c >> 1) & 0x7;
+            [
+                (r << 1) | (r >> 3),
+                (g << 1) | (g >> 3),
+                (b << 2) | (b >> 1),
+                ((c >> 12) & 0x7) << 1,
+            ]
+        }
+    }
+
+    /// Color B as five bits per color channel and four bits of alpha.
+    fn color_b(self) -> [u32; 4] {
+        let c = self.color >> 16;
+        if c & 0x8000 != 0 {
+            [(c >> 10) & 0x1f, (c >> 5) & 0x1f, c & 0x1f, 0xf]
+        } else {
+            let r = (c >> 8) & 0xf;
+            let g = (c >> 4) & 0xf;
+            let b = c & 0xf;
+            [
+                (r << 1) | (r >> 3),
+                (g << 1) | (g >> 3),
+                (b << 1) | (b >> 3),
+                ((c >> 12) & 0x7) << 1,
+            ]
+        }
+    }
+}
+
+/// How the unstored modulation values of a 2bpp block are filled in.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Interpolation {
+    /// Every pixel has a one-bit value.
+    None,
+    Both,
+    Horizontal,
+    Vertical,
+}
+
+/// Position of a block in the Morton order, with the leftover bits of the longer side on top.
+fn twiddle(x: usize, y: usize, blocks_x: usize, blocks_y: usize) -> usize {
+    let min = blocks_x.min(blocks_y);
+    let mut index = 0;
+    let mut shift = 0;
+    while (1 << shift) < min {
+        index |= ((y >> shift) & 1) << (2 * shift);
+        index |= ((x >> shift) & 1) << (2 * shift + 1);
+        shift += 1;
+    }
+    let rest = if blocks_x > blocks_y { x } else { y };
+    index | ((rest >> shift) << (2 * shift))
+}
+
+struct Image {
+    format: DecodeFormat,
+    blocks: Vec<Block>,
+    blocks_x: usize,
+    blocks_y: usize,
+    width: usize,
+    height: usize,
+    /// Modulation value of every pixel, or `PUNCH_THROUGH`.
+    modulation: Vec<u32>,
+}
+
+impl Image {
+    fn block(&self, x: usize, y: usize) -> Block {
+        self.blocks[twiddle(x, y, self.blocks_x, self.blocks_y)]
+    }
+
+    fn unpack_modulation(&mut self) {
+        let block_width = self.format.block_width();
+        let mut modes = vec![Interpolation::None; self.width * self.height];
+        for block_y in 0..self.blocks_y {
+            for block_x in 0..self.blocks_x {
+                let block = self.block(block_x, block_y);
+                let mut bits = block.modulation;
+                let mut mode = Interpolation::None;
+                if self.format == DecodeFormat::Pvrtc2Bpp && block.modulation_flag() {
+                    // Only every other pixel is stored, as two bits. The lowest bit of the first
+                    // and the ninth value pick how the others are filled in instead.
+                    mode = match (bits & 1 != 0, bits & (1 << 20) != 0) {
+                        (false, _) => Interpolation::Both,
+                        (true, false) => Interpolation::Horizontal,
+                        (true, true) => Interpolation::Vertical,
+                    };
+                    if mode != Interpolation::Both {
+                        bits = (bits & !(1 << 20)) | ((bits >> 1) & (1 << 20));
+                    }
+                    bits = (bits & !1) | ((bits >> 1) & 1);
+                }
+                for y in 0..BLOCK_HEIGHT {
+                    for x in 0..block_width {
+                        let index =
+                            (block_y * BLOCK_HEIGHT + y) * self.width + block_x * block_width + x;
+                        modes[index] = mode;
+                        let value = match (self.format, mode) {
+                            (DecodeFormat::Pvrtc4Bpp, _) => {
+                                let value = bits & 3;
+                                bits >>= 2;
+                                if block.modulation_flag() {
+                                    [0, 4, PUNCH_THROUGH, 8][value as usize]
+                                } else {
+                                    MODULATION_WEIGHTS[value as usize]
+                                }
+                            }
+                            (DecodeFormat::Pvrtc2Bpp, Interpolation::None) => {
+                                let value = bits & 1;
+                                bits >>= 1;
+                                value * 8
+                            }
+                            (DecodeFormat::Pvrtc2Bpp, _) if (x ^ y) & 1 == 0 => {
+                                let value = bits & 3;
+                                bits >>= 2;
+                                MODULATION_WEIGHTS[value as usize]
+                            }
+                            // Filled in once every stored value is known.
+                            (DecodeFormat::Pvrtc2Bpp, _) => 0,
+                        };
+                        self.modulation[index] = value;
+                    }
+                }
+            }
+        }
+
+        if self.format == DecodeFormat::Pvrtc2Bpp {
+            let stored = self.modulation.clone();
+            let (width, height) = (self.width, self.height);
+            // The image wraps around at the edges.
+            let at = |x: usize, y: usize| stored[(y % height) * width + x % width];
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let index = y * self.width + x;
+                    if (x ^ y) & 1 == 0 {
+                        continue;
+                    }
+                    let left = at(x + self.width - 1, y);
+                    let right = at(x + 1, y);
+                    let up = at(x, y + self.height - 1);
+                    let down = at(x, y + 1);
+                    self.modulation[index] = match modes[index] {
+                        Interpolation::None => continue,
+                        Interpolation::Both => (left + right + up + down + 2) / 4,
+                        Interpolation::Horizontal => (left + right).div_ceil(2),
+                        Interpolation::Vertical => (up + down).div_ceil(2),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Blends the colors of the four blocks whose centers surround a pixel.
+    fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let block_width = self.format.block_width();
+        // Shift by half a block so that weights are relative to the top left block center.
+        let x_shifted = x + self.width - block_width / 2;
+        let y_shifted = y + self.height - BLOCK_HEIGHT / 2;
+        let left = (x_shifted / block_width) % self.blocks_x;
+        let top = (y_shifted / BLOCK_HEIGHT) % self.blocks_y;
+        let right = (left + 1) % self.blocks_x;
+        let bottom = (top + 1) % self.blocks_y;
+        let dx = (x_shifted % block_width) as u32;
+        let dy = (y_shifted % BLOCK_HEIGHT) as u32;
+        let weights = [
+            (block_width as u32 - dx) * (BLOCK_HEIGHT as u32 - dy),
+            dx * (BLOCK_HEIGHT as u32 - dy),
+            (block_width as u32 - dx) * dy,
+            dx * dy,
+        ];
+        let corners = [
+            self.block(left, top),
+            self.block(right, top),
+            self.block(left, bottom),
+            self.block(right, bottom),
+        ];
+        let blend = |color: fn(Block) -> [u32; 4]| {
+            let mut sum = [0u32; 4];
+            for (corner, weight) in corners.iter().zip(&weights) {
+                for (total, channel) in sum.iter_mut().zip(&color(*corner)) {
+                    *total += channel * weight;
+                }
+            }
+            // Expand to eight bits. The weights add up to 16 or 32 times the original scale.
+            let shift = if block_width == 8 { 1 } else { 0 };
+            let c = |v: u32| (v >> (6 + shift)) + (v >> (1 + shift));
+            let a = |v: u32| (v >> (4 + shift)) + (v >> shift);
+            [c(sum[0]), c(sum[1]), c(sum[2]), a(sum[3])]
+        };
+        let a = blend(Block::color_a);
+        let b = blend(Block::color_b);
+
+        let modulation = self.modulation[y * self.width + x];
+        let (modulation, transparent) = if modulation == PUNCH_THROUGH {
+            (4, true)
+        } else {
+            (modulation, false)
+        };
+        let mut pixel = [0u8; 4];
+        for (out, (a, b)) in pixel.iter_mut().zip(a.iter().zip(&b)) {
+            *out = ((a * (8 - modulation) + b * modulation) / 8) as u8;
+        }
+        if transparent {
+            pixel[3] = 0;
+        }
+        pixel
+    }
+}
+
+/// Decodes an image into RGBA8 pixels, with rows in the order they are stored.
+///
+/// Both dimensions must be powers of two.
+pub fn decode_image(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: DecodeFormat,
+) -> io::Result<Vec<u8>> {
+    if !width.is_power_of_two() || !height.is_power_of_two() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("PVRTC image is not a power of two: {}x{}", width, height),
+        ));
+    }
+    let data = data
+        .get(..format.image_size(width, height))
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+    let blocks = data
+        .chunks_exact(BLOCK_BYTES)
+        .map(|block| Block {
+            modulation: u32::from_le_bytes([block[0], block[1], block[2], block[3]]),
+            color: u32::from_le_bytes([block[4], block[5], block[6], block[7]]),
+        })
+        .collect();
+    let (blocks_x, blocks_y) = format.blocks(width, height);
+    let padded_width = blocks_x * format.block_width();
+    let padded_height = blocks_y * BLOCK_HEIGHT;
+    let mut image = Image {
+        format,
+        blocks,
+        blocks_x,
+        blocks_y,
+        width: padded_width,
+        height: padded_height,
+        modulation: vec![0; padded_width * padded_height],
+    };
+    image.unpack_modulation();
+
+    let mut buf = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            buf.extend_from_slice(&image.pixel(x, y));
+        }
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs blocks of `(modulation, color)` words, given in Morton order.
+    fn image(blocks: &[(u32, u32)]) -> Vec<u8> {
+        blocks
+            .iter()
+            .flat_map(|&(modulation, color)| {
+                let mut block = modulation.to_le_bytes().to_vec();
+                block.extend_from_slice(&color.to_le_bytes());
+                block
+            })
+            .collect()
+    }
+
+    /// Checks the red and green channels and the alpha of an image, whose blue matches its green.
+    fn check(rgba: &[u8], red: &[&[u8]], green: &[&[u8]], alpha: &[&[u8]]) {
+        let width = red[0].len();
+        assert_eq!(rgba.len(), width * red.len() * 4);
+        for (idx, pixel) in rgba.chunks(4).enumerate() {
+            let (x, y) = (idx % width, idx / width);
+            let expected = [red[y][x], green[y][x], green[y][x], alpha[y][x]];
+            assert_eq!(pixel, expected, "at {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn pvrtc_4bpp() {
+        // Opaque blocks with color A of red 0, 10, 31 and 20 (top left, bottom left, top right,
+        // bottom right) and a white color B. Only the last one is modulated, cycling through the
+        // four punch-through values across each row.
+        let data = image(&[
+            (0, 0xffff_8000),
+            (0, 0xffff_a800),
+            (0, 0xffff_fc00),
+            (0xe4e4_e4e4, 0xffff_d001),
+        ]);
+        let rgba = decode_image(&data, 8, 8, DecodeFormat::Pvrtc4Bpp).unwrap();
+        check(
+            &rgba,
+            &[
+                &[125, 83, 41, 83, 125, 168, 210, 168],
+                &[126, 73, 20, 73, 126, 179, 233, 179],
+                &[127, 63, 0, 63, 127, 191, 255, 191],
+                &[126, 73, 20, 73, 126, 179, 233, 179],
+                &[125, 83, 41, 83, 125, 211, 232, 255],
+                &[124, 92, 61, 92, 124, 205, 221, 255],
+                &[123, 103, 82, 103, 123, 199, 210, 255],
+                &[124, 92, 61, 92, 124, 205, 221, 255],
+            ],
+            &[
+                &[0; 8],
+                &[0; 8],
+                &[0; 8],
+                &[0; 8],
+                &[0, 0, 0, 0, 0, 127, 127, 255],
+                &[0, 0, 0, 0, 0, 127, 127, 255],
+                &[0, 0, 0, 0, 0, 127, 127, 255],
+                &[0, 0, 0, 0, 0, 127, 127, 255],
+            ],
+            &[
+                &[255; 8],
+                &[255; 8],
+                &[255; 8],
+                &[255; 8],
+                &[255, 255, 255, 255, 255, 255, 0, 255],
+                &[255, 255, 255, 255, 255, 255, 0, 255],
+                &[255, 255, 255, 255, 255, 255, 0, 255],
+                &[255, 255, 255, 255, 255, 255, 0, 255],
+            ],
+        );
+    }
+
+    #[test]
+    fn pvrtc_2bpp() {
+        // The reds of the 4bpp test for color A, and cyan for color B. The top left block has a
+        // bit per pixel; the others interpolate the missing values from both, horizontal and
+        // vertical neighbours (top right, bottom left and bottom right).
+        let data = image(&[
+            (0x0ff0_55aa, 0x83ff_8000),
+            (0x93a5_c6e7, 0x83ff_a801),
+            (0x6c4e_19b2, 0x83ff_fc01),
+            (0x3c71_8d2b, 0x83ff_d001),
+        ]);
+        let rgba = decode_image(&data, 16, 8, DecodeFormat::Pvrtc2Bpp).unwrap();
+        #[rustfmt::skip]
+        let red: [&[u8]; 8] = [
+            &[125, 0, 83, 0, 41, 0, 83, 0, 0, 91, 168, 70, 0, 47, 63, 127],
+            &[0, 100, 0, 46, 0, 46, 0, 100, 63, 95, 89, 77, 116, 128, 111, 152],
+            &[127, 95, 63, 31, 0, 0, 0, 0, 47, 79, 0, 83, 255, 139, 119, 119],
+            &[0, 0, 0, 0, 20, 46, 73, 100, 78, 152, 67, 0, 87, 77, 111, 95],
+            &[0, 26, 51, 30, 15, 7, 0, 0, 0, 109, 63, 0, 78, 117, 168, 73],
+            &[46, 40, 46, 48, 45, 77, 46, 0, 31, 87, 96, 0, 23, 171, 116, 52],
+            &[76, 70, 64, 23, 0, 11, 38, 56, 76, 100, 144, 0, 0, 77, 90, 83],
+            &[62, 0, 46, 77, 45, 48, 46, 40, 31, 140, 155, 0, 0, 0, 77, 140],
+        ];
+        #[rustfmt::skip]
+        let green: [&[u8]; 8] = [
+            &[0, 255, 0, 255, 0, 255, 0, 255, 255, 95, 0, 159, 255, 191, 159, 31],
+            &[255, 0, 255, 0, 255, 0, 255, 0, 127, 95, 127, 159, 127, 95, 95, 0],
+            &[0, 0, 0, 0, 255, 255, 255, 255, 159, 127, 255, 159, 0, 95, 95, 63],
+            &[255, 255, 255, 255, 0, 0, 0, 0, 95, 0, 159, 255, 159, 159, 95, 95],
+            &[255, 191, 95, 127, 159, 223, 255, 255, 255, 63, 159, 255, 159, 95, 0, 127],
+            &[159, 159, 127, 95, 63, 0, 127, 255, 191, 95, 95, 255, 223, 0, 63, 159],
+            &[95, 95, 95, 191, 255, 223, 159, 127, 95, 63, 0, 255, 255, 127, 95, 95],
+            &[127, 255, 127, 0, 63, 95, 127, 159, 191, 0, 0, 255, 255, 255, 127, 0],
+        ];
+        check(&rgba, &red, &green, &[&[255u8; 16][..]; 8]);
+    }
+
+    #[test]
+    fn small_images_are_padded() {
+        // A 4x4 image still takes two blocks across and down.
+        let data = image(&[(0, 0xffff_8000); 4]);
+        assert_eq!(DecodeFormat::Pvrtc4Bpp.image_size(4, 4), data.len());
+        let rgba = decode_image(&data, 4, 4, DecodeFormat::Pvrtc4Bpp).unwrap();
+        assert_eq!(rgba, [0, 0, 0, 0xff].repeat(16));
+        assert!(decode_image(&data[..data.len() - 1], 4, 4, DecodeFormat::Pvrtc4Bpp).is_err());
+    }
+
+    #[test]
+    fn not_a_power_of_two() {
+        let data = vec![0; 1024];
+        for &(width, height) in &[(12, 8), (8, 12), (0, 8)] {
+            let err = decode_image(&data, width, height, DecodeFormat::Pvrtc4Bpp).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
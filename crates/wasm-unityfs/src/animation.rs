@@ -0,0 +1,197 @@
+use js_sys::{Array, Error, Object, Reflect, TypeError};
+use wasm_bindgen::prelude::*;
+
+use unityfs::{Data, DataError};
+
+/// An animation clip. Only the curves stored as keyframes are read; clips that were compressed
+/// into `m_MuscleClip` on build have none of these.
+#[wasm_bindgen]
+pub struct AnimationClip {
+    name: String,
+    sample_rate: Option<f32>,
+    float_curves: Vec<Curve<f32>>,
+    position_curves: Vec<Curve<[f32; 3]>>,
+    scale_curves: Vec<Curve<[f32; 3]>>,
+    euler_curves: Vec<Curve<[f32; 3]>>,
+}
+
+/// Keyframes animating one property of the object at `path`.
+struct Curve<T> {
+    path: String,
+    /// Name of the property and the class ID of the component it belongs to, for float curves.
+    attribute: Option<(String, i32)>,
+    keyframes: Vec<Keyframe<T>>,
+}
+
+struct Keyframe<T> {
+    time: f32,
+    value: T,
+    in_slope: T,
+    out_slope: T,
+}
+
+fn data_error(e: DataError) -> JsValue {
+    Error::new(&e.to_string()).into()
+}
+
+/// Values of a keyframe, either `float` or `Vector3f`.
+trait CurveValue: Sized {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue>;
+    fn to_js(&self) -> JsValue;
+}
+
+impl CurveValue for f32 {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        data.as_f32()
+            .ok_or_else(|| Error::new("keyframe type mismatch").into())
+    }
+
+    fn to_js(&self) -> JsValue {
+        JsValue::from_f64((*self).into())
+    }
+}
+
+impl CurveValue for [f32; 3] {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        Ok([
+            data.get("x").map_err(data_error)?,
+            data.get("y").map_err(data_error)?,
+            data.get("z").map_err(data_error)?,
+        ])
+    }
+
+    fn to_js(&self) -> JsValue {
+        self.iter()
+            .map(|v| JsValue::from_f64((*v).into()))
+            .collect::<Array>()
+            .into()
+    }
+}
+
+impl<T: CurveValue> Curve<T> {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let path = data.get::<String>("path").map_err(data_error)?;
+        let attribute = if data.field("attribute").is_some() {
+            Some((
+                data.get("attribute").map_err(data_error)?,
+                data.get("classID").map_err(data_error)?,
+            ))
+        } else {
+            None
+        };
+        let keyframes = data
+            .field("curve")
+            .and_then(|curve| curve.field("m_Curve"))
+            .and_then(Data::as_array)
+            .ok_or_else(|| Error::new("m_Curve not found"))?
+            .iter()
+            .map(|key| {
+                let field = |name: &str| {
+                    key.field(name)
+                        .ok_or_else(|| JsValue::from(Error::new(&format!("{} not found", name))))
+                };
+                Ok(Keyframe {
+                    time: key.get("time").map_err(data_error)?,
+                    value: T::from_data(field("value")?)?,
+                    in_slope: T::from_data(field("inSlope")?)?,
+                    out_slope: T::from_data(field("outSlope")?)?,
+                })
+            })
+            .collect::<Result<_, JsValue>>()?;
+        Ok(Self {
+            path,
+            attribute,
+            keyframes,
+        })
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let keyframes = self
+            .keyframes
+            .iter()
+            .map(|key| {
+                let obj = Object::new();
+                Reflect::set(&obj, &"time".into(), &key.time.into())?;
+                Reflect::set(&obj, &"value".into(), &key.value.to_js())?;
+                Reflect::set(&obj, &"inSlope".into(), &key.in_slope.to_js())?;
+                Reflect::set(&obj, &"outSlope".into(), &key.out_slope.to_js())?;
+                Ok(obj)
+            })
+            .collect::<Result<Array, JsValue>>()?;
+        let obj = Object::new();
+        Reflect::set(&obj, &"path".into(), &self.path.as_str().into())?;
+        if let Some((attribute, class_id)) = &self.attribute {
+            Reflect::set(&obj, &"attribute".into(), &attribute.as_str().into())?;
+            Reflect::set(&obj, &"classId".into(), &(*class_id).into())?;
+        }
+        Reflect::set(&obj, &"keyframes".into(), &keyframes)?;
+        Ok(obj.into())
+    }
+}
+
+/// Reads the curves of an array field, which is missing in some versions.
+fn curves<T: CurveValue>(data: &Data<'_>, name: &str) -> Result<Vec<Curve<T>>, JsValue> {
+    match data.field(name).and_then(Data::as_array) {
+        Some(curves) => curves.iter().map(Curve::from_data).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn curves_to_js<T: CurveValue>(curves: &[Curve<T>]) -> Result<Array, JsValue> {
+    curves.iter().map(Curve::to_js).collect()
+}
+
+impl AnimationClip {
+    pub(crate) fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        match data {
+            Data::GenericStruct { type_name, .. } if type_name == "AnimationClip" => {}
+            _ => return Err(TypeError::new("AnimationClip type mismatch").into()),
+        }
+        Ok(Self {
+            name: data.get("m_Name").map_err(data_error)?,
+            sample_rate: data.get("m_SampleRate").ok(),
+            float_curves: curves(data, "m_FloatCurves")?,
+            position_curves: curves(data, "m_PositionCurves")?,
+            scale_curves: curves(data, "m_ScaleCurves")?,
+            euler_curves: curves(data, "m_EulerCurves")?,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl AnimationClip {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = sampleRate)]
+    pub fn sample_rate(&self) -> Option<f32> {
+        self.sample_rate
+    }
+
+    /// Curves of single float properties, as `{ path, attribute, classId, keyframes }` where
+    /// each keyframe is `{ time, value, inSlope, outSlope }`.
+    #[wasm_bindgen(js_name = floatCurves)]
+    pub fn float_curves(&self) -> Result<Array, JsValue> {
+        curves_to_js(&self.float_curves)
+    }
+
+    /// Curves of local positions, as `{ path, keyframes }` with `[x, y, z]` values.
+    #[wasm_bindgen(js_name = positionCurves)]
+    pub fn position_curves(&self) -> Result<Array, JsValue> {
+        curves_to_js(&self.position_curves)
+    }
+
+    /// Curves of local scales, like `positionCurves`.
+    #[wasm_bindgen(js_name = scaleCurves)]
+    pub fn scale_curves(&self) -> Result<Array, JsValue> {
+        curves_to_js(&self.scale_curves)
+    }
+
+    /// Curves of local rotations as Euler angles in degrees, like `positionCurves`.
+    #[wasm_bindgen(js_name = eulerCurves)]
+    pub fn euler_curves(&self) -> Result<Array, JsValue> {
+        curves_to_js(&self.euler_curves)
+    }
+}
@@ -1,54 +1,228 @@
-use js_sys::{Array, Error, Object, Reflect, TypeError, Uint8Array};
+use js_sys::{Array, Error, Function, Map, Object, Reflect, TypeError, Uint8Array};
+use std::io::Cursor;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 
-use image::codecs::dxt;
-use unityfs::Data;
+use unityfs::texture::{self, DecodeFormat};
+use unityfs::{Data, Fields, Platform, TextureFormat};
+
+mod animation;
+mod mesh;
+mod shader;
+mod sprite;
+mod texture_array;
+
+pub use animation::AnimationClip;
+pub use mesh::Mesh;
+pub use shader::Shader;
+pub use sprite::{Sprite, SpriteAtlas};
+pub use texture_array::TextureArray;
 
 #[wasm_bindgen]
 pub struct UnityFs {
-    input: Vec<u8>,
+    /// Shared with the object iterators made from it.
+    input: Rc<[u8]>,
 }
 
 #[wasm_bindgen]
 impl UnityFs {
     pub fn load(input: Vec<u8>) -> UnityFs {
         console_error_panic_hook::set_once();
-        Self { input }
+        Self {
+            input: input.into(),
+        }
     }
 
     #[wasm_bindgen(getter, js_name = mainAsset)]
     pub fn main_asset(&self) -> Result<Object, JsValue> {
         let (_, meta) = unityfs::UnityFsMeta::parse(&self.input)
-            .map_err(|e| Error::new(&format!("parse failed: {:?}", e)))?;
-        let fs = meta.read_unityfs();
+            .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+        let fs = meta
+            .read_unityfs()
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
 
         let asset = fs.main_asset();
         let name = asset.name();
+        let target_platform = asset.target_platform();
         let objects = asset
             .objects()
-            .map(UnityObject::from_object)
+            .map(|object| UnityObject::from_object(object, target_platform))
             .map(JsValue::from)
             .collect::<Array>();
+        // Values Unity doesn't name are given as they are stored.
+        let platform_name = match target_platform.name() {
+            Some(name) => name.to_owned(),
+            None => target_platform.to_u32().to_string(),
+        };
         let obj = Object::new();
         Reflect::set(&obj, &"name".into(), &name.into())?;
+        Reflect::set(&obj, &"targetPlatform".into(), &platform_name.into())?;
         Reflect::set(&obj, &"objects".into(), &objects.into())?;
         Ok(obj)
     }
+
+    /// Every `Texture2D` in the bundle, in one call.
+    ///
+    /// Image data streamed from the bundle's own resources is decoded as well; textures streamed
+    /// from other bundles still need `tryResolve`.
+    ///
+    /// With `maxSize`, mipmapped textures are decoded from their first mip no larger than that
+    /// on either side, which is much faster for thumbnails.
+    pub fn textures(&self, max_size: Option<u32>) -> Result<Array, JsValue> {
+        let (_, meta) = unityfs::UnityFsMeta::parse(&self.input)
+            .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+        let fs = meta
+            .read_unityfs()
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+
+        fs.all_objects()
+            .filter(|(_, object)| {
+                matches!(
+                    &object.data,
+                    Data::GenericStruct { type_name, .. } if type_name == "Texture2D"
+                )
+            })
+            .map(|(asset, object)| {
+                let target_platform = asset.asset().target_platform();
+                let mut texture =
+                    Texture2D::from_data(&object.data, Some(target_platform), max_size)?;
+                let rgba = match &texture.image_data {
+                    ImageData::Streaming(format, info) => fs
+                        .stream_data(&info.path, info.offset, info.size)
+                        .map(|buf| {
+                            Texture2D::read(texture.width, texture.height, *format, buf, None)
+                        })
+                        .transpose()?,
+                    _ => None,
+                };
+                if let Some(rgba) = rgba {
+                    texture.image_data = ImageData::loaded(rgba);
+                }
+                Ok(JsValue::from(texture))
+            })
+            .collect()
+    }
+
+    /// Number of objects in the main asset, read from its object table without parsing any
+    /// object.
+    #[wasm_bindgen(getter, js_name = objectCount)]
+    pub fn object_count(&self) -> Result<usize, JsValue> {
+        let fs = unityfs::UnityFsMeta::parse_reader(Cursor::new(&*self.input))
+            .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+        let asset = fs
+            .main_asset()
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+        Ok(asset.object_count())
+    }
+
+    /// The object of the main asset with the given path ID, which can be a `BigInt`, a number
+    /// or a decimal string.
+    ///
+    /// Unlike `mainAsset`, only the blocks holding the object are decompressed and no other
+    /// object is parsed.
+    #[wasm_bindgen(js_name = objectByPathId)]
+    pub fn object_by_path_id(&self, path_id: &JsValue) -> Result<Option<UnityObject>, JsValue> {
+        let path_id = js_string(path_id)
+            .parse::<i64>()
+            .map_err(|_| TypeError::new("path ID is not an integer"))?;
+        let fs = unityfs::UnityFsMeta::parse_reader(Cursor::new(&*self.input))
+            .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+        let asset = fs
+            .main_asset()
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+        let object = asset
+            .get_object(&path_id)
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+        Ok(object
+            .as_ref()
+            .map(|object| UnityObject::from_object(object, asset.target_platform())))
+    }
+
+    /// A cursor over the objects of the main asset, which parses one object per `next()` call
+    /// so that reading a big bundle can be spread over several tasks.
+    ///
+    /// As with `objectByPathId`, only the blocks holding each object are decompressed.
+    #[wasm_bindgen(js_name = objectIterator)]
+    pub fn object_iterator(&self) -> Result<ObjectIterator, JsValue> {
+        let fs = unityfs::UnityFsMeta::parse_reader(Cursor::new(Rc::clone(&self.input)))
+            .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+        let asset = fs
+            .into_main_asset()
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+        let path_ids = asset.path_ids().collect();
+        Ok(ObjectIterator {
+            asset,
+            path_ids,
+            next: 0,
+        })
+    }
+}
+
+/// Objects of the main asset of a bundle, read on demand by `UnityFs.objectIterator`.
+#[wasm_bindgen]
+pub struct ObjectIterator {
+    asset: unityfs::AssetReader<'static, Cursor<Rc<[u8]>>>,
+    path_ids: Vec<i64>,
+    /// Position in `path_ids` of the next object to read.
+    next: usize,
+}
+
+#[wasm_bindgen]
+impl ObjectIterator {
+    /// Reads the next object in path ID order, or returns `undefined` once all have been read.
+    #[wasm_bindgen(js_name = next)]
+    pub fn next_object(&mut self) -> Result<Option<UnityObject>, JsValue> {
+        let path_id = match self.path_ids.get(self.next) {
+            Some(&path_id) => path_id,
+            None => return Ok(None),
+        };
+        self.next += 1;
+        let object = self
+            .asset
+            .get_object(&path_id)
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+        Ok(object
+            .as_ref()
+            .map(|object| UnityObject::from_object(object, self.asset.target_platform())))
+    }
+
+    /// Number of objects left to read.
+    #[wasm_bindgen(getter)]
+    pub fn remaining(&self) -> usize {
+        self.path_ids.len() - self.next
+    }
 }
 
 #[wasm_bindgen]
 pub struct UnityObject {
     data: Data<'static>,
+    /// Hash of the serialized bytes, for objects read directly from an asset.
+    content_hash: Option<u64>,
+    /// Platform of the asset, for objects read directly from one.
+    target_platform: Option<Platform>,
 }
 
 impl UnityObject {
-    fn from_object(val: &unityfs::Object<'_>) -> Self {
-        Self::from_data(&val.data)
+    fn from_object(val: &unityfs::Object<'_>, target_platform: Platform) -> Self {
+        Self {
+            content_hash: Some(val.content_hash()),
+            target_platform: Some(target_platform),
+            ..Self::from_data(&val.data)
+        }
     }
 
     fn from_data(val: &Data<'_>) -> Self {
         Self {
             data: val.clone_owned(),
+            content_hash: None,
+            target_platform: None,
+        }
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        match &self.data {
+            Data::UInt8Array(data) | Data::GenericPrimitive { data, .. } => Some(data),
+            _ => None,
         }
     }
 }
@@ -79,8 +253,49 @@ impl UnityObject {
         }
     }
 
+    /// The `m_Name` field of the object, which most asset types start with, or `undefined` if
+    /// it has none.
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> Option<String> {
+        let name = match &self.data {
+            Data::GenericStruct { fields, .. } => fields.get("m_Name")?.as_bytes()?,
+            _ => return None,
+        };
+        Some(String::from_utf8_lossy(name).into_owned())
+    }
+
+    /// Converts the value to JS. Byte arrays and primitives of unknown types become a copy of
+    /// their bytes; `bytesPtr` views them in place instead.
     pub fn data(&self) -> Result<JsValue, JsValue> {
-        convert_data(&self.data)
+        convert_data(&self.data, self.target_platform)
+    }
+
+    /// Pointer to the bytes of a byte array or of a primitive of an unknown type, such as the
+    /// `image data` of a texture, which skips the copy `data()` makes.
+    ///
+    /// A view of them is only valid until this object is freed or the wasm memory grows.
+    #[wasm_bindgen(getter, js_name = bytesPtr)]
+    pub fn bytes_ptr(&self) -> *const u8 {
+        self.bytes().map_or(std::ptr::null(), <[u8]>::as_ptr)
+    }
+
+    #[wasm_bindgen(getter, js_name = bytesLen)]
+    pub fn bytes_len(&self) -> Option<usize> {
+        self.bytes().map(<[u8]>::len)
+    }
+
+    /// XXH64 of the serialized bytes as a `BigInt`, which is unchanged between bundle builds
+    /// unless the object is. `undefined` for values nested in other objects.
+    #[wasm_bindgen(getter, js_name = contentHash)]
+    pub fn content_hash(&self) -> Option<JsValue> {
+        self.content_hash.map(big_int)
+    }
+
+    /// Serializes the whole object to JSON. Byte buffers become base64 strings and 64-bit
+    /// integers become decimal strings.
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        self.data.to_json()
     }
 }
 
@@ -92,117 +307,238 @@ pub struct Texture2D {
     #[wasm_bindgen(readonly)]
     pub height: u32,
     image_data: ImageData,
+    webp_quality: f32,
+    png_options: texture::PngOptions,
+    normal_map: bool,
+    alpha_as_luminance: bool,
+    /// The `m_TextureFormat`, kept for textures that can't be decoded too.
+    texture_format: i32,
+    color_space: Option<i32>,
+    /// The mip the pixels were read from, 0 for the full image.
+    mip_level: u32,
 }
 
 struct StreamingInfo {
     path: String,
-    offset: u32,
-    size: u32,
+    offset: u64,
+    size: u64,
 }
 
 impl StreamingInfo {
     fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
-        let fields = match data {
-            Data::GenericStruct { type_name, fields } if type_name == "StreamingInfo" => fields,
-            _ => return Err(TypeError::new("StreamingInfo type mismatch").into()),
-        };
-        let path = match fields.get("path") {
-            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
-            _ => return Err(TypeError::new("StreamingInfo type mismatch").into()),
-        };
-        let offset = match fields.get("offset") {
-            Some(Data::UInt32(v)) => *v,
-            _ => return Err(TypeError::new("StreamingInfo type mismatch").into()),
+        let info = match data {
+            Data::GenericStruct { type_name, .. } if type_name == "StreamingInfo" => {
+                Self::from_fields(data, "path", "offset", "size")
+            }
+            _ => None,
         };
-        let size = match fields.get("size") {
-            Some(Data::UInt32(v)) => *v,
-            _ => return Err(TypeError::new("StreamingInfo type mismatch").into()),
+        info.ok_or_else(|| TypeError::new("StreamingInfo type mismatch").into())
+    }
+
+    /// Reads a `StreamedResource`, which describes the same thing with different field names.
+    fn from_resource(data: &Data<'_>) -> Result<Self, JsValue> {
+        let info = match data {
+            Data::GenericStruct { type_name, .. } if type_name == "StreamedResource" => {
+                Self::from_fields(data, "m_Source", "m_Offset", "m_Size")
+            }
+            _ => None,
         };
-        Ok(Self { path, offset, size })
+        info.ok_or_else(|| TypeError::new("StreamedResource type mismatch").into())
     }
+
+    fn from_fields(data: &Data<'_>, path: &str, offset: &str, size: &str) -> Option<Self> {
+        Some(Self {
+            path: String::from_utf8_lossy(data.field(path)?.as_bytes()?).into_owned(),
+            offset: data.field(offset)?.as_u64()?,
+            size: data.field(size)?.as_u64()?,
+        })
+    }
+}
+
+/// Looks up the data a `StreamingInfo` points to.
+///
+/// Returns `None` if the data lives in another bundle, or if the path or range doesn't match
+/// anything in this one.
+fn resolve_stream(
+    fs: &UnityFs,
+    streaming_info: &StreamingInfo,
+) -> Result<Option<Vec<u8>>, JsValue> {
+    let (_, meta) = unityfs::UnityFsMeta::parse(&fs.input)
+        .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+    let fs = meta
+        .read_unityfs()
+        .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+
+    Ok(fs
+        .stream_data(
+            &streaming_info.path,
+            streaming_info.offset,
+            streaming_info.size,
+        )
+        .map(<[u8]>::to_vec))
 }
 
 enum ImageData {
-    Loaded(Vec<u8>),
+    /// Decoded RGBA8 pixels, top row first, and their PNG and WebP encodings once requested.
+    Loaded {
+        rgba: Vec<u8>,
+        /// The pixels as shown, for normal maps and masks shown as luminance.
+        shown: std::cell::OnceCell<Vec<u8>>,
+        png: std::cell::OnceCell<Option<Vec<u8>>>,
+        webp: std::cell::OnceCell<Option<Vec<u8>>>,
+    },
     Streaming(DecodeFormat, StreamingInfo),
     Unknown,
 }
 
-#[derive(Copy, Clone)]
-enum DecodeFormat {
-    Etc(etcdec::DecodeFormat),
-    Dxt(dxt::DXTVariant),
+impl ImageData {
+    fn loaded(rgba: Vec<u8>) -> Self {
+        ImageData::Loaded {
+            rgba,
+            shown: std::cell::OnceCell::new(),
+            png: std::cell::OnceCell::new(),
+            webp: std::cell::OnceCell::new(),
+        }
+    }
 }
 
 impl Texture2D {
-    fn read_etc(
-        width: u32,
-        height: u32,
-        format: etcdec::DecodeFormat,
-        mut image_data: impl std::io::Read,
-    ) -> Result<Vec<u8>, JsValue> {
-        let block_width = (width + 3) / 4;
-        let block_height = (height + 3) / 4;
-        let scanline = (width * 4) as usize;
-        let mut buf = vec![0u8; scanline * height as usize];
-        for block_y in 0..block_height {
-            let y = block_y * 4;
-            for block_x in 0..block_width {
-                let x = block_x * 4;
-                let block = etcdec::decode_single_block(&mut image_data, format)
-                    .map_err(|_| Error::new("read error"))?;
-                for (block_raw, target) in block.iter().zip(
-                    buf[(4 * x as usize)..]
-                        .chunks_mut(scanline)
-                        .rev()
-                        .skip(y as usize)
-                        .take(4),
-                ) {
-                    target[..16].copy_from_slice(block_raw);
+    const DEFAULT_WEBP_QUALITY: f32 = 75.0;
+    const USAGE_NORMAL_MAP_DXT5NM: i32 = 3;
+    const USAGE_NORMAL_MAP_ASTCNM: i32 = 12;
+
+    /// Reads a `Texture2D` from an asset built for `target_platform`, which tells whether its
+    /// pixels are swizzled. With `max_size`, the first mip no larger than that is read instead
+    /// of the full image.
+    fn from_data(
+        data: &Data<'_>,
+        target_platform: Option<Platform>,
+        max_size: Option<u32>,
+    ) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "Texture2D" => fields,
+            _ => return Err(TypeError::new("Texture2D type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let width = match fields.get("m_Width") {
+            Some(Data::SInt32(width)) => (*width) as u32,
+            Some(_) => return Err(Error::new("m_Width type mismatch").into()),
+            None => return Err(Error::new("m_Width not found").into()),
+        };
+        let height = match fields.get("m_Height") {
+            Some(Data::SInt32(height)) => (*height) as u32,
+            Some(_) => return Err(Error::new("m_Height type mismatch").into()),
+            None => return Err(Error::new("m_Height not found").into()),
+        };
+        let image_data = match fields.get("image data") {
+            Some(Data::UInt8Array(buf)) => buf,
+            Some(_) => return Err(Error::new("image data type mismatch").into()),
+            None => return Err(Error::new("image data not found").into()),
+        };
+        let mut image_data = Cursor::new(image_data);
+        let texture_format = match fields.get("m_TextureFormat") {
+            Some(Data::SInt32(format)) => *format,
+            Some(_) => return Err(Error::new("m_TextureFormat type mismatch").into()),
+            None => return Err(Error::new("m_TextureFormat not found").into()),
+        };
+        let format = DecodeFormat::from_texture_format(
+            texture_format,
+            fields.contains_key("m_ForcedFallbackFormat"),
+        );
+        // Switch builds store pixels in the GPU's tiled layout, described by m_PlatformBlob.
+        let format = match (
+            format,
+            fields.get("m_PlatformBlob").and_then(Data::as_bytes),
+        ) {
+            (Some(format), Some(blob)) if target_platform == Some(Platform::Switch) => {
+                Some(format.switch_swizzled(blob))
+            }
+            (format, _) => format,
+        };
+        let mip_count = match fields.get("m_MipCount") {
+            Some(Data::SInt32(count)) => (*count).max(1) as u32,
+            // Textures from before 5.2 only tell whether they have a full mip chain.
+            _ if fields.get("m_MipMap").and_then(Data::as_bool) == Some(true) => {
+                u32::BITS - width.max(height).leading_zeros()
+            }
+            _ => 1,
+        };
+        let mip = max_size.zip(format).and_then(|(max_size, format)| {
+            let level = texture::mip_for_max_size(width, height, mip_count, max_size);
+            Some((level, format.mip_offset(width, height, level)?))
+        });
+        let (mip_level, mip_offset) = mip.unwrap_or((0, 0));
+        let (width, height) = texture::mip_size(width, height, mip_level);
+        image_data.set_position(mip_offset);
+        let mut texture = if let Some(format) = format {
+            // Textures from before 5.3 have no m_StreamData and always keep their pixels inline.
+            let streaming_info = fields
+                .get("m_StreamData")
+                .map(StreamingInfo::from_data)
+                .transpose()?
+                .filter(|info| !info.path.is_empty())
+                .map(|info| StreamingInfo {
+                    offset: info.offset + mip_offset,
+                    size: info.size.saturating_sub(mip_offset),
+                    ..info
+                });
+            match streaming_info {
+                Some(streaming_info) => {
+                    Texture2D::defer(name, width, height, format, streaming_info)
                 }
+                None => Texture2D::load(name, width, height, format, image_data)?,
             }
-        }
-        Ok(buf)
-    }
-
-    fn read_dxt(
-        width: u32,
-        height: u32,
-        variant: dxt::DXTVariant,
-        image_data: impl std::io::Read,
-    ) -> Result<Vec<u8>, JsValue> {
-        let dec = dxt::DxtDecoder::new(image_data, width, height, variant)
-            .map_err(|e| Error::new(&format!("failed to build decoder: {}", e)))?;
-        let image = image::DynamicImage::from_decoder(dec)
-            .map_err(|e| Error::new(&format!("failed to decode: {}", e)))?;
-        let image = image.flipv().into_rgba8();
-        Ok(image.into_vec())
+        } else {
+            Texture2D::unknown(name, width, height)
+        };
+        // m_LightmapFormat holds the texture usage. Normal maps compressed as DXT5nm or ASTC
+        // store their normals in alpha and green.
+        texture.normal_map = matches!(
+            fields.get("m_LightmapFormat"),
+            Some(Data::SInt32(Texture2D::USAGE_NORMAL_MAP_DXT5NM))
+                | Some(Data::SInt32(Texture2D::USAGE_NORMAL_MAP_ASTCNM))
+        );
+        texture.texture_format = texture_format;
+        texture.mip_level = mip_level;
+        texture.color_space = match fields.get("m_ColorSpace") {
+            Some(Data::SInt32(color_space)) => Some(*color_space),
+            _ => None,
+        };
+        Ok(texture)
     }
 
+    /// Decodes the image, calling `progress` with the decoded rows and the height if given.
+    ///
+    /// Decoding stops if `progress` returns `false` or throws.
     fn read(
         width: u32,
         height: u32,
         format: DecodeFormat,
         image_data: impl std::io::Read,
+        progress: Option<&Function>,
     ) -> Result<Vec<u8>, JsValue> {
-        let raw = match format {
-            DecodeFormat::Etc(format) => Self::read_etc(width, height, format, image_data),
-            DecodeFormat::Dxt(variant) => Self::read_dxt(width, height, variant, image_data),
-        }?;
-
-        let mut buf = Vec::new();
-        let w = std::io::BufWriter::new(&mut buf);
-        let mut encoder = png::Encoder::new(w, width, height);
-        encoder.set_compression(png::Compression::Fast);
-        encoder.set_color(png::ColorType::RGBA);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut w = encoder
-            .write_header()
-            .map_err(|e| Error::new(&format!("error initializing encoder: {}", e)))?;
-        w.write_image_data(&raw)
-            .map_err(|e| Error::new(&format!("error while encoding: {}", e)))?;
-        drop(w);
-        Ok(buf)
+        let report = |rows: u32, height: u32| match progress {
+            Some(progress) => progress
+                .call2(&JsValue::NULL, &rows.into(), &height.into())
+                .is_ok_and(|ret| ret.as_bool() != Some(false)),
+            None => true,
+        };
+        format
+            .decode_with_progress(width, height, image_data, report)
+            .map_err(|e| Error::new(&format!("failed to decode: {}", e)).into())
+    }
+
+    fn encode_png(width: u32, height: u32, raw: &[u8]) -> Result<Vec<u8>, JsValue> {
+        texture::encode_png(width, height, raw).map_err(|e| Error::new(&e.to_string()).into())
+    }
+
+    fn encode_webp(width: u32, height: u32, raw: &[u8], quality: f32) -> Result<Vec<u8>, JsValue> {
+        texture::encode_webp(width, height, raw, quality)
+            .map_err(|e| Error::new(&e.to_string()).into())
     }
 
     fn load(
@@ -212,12 +548,19 @@ impl Texture2D {
         format: DecodeFormat,
         image_data: impl std::io::Read,
     ) -> Result<Self, JsValue> {
-        let image_data = Texture2D::read(width, height, format, image_data)?;
+        let image_data = Texture2D::read(width, height, format, image_data, None)?;
         Ok(Self {
             name,
             width,
             height,
-            image_data: ImageData::Loaded(image_data),
+            image_data: ImageData::loaded(image_data),
+            webp_quality: Texture2D::DEFAULT_WEBP_QUALITY,
+            png_options: texture::PngOptions::default(),
+            normal_map: false,
+            alpha_as_luminance: false,
+            texture_format: 0,
+            color_space: None,
+            mip_level: 0,
         })
     }
 
@@ -233,6 +576,13 @@ impl Texture2D {
             width,
             height,
             image_data: ImageData::Streaming(format, streaming_info),
+            webp_quality: Texture2D::DEFAULT_WEBP_QUALITY,
+            png_options: texture::PngOptions::default(),
+            normal_map: false,
+            alpha_as_luminance: false,
+            texture_format: 0,
+            color_space: None,
+            mip_level: 0,
         }
     }
 
@@ -242,33 +592,283 @@ impl Texture2D {
             width,
             height,
             image_data: ImageData::Unknown,
+            webp_quality: Texture2D::DEFAULT_WEBP_QUALITY,
+            png_options: texture::PngOptions::default(),
+            normal_map: false,
+            alpha_as_luminance: false,
+            texture_format: 0,
+            color_space: None,
+            mip_level: 0,
         }
     }
 }
 
 #[wasm_bindgen]
 impl Texture2D {
+    /// Reads a `Texture2D` object like `data()` does, decoding the first mip no larger than
+    /// `maxSize` on either side if given. Textures without mips are decoded in full.
+    #[wasm_bindgen(js_name = fromObject)]
+    pub fn from_object(object: &UnityObject, max_size: Option<u32>) -> Result<Texture2D, JsValue> {
+        Texture2D::from_data(&object.data, object.target_platform, max_size)
+    }
+
     #[wasm_bindgen(getter)]
     pub fn name(&self) -> String {
         self.name.clone()
     }
 
-    #[wasm_bindgen(getter, js_name = imagePngPtr)]
-    pub fn image_png_ptr(&self) -> *const u8 {
+    /// Returns the decoded pixels, with normals unpacked if this is a normal map and alpha
+    /// turned into luminance if asked to.
+    fn pixels(&self) -> Option<&[u8]> {
         match &self.image_data {
-            ImageData::Loaded(data) => data.as_ptr(),
-            _ => std::ptr::null(),
+            ImageData::Loaded { rgba, .. } if !self.normal_map && !self.alpha_as_luminance => {
+                Some(rgba)
+            }
+            ImageData::Loaded { rgba, shown, .. } => Some(shown.get_or_init(|| {
+                if self.normal_map {
+                    texture::unpack_normal_map(rgba)
+                } else {
+                    texture::alpha_to_luminance(rgba)
+                }
+            })),
+            _ => None,
+        }
+    }
+
+    /// Drops the pixels as shown and their encodings, once how they are shown changes.
+    fn reset_shown(&mut self) {
+        if let ImageData::Loaded {
+            shown, png, webp, ..
+        } = &mut self.image_data
+        {
+            shown.take();
+            png.take();
+            webp.take();
+        }
+    }
+
+    /// Drops the PNG encoding, once its settings change.
+    fn reset_png(&mut self) {
+        if let ImageData::Loaded { png, .. } = &mut self.image_data {
+            png.take();
+        }
+    }
+
+    /// Returns the PNG encoding of the image, encoding it on first use.
+    fn png(&self) -> Option<&[u8]> {
+        match &self.image_data {
+            ImageData::Loaded { png, .. } => png
+                .get_or_init(|| {
+                    let pixels = self.pixels()?;
+                    texture::encode_png_with(self.width, self.height, pixels, self.png_options).ok()
+                })
+                .as_deref(),
+            _ => None,
         }
     }
 
+    #[wasm_bindgen(getter, js_name = imagePngPtr)]
+    pub fn image_png_ptr(&self) -> *const u8 {
+        self.png().map_or(std::ptr::null(), <[u8]>::as_ptr)
+    }
+
     #[wasm_bindgen(getter, js_name = imagePngLen)]
     pub fn image_png_len(&self) -> Option<usize> {
+        self.png().map(<[u8]>::len)
+    }
+
+    /// Returns the WebP encoding of the image, encoding it on first use.
+    fn webp(&self) -> Option<&[u8]> {
         match &self.image_data {
-            ImageData::Loaded(data) => Some(data.len()),
+            ImageData::Loaded { webp, .. } => webp
+                .get_or_init(|| {
+                    let pixels = self.pixels()?;
+                    Texture2D::encode_webp(self.width, self.height, pixels, self.webp_quality).ok()
+                })
+                .as_deref(),
             _ => None,
         }
     }
 
+    /// Compression level of the PNG encoding: `"fast"`, the default, `"default"` or `"best"`.
+    #[wasm_bindgen(getter, js_name = pngCompression)]
+    pub fn png_compression(&self) -> String {
+        match self.png_options.compression {
+            texture::PngCompression::Fast => "fast",
+            texture::PngCompression::Default => "default",
+            texture::PngCompression::Best => "best",
+        }
+        .into()
+    }
+
+    #[wasm_bindgen(setter, js_name = pngCompression)]
+    pub fn set_png_compression(&mut self, compression: &str) -> Result<(), JsValue> {
+        self.png_options.compression = match compression {
+            "fast" => texture::PngCompression::Fast,
+            "default" => texture::PngCompression::Default,
+            "best" => texture::PngCompression::Best,
+            _ => return Err(TypeError::new("unknown PNG compression level").into()),
+        };
+        self.reset_png();
+        Ok(())
+    }
+
+    /// Row filter of the PNG encoding: `"sub"`, the default, or `"none"`.
+    #[wasm_bindgen(getter, js_name = pngFilter)]
+    pub fn png_filter(&self) -> String {
+        match self.png_options.filter {
+            texture::PngFilter::None => "none",
+            texture::PngFilter::Sub => "sub",
+        }
+        .into()
+    }
+
+    #[wasm_bindgen(setter, js_name = pngFilter)]
+    pub fn set_png_filter(&mut self, filter: &str) -> Result<(), JsValue> {
+        self.png_options.filter = match filter {
+            "none" => texture::PngFilter::None,
+            "sub" => texture::PngFilter::Sub,
+            _ => return Err(TypeError::new("unknown PNG filter").into()),
+        };
+        self.reset_png();
+        Ok(())
+    }
+
+    /// Quality of the WebP encoding from 0 to 100, defaulting to 75.
+    #[wasm_bindgen(getter, js_name = webpQuality)]
+    pub fn webp_quality(&self) -> f32 {
+        self.webp_quality
+    }
+
+    #[wasm_bindgen(setter, js_name = webpQuality)]
+    pub fn set_webp_quality(&mut self, quality: f32) {
+        self.webp_quality = quality.clamp(0.0, 100.0);
+        if let ImageData::Loaded { webp, .. } = &mut self.image_data {
+            webp.take();
+        }
+    }
+
+    #[wasm_bindgen(getter, js_name = imageWebpPtr)]
+    pub fn image_webp_ptr(&self) -> *const u8 {
+        self.webp().map_or(std::ptr::null(), <[u8]>::as_ptr)
+    }
+
+    #[wasm_bindgen(getter, js_name = imageWebpLen)]
+    pub fn image_webp_len(&self) -> Option<usize> {
+        self.webp().map(<[u8]>::len)
+    }
+
+    /// The `m_TextureFormat` of the texture.
+    #[wasm_bindgen(getter, js_name = textureFormat)]
+    pub fn texture_format(&self) -> i32 {
+        self.texture_format
+    }
+
+    /// Unity's name for the texture format, such as `ETC2_RGBA8`, so that textures that can't be
+    /// decoded can tell why.
+    #[wasm_bindgen(getter, js_name = formatName)]
+    pub fn format_name(&self) -> Option<String> {
+        TextureFormat::from_i32(self.texture_format).map(|format| format.name().into())
+    }
+
+    /// The `m_ColorSpace` of the texture: 1 if the pixels are sRGB encoded and 0 if they are
+    /// linear, like normal maps and masks usually are. `undefined` for textures from before
+    /// Unity 5.
+    ///
+    /// Decoded pixels are left as they are stored either way, so that they can be uploaded with
+    /// the matching WebGL format.
+    #[wasm_bindgen(getter, js_name = colorSpace)]
+    pub fn color_space(&self) -> Option<i32> {
+        self.color_space
+    }
+
+    /// The mip the image was read from, 0 for the full image. `width` and `height` are those
+    /// of the mip.
+    #[wasm_bindgen(getter, js_name = mipLevel)]
+    pub fn mip_level(&self) -> u32 {
+        self.mip_level
+    }
+
+    /// Whether the image is a normal map packed into alpha and green. Unpacked normals are
+    /// shown in place of the decoded pixels.
+    ///
+    /// This is set for textures whose usage says so, and can be changed for the others.
+    #[wasm_bindgen(getter, js_name = normalMap)]
+    pub fn normal_map(&self) -> bool {
+        self.normal_map
+    }
+
+    #[wasm_bindgen(setter, js_name = normalMap)]
+    pub fn set_normal_map(&mut self, normal_map: bool) {
+        if self.normal_map == normal_map {
+            return;
+        }
+        self.normal_map = normal_map;
+        self.reset_shown();
+    }
+
+    /// Whether alpha is shown as gray with full alpha instead, as is handy for `Alpha8` masks
+    /// and font atlases. Off by default, so that alpha stays in alpha as Unity samples it.
+    ///
+    /// Normal maps are shown unpacked either way.
+    #[wasm_bindgen(getter, js_name = alphaAsLuminance)]
+    pub fn alpha_as_luminance(&self) -> bool {
+        self.alpha_as_luminance
+    }
+
+    #[wasm_bindgen(setter, js_name = alphaAsLuminance)]
+    pub fn set_alpha_as_luminance(&mut self, alpha_as_luminance: bool) {
+        if self.alpha_as_luminance == alpha_as_luminance {
+            return;
+        }
+        self.alpha_as_luminance = alpha_as_luminance;
+        self.reset_shown();
+    }
+
+    /// Pointer to the decoded RGBA8 pixels, which skips PNG encoding.
+    #[wasm_bindgen(getter, js_name = rawRgbaPtr)]
+    pub fn raw_rgba_ptr(&self) -> *const u8 {
+        self.pixels().map_or(std::ptr::null(), <[u8]>::as_ptr)
+    }
+
+    #[wasm_bindgen(getter, js_name = rawRgbaLen)]
+    pub fn raw_rgba_len(&self) -> Option<usize> {
+        self.pixels().map(<[u8]>::len)
+    }
+
+    /// Returns a copy of this texture with the luminance of `alpha` as its alpha channel, for
+    /// textures whose alpha is stored separately, often as `<name>_alpha`.
+    ///
+    /// Both textures must be decoded.
+    #[wasm_bindgen(js_name = withAlpha)]
+    pub fn with_alpha(&self, alpha: &Texture2D) -> Result<Texture2D, JsValue> {
+        let (rgba, alpha_rgba) = match (self.pixels(), alpha.pixels()) {
+            (Some(rgba), Some(alpha_rgba)) => (rgba, alpha_rgba),
+            _ => return Err(Error::new("texture is not decoded").into()),
+        };
+        let merged = texture::merge_alpha(
+            self.width,
+            self.height,
+            rgba,
+            alpha.width,
+            alpha.height,
+            alpha_rgba,
+        );
+        Ok(Self {
+            name: self.name.clone(),
+            width: self.width,
+            height: self.height,
+            image_data: ImageData::loaded(merged),
+            webp_quality: self.webp_quality,
+            png_options: self.png_options,
+            normal_map: false,
+            alpha_as_luminance: false,
+            texture_format: self.texture_format,
+            color_space: self.color_space,
+            mip_level: self.mip_level,
+        })
+    }
+
     #[wasm_bindgen(js_name = assetDependency)]
     pub fn asset_dependency(&self) -> Option<String> {
         match &self.image_data {
@@ -277,118 +877,671 @@ impl Texture2D {
         }
     }
 
+    /// Decodes streamed image data found in `fs`.
+    ///
+    /// `progress` is called with the number of decoded rows and the height, and can return
+    /// `false` to cancel decoding.
     #[wasm_bindgen(js_name = tryResolve)]
-    pub fn try_resolve(&mut self, fs: &UnityFs) -> Result<(), JsValue> {
-        let (_, meta) = unityfs::UnityFsMeta::parse(&fs.input)
-            .map_err(|e| Error::new(&format!("parse failed: {:?}", e)))?;
-        let fs = meta.read_unityfs();
-
+    pub fn try_resolve(&mut self, fs: &UnityFs, progress: Option<Function>) -> Result<(), JsValue> {
         let (format, streaming_info) = match &self.image_data {
             ImageData::Streaming(format, val) => (format, val),
             _ => return Ok(()),
         };
-        if !streaming_info.path.starts_with("archive:/") {
-            return Ok(());
+        let buf = match resolve_stream(fs, streaming_info)? {
+            Some(buf) => buf,
+            None => return Ok(()),
+        };
+        let image_data = Texture2D::read(
+            self.width,
+            self.height,
+            *format,
+            Cursor::new(buf),
+            progress.as_ref(),
+        )?;
+        self.image_data = ImageData::loaded(image_data);
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+pub struct AudioClip {
+    name: String,
+    audio_data: ClipData,
+}
+
+/// Data of an audio or video clip, which is usually kept in a resource rather than inline.
+enum ClipData {
+    Loaded(Vec<u8>),
+    Streaming(StreamingInfo),
+    Unknown,
+}
+
+impl AudioClip {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "AudioClip" => fields,
+            _ => return Err(TypeError::new("AudioClip type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let streaming_info = match fields.get("m_Resource") {
+            Some(resource) => Some(StreamingInfo::from_resource(resource)?),
+            None => None,
+        };
+        let audio_data = match streaming_info {
+            Some(streaming_info) if !streaming_info.path.is_empty() => {
+                ClipData::Streaming(streaming_info)
+            }
+            // Without a resource the bank may be kept inline. Clips before Unity 5 store their
+            // data inline too, but in a different format.
+            _ => match fields.get("m_AudioData").and_then(Data::as_bytes) {
+                Some(bank) if bank.starts_with(b"FSB5") => match AudioClip::read(bank)? {
+                    Some(wav) => ClipData::Loaded(wav),
+                    None => ClipData::Unknown,
+                },
+                _ => ClipData::Unknown,
+            },
+        };
+        Ok(Self { name, audio_data })
+    }
+
+    /// Extracts the first subsound of an FSB5 bank as WAV, or `None` if its codec isn't supported.
+    fn read(bank: &[u8]) -> Result<Option<Vec<u8>>, JsValue> {
+        let bank = fsbdec::Fsb5::parse(bank)
+            .map_err(|e| Error::new(&format!("failed to parse sound bank: {}", e)))?;
+        if !bank.codec().is_pcm() {
+            return Ok(None);
+        }
+        let wav = bank
+            .to_wav(0)
+            .map_err(|e| Error::new(&format!("failed to extract sound: {}", e)))?;
+        Ok(Some(wav))
+    }
+}
+
+#[wasm_bindgen]
+impl AudioClip {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = wavPtr)]
+    pub fn wav_ptr(&self) -> *const u8 {
+        match &self.audio_data {
+            ClipData::Loaded(data) => data.as_ptr(),
+            _ => std::ptr::null(),
+        }
+    }
+
+    #[wasm_bindgen(getter, js_name = wavLen)]
+    pub fn wav_len(&self) -> Option<usize> {
+        match &self.audio_data {
+            ClipData::Loaded(data) => Some(data.len()),
+            _ => None,
+        }
+    }
+
+    #[wasm_bindgen(js_name = assetDependency)]
+    pub fn asset_dependency(&self) -> Option<String> {
+        match &self.audio_data {
+            ClipData::Streaming(StreamingInfo { path, .. }) => Some(path.clone()),
+            _ => None,
         }
-        let mut path_segments = streaming_info.path[9..].split('/');
-        let (bundle_name, resource_name) = match (path_segments.next(), path_segments.next()) {
-            (Some(x), Some(y)) => (x, y),
+    }
+
+    #[wasm_bindgen(js_name = tryResolve)]
+    pub fn try_resolve(&mut self, fs: &UnityFs) -> Result<(), JsValue> {
+        let streaming_info = match &self.audio_data {
+            ClipData::Streaming(val) => val,
             _ => return Ok(()),
         };
-        if fs.name() != bundle_name {
-            return Ok(());
+        let buf = match resolve_stream(fs, streaming_info)? {
+            Some(buf) => buf,
+            None => return Ok(()),
+        };
+        self.audio_data = match AudioClip::read(&buf)? {
+            Some(wav) => ClipData::Loaded(wav),
+            None => ClipData::Unknown,
+        };
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+pub struct TextAsset {
+    name: String,
+    script: Vec<u8>,
+}
+
+impl TextAsset {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "TextAsset" => fields,
+            _ => return Err(TypeError::new("TextAsset type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let script = match fields.get("m_Script") {
+            Some(Data::String(s)) | Some(Data::UInt8Array(s)) => s.to_vec(),
+            Some(_) => return Err(Error::new("m_Script type mismatch").into()),
+            None => return Err(Error::new("m_Script not found").into()),
+        };
+        Ok(Self { name, script })
+    }
+}
+
+#[wasm_bindgen]
+impl TextAsset {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Returns the contents as a string, or as raw bytes if they aren't valid UTF-8.
+    pub fn text(&self) -> JsValue {
+        std::str::from_utf8(&self.script)
+            .map(JsValue::from_str)
+            .unwrap_or_else(|_| Uint8Array::from(&*self.script).into())
+    }
+}
+
+#[wasm_bindgen]
+pub struct Font {
+    name: String,
+    font_data: Vec<u8>,
+    ascent: Option<f32>,
+    line_spacing: Option<f32>,
+}
+
+impl Font {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "Font" => fields,
+            _ => return Err(TypeError::new("Font type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        Ok(Self {
+            name,
+            font_data: byte_vector(fields, "m_FontData")?,
+            ascent: fields.get("m_Ascent").and_then(Data::as_f32),
+            line_spacing: fields.get("m_LineSpacing").and_then(Data::as_f32),
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl Font {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The embedded font file, empty for fonts that refer to a system font.
+    #[wasm_bindgen(js_name = fontData)]
+    pub fn font_data(&self) -> Uint8Array {
+        Uint8Array::from(&*self.font_data)
+    }
+
+    /// MIME type of the embedded font file, guessed from its signature.
+    #[wasm_bindgen(getter, js_name = mimeType)]
+    pub fn mime_type(&self) -> Option<String> {
+        let mime = match self.font_data.get(..4)? {
+            b"OTTO" => "font/otf",
+            b"\0\x01\0\0" | b"true" => "font/ttf",
+            b"ttcf" => "font/collection",
+            b"wOFF" => "font/woff",
+            b"wOF2" => "font/woff2",
+            _ => return None,
+        };
+        Some(mime.into())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ascent(&self) -> Option<f32> {
+        self.ascent
+    }
+
+    #[wasm_bindgen(getter, js_name = lineSpacing)]
+    pub fn line_spacing(&self) -> Option<f32> {
+        self.line_spacing
+    }
+}
+
+/// Reads a byte buffer field, which may also be a `vector<char>`.
+fn byte_vector(fields: &Fields<'_>, name: &str) -> Result<Vec<u8>, JsValue> {
+    // `vector<char>` is read as an array of one-byte primitives.
+    Ok(match fields.get(name) {
+        Some(Data::String(s)) | Some(Data::UInt8Array(s)) => s.to_vec(),
+        Some(Data::GenericArray(chars)) => chars
+            .iter()
+            .map(|c| match c {
+                Data::GenericPrimitive { data, .. } if data.len() == 1 => Ok(data[0]),
+                Data::SInt8(v) => Ok(*v as u8),
+                Data::UInt8(v) => Ok(*v),
+                _ => Err(Error::new(&format!("{} type mismatch", name))),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(Error::new(&format!("{} type mismatch", name)).into()),
+        None => return Err(Error::new(&format!("{} not found", name)).into()),
+    })
+}
+
+/// A render target. Its pixels only exist on the GPU, so only its description is stored.
+#[wasm_bindgen]
+pub struct RenderTexture {
+    name: String,
+    width: u32,
+    height: u32,
+    depth: Option<u32>,
+    dimension: Option<i32>,
+    color_format: Option<i32>,
+    anti_aliasing: Option<i32>,
+    mip_map: bool,
+}
+
+impl RenderTexture {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "RenderTexture" => fields,
+            _ => return Err(TypeError::new("RenderTexture type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let width = match fields.get("m_Width") {
+            Some(Data::SInt32(width)) => (*width) as u32,
+            Some(_) => return Err(Error::new("m_Width type mismatch").into()),
+            None => return Err(Error::new("m_Width not found").into()),
+        };
+        let height = match fields.get("m_Height") {
+            Some(Data::SInt32(height)) => (*height) as u32,
+            Some(_) => return Err(Error::new("m_Height type mismatch").into()),
+            None => return Err(Error::new("m_Height not found").into()),
+        };
+        Ok(Self {
+            name,
+            width,
+            height,
+            depth: fields
+                .get("m_VolumeDepth")
+                .and_then(Data::as_i32)
+                .map(|v| v as u32),
+            dimension: fields.get("m_Dimension").and_then(Data::as_i32),
+            color_format: fields.get("m_ColorFormat").and_then(Data::as_i32),
+            anti_aliasing: fields.get("m_AntiAliasing").and_then(Data::as_i32),
+            mip_map: fields
+                .get("m_MipMap")
+                .and_then(Data::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl RenderTexture {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of slices of 3D textures and arrays.
+    #[wasm_bindgen(getter)]
+    pub fn depth(&self) -> Option<u32> {
+        self.depth
+    }
+
+    /// The `TextureDimension`, such as 2 for 2D textures and 4 for cubemaps.
+    #[wasm_bindgen(getter)]
+    pub fn dimension(&self) -> Option<i32> {
+        self.dimension
+    }
+
+    /// The `RenderTextureFormat` of the color buffer.
+    #[wasm_bindgen(getter, js_name = colorFormat)]
+    pub fn color_format(&self) -> Option<i32> {
+        self.color_format
+    }
+
+    /// Number of samples per pixel.
+    #[wasm_bindgen(getter, js_name = antiAliasing)]
+    pub fn anti_aliasing(&self) -> Option<i32> {
+        self.anti_aliasing
+    }
+
+    #[wasm_bindgen(getter, js_name = mipMap)]
+    pub fn mip_map(&self) -> bool {
+        self.mip_map
+    }
+}
+
+/// A legacy movie clip. The movie is stored as is, usually as Ogg Theora.
+///
+/// Its dimensions are not serialized; they are only known after playing the movie.
+#[wasm_bindgen]
+pub struct MovieTexture {
+    name: String,
+    movie_data: Vec<u8>,
+    looping: bool,
+}
+
+impl MovieTexture {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "MovieTexture" => fields,
+            _ => return Err(TypeError::new("MovieTexture type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        Ok(Self {
+            name,
+            movie_data: byte_vector(fields, "m_MovieData")?,
+            looping: fields
+                .get("m_Loop")
+                .and_then(Data::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl MovieTexture {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(js_name = movieData)]
+    pub fn movie_data(&self) -> Uint8Array {
+        Uint8Array::from(&*self.movie_data)
+    }
+
+    /// MIME type of the movie, guessed from its signature.
+    #[wasm_bindgen(getter, js_name = mimeType)]
+    pub fn mime_type(&self) -> Option<String> {
+        match self.movie_data.get(..4)? {
+            b"OggS" => Some("video/ogg".into()),
+            _ => None,
         }
-        let resource = if let Some(buf) = fs.resource(resource_name) {
-            buf
-        } else {
-            return Ok(());
+    }
+
+    #[wasm_bindgen(getter, js_name = loop)]
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+}
+
+/// A video clip, whose movie is kept as it was imported in a resource next to the bundle.
+#[wasm_bindgen]
+pub struct VideoClip {
+    name: String,
+    original_path: String,
+    #[wasm_bindgen(readonly)]
+    pub width: u32,
+    #[wasm_bindgen(readonly)]
+    pub height: u32,
+    frame_rate: Option<f64>,
+    frame_count: Option<u64>,
+    format: Option<i32>,
+    video_data: ClipData,
+}
+
+impl VideoClip {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "VideoClip" => fields,
+            _ => return Err(TypeError::new("VideoClip type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let video_data = match fields.get("m_ExternalResources") {
+            Some(resource) => {
+                let streaming_info = StreamingInfo::from_resource(resource)?;
+                if streaming_info.path.is_empty() {
+                    ClipData::Unknown
+                } else {
+                    ClipData::Streaming(streaming_info)
+                }
+            }
+            None => return Err(Error::new("m_ExternalResources not found").into()),
+        };
+        let dimension = |name: &str| fields.get(name).and_then(Data::as_u32).unwrap_or(0);
+        Ok(Self {
+            name,
+            original_path: fields
+                .get("m_OriginalPath")
+                .and_then(Data::as_bytes)
+                .map(|path| String::from_utf8_lossy(path).into_owned())
+                .unwrap_or_default(),
+            width: dimension("Width"),
+            height: dimension("Height"),
+            frame_rate: fields.get("m_FrameRate").and_then(Data::as_f64),
+            frame_count: fields.get("m_FrameCount").and_then(Data::as_u64),
+            format: fields.get("m_Format").and_then(Data::as_i32),
+            video_data,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl VideoClip {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Path of the movie in the project it was imported from.
+    #[wasm_bindgen(getter, js_name = originalPath)]
+    pub fn original_path(&self) -> String {
+        self.original_path.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = frameRate)]
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.frame_rate
+    }
+
+    /// Number of frames as a `BigInt`.
+    #[wasm_bindgen(getter, js_name = frameCount)]
+    pub fn frame_count(&self) -> Option<JsValue> {
+        self.frame_count.map(big_int)
+    }
+
+    /// The `m_Format` of the clip, `0` for movies kept as imported.
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> Option<i32> {
+        self.format
+    }
+
+    /// The movie file once resolved, such as an MP4 or WebM file.
+    #[wasm_bindgen(js_name = videoData)]
+    pub fn video_data(&self) -> Option<Uint8Array> {
+        match &self.video_data {
+            ClipData::Loaded(data) => Some(Uint8Array::from(&**data)),
+            _ => None,
+        }
+    }
+
+    /// MIME type of the movie, guessed from its signature once resolved and from the extension
+    /// of its original path otherwise.
+    #[wasm_bindgen(getter, js_name = mimeType)]
+    pub fn mime_type(&self) -> Option<String> {
+        let sniffed = match &self.video_data {
+            ClipData::Loaded(data) => match (data.get(..4), data.get(4..8)) {
+                (_, Some(b"ftyp")) => match data.get(8..12) {
+                    Some(b"qt  ") => Some("video/quicktime"),
+                    _ => Some("video/mp4"),
+                },
+                (Some(b"\x1a\x45\xdf\xa3"), _) => Some("video/webm"),
+                (Some(b"OggS"), _) => Some("video/ogg"),
+                _ => None,
+            },
+            _ => None,
         };
-        let buf = &resource[streaming_info.offset as usize..][..streaming_info.size as usize];
-        let image_data =
-            Texture2D::read(self.width, self.height, *format, std::io::Cursor::new(buf))?;
-        self.image_data = ImageData::Loaded(image_data);
+        let extension = self.original_path.rsplit_once('.').map(|(_, ext)| ext);
+        let mime = sniffed.or_else(|| match extension?.to_ascii_lowercase().as_str() {
+            "mp4" | "m4v" => Some("video/mp4"),
+            "webm" => Some("video/webm"),
+            "mov" => Some("video/quicktime"),
+            "ogv" => Some("video/ogg"),
+            _ => None,
+        })?;
+        Some(mime.into())
+    }
+
+    #[wasm_bindgen(js_name = assetDependency)]
+    pub fn asset_dependency(&self) -> Option<String> {
+        match &self.video_data {
+            ClipData::Streaming(StreamingInfo { path, .. }) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    #[wasm_bindgen(js_name = tryResolve)]
+    pub fn try_resolve(&mut self, fs: &UnityFs) -> Result<(), JsValue> {
+        let streaming_info = match &self.video_data {
+            ClipData::Streaming(val) => val,
+            _ => return Ok(()),
+        };
+        if let Some(buf) = resolve_stream(fs, streaming_info)? {
+            self.video_data = ClipData::Loaded(buf);
+        }
         Ok(())
     }
 }
 
+#[wasm_bindgen]
+extern "C" {
+    /// The global `BigInt` function, which `js-sys` has no binding for yet.
+    #[wasm_bindgen(js_name = BigInt)]
+    fn big_int_from_str(value: &str) -> JsValue;
+
+    /// The global `String` function, which formats `BigInt`s without the `n` suffix.
+    #[wasm_bindgen(js_name = String)]
+    fn js_string(value: &JsValue) -> String;
+}
+
+/// Converts a 64-bit integer into a `BigInt`, as numbers lose precision past 2^53.
+fn big_int(value: impl ToString) -> JsValue {
+    big_int_from_str(&value.to_string())
+}
+
 fn convert_shallow(data: &Data<'_>) -> JsValue {
     match data {
         Data::Bool(b) => JsValue::from_bool(*b),
         Data::UInt8(v) => JsValue::from_f64((*v).into()),
         Data::UInt16(v) => JsValue::from_f64((*v).into()),
         Data::UInt32(v) => JsValue::from_f64((*v).into()),
-        Data::UInt64(v) => JsValue::from_f64(*v as f64),
+        Data::UInt64(v) => big_int(v),
         Data::SInt8(v) => JsValue::from_f64((*v).into()),
         Data::SInt16(v) => JsValue::from_f64((*v).into()),
         Data::SInt32(v) => JsValue::from_f64((*v).into()),
-        Data::SInt64(v) => JsValue::from_f64(*v as f64),
+        Data::SInt64(v) => big_int(v),
         Data::Float(v) => JsValue::from_f64((*v).into()),
-        Data::Double(v) => JsValue::from_f64((*v).into()),
-        Data::String(s) => std::str::from_utf8(&**s)
+        Data::Double(v) => JsValue::from_f64(*v),
+        Data::String(s) => std::str::from_utf8(s)
             .map(JsValue::from_str)
             .unwrap_or_else(|_| Uint8Array::from(&**s).into()),
         v => UnityObject::from_data(v).into(),
     }
 }
 
-fn convert_data(data: &Data<'_>) -> Result<JsValue, JsValue> {
+/// The key of a dictionary entry as a string, for keys of primitive types.
+fn dictionary_key(key: &Data<'_>) -> Option<String> {
+    match key {
+        Data::String(_) => key.as_str().map(str::to_owned),
+        Data::Bool(v) => Some(v.to_string()),
+        _ => key
+            .as_i64()
+            .map(|v| v.to_string())
+            .or_else(|| key.as_u64().map(|v| v.to_string()))
+            .or_else(|| key.as_f64().map(|v| v.to_string())),
+    }
+}
+
+/// Converts an array of pairs, which is how dictionaries are serialized, into a `Map` keyed by
+/// the first element of each pair as a string.
+///
+/// Returns `None` for empty arrays, arrays of anything else, and dictionaries whose keys aren't
+/// primitives or collide once turned into strings.
+fn convert_dictionary(arr: &[Data<'_>]) -> Option<Map> {
+    if arr.is_empty() {
+        return None;
+    }
+    let map = Map::new();
+    for entry in arr {
+        let (key, value) = match entry {
+            Data::Pair(key, value) => (key, value),
+            _ => return None,
+        };
+        map.set(&dictionary_key(key)?.into(), &convert_shallow(value));
+    }
+    Some(map).filter(|map| map.size() as usize == arr.len())
+}
+
+/// Converts an object to its wrapper type if it has one. `target_platform` is the platform of
+/// the asset it was read from, if known.
+fn convert_data(data: &Data<'_>, target_platform: Option<Platform>) -> Result<JsValue, JsValue> {
     Ok(match data {
         Data::GenericPrimitive { data, .. } => Uint8Array::from(&**data).into(),
         Data::GenericStruct { type_name, fields } => {
             if type_name == "Texture2D" {
-                let name = match fields.get("m_Name") {
-                    Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
-                    Some(_) => return Err(Error::new("m_Name type mismatch").into()),
-                    None => return Err(Error::new("m_Name not found").into()),
-                };
-                let width = match fields.get("m_Width") {
-                    Some(Data::SInt32(width)) => (*width) as u32,
-                    Some(_) => return Err(Error::new("m_Width type mismatch").into()),
-                    None => return Err(Error::new("m_Width not found").into()),
-                };
-                let height = match fields.get("m_Height") {
-                    Some(Data::SInt32(height)) => (*height) as u32,
-                    Some(_) => return Err(Error::new("m_Height type mismatch").into()),
-                    None => return Err(Error::new("m_Height not found").into()),
-                };
-                let image_data = match fields.get("image data") {
-                    Some(Data::UInt8Array(buf)) => buf,
-                    Some(_) => return Err(Error::new("image data type mismatch").into()),
-                    None => return Err(Error::new("image data not found").into()),
-                };
-                let image_data = std::io::Cursor::new(image_data);
-                let format = match fields.get("m_TextureFormat") {
-                    Some(Data::SInt32(34)) => {
-                        Some(DecodeFormat::Etc(etcdec::DecodeFormat::EtcRgb4))
-                    }
-                    Some(Data::SInt32(45)) => {
-                        Some(DecodeFormat::Etc(etcdec::DecodeFormat::Etc2Rgb))
-                    }
-                    Some(Data::SInt32(46)) => {
-                        Some(DecodeFormat::Etc(etcdec::DecodeFormat::Etc2Rgba1))
-                    }
-                    Some(Data::SInt32(47)) => {
-                        Some(DecodeFormat::Etc(etcdec::DecodeFormat::Etc2Rgba8))
-                    }
-                    Some(Data::SInt32(10)) => Some(DecodeFormat::Dxt(dxt::DXTVariant::DXT1)),
-                    Some(Data::SInt32(12)) => Some(DecodeFormat::Dxt(dxt::DXTVariant::DXT5)),
-                    Some(Data::SInt32(_)) => None,
-                    Some(_) => return Err(Error::new("m_TextureFormat type mismatch").into()),
-                    None => return Err(Error::new("m_TextureFormat not found").into()),
-                };
-                if let Some(format) = format {
-                    let streaming_info = fields
-                        .get("m_StreamData")
-                        .ok_or_else(|| Error::new("m_StreamData not found").into())
-                        .and_then(StreamingInfo::from_data)?;
-                    if streaming_info.path.is_empty() {
-                        Texture2D::load(name, width, height, format, image_data)?.into()
-                    } else {
-                        Texture2D::defer(name, width, height, format, streaming_info).into()
-                    }
-                } else {
-                    Texture2D::unknown(name, width, height).into()
-                }
+                Texture2D::from_data(data, target_platform, None)?.into()
+            } else if type_name == "Cubemap"
+                || type_name == "Texture2DArray"
+                || type_name == "Texture3D"
+            {
+                TextureArray::from_data(data)?.into()
+            } else if type_name == "AudioClip" {
+                AudioClip::from_data(data)?.into()
+            } else if type_name == "TextAsset" {
+                TextAsset::from_data(data)?.into()
+            } else if type_name == "Sprite" {
+                Sprite::from_data(data)?.into()
+            } else if type_name == "SpriteAtlas" {
+                SpriteAtlas::from_data(data)?.into()
+            } else if type_name == "Font" {
+                Font::from_data(data)?.into()
+            } else if type_name == "RenderTexture" {
+                RenderTexture::from_data(data)?.into()
+            } else if type_name == "VideoClip" {
+                VideoClip::from_data(data)?.into()
+            } else if type_name == "MovieTexture" {
+                MovieTexture::from_data(data)?.into()
+            } else if type_name == "AnimationClip" {
+                AnimationClip::from_data(data)?.into()
+            } else if type_name == "Shader" && fields.contains_key("compressedBlob") {
+                Shader::from_data(data)?.into()
+            } else if type_name == "Mesh" && mesh::is_uncompressed(fields) {
+                Mesh::from_data(data)?.into()
             } else {
                 let fields: Array = fields
                     .iter()
@@ -400,28 +1553,28 @@ fn convert_data(data: &Data<'_>) -> Result<JsValue, JsValue> {
                 Object::from_entries(&fields)?.into()
             }
         }
-        Data::GenericArray(arr) => {
-            let arr = arr.iter().map(convert_shallow).collect::<Array>();
-            arr.into()
-        }
+        Data::GenericArray(arr) => match convert_dictionary(arr) {
+            Some(map) => map.into(),
+            None => arr.iter().map(convert_shallow).collect::<Array>().into(),
+        },
         Data::Bool(b) => JsValue::from_bool(*b),
         Data::UInt8(v) => JsValue::from_f64((*v).into()),
         Data::UInt16(v) => JsValue::from_f64((*v).into()),
         Data::UInt32(v) => JsValue::from_f64((*v).into()),
-        Data::UInt64(v) => JsValue::from_f64(*v as f64),
+        Data::UInt64(v) => big_int(v),
         Data::SInt8(v) => JsValue::from_f64((*v).into()),
         Data::SInt16(v) => JsValue::from_f64((*v).into()),
         Data::SInt32(v) => JsValue::from_f64((*v).into()),
-        Data::SInt64(v) => JsValue::from_f64(*v as f64),
+        Data::SInt64(v) => big_int(v),
         Data::Float(v) => JsValue::from_f64((*v).into()),
-        Data::Double(v) => JsValue::from_f64((*v).into()),
+        Data::Double(v) => JsValue::from_f64(*v),
         Data::Pair(fst, snd) => {
             let fst = UnityObject::from_data(fst).into();
             let snd = UnityObject::from_data(snd).into();
             Array::of2(&fst, &snd).into()
         }
         Data::UInt8Array(s) => Uint8Array::from(&**s).into(),
-        Data::String(s) => std::str::from_utf8(&**s)
+        Data::String(s) => std::str::from_utf8(s)
             .map(JsValue::from_str)
             .unwrap_or_else(|_| Uint8Array::from(&**s).into()),
     })
@@ -0,0 +1,572 @@
+use js_sys::{Error, Float32Array, TypeError, Uint16Array, Uint32Array};
+use wasm_bindgen::prelude::*;
+
+use unityfs::{Data, Fields};
+
+use crate::{resolve_stream, StreamingInfo, UnityFs};
+
+#[wasm_bindgen]
+pub struct Mesh {
+    name: String,
+    vertex_count: u32,
+    channels: Vec<ChannelInfo>,
+    vertex_data: VertexData,
+    indices: Indices,
+}
+
+enum VertexData {
+    Loaded(Vec<u8>),
+    Streaming(StreamingInfo),
+}
+
+enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+/// Index range and primitive type of a submesh.
+struct SubMesh {
+    first_byte: u32,
+    index_count: u32,
+    topology: i32,
+    /// Added to every index of the submesh, since Unity 2017.3.
+    base_vertex: u32,
+}
+
+/// Values of `MeshTopology`.
+const TOPOLOGY_TRIANGLES: i32 = 0;
+const TOPOLOGY_TRIANGLE_STRIP: i32 = 1;
+const TOPOLOGY_QUADS: i32 = 2;
+
+#[derive(Copy, Clone)]
+struct ChannelInfo {
+    stream: u8,
+    offset: u8,
+    format: u8,
+    dimension: u8,
+}
+
+/// Component type of a vertex channel.
+#[derive(Copy, Clone)]
+enum VertexFormat {
+    Float32,
+    Float16,
+    UNorm8,
+    SNorm8,
+    UNorm16,
+    SNorm16,
+    UInt8,
+    SInt8,
+    UInt16,
+    SInt16,
+    UInt32,
+    SInt32,
+}
+
+impl VertexFormat {
+    /// Reads a `VertexFormat`, used since Unity 2019.1.
+    fn from_u8(format: u8) -> Option<Self> {
+        Some(match format {
+            0 => VertexFormat::Float32,
+            1 => VertexFormat::Float16,
+            2 => VertexFormat::UNorm8,
+            3 => VertexFormat::SNorm8,
+            4 => VertexFormat::UNorm16,
+            5 => VertexFormat::SNorm16,
+            6 => VertexFormat::UInt8,
+            7 => VertexFormat::SInt8,
+            8 => VertexFormat::UInt16,
+            9 => VertexFormat::SInt16,
+            10 => VertexFormat::UInt32,
+            11 => VertexFormat::SInt32,
+            _ => return None,
+        })
+    }
+
+    /// Reads a `VertexChannelFormat`, used before Unity 2019.1.
+    fn from_legacy_u8(format: u8) -> Option<Self> {
+        Some(match format {
+            0 => VertexFormat::Float32,
+            1 => VertexFormat::Float16,
+            2 => VertexFormat::UNorm8,
+            3 => VertexFormat::UInt8,
+            4 => VertexFormat::UInt32,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            VertexFormat::UNorm8
+            | VertexFormat::SNorm8
+            | VertexFormat::UInt8
+            | VertexFormat::SInt8 => 1,
+            VertexFormat::Float16
+            | VertexFormat::UNorm16
+            | VertexFormat::SNorm16
+            | VertexFormat::UInt16
+            | VertexFormat::SInt16 => 2,
+            VertexFormat::Float32 | VertexFormat::UInt32 | VertexFormat::SInt32 => 4,
+        }
+    }
+
+    fn read(self, b: &[u8]) -> f32 {
+        let u16_at = || u16::from_le_bytes([b[0], b[1]]);
+        let u32_at = || u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        match self {
+            VertexFormat::Float32 => f32::from_bits(u32_at()),
+            VertexFormat::Float16 => f16_to_f32(u16_at()),
+            VertexFormat::UNorm8 => f32::from(b[0]) / 255.0,
+            VertexFormat::SNorm8 => (f32::from(b[0] as i8) / 127.0).max(-1.0),
+            VertexFormat::UNorm16 => f32::from(u16_at()) / 65535.0,
+            VertexFormat::SNorm16 => (f32::from(u16_at() as i16) / 32767.0).max(-1.0),
+            VertexFormat::UInt8 => b[0].into(),
+            VertexFormat::SInt8 => (b[0] as i8).into(),
+            VertexFormat::UInt16 => u16_at().into(),
+            VertexFormat::SInt16 => (u16_at() as i16).into(),
+            VertexFormat::UInt32 => u32_at() as f32,
+            VertexFormat::SInt32 => u32_at() as i32 as f32,
+        }
+    }
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half >> 15) << 31;
+    let exponent = u32::from((half >> 10) & 0x1f);
+    let mantissa = u32::from(half & 0x3ff);
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal; normalize it.
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((mantissa << shift) & 0x3ff) << 13)
+        }
+        (0x1f, _) => sign | 0x7f800000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Where a channel lives in the vertex buffer.
+#[derive(Copy, Clone)]
+struct Channel {
+    offset: usize,
+    stride: usize,
+    format: VertexFormat,
+    dimension: usize,
+}
+
+/// Semantic of a vertex channel.
+#[derive(Copy, Clone)]
+enum Semantic {
+    Position,
+    Normal,
+    TexCoord0,
+}
+
+impl Mesh {
+    pub(crate) fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "Mesh" => fields,
+            _ => return Err(TypeError::new("Mesh type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let vertex_fields = match fields.get("m_VertexData") {
+            Some(Data::GenericStruct { fields, .. }) => fields,
+            Some(_) => return Err(Error::new("m_VertexData type mismatch").into()),
+            None => return Err(Error::new("m_VertexData not found").into()),
+        };
+        let vertex_count = match vertex_fields.get("m_VertexCount") {
+            Some(Data::UInt32(v)) => *v,
+            Some(_) => return Err(Error::new("m_VertexCount type mismatch").into()),
+            None => return Err(Error::new("m_VertexCount not found").into()),
+        };
+        let channels = match vertex_fields.get("m_Channels") {
+            Some(Data::GenericArray(channels)) => channels
+                .iter()
+                .map(ChannelInfo::from_data)
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(_) => return Err(Error::new("m_Channels type mismatch").into()),
+            None => return Err(Error::new("m_Channels not found").into()),
+        };
+        let data = match vertex_fields.get("m_DataSize") {
+            Some(Data::UInt8Array(data)) => data,
+            Some(_) => return Err(Error::new("m_DataSize type mismatch").into()),
+            None => return Err(Error::new("m_DataSize not found").into()),
+        };
+        // Since 2018.3 the vertex buffer may be stored in a resource file instead.
+        let streaming_info = fields
+            .get("m_StreamData")
+            .map(StreamingInfo::from_data)
+            .transpose()?
+            .filter(|info| data.is_empty() && !info.path.is_empty());
+        let vertex_data = match streaming_info {
+            Some(info) => VertexData::Streaming(info),
+            None => VertexData::Loaded(data.to_vec()),
+        };
+
+        let index_buffer = match fields.get("m_IndexBuffer") {
+            Some(Data::UInt8Array(data)) => data,
+            Some(_) => return Err(Error::new("m_IndexBuffer type mismatch").into()),
+            None => return Err(Error::new("m_IndexBuffer not found").into()),
+        };
+        let submeshes = match fields.get("m_SubMeshes") {
+            Some(Data::GenericArray(submeshes)) => Some(
+                submeshes
+                    .iter()
+                    .map(SubMesh::from_data)
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            Some(_) => return Err(Error::new("m_SubMeshes type mismatch").into()),
+            None => None,
+        };
+        let indices = match fields.get("m_IndexFormat") {
+            Some(Data::SInt32(1)) => Indices::U32(triangle_list(
+                &index_buffer
+                    .chunks_exact(4)
+                    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect::<Vec<_>>(),
+                4,
+                submeshes.as_deref(),
+            )),
+            Some(Data::SInt32(_)) | None => {
+                let triangles = triangle_list(
+                    &index_buffer
+                        .chunks_exact(2)
+                        .map(|b| u16::from_le_bytes([b[0], b[1]]).into())
+                        .collect::<Vec<_>>(),
+                    2,
+                    submeshes.as_deref(),
+                );
+                // 16-bit indices stay 16-bit unless a base vertex pushed them past it.
+                if triangles.iter().all(|&index| index <= u32::from(u16::MAX)) {
+                    Indices::U16(triangles.into_iter().map(|index| index as u16).collect())
+                } else {
+                    Indices::U32(triangles)
+                }
+            }
+            Some(_) => return Err(Error::new("m_IndexFormat type mismatch").into()),
+        };
+
+        Ok(Self {
+            name,
+            vertex_count,
+            channels,
+            vertex_data,
+            indices,
+        })
+    }
+
+    /// Locates every channel in a vertex buffer of `len` bytes.
+    ///
+    /// Streams are laid out back to back, each aligned to 16 bytes, with the channels of a
+    /// stream interleaved. The meaning of the format byte changed in 2019.1 without anything in
+    /// the data saying which one is used, so take the one whose layout fills the buffer.
+    fn layout(&self, len: usize) -> Option<Vec<Option<Channel>>> {
+        let layout_with = |from_u8: fn(u8) -> Option<VertexFormat>| {
+            let formats = self
+                .channels
+                .iter()
+                .map(|channel| match channel.dimension & 0xf {
+                    0 => Some(None),
+                    _ => from_u8(channel.format).map(Some),
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let stream_count = self
+                .channels
+                .iter()
+                .zip(&formats)
+                .filter(|(_, format)| format.is_some())
+                .map(|(channel, _)| channel.stream as usize + 1)
+                .max()
+                .unwrap_or(0);
+            let mut stream_offsets = Vec::with_capacity(stream_count);
+            let mut strides = Vec::with_capacity(stream_count);
+            let mut offset = 0usize;
+            let mut end = 0usize;
+            for stream in 0..stream_count {
+                let stride: usize = self
+                    .channels
+                    .iter()
+                    .zip(&formats)
+                    .filter(|(channel, _)| channel.stream as usize == stream)
+                    .filter_map(|(channel, format)| {
+                        format.map(|format| format.size() * (channel.dimension & 0xf) as usize)
+                    })
+                    .sum();
+                stream_offsets.push(offset);
+                strides.push(stride);
+                end = offset + stride * self.vertex_count as usize;
+                offset = (end + 15) & !15;
+            }
+            if end > len || len > offset {
+                return None;
+            }
+            Some(
+                self.channels
+                    .iter()
+                    .zip(formats)
+                    .map(|(channel, format)| {
+                        let stream = channel.stream as usize;
+                        format.map(|format| Channel {
+                            offset: stream_offsets[stream] + channel.offset as usize,
+                            stride: strides[stream],
+                            format,
+                            dimension: (channel.dimension & 0xf) as usize,
+                        })
+                    })
+                    .collect(),
+            )
+        };
+        layout_with(VertexFormat::from_u8).or_else(|| layout_with(VertexFormat::from_legacy_u8))
+    }
+
+    /// Reads `components` values per vertex from a channel, padding missing ones with zero.
+    fn read_channel(&self, semantic: Semantic, components: usize) -> Option<Float32Array> {
+        let data = match &self.vertex_data {
+            VertexData::Loaded(data) => data,
+            VertexData::Streaming(_) => return None,
+        };
+        // Unity 2018 added tangents and more texture coordinates in between.
+        let index = match (semantic, self.channels.len()) {
+            (Semantic::Position, _) => 0,
+            (Semantic::Normal, _) => 1,
+            (Semantic::TexCoord0, 8) => 3,
+            (Semantic::TexCoord0, _) => 4,
+        };
+        let channel = (*self.layout(data.len())?.get(index)?)?;
+        let size = channel.format.size();
+        let mut values = Vec::with_capacity(self.vertex_count as usize * components);
+        for vertex in 0..self.vertex_count as usize {
+            let start = channel.offset + vertex * channel.stride;
+            for component in 0..components {
+                let value = if component < channel.dimension {
+                    let offset = start + component * size;
+                    channel.format.read(data.get(offset..(offset + size))?)
+                } else {
+                    0.0
+                };
+                values.push(value);
+            }
+        }
+        Some(Float32Array::from(&values[..]))
+    }
+}
+
+/// Turns the index buffer, whose indices took `index_size` bytes each, into a list of
+/// triangles, going through each submesh by its topology and adding its base vertex.
+///
+/// Quads are split in two, while lines and points have no triangles to give. Without a submesh
+/// table the buffer is used as it is.
+fn triangle_list(indices: &[u32], index_size: usize, submeshes: Option<&[SubMesh]>) -> Vec<u32> {
+    let submeshes = match submeshes {
+        Some(submeshes) => submeshes,
+        None => return indices.to_vec(),
+    };
+    let mut triangles = Vec::with_capacity(indices.len());
+    for submesh in submeshes {
+        let start = submesh.first_byte as usize / index_size;
+        let range = indices.get(start..).unwrap_or_default();
+        let range = &range[..std::cmp::min(range.len(), submesh.index_count as usize)];
+        let first = triangles.len();
+        match submesh.topology {
+            TOPOLOGY_TRIANGLES => triangles.extend_from_slice(range),
+            TOPOLOGY_TRIANGLE_STRIP => unroll_strip(range, &mut triangles),
+            TOPOLOGY_QUADS => {
+                for quad in range.chunks_exact(4) {
+                    triangles.extend_from_slice(&[quad[0], quad[1], quad[2]]);
+                    triangles.extend_from_slice(&[quad[0], quad[2], quad[3]]);
+                }
+            }
+            _ => {}
+        }
+        for index in &mut triangles[first..] {
+            *index = index.wrapping_add(submesh.base_vertex);
+        }
+    }
+    triangles
+}
+
+/// Appends the triangles of a strip to `triangles`, flipping every other one to keep the
+/// winding and dropping the degenerate triangles that join strips together.
+fn unroll_strip(strip: &[u32], triangles: &mut Vec<u32>) {
+    for (i, window) in strip.windows(3).enumerate() {
+        let (a, b, c) = (window[0], window[1], window[2]);
+        if a == b || b == c || a == c {
+            continue;
+        }
+        if i % 2 == 0 {
+            triangles.extend_from_slice(&[a, b, c]);
+        } else {
+            triangles.extend_from_slice(&[b, a, c]);
+        }
+    }
+}
+
+impl SubMesh {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "SubMesh" => fields,
+            _ => return Err(TypeError::new("SubMesh type mismatch").into()),
+        };
+        let uint = |name: &str| match fields.get(name) {
+            Some(Data::UInt32(v)) => Ok(*v),
+            _ => Err(JsValue::from(TypeError::new("SubMesh type mismatch"))),
+        };
+        let topology = match fields.get("topology") {
+            Some(Data::SInt32(v)) => *v,
+            _ => return Err(TypeError::new("SubMesh type mismatch").into()),
+        };
+        let base_vertex = match fields.get("baseVertex") {
+            Some(Data::UInt32(v)) => *v,
+            Some(_) => return Err(TypeError::new("SubMesh type mismatch").into()),
+            None => 0,
+        };
+        Ok(Self {
+            first_byte: uint("firstByte")?,
+            index_count: uint("indexCount")?,
+            topology,
+            base_vertex,
+        })
+    }
+}
+
+impl ChannelInfo {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "ChannelInfo" => fields,
+            _ => return Err(TypeError::new("ChannelInfo type mismatch").into()),
+        };
+        let byte = |name: &str| match fields.get(name) {
+            Some(Data::UInt8(v)) => Ok(*v),
+            _ => Err(JsValue::from(TypeError::new("ChannelInfo type mismatch"))),
+        };
+        Ok(Self {
+            stream: byte("stream")?,
+            offset: byte("offset")?,
+            format: byte("format")?,
+            dimension: byte("dimension")?,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl Mesh {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = vertexCount)]
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    /// Vertex positions as `x, y, z` triples, or `undefined` if the vertex data isn't loaded.
+    #[wasm_bindgen(getter)]
+    pub fn positions(&self) -> Option<Float32Array> {
+        self.read_channel(Semantic::Position, 3)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn normals(&self) -> Option<Float32Array> {
+        self.read_channel(Semantic::Normal, 3)
+    }
+
+    /// First set of texture coordinates as `u, v` pairs.
+    #[wasm_bindgen(getter)]
+    pub fn uvs(&self) -> Option<Float32Array> {
+        self.read_channel(Semantic::TexCoord0, 2)
+    }
+
+    /// Triangle indices of all submeshes, as a `Uint16Array` or `Uint32Array` with three indices
+    /// per triangle whatever the topology of the submesh.
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> JsValue {
+        match &self.indices {
+            Indices::U16(indices) => Uint16Array::from(&indices[..]).into(),
+            Indices::U32(indices) => Uint32Array::from(&indices[..]).into(),
+        }
+    }
+
+    #[wasm_bindgen(js_name = assetDependency)]
+    pub fn asset_dependency(&self) -> Option<String> {
+        match &self.vertex_data {
+            VertexData::Streaming(StreamingInfo { path, .. }) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    #[wasm_bindgen(js_name = tryResolve)]
+    pub fn try_resolve(&mut self, fs: &UnityFs) -> Result<(), JsValue> {
+        let streaming_info = match &self.vertex_data {
+            VertexData::Streaming(val) => val,
+            _ => return Ok(()),
+        };
+        if let Some(buf) = resolve_stream(fs, streaming_info)? {
+            self.vertex_data = VertexData::Loaded(buf);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the vertices of a mesh are stored as-is rather than quantized in `m_CompressedMesh`.
+pub(crate) fn is_uncompressed(fields: &Fields<'_>) -> bool {
+    matches!(fields.get("m_MeshCompression"), Some(Data::UInt8(0)) | None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submesh(first_byte: u32, index_count: u32, topology: i32, base_vertex: u32) -> SubMesh {
+        SubMesh {
+            first_byte,
+            index_count,
+            topology,
+            base_vertex,
+        }
+    }
+
+    #[test]
+    fn strip_winding_alternates() {
+        let mut triangles = Vec::new();
+        unroll_strip(&[0, 1, 2, 3, 4], &mut triangles);
+        assert_eq!(triangles, [0, 1, 2, 2, 1, 3, 2, 3, 4]);
+    }
+
+    #[test]
+    fn strip_degenerate_joins() {
+        // Two strips of two triangles, joined by repeating the last index of the first and the
+        // first of the second.
+        let mut triangles = Vec::new();
+        unroll_strip(&[0, 1, 2, 3, 3, 4, 4, 5, 6, 7], &mut triangles);
+        assert_eq!(triangles, [0, 1, 2, 2, 1, 3, 4, 5, 6, 6, 5, 7]);
+    }
+
+    #[test]
+    fn quads() {
+        let submeshes = [submesh(0, 8, TOPOLOGY_QUADS, 0)];
+        let triangles = triangle_list(&[0, 1, 2, 3, 4, 5, 6, 7], 2, Some(&submeshes));
+        assert_eq!(triangles, [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7]);
+    }
+
+    #[test]
+    fn base_vertex() {
+        let indices = [0, 1, 2, 0, 1, 2, 3];
+        let submeshes = [
+            submesh(0, 3, TOPOLOGY_TRIANGLES, 0),
+            submesh(6, 4, TOPOLOGY_TRIANGLE_STRIP, 3),
+        ];
+        let triangles = triangle_list(&indices, 2, Some(&submeshes));
+        assert_eq!(triangles, [0, 1, 2, 3, 4, 5, 5, 4, 6]);
+        // Past the range of 16-bit indices, with each index taking four bytes.
+        let submeshes = [submesh(12, 3, TOPOLOGY_TRIANGLES, 70_000)];
+        let triangles = triangle_list(&indices, 4, Some(&submeshes));
+        assert_eq!(triangles, [70_000, 70_001, 70_002]);
+    }
+}
@@ -0,0 +1,114 @@
+use js_sys::{Error, TypeError, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use unityfs::{CompressedBlock, Data, DataError};
+
+/// Compression type ID of the LZ4 blocks of `compressedBlob`.
+const LZ4_COMPRESSION: u32 = 2;
+
+/// A compiled shader. The subprograms of each platform are kept as one or more LZ4 compressed
+/// segments of `compressedBlob`, which are decompressed as they are asked for.
+#[wasm_bindgen]
+pub struct Shader {
+    name: String,
+    platforms: Vec<u32>,
+    /// Offset, compressed length and decompressed length of the segments of each platform.
+    segments: Vec<Vec<(u32, u32, u32)>>,
+    compressed_blob: Vec<u8>,
+}
+
+fn data_error(e: DataError) -> JsValue {
+    Error::new(&e.to_string()).into()
+}
+
+/// Reads a per-platform table, which has one segment per platform before Unity 2019.3.
+fn platform_table(data: &Data<'_>, name: &str) -> Result<Vec<Vec<u32>>, JsValue> {
+    match data.get::<Vec<Vec<u32>>>(name) {
+        Ok(table) => Ok(table),
+        Err(DataError::TypeMismatch { .. }) => data
+            .get::<Vec<u32>>(name)
+            .map(|table| table.into_iter().map(|v| vec![v]).collect())
+            .map_err(data_error),
+        Err(e) => Err(data_error(e)),
+    }
+}
+
+impl Shader {
+    pub(crate) fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        match data {
+            Data::GenericStruct { type_name, .. } if type_name == "Shader" => {}
+            _ => return Err(TypeError::new("Shader type mismatch").into()),
+        }
+        // Since Unity 5.5 the name is in the parsed form, and m_Name is left empty.
+        let name = data
+            .field("m_ParsedForm")
+            .and_then(|form| form.get::<String>("m_Name").ok())
+            .filter(|name| !name.is_empty())
+            .map_or_else(|| data.get::<String>("m_Name"), Ok)
+            .map_err(data_error)?;
+        let platforms = data.get::<Vec<u32>>("platforms").map_err(data_error)?;
+        let offsets = platform_table(data, "offsets")?;
+        let compressed_lengths = platform_table(data, "compressedLengths")?;
+        let decompressed_lengths = platform_table(data, "decompressedLengths")?;
+        let segments = offsets
+            .iter()
+            .zip(&compressed_lengths)
+            .zip(&decompressed_lengths)
+            .map(|((offsets, compressed), decompressed)| {
+                offsets
+                    .iter()
+                    .zip(compressed)
+                    .zip(decompressed)
+                    .map(|((offset, compressed), decompressed)| {
+                        (*offset, *compressed, *decompressed)
+                    })
+                    .collect()
+            })
+            .collect();
+        let compressed_blob = data.get::<Vec<u8>>("compressedBlob").map_err(data_error)?;
+        Ok(Self {
+            name,
+            platforms,
+            segments,
+            compressed_blob,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl Shader {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The `ShaderCompilerPlatform` of each compiled program set, such as 4 for Direct3D 11 and
+    /// 9 for OpenGL ES 3.
+    #[wasm_bindgen(getter)]
+    pub fn platforms(&self) -> Vec<u32> {
+        self.platforms.clone()
+    }
+
+    /// Decompresses the subprograms of the platform at `index` of `platforms`, with the
+    /// segments joined together.
+    pub fn blob(&self, index: usize) -> Result<Uint8Array, JsValue> {
+        let segments = self
+            .segments
+            .get(index)
+            .ok_or_else(|| Error::new("platform index out of range"))?;
+        let mut out = Vec::new();
+        for &(offset, compressed_length, decompressed_length) in segments {
+            let (start, len) = (offset as usize, compressed_length as usize);
+            let compressed = start
+                .checked_add(len)
+                .and_then(|end| self.compressed_blob.get(start..end))
+                .ok_or_else(|| Error::new("shader blob out of range"))?;
+            let block =
+                CompressedBlock::from_slice(decompressed_length, LZ4_COMPRESSION, compressed)
+                    .decompress()
+                    .map_err(|e| Error::new(&format!("failed to decompress: {}", e)))?;
+            out.extend(block);
+        }
+        Ok(Uint8Array::from(&*out))
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use js_sys::{Array, Error, Float32Array, TypeError};
+use wasm_bindgen::prelude::*;
+
+use unityfs::{Data, Fields};
+
+use crate::{big_int, ImageData, Texture2D, UnityFs};
+
+#[wasm_bindgen]
+pub struct Sprite {
+    name: String,
+    rect: Rect,
+    pivot: (f32, f32),
+    pixels_to_units: f32,
+    render_data: RenderData,
+    /// `m_SpriteAtlas` and `m_RenderDataKey`, set when the sprite was packed into an atlas.
+    atlas: Option<((i32, i64), RenderDataKey)>,
+}
+
+#[derive(Copy, Clone)]
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+/// Where the pixels of a sprite are.
+#[derive(Copy, Clone)]
+struct RenderData {
+    texture: (i32, i64),
+    texture_rect: Rect,
+}
+
+type RenderDataKey = ([u32; 4], i64);
+
+#[wasm_bindgen]
+pub struct SpriteAtlas {
+    name: String,
+    tag: String,
+    packed_sprites: Vec<(i32, i64)>,
+    packed_sprite_names: Vec<String>,
+    render_data_map: HashMap<RenderDataKey, RenderData>,
+}
+
+fn get_float(fields: &Fields<'_>, name: &str) -> Result<f32, JsValue> {
+    match fields.get(name) {
+        Some(Data::Float(v)) => Ok(*v),
+        Some(_) => Err(Error::new(&format!("{} type mismatch", name)).into()),
+        None => Err(Error::new(&format!("{} not found", name)).into()),
+    }
+}
+
+fn get_pptr(fields: &Fields<'_>, name: &str) -> Result<(i32, i64), JsValue> {
+    match fields.get(name) {
+        Some(pptr) => pptr
+            .as_pptr()
+            .ok_or_else(|| Error::new(&format!("{} type mismatch", name)).into()),
+        None => Err(Error::new(&format!("{} not found", name)).into()),
+    }
+}
+
+impl Rect {
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "Rectf" => fields,
+            _ => return Err(TypeError::new("Rectf type mismatch").into()),
+        };
+        Ok(Self {
+            x: get_float(fields, "x")?,
+            y: get_float(fields, "y")?,
+            width: get_float(fields, "width")?,
+            height: get_float(fields, "height")?,
+        })
+    }
+
+    fn to_array(self) -> Float32Array {
+        Float32Array::from(&[self.x, self.y, self.width, self.height][..])
+    }
+}
+
+impl RenderData {
+    /// Reads `SpriteRenderData` of a sprite, or `SpriteAtlasData` of an atlas entry.
+    fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields }
+                if type_name == "SpriteRenderData" || type_name == "SpriteAtlasData" =>
+            {
+                fields
+            }
+            _ => return Err(TypeError::new("SpriteRenderData type mismatch").into()),
+        };
+        let texture_rect = match fields.get("textureRect") {
+            Some(rect) => Rect::from_data(rect)?,
+            None => return Err(Error::new("textureRect not found").into()),
+        };
+        Ok(Self {
+            texture: get_pptr(fields, "texture")?,
+            texture_rect,
+        })
+    }
+}
+
+/// Reads a `pair<GUID, SInt64>` identifying an entry of `SpriteAtlas::m_RenderDataMap`.
+fn render_data_key(data: &Data<'_>) -> Option<RenderDataKey> {
+    let (guid, id) = match data {
+        Data::Pair(guid, id) => (guid, id),
+        _ => return None,
+    };
+    let guid = match &**guid {
+        Data::GenericStruct { fields, .. } => fields,
+        _ => return None,
+    };
+    let mut parts = [0; 4];
+    for (idx, part) in parts.iter_mut().enumerate() {
+        *part = match guid.get(&format!("data[{}]", idx))? {
+            Data::UInt32(v) => *v,
+            _ => return None,
+        };
+    }
+    match &**id {
+        Data::SInt64(id) => Some((parts, *id)),
+        _ => None,
+    }
+}
+
+impl SpriteAtlas {
+    pub(crate) fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "SpriteAtlas" => fields,
+            _ => return Err(TypeError::new("SpriteAtlas type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let tag = match fields.get("m_Tag") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Tag type mismatch").into()),
+            None => String::new(),
+        };
+        let packed_sprites = match fields.get("m_PackedSprites") {
+            Some(Data::GenericArray(sprites)) => sprites
+                .iter()
+                .map(|pptr| {
+                    pptr.as_pptr()
+                        .ok_or_else(|| Error::new("m_PackedSprites type mismatch").into())
+                })
+                .collect::<Result<Vec<_>, JsValue>>()?,
+            Some(_) => return Err(Error::new("m_PackedSprites type mismatch").into()),
+            None => return Err(Error::new("m_PackedSprites not found").into()),
+        };
+        let packed_sprite_names = match fields.get("m_PackedSpriteNamesToIndex") {
+            Some(Data::GenericArray(names)) => names
+                .iter()
+                .map(|name| match name {
+                    Data::String(s) => Ok(String::from_utf8_lossy(s).into_owned()),
+                    _ => Err(Error::new("m_PackedSpriteNamesToIndex type mismatch").into()),
+                })
+                .collect::<Result<Vec<_>, JsValue>>()?,
+            Some(_) => return Err(Error::new("m_PackedSpriteNamesToIndex type mismatch").into()),
+            None => return Err(Error::new("m_PackedSpriteNamesToIndex not found").into()),
+        };
+        let render_data_map = match fields.get("m_RenderDataMap") {
+            Some(Data::GenericArray(entries)) => entries
+                .iter()
+                .map(|entry| match entry {
+                    Data::Pair(key, data) => Ok((
+                        render_data_key(key)
+                            .ok_or_else(|| Error::new("m_RenderDataMap type mismatch"))?,
+                        RenderData::from_data(data)?,
+                    )),
+                    _ => Err(Error::new("m_RenderDataMap type mismatch").into()),
+                })
+                .collect::<Result<HashMap<_, _>, JsValue>>()?,
+            Some(_) => return Err(Error::new("m_RenderDataMap type mismatch").into()),
+            None => return Err(Error::new("m_RenderDataMap not found").into()),
+        };
+        Ok(Self {
+            name,
+            tag,
+            packed_sprites,
+            packed_sprite_names,
+            render_data_map,
+        })
+    }
+
+    /// Finds where the given sprite was packed, if it belongs to this atlas.
+    fn render_data(&self, sprite: &Sprite) -> Option<RenderData> {
+        let (_, key) = sprite.atlas?;
+        self.render_data_map.get(&key).copied()
+    }
+}
+
+#[wasm_bindgen]
+impl SpriteAtlas {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tag(&self) -> String {
+        self.tag.clone()
+    }
+
+    /// Path IDs of the packed sprites as `BigInt`s, in the same order as `packedSpriteNames`.
+    #[wasm_bindgen(getter, js_name = packedSprites)]
+    pub fn packed_sprites(&self) -> Array {
+        self.packed_sprites
+            .iter()
+            .map(|(_, path_id)| big_int(path_id))
+            .collect()
+    }
+
+    #[wasm_bindgen(getter, js_name = packedSpriteNames)]
+    pub fn packed_sprite_names(&self) -> Array {
+        self.packed_sprite_names
+            .iter()
+            .map(|name| JsValue::from_str(name))
+            .collect()
+    }
+
+    /// Path ID of the atlas texture holding the given sprite.
+    ///
+    /// Returns `undefined` if the sprite isn't packed into this atlas.
+    #[wasm_bindgen(js_name = spriteTexture)]
+    pub fn sprite_texture(&self, sprite: &Sprite) -> Option<JsValue> {
+        let RenderData {
+            texture: (_, path_id),
+            ..
+        } = self.render_data(sprite)?;
+        Some(big_int(path_id))
+    }
+
+    /// `x, y, width, height` of the given sprite in its atlas texture, measured from the bottom
+    /// left.
+    #[wasm_bindgen(js_name = spriteTextureRect)]
+    pub fn sprite_texture_rect(&self, sprite: &Sprite) -> Option<Float32Array> {
+        let RenderData { texture_rect, .. } = self.render_data(sprite)?;
+        Some(texture_rect.to_array())
+    }
+}
+
+impl Sprite {
+    pub(crate) fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let fields = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "Sprite" => fields,
+            _ => return Err(TypeError::new("Sprite type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let rect = match fields.get("m_Rect") {
+            Some(rect) => Rect::from_data(rect)?,
+            None => return Err(Error::new("m_Rect not found").into()),
+        };
+        let pivot = match fields.get("m_Pivot") {
+            Some(Data::GenericStruct { fields, .. }) => {
+                (get_float(fields, "x")?, get_float(fields, "y")?)
+            }
+            Some(_) => return Err(Error::new("m_Pivot type mismatch").into()),
+            // Sprites before 5.4 are always centered.
+            None => (0.5, 0.5),
+        };
+        let pixels_to_units = get_float(fields, "m_PixelsToUnits")?;
+        let render_data = match fields.get("m_RD") {
+            Some(rd) => RenderData::from_data(rd)?,
+            None => return Err(Error::new("m_RD not found").into()),
+        };
+        let atlas = match (fields.get("m_SpriteAtlas"), fields.get("m_RenderDataKey")) {
+            (Some(atlas), Some(key)) => atlas
+                .as_pptr()
+                .filter(|&(_, path_id)| path_id != 0)
+                .zip(render_data_key(key)),
+            _ => None,
+        };
+        Ok(Self {
+            name,
+            rect,
+            pivot,
+            pixels_to_units,
+            render_data,
+            atlas,
+        })
+    }
+
+    /// Finds the texture and rect of the sprite, looking into its atlas if it has one.
+    fn locate(&self, asset: &unityfs::Asset<'_>) -> Result<RenderData, JsValue> {
+        let (file_id, path_id) = match self.atlas {
+            Some((atlas, _)) => atlas,
+            None => return Ok(self.render_data),
+        };
+        let atlas = match (file_id, asset.object_by_path_id(path_id)) {
+            (0, Some(atlas)) => SpriteAtlas::from_data(&atlas.data)?,
+            _ => return Ok(self.render_data),
+        };
+        Ok(atlas.render_data(self).unwrap_or(self.render_data))
+    }
+}
+
+#[wasm_bindgen]
+impl Sprite {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// `x, y, width, height` of the sprite in pixels, measured from the bottom left.
+    #[wasm_bindgen(getter)]
+    pub fn rect(&self) -> Float32Array {
+        self.rect.to_array()
+    }
+
+    /// Pivot of the sprite relative to its rect, from `0, 0` at the bottom left to `1, 1`.
+    #[wasm_bindgen(getter)]
+    pub fn pivot(&self) -> Float32Array {
+        Float32Array::from(&[self.pivot.0, self.pivot.1][..])
+    }
+
+    #[wasm_bindgen(getter, js_name = pixelsToUnits)]
+    pub fn pixels_to_units(&self) -> f32 {
+        self.pixels_to_units
+    }
+
+    /// Decodes the texture of the sprite and returns the sprite's part of it as PNG.
+    ///
+    /// Returns `undefined` if the texture is in another file or can't be decoded. Sprites packed
+    /// with tight packing come out with their neighbours' pixels around them, as only the
+    /// bounding rect is cut out.
+    pub fn data(&self, fs: &UnityFs) -> Result<Option<Vec<u8>>, JsValue> {
+        let (_, meta) = unityfs::UnityFsMeta::parse(&fs.input)
+            .map_err(|e| Error::new(&format!("parse failed: {}", e)))?;
+        let bundle = meta
+            .read_unityfs()
+            .map_err(|e| Error::new(&format!("read failed: {}", e)))?;
+        let asset = bundle.main_asset();
+
+        let RenderData {
+            texture: (file_id, path_id),
+            texture_rect,
+        } = self.locate(asset)?;
+        let texture = match (file_id, asset.object_by_path_id(path_id)) {
+            (0, Some(texture)) => texture,
+            _ => return Ok(None),
+        };
+        let mut texture = Texture2D::from_data(&texture.data, Some(asset.target_platform()), None)?;
+        texture.try_resolve(fs, None)?;
+        let rgba = match &texture.image_data {
+            ImageData::Loaded { rgba, .. } => rgba,
+            _ => return Ok(None),
+        };
+
+        // Decoded rows are stored top first, while the rect is measured from the bottom.
+        let x = (texture_rect.x.round().max(0.0) as u32).min(texture.width);
+        let y = (texture_rect.y.round().max(0.0) as u32).min(texture.height);
+        let width = (texture_rect.width.round().max(0.0) as u32).min(texture.width - x);
+        let height = (texture_rect.height.round().max(0.0) as u32).min(texture.height - y);
+        if width == 0 || height == 0 {
+            return Texture2D::encode_png(0, 0, &[]).map(Some);
+        }
+        let top = texture.height - y - height;
+        let scanline = texture.width as usize * 4;
+        let mut cropped = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in rgba
+            .chunks(scanline)
+            .skip(top as usize)
+            .take(height as usize)
+        {
+            cropped.extend_from_slice(&row[(x as usize * 4)..((x + width) as usize * 4)]);
+        }
+        Texture2D::encode_png(width, height, &cropped).map(Some)
+    }
+}
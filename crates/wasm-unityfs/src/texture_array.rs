@@ -0,0 +1,212 @@
+use js_sys::{Array, Error, TypeError, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use unityfs::texture::DecodeFormat;
+use unityfs::Data;
+
+use crate::{resolve_stream, StreamingInfo, Texture2D, UnityFs};
+
+/// Faces of a `Cubemap`, slices of a `Texture2DArray` or depth slices of a `Texture3D`, each as
+/// large as the whole texture.
+#[wasm_bindgen]
+pub struct TextureArray {
+    name: String,
+    #[wasm_bindgen(readonly)]
+    pub width: u32,
+    #[wasm_bindgen(readonly)]
+    pub height: u32,
+    layer_count: u32,
+    /// Whether the layers are the depth slices of a `Texture3D`, which share one mip chain.
+    volume: bool,
+    layers: LayerData,
+}
+
+enum LayerData {
+    /// Decoded RGBA8 pixels of every layer, top row first.
+    Loaded(Vec<Vec<u8>>),
+    Streaming(DecodeFormat, StreamingInfo),
+    Unknown,
+}
+
+impl TextureArray {
+    pub(crate) fn from_data(data: &Data<'_>) -> Result<Self, JsValue> {
+        let (fields, is_cubemap, volume) = match data {
+            Data::GenericStruct { type_name, fields } if type_name == "Cubemap" => {
+                (fields, true, false)
+            }
+            Data::GenericStruct { type_name, fields } if type_name == "Texture2DArray" => {
+                (fields, false, false)
+            }
+            Data::GenericStruct { type_name, fields } if type_name == "Texture3D" => {
+                (fields, false, true)
+            }
+            _ => return Err(TypeError::new("TextureArray type mismatch").into()),
+        };
+        let name = match fields.get("m_Name") {
+            Some(Data::String(s)) => String::from_utf8_lossy(s).into_owned(),
+            Some(_) => return Err(Error::new("m_Name type mismatch").into()),
+            None => return Err(Error::new("m_Name not found").into()),
+        };
+        let width = match fields.get("m_Width") {
+            Some(Data::SInt32(width)) => (*width) as u32,
+            Some(_) => return Err(Error::new("m_Width type mismatch").into()),
+            None => return Err(Error::new("m_Width not found").into()),
+        };
+        let height = match fields.get("m_Height") {
+            Some(Data::SInt32(height)) => (*height) as u32,
+            Some(_) => return Err(Error::new("m_Height type mismatch").into()),
+            None => return Err(Error::new("m_Height not found").into()),
+        };
+        let image_data = match fields.get("image data") {
+            Some(Data::UInt8Array(buf)) => buf,
+            Some(_) => return Err(Error::new("image data type mismatch").into()),
+            None => return Err(Error::new("image data not found").into()),
+        };
+        // Cubemaps are textures with six images, while arrays and 3D textures use graphics
+        // formats and have a depth instead.
+        let (count_field, format_field) = if is_cubemap {
+            ("m_ImageCount", "m_TextureFormat")
+        } else {
+            ("m_Depth", "m_Format")
+        };
+        let layer_count = match fields.get(count_field) {
+            Some(Data::SInt32(count)) => (*count).max(0) as u32,
+            Some(_) => return Err(Error::new(&format!("{} type mismatch", count_field)).into()),
+            None => return Err(Error::new(&format!("{} not found", count_field)).into()),
+        };
+        let format = match fields.get(format_field) {
+            Some(Data::SInt32(format)) if is_cubemap => DecodeFormat::from_texture_format(
+                *format,
+                fields.contains_key("m_ForcedFallbackFormat"),
+            ),
+            Some(Data::SInt32(format)) => DecodeFormat::from_graphics_format(*format),
+            Some(_) => return Err(Error::new(&format!("{} type mismatch", format_field)).into()),
+            None => return Err(Error::new(&format!("{} not found", format_field)).into()),
+        };
+        let mut texture = Self {
+            name,
+            width,
+            height,
+            layer_count,
+            volume,
+            layers: LayerData::Unknown,
+        };
+        if let Some(format) = format {
+            let streaming_info = fields
+                .get("m_StreamData")
+                .map(StreamingInfo::from_data)
+                .transpose()?
+                .filter(|info| !info.path.is_empty());
+            texture.layers = match streaming_info {
+                Some(streaming_info) => LayerData::Streaming(format, streaming_info),
+                None => LayerData::Loaded(texture.read(format, image_data)?),
+            };
+        }
+        Ok(texture)
+    }
+
+    /// Decodes every layer, which are stored one after another along with their mips.
+    ///
+    /// The depth slices of a 3D texture are all part of its first mip, so they follow each other
+    /// directly, one full-resolution image apart.
+    fn read(&self, format: DecodeFormat, image_data: &[u8]) -> Result<Vec<Vec<u8>>, JsValue> {
+        if self.layer_count == 0 {
+            return Ok(Vec::new());
+        }
+        let layer_size = match format.level_size(self.width, self.height) {
+            Some(size) if self.volume => size as usize,
+            _ => image_data.len() / self.layer_count as usize,
+        };
+        image_data
+            .chunks(layer_size.max(1))
+            .take(self.layer_count as usize)
+            .map(|layer| Texture2D::read(self.width, self.height, format, layer, None))
+            .collect()
+    }
+
+    fn layer(&self, index: u32) -> Option<&[u8]> {
+        match &self.layers {
+            LayerData::Loaded(layers) => layers.get(index as usize).map(Vec::as_slice),
+            _ => None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl TextureArray {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Number of layers: six faces for a cubemap, in the order +X, -X, +Y, -Y, +Z, -Z, and the
+    /// depth for a 3D texture, front slice first.
+    #[wasm_bindgen(getter, js_name = layerCount)]
+    pub fn layer_count(&self) -> u32 {
+        self.layer_count
+    }
+
+    /// PNG encoding of a single layer, or `undefined` if it isn't decoded.
+    #[wasm_bindgen(js_name = layerPng)]
+    pub fn layer_png(&self, index: u32) -> Result<Option<Vec<u8>>, JsValue> {
+        self.layer(index)
+            .map(|rgba| Texture2D::encode_png(self.width, self.height, rgba))
+            .transpose()
+    }
+
+    /// PNG encoding of every layer in order, or `undefined` if they aren't decoded.
+    #[wasm_bindgen(js_name = layerPngs)]
+    pub fn layer_pngs(&self) -> Result<Option<Array>, JsValue> {
+        let layers = match &self.layers {
+            LayerData::Loaded(layers) => layers,
+            _ => return Ok(None),
+        };
+        layers
+            .iter()
+            .map(|rgba| {
+                Texture2D::encode_png(self.width, self.height, rgba)
+                    .map(|png| JsValue::from(Uint8Array::from(&*png)))
+            })
+            .collect::<Result<Array, JsValue>>()
+            .map(Some)
+    }
+
+    /// PNG encoding of every layer laid side by side from left to right.
+    #[wasm_bindgen(js_name = stripPng)]
+    pub fn strip_png(&self) -> Result<Option<Vec<u8>>, JsValue> {
+        let layers = match &self.layers {
+            LayerData::Loaded(layers) if !layers.is_empty() => layers,
+            _ => return Ok(None),
+        };
+        let scanline = self.width as usize * 4;
+        let mut strip = Vec::with_capacity(scanline * self.height as usize * layers.len());
+        for y in 0..self.height as usize {
+            for layer in layers {
+                strip.extend_from_slice(&layer[(y * scanline)..((y + 1) * scanline)]);
+            }
+        }
+        Texture2D::encode_png(self.width * layers.len() as u32, self.height, &strip).map(Some)
+    }
+
+    #[wasm_bindgen(js_name = assetDependency)]
+    pub fn asset_dependency(&self) -> Option<String> {
+        match &self.layers {
+            LayerData::Streaming(_, StreamingInfo { path, .. }) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    #[wasm_bindgen(js_name = tryResolve)]
+    pub fn try_resolve(&mut self, fs: &UnityFs) -> Result<(), JsValue> {
+        let (format, streaming_info) = match &self.layers {
+            LayerData::Streaming(format, val) => (*format, val),
+            _ => return Ok(()),
+        };
+        let buf = match resolve_stream(fs, streaming_info)? {
+            Some(buf) => buf,
+            None => return Ok(()),
+        };
+        self.layers = LayerData::Loaded(self.read(format, &buf)?);
+        Ok(())
+    }
+}
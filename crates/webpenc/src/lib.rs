@@ -0,0 +1,2037 @@
+//! Encoder for WebP images.
+//!
+//! Colour is stored as a lossy VP8 key frame that only uses whole-macroblock intra prediction.
+//! Alpha, when the image has any, is stored losslessly as a VP8L stream of Huffman-coded values
+//! and run-length references. Both trade some compression for a small encoder.
+
+use std::io;
+
+const MAX_DIMENSION: u32 = 16383;
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Encodes RGBA8 pixels, top row first, as a lossy WebP image.
+///
+/// `quality` ranges from 0 to 100, higher values giving larger and more faithful images.
+pub fn encode_rgba(width: u32, height: u32, rgba: &[u8], quality: f32) -> io::Result<Vec<u8>> {
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(invalid_input("image dimensions out of range"));
+    }
+    if rgba.len() != width as usize * height as usize * 4 {
+        return Err(invalid_input("pixel buffer doesn't match image dimensions"));
+    }
+
+    let mut chunks = Vec::new();
+    if rgba.chunks(4).any(|pixel| pixel[3] != 0xff) {
+        let mut header = vec![0x10, 0, 0, 0];
+        header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        write_chunk(&mut chunks, b"VP8X", &header);
+        write_chunk(&mut chunks, b"ALPH", &encode_alpha(rgba));
+    }
+    let q_index = ((100.0 - quality.clamp(0.0, 100.0)) * 1.27).round() as usize;
+    write_chunk(
+        &mut chunks,
+        b"VP8 ",
+        &encode_vp8(width, height, rgba, q_index),
+    );
+
+    let mut out = Vec::with_capacity(chunks.len() + 12);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&chunks);
+    Ok(out)
+}
+
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+// VP8
+
+type TokenProbs = [[[[u8; 11]; 3]; 8]; 4];
+
+#[rustfmt::skip]
+const COEFF_UPDATE_PROBS: TokenProbs = [
+    [
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [176, 246, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [223, 241, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 244, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [234, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 246, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [239, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 253, 255, 254, 255, 255, 255, 255, 255, 255],
+            [250, 255, 254, 255, 254, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [217, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [225, 252, 241, 253, 255, 255, 254, 255, 255, 255, 255],
+            [234, 250, 241, 250, 253, 255, 253, 254, 255, 255, 255],
+        ],
+        [
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [223, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [238, 253, 254, 254, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [247, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [186, 251, 250, 255, 255, 255, 255, 255, 255, 255, 255],
+            [234, 251, 244, 254, 255, 255, 255, 255, 255, 255, 255],
+            [251, 251, 243, 253, 254, 255, 254, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [236, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 253, 253, 254, 254, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [248, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 254, 252, 254, 255, 255, 255, 255, 255, 255, 255],
+            [248, 254, 249, 253, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [246, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 254, 251, 254, 254, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [248, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 254, 254, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [245, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 251, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 252, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+];
+
+#[rustfmt::skip]
+const COEFF_PROBS: TokenProbs = [
+    [
+        [
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [253, 136, 254, 255, 228, 219, 128, 128, 128, 128, 128],
+            [189, 129, 242, 255, 227, 213, 255, 219, 128, 128, 128],
+            [106, 126, 227, 252, 214, 209, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 98, 248, 255, 236, 226, 255, 255, 128, 128, 128],
+            [181, 133, 238, 254, 221, 234, 255, 154, 128, 128, 128],
+            [78, 134, 202, 247, 198, 180, 255, 219, 128, 128, 128],
+        ],
+        [
+            [1, 185, 249, 255, 243, 255, 128, 128, 128, 128, 128],
+            [184, 150, 247, 255, 236, 224, 128, 128, 128, 128, 128],
+            [77, 110, 216, 255, 236, 230, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 101, 251, 255, 241, 255, 128, 128, 128, 128, 128],
+            [170, 139, 241, 252, 236, 209, 255, 255, 128, 128, 128],
+            [37, 116, 196, 243, 228, 255, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 204, 254, 255, 245, 255, 128, 128, 128, 128, 128],
+            [207, 160, 250, 255, 238, 128, 128, 128, 128, 128, 128],
+            [102, 103, 231, 255, 211, 171, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 152, 252, 255, 240, 255, 128, 128, 128, 128, 128],
+            [177, 135, 243, 255, 234, 225, 128, 128, 128, 128, 128],
+            [80, 129, 211, 255, 194, 224, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [246, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [255, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [198, 35, 237, 223, 193, 187, 162, 160, 145, 155, 62],
+            [131, 45, 198, 221, 172, 176, 220, 157, 252, 221, 1],
+            [68, 47, 146, 208, 149, 167, 221, 162, 255, 223, 128],
+        ],
+        [
+            [1, 149, 241, 255, 221, 224, 255, 255, 128, 128, 128],
+            [184, 141, 234, 253, 222, 220, 255, 199, 128, 128, 128],
+            [81, 99, 181, 242, 176, 190, 249, 202, 255, 255, 128],
+        ],
+        [
+            [1, 129, 232, 253, 214, 197, 242, 196, 255, 255, 128],
+            [99, 121, 210, 250, 201, 198, 255, 202, 128, 128, 128],
+            [23, 91, 163, 242, 170, 187, 247, 210, 255, 255, 128],
+        ],
+        [
+            [1, 200, 246, 255, 234, 255, 128, 128, 128, 128, 128],
+            [109, 178, 241, 255, 231, 245, 255, 255, 128, 128, 128],
+            [44, 130, 201, 253, 205, 192, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 132, 239, 251, 219, 209, 255, 165, 128, 128, 128],
+            [94, 136, 225, 251, 218, 190, 255, 255, 128, 128, 128],
+            [22, 100, 174, 245, 186, 161, 255, 199, 128, 128, 128],
+        ],
+        [
+            [1, 182, 249, 255, 232, 235, 128, 128, 128, 128, 128],
+            [124, 143, 241, 255, 227, 234, 128, 128, 128, 128, 128],
+            [35, 77, 181, 251, 193, 211, 255, 205, 128, 128, 128],
+        ],
+        [
+            [1, 157, 247, 255, 236, 231, 255, 255, 128, 128, 128],
+            [121, 141, 235, 255, 225, 227, 255, 255, 128, 128, 128],
+            [45, 99, 188, 251, 195, 217, 255, 224, 128, 128, 128],
+        ],
+        [
+            [1, 1, 251, 255, 213, 255, 128, 128, 128, 128, 128],
+            [203, 1, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+            [137, 1, 177, 255, 224, 255, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [253, 9, 248, 251, 207, 208, 255, 192, 128, 128, 128],
+            [175, 13, 224, 243, 193, 185, 249, 198, 255, 255, 128],
+            [73, 17, 171, 221, 161, 179, 236, 167, 255, 234, 128],
+        ],
+        [
+            [1, 95, 247, 253, 212, 183, 255, 255, 128, 128, 128],
+            [239, 90, 244, 250, 211, 209, 255, 255, 128, 128, 128],
+            [155, 77, 195, 248, 188, 195, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 24, 239, 251, 218, 219, 255, 205, 128, 128, 128],
+            [201, 51, 219, 255, 196, 186, 128, 128, 128, 128, 128],
+            [69, 46, 190, 239, 201, 218, 255, 228, 128, 128, 128],
+        ],
+        [
+            [1, 191, 251, 255, 255, 128, 128, 128, 128, 128, 128],
+            [223, 165, 249, 255, 213, 255, 128, 128, 128, 128, 128],
+            [141, 124, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 16, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+            [190, 36, 230, 255, 236, 255, 128, 128, 128, 128, 128],
+            [149, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 226, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [247, 192, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [240, 128, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 134, 252, 255, 255, 128, 128, 128, 128, 128, 128],
+            [213, 62, 250, 255, 255, 128, 128, 128, 128, 128, 128],
+            [55, 93, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [202, 24, 213, 235, 186, 191, 220, 160, 240, 175, 255],
+            [126, 38, 182, 232, 169, 184, 228, 174, 255, 187, 128],
+            [61, 46, 138, 219, 151, 178, 240, 170, 255, 216, 128],
+        ],
+        [
+            [1, 112, 230, 250, 199, 191, 247, 159, 255, 255, 128],
+            [166, 109, 228, 252, 211, 215, 255, 174, 128, 128, 128],
+            [39, 77, 162, 232, 172, 180, 245, 178, 255, 255, 128],
+        ],
+        [
+            [1, 52, 220, 246, 198, 199, 249, 220, 255, 255, 128],
+            [124, 74, 191, 243, 183, 193, 250, 221, 255, 255, 128],
+            [24, 71, 130, 219, 154, 170, 243, 182, 255, 255, 128],
+        ],
+        [
+            [1, 182, 225, 249, 219, 240, 255, 224, 128, 128, 128],
+            [149, 150, 226, 252, 216, 205, 255, 171, 128, 128, 128],
+            [28, 108, 170, 242, 183, 194, 254, 223, 255, 255, 128],
+        ],
+        [
+            [1, 81, 230, 252, 204, 203, 255, 192, 128, 128, 128],
+            [123, 102, 209, 247, 188, 196, 255, 233, 128, 128, 128],
+            [20, 95, 153, 243, 164, 173, 255, 203, 128, 128, 128],
+        ],
+        [
+            [1, 222, 248, 255, 216, 213, 128, 128, 128, 128, 128],
+            [168, 175, 246, 252, 235, 205, 255, 255, 128, 128, 128],
+            [47, 116, 215, 255, 211, 212, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 121, 236, 253, 212, 214, 255, 255, 128, 128, 128],
+            [141, 84, 213, 252, 201, 202, 255, 219, 128, 128, 128],
+            [42, 80, 160, 240, 162, 185, 255, 205, 128, 128, 128],
+        ],
+        [
+            [1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [244, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [238, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+];
+
+const DC_PRED: u8 = 0;
+const V_PRED: u8 = 1;
+const H_PRED: u8 = 2;
+const TM_PRED: u8 = 3;
+const B_PRED: u8 = 4;
+
+// Trees list the two branches of every node; positive entries point to the next node and the
+// others are negated leaves.
+const KEYFRAME_YMODE_TREE: [i8; 8] = [
+    -(B_PRED as i8),
+    2,
+    4,
+    6,
+    -(DC_PRED as i8),
+    -(V_PRED as i8),
+    -(H_PRED as i8),
+    -(TM_PRED as i8),
+];
+const KEYFRAME_YMODE_PROBS: [u8; 4] = [145, 156, 163, 128];
+const KEYFRAME_UV_MODE_TREE: [i8; 6] = [
+    -(DC_PRED as i8),
+    2,
+    -(V_PRED as i8),
+    4,
+    -(H_PRED as i8),
+    -(TM_PRED as i8),
+];
+const KEYFRAME_UV_MODE_PROBS: [u8; 3] = [142, 114, 183];
+
+const DCT_EOB: i8 = 11;
+const DCT_CAT1: i8 = 5;
+const DCT_TOKEN_TREE: [i8; 22] = [
+    -DCT_EOB, 2, 0, 4, -1, 6, 8, 12, -2, 10, -3, -4, 14, 16, -5, -6, 18, 20, -7, -8, -9, -10,
+];
+const PROB_DCT_CAT: [&[u8]; 6] = [
+    &[159],
+    &[165, 145],
+    &[173, 148, 140],
+    &[176, 155, 140, 135],
+    &[180, 157, 141, 134, 130],
+    &[254, 254, 243, 230, 196, 177, 153, 140, 133, 130, 129],
+];
+const DCT_CAT_BASE: [u16; 6] = [5, 7, 11, 19, 35, 67];
+const MAX_LEVEL: i32 = 2047;
+const COEFF_BANDS: [usize; 16] = [0, 1, 2, 3, 6, 4, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7];
+const ZIGZAG: [usize; 16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+
+/// Block types selecting a set of token probabilities.
+const PLANE_Y_AFTER_Y2: usize = 0;
+const PLANE_Y2: usize = 1;
+const PLANE_UV: usize = 2;
+
+#[rustfmt::skip]
+const DC_QUANT: [i32; 128] = [
+      4,   5,   6,   7,   8,   9,  10,  10,  11,  12,  13,  14,  15,  16,  17,  17,
+     18,  19,  20,  20,  21,  21,  22,  22,  23,  23,  24,  25,  25,  26,  27,  28,
+     29,  30,  31,  32,  33,  34,  35,  36,  37,  37,  38,  39,  40,  41,  42,  43,
+     44,  45,  46,  46,  47,  48,  49,  50,  51,  52,  53,  54,  55,  56,  57,  58,
+     59,  60,  61,  62,  63,  64,  65,  66,  67,  68,  69,  70,  71,  72,  73,  74,
+     75,  76,  76,  77,  78,  79,  80,  81,  82,  83,  84,  85,  86,  87,  88,  89,
+     91,  93,  95,  96,  98, 100, 101, 102, 104, 106, 108, 110, 112, 114, 116, 118,
+    122, 124, 126, 128, 130, 132, 134, 136, 138, 140, 143, 145, 148, 151, 154, 157,
+];
+
+#[rustfmt::skip]
+const AC_QUANT: [i32; 128] = [
+      4,   5,   6,   7,   8,   9,  10,  11,  12,  13,  14,  15,  16,  17,  18,  19,
+     20,  21,  22,  23,  24,  25,  26,  27,  28,  29,  30,  31,  32,  33,  34,  35,
+     36,  37,  38,  39,  40,  41,  42,  43,  44,  45,  46,  47,  48,  49,  50,  51,
+     52,  53,  54,  55,  56,  57,  58,  60,  62,  64,  66,  68,  70,  72,  74,  76,
+     78,  80,  82,  84,  86,  88,  90,  92,  94,  96,  98, 100, 102, 104, 106, 108,
+    110, 112, 114, 116, 119, 122, 125, 128, 131, 134, 137, 140, 143, 146, 149, 152,
+    155, 158, 161, 164, 167, 170, 173, 177, 181, 185, 189, 193, 197, 201, 205, 209,
+    213, 217, 221, 225, 229, 234, 239, 245, 249, 254, 259, 264, 269, 274, 279, 284,
+];
+
+/// Dequantization factors, derived from the quantizer index as the decoder does.
+struct Quant {
+    y_dc: i32,
+    y_ac: i32,
+    y2_dc: i32,
+    y2_ac: i32,
+    uv_dc: i32,
+    uv_ac: i32,
+}
+
+impl Quant {
+    fn new(index: usize) -> Self {
+        Self {
+            y_dc: DC_QUANT[index],
+            y_ac: AC_QUANT[index],
+            y2_dc: DC_QUANT[index] * 2,
+            y2_ac: std::cmp::max(AC_QUANT[index] * 155 / 100, 8),
+            uv_dc: std::cmp::min(DC_QUANT[index], 132),
+            uv_ac: AC_QUANT[index],
+        }
+    }
+}
+
+/// Quantizes a coefficient, rounding magnitudes down a little to favour zeroes.
+fn quantize(coeff: i32, q: i32, bias: i32) -> i16 {
+    let level = std::cmp::min((coeff.abs() * 8 + q * bias) / (q * 8), MAX_LEVEL);
+    (if coeff < 0 { -level } else { level }) as i16
+}
+
+/// Rounding biases in eighths of a quantizer step.
+const DC_BIAS: i32 = 4;
+const AC_BIAS: i32 = 3;
+
+fn fdct4x4(input: &[i32; 16]) -> [i32; 16] {
+    let mut tmp = [0i32; 16];
+    for i in 0..4 {
+        let ip = &input[(4 * i)..(4 * i + 4)];
+        let a1 = (ip[0] + ip[3]) * 8;
+        let b1 = (ip[1] + ip[2]) * 8;
+        let c1 = (ip[1] - ip[2]) * 8;
+        let d1 = (ip[0] - ip[3]) * 8;
+        tmp[4 * i] = a1 + b1;
+        tmp[4 * i + 2] = a1 - b1;
+        tmp[4 * i + 1] = (c1 * 2217 + d1 * 5352 + 14500) >> 12;
+        tmp[4 * i + 3] = (d1 * 2217 - c1 * 5352 + 7500) >> 12;
+    }
+    let mut out = [0i32; 16];
+    for i in 0..4 {
+        let a1 = tmp[i] + tmp[12 + i];
+        let b1 = tmp[4 + i] + tmp[8 + i];
+        let c1 = tmp[4 + i] - tmp[8 + i];
+        let d1 = tmp[i] - tmp[12 + i];
+        out[i] = (a1 + b1 + 7) >> 4;
+        out[8 + i] = (a1 - b1 + 7) >> 4;
+        out[4 + i] = ((c1 * 2217 + d1 * 5352 + 12000) >> 16) + i32::from(d1 != 0);
+        out[12 + i] = (d1 * 2217 - c1 * 5352 + 51000) >> 16;
+    }
+    out
+}
+
+fn idct4x4(block: &mut [i32; 16]) {
+    const CONST1: i64 = 20091;
+    const CONST2: i64 = 35468;
+    let mut tmp = [0i64; 16];
+    for i in 0..4 {
+        let x = |k: usize| i64::from(block[4 * k + i]);
+        let a1 = x(0) + x(2);
+        let b1 = x(0) - x(2);
+        let c1 = ((x(1) * CONST2) >> 16) - (x(3) + ((x(3) * CONST1) >> 16));
+        let d1 = (x(1) + ((x(1) * CONST1) >> 16)) + ((x(3) * CONST2) >> 16);
+        tmp[i] = a1 + d1;
+        tmp[4 + i] = b1 + c1;
+        tmp[8 + i] = b1 - c1;
+        tmp[12 + i] = a1 - d1;
+    }
+    for i in 0..4 {
+        let x = |k: usize| tmp[4 * i + k];
+        let a1 = x(0) + x(2);
+        let b1 = x(0) - x(2);
+        let c1 = ((x(1) * CONST2) >> 16) - (x(3) + ((x(3) * CONST1) >> 16));
+        let d1 = (x(1) + ((x(1) * CONST1) >> 16)) + ((x(3) * CONST2) >> 16);
+        block[4 * i] = ((a1 + d1 + 4) >> 3) as i32;
+        block[4 * i + 1] = ((b1 + c1 + 4) >> 3) as i32;
+        block[4 * i + 2] = ((b1 - c1 + 4) >> 3) as i32;
+        block[4 * i + 3] = ((a1 - d1 + 4) >> 3) as i32;
+    }
+}
+
+/// One pass of the Walsh-Hadamard butterfly, which is its own inverse up to scale.
+fn wht_1d(x: [i32; 4]) -> [i32; 4] {
+    let a1 = x[0] + x[3];
+    let b1 = x[1] + x[2];
+    let c1 = x[1] - x[2];
+    let d1 = x[0] - x[3];
+    [a1 + b1, c1 + d1, a1 - b1, d1 - c1]
+}
+
+fn wht_2d(block: &[i32; 16]) -> [i32; 16] {
+    let mut tmp = [0i32; 16];
+    for i in 0..4 {
+        let col = wht_1d([block[i], block[4 + i], block[8 + i], block[12 + i]]);
+        for (k, v) in col.iter().enumerate() {
+            tmp[4 * k + i] = *v;
+        }
+    }
+    let mut out = [0i32; 16];
+    for i in 0..4 {
+        let row = wht_1d([tmp[4 * i], tmp[4 * i + 1], tmp[4 * i + 2], tmp[4 * i + 3]]);
+        out[(4 * i)..(4 * i + 4)].copy_from_slice(&row);
+    }
+    out
+}
+
+fn fwht4x4(block: &[i32; 16]) -> [i32; 16] {
+    let mut out = wht_2d(block);
+    for v in out.iter_mut() {
+        *v = (*v + v.signum()) / 2;
+    }
+    out
+}
+
+fn iwht4x4(block: &[i32; 16]) -> [i32; 16] {
+    let mut out = wht_2d(block);
+    for v in out.iter_mut() {
+        *v = (*v + 3) >> 3;
+    }
+    out
+}
+
+/// A plane padded to whole macroblocks.
+struct Plane {
+    data: Vec<u8>,
+    stride: usize,
+}
+
+impl Plane {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            data: vec![0; width * height],
+            stride: width,
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> u8 {
+        self.data[y * self.stride + x]
+    }
+}
+
+/// Converts to BT.601 YCbCr with 4:2:0 subsampling, repeating edge pixels into the padding.
+fn to_yuv(width: u32, height: u32, rgba: &[u8], mb_width: usize, mb_height: usize) -> [Plane; 3] {
+    let (width, height) = (width as usize, height as usize);
+    let pixel = |x: usize, y: usize| {
+        let offset = (std::cmp::min(y, height - 1) * width + std::cmp::min(x, width - 1)) * 4;
+        [
+            i32::from(rgba[offset]),
+            i32::from(rgba[offset + 1]),
+            i32::from(rgba[offset + 2]),
+        ]
+    };
+    let mut y_plane = Plane::new(mb_width * 16, mb_height * 16);
+    for y in 0..(mb_height * 16) {
+        for x in 0..(mb_width * 16) {
+            let [r, g, b] = pixel(x, y);
+            let luma = (16839 * r + 33059 * g + 6420 * b + (16 << 16) + (1 << 15)) >> 16;
+            y_plane.data[y * y_plane.stride + x] = luma.clamp(0, 255) as u8;
+        }
+    }
+    let mut u_plane = Plane::new(mb_width * 8, mb_height * 8);
+    let mut v_plane = Plane::new(mb_width * 8, mb_height * 8);
+    for y in 0..(mb_height * 8) {
+        for x in 0..(mb_width * 8) {
+            let mut sum = [0i32; 3];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let p = pixel(2 * x + dx, 2 * y + dy);
+                for (s, v) in sum.iter_mut().zip(p) {
+                    *s += v;
+                }
+            }
+            let [r, g, b] = sum;
+            let u = (-9719 * r - 19081 * g + 28800 * b + (128 << 18) + (1 << 17)) >> 18;
+            let v = (28800 * r - 24116 * g - 4684 * b + (128 << 18) + (1 << 17)) >> 18;
+            u_plane.data[y * u_plane.stride + x] = u.clamp(0, 255) as u8;
+            v_plane.data[y * v_plane.stride + x] = v.clamp(0, 255) as u8;
+        }
+    }
+    [y_plane, u_plane, v_plane]
+}
+
+/// Predicts a `size`-square block at macroblock `(mbx, mby)` from reconstructed pixels, with the
+/// fixed borders the decoder assumes outside the frame.
+fn predict(mode: u8, plane: &Plane, size: usize, mbx: usize, mby: usize) -> [u8; 256] {
+    let (x0, y0) = (mbx * size, mby * size);
+    let above = |i: usize| {
+        if mby == 0 {
+            127
+        } else {
+            plane.at(x0 + i, y0 - 1)
+        }
+    };
+    let left = |j: usize| {
+        if mbx == 0 {
+            129
+        } else {
+            plane.at(x0 - 1, y0 + j)
+        }
+    };
+    let corner = if mby == 0 {
+        127
+    } else if mbx == 0 {
+        129
+    } else {
+        plane.at(x0 - 1, y0 - 1)
+    };
+
+    let mut out = [0u8; 256];
+    match mode {
+        DC_PRED => {
+            let mut sum = 0u32;
+            let mut shift = if size == 8 { 2 } else { 3 };
+            if mbx > 0 {
+                sum += (0..size).map(|j| u32::from(left(j))).sum::<u32>();
+                shift += 1;
+            }
+            if mby > 0 {
+                sum += (0..size).map(|i| u32::from(above(i))).sum::<u32>();
+                shift += 1;
+            }
+            let dc = if mbx == 0 && mby == 0 {
+                128
+            } else {
+                ((sum + (1 << (shift - 1))) >> shift) as u8
+            };
+            out[..(size * size)].iter_mut().for_each(|p| *p = dc);
+        }
+        _ => {
+            for j in 0..size {
+                for i in 0..size {
+                    out[j * size + i] = match mode {
+                        V_PRED => above(i),
+                        H_PRED => left(j),
+                        _ => (i32::from(left(j)) + i32::from(above(i)) - i32::from(corner))
+                            .clamp(0, 255) as u8,
+                    };
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Coefficient levels of a macroblock: 16 luma blocks, 4 U, 4 V and the Y2 block.
+struct Macroblock {
+    y_mode: u8,
+    uv_mode: u8,
+    levels: [[i16; 16]; 25],
+}
+
+impl Macroblock {
+    fn is_empty(&self) -> bool {
+        self.levels
+            .iter()
+            .all(|block| block.iter().all(|&v| v == 0))
+    }
+}
+
+/// Picks the mode whose prediction is closest to the source, considering only modes that don't
+/// look outside the frame.
+fn choose_mode(source: &[&Plane], recon: &[&Plane], size: usize, mbx: usize, mby: usize) -> u8 {
+    let mut modes = vec![DC_PRED];
+    if mby > 0 {
+        modes.push(V_PRED);
+    }
+    if mbx > 0 {
+        modes.push(H_PRED);
+    }
+    if mbx > 0 && mby > 0 {
+        modes.push(TM_PRED);
+    }
+    let error = |mode: u8| -> u64 {
+        let mut error = 0;
+        for (source, recon) in source.iter().zip(recon) {
+            let pred = predict(mode, recon, size, mbx, mby);
+            for j in 0..size {
+                for i in 0..size {
+                    let diff = i64::from(source.at(mbx * size + i, mby * size + j))
+                        - i64::from(pred[j * size + i]);
+                    error += (diff * diff) as u64;
+                }
+            }
+        }
+        error
+    };
+    modes.into_iter().min_by_key(|&mode| error(mode)).unwrap()
+}
+
+/// Transforms and quantizes one plane of a macroblock, writing the reconstruction back.
+///
+/// Luma DC coefficients are collected into the Y2 block instead of being coded in place.
+#[allow(clippy::too_many_arguments)]
+fn code_plane(
+    source: &Plane,
+    recon: &mut Plane,
+    pred: &[u8; 256],
+    size: usize,
+    mbx: usize,
+    mby: usize,
+    quant: (i32, i32),
+    levels: &mut [[i16; 16]],
+    y2: Option<(&mut [i16; 16], (i32, i32))>,
+) {
+    let blocks_per_row = size / 4;
+    let mut coeffs = Vec::with_capacity(blocks_per_row * blocks_per_row);
+    for b in 0..(blocks_per_row * blocks_per_row) {
+        let (bx, by) = (b % blocks_per_row * 4, b / blocks_per_row * 4);
+        let mut residual = [0i32; 16];
+        for j in 0..4 {
+            for i in 0..4 {
+                residual[j * 4 + i] =
+                    i32::from(source.at(mbx * size + bx + i, mby * size + by + j))
+                        - i32::from(pred[(by + j) * size + bx + i]);
+            }
+        }
+        coeffs.push(fdct4x4(&residual));
+    }
+
+    let (dc_q, ac_q) = quant;
+    let mut dcs = [0i32; 16];
+    match y2 {
+        Some((y2_levels, (y2_dc_q, y2_ac_q))) => {
+            let mut y2_coeffs = [0i32; 16];
+            for (c, coeff) in y2_coeffs.iter_mut().zip(&coeffs) {
+                *c = coeff[0];
+            }
+            let y2_coeffs = fwht4x4(&y2_coeffs);
+            let mut dequant = [0i32; 16];
+            for k in 0..16 {
+                let q = if k == 0 { y2_dc_q } else { y2_ac_q };
+                y2_levels[k] = quantize(y2_coeffs[k], q, if k == 0 { DC_BIAS } else { AC_BIAS });
+                dequant[k] = i32::from(y2_levels[k]) * q;
+            }
+            dcs = iwht4x4(&dequant);
+        }
+        None => {
+            for ((dc, coeff), levels) in dcs.iter_mut().zip(&coeffs).zip(levels.iter_mut()) {
+                levels[0] = quantize(coeff[0], dc_q, DC_BIAS);
+                *dc = i32::from(levels[0]) * dc_q;
+            }
+        }
+    }
+
+    for (b, (coeff, levels)) in coeffs.iter().zip(levels.iter_mut()).enumerate() {
+        let mut block = [0i32; 16];
+        block[0] = dcs[b];
+        for k in 1..16 {
+            levels[k] = quantize(coeff[k], ac_q, AC_BIAS);
+            block[k] = i32::from(levels[k]) * ac_q;
+        }
+        idct4x4(&mut block);
+        let (bx, by) = (b % blocks_per_row * 4, b / blocks_per_row * 4);
+        for j in 0..4 {
+            for i in 0..4 {
+                let (x, y) = (mbx * size + bx + i, mby * size + by + j);
+                let value = i32::from(pred[(by + j) * size + bx + i]) + block[j * 4 + i];
+                recon.data[y * recon.stride + x] = value.clamp(0, 255) as u8;
+            }
+        }
+    }
+}
+
+fn encode_vp8(width: u32, height: u32, rgba: &[u8], q_index: usize) -> Vec<u8> {
+    let mb_width = width.div_ceil(16) as usize;
+    let mb_height = height.div_ceil(16) as usize;
+    let [src_y, src_u, src_v] = to_yuv(width, height, rgba, mb_width, mb_height);
+    let mut rec_y = Plane::new(src_y.stride, mb_height * 16);
+    let mut rec_u = Plane::new(src_u.stride, mb_height * 8);
+    let mut rec_v = Plane::new(src_v.stride, mb_height * 8);
+    let quant = Quant::new(q_index);
+
+    let mut macroblocks = Vec::with_capacity(mb_width * mb_height);
+    for mby in 0..mb_height {
+        for mbx in 0..mb_width {
+            let mut mb = Macroblock {
+                y_mode: choose_mode(&[&src_y], &[&rec_y], 16, mbx, mby),
+                uv_mode: choose_mode(&[&src_u, &src_v], &[&rec_u, &rec_v], 8, mbx, mby),
+                levels: [[0; 16]; 25],
+            };
+            let (y_levels, rest) = mb.levels.split_at_mut(16);
+            let (u_levels, rest) = rest.split_at_mut(4);
+            let (v_levels, y2_levels) = rest.split_at_mut(4);
+
+            let pred = predict(mb.y_mode, &rec_y, 16, mbx, mby);
+            let y2 = Some((&mut y2_levels[0], (quant.y2_dc, quant.y2_ac)));
+            let y_quant = (quant.y_dc, quant.y_ac);
+            code_plane(
+                &src_y, &mut rec_y, &pred, 16, mbx, mby, y_quant, y_levels, y2,
+            );
+            let uv_quant = (quant.uv_dc, quant.uv_ac);
+            let pred = predict(mb.uv_mode, &rec_u, 8, mbx, mby);
+            code_plane(
+                &src_u, &mut rec_u, &pred, 8, mbx, mby, uv_quant, u_levels, None,
+            );
+            let pred = predict(mb.uv_mode, &rec_v, 8, mbx, mby);
+            code_plane(
+                &src_v, &mut rec_v, &pred, 8, mbx, mby, uv_quant, v_levels, None,
+            );
+            macroblocks.push(mb);
+        }
+    }
+
+    // Gather token statistics first so that the probabilities can be tuned to the image.
+    let mut counter = TokenCounter {
+        counts: [[[[[0; 2]; 11]; 3]; 8]; 4],
+    };
+    write_tokens(&mut counter, &macroblocks, mb_width);
+    let skipped = macroblocks.iter().filter(|mb| mb.is_empty()).count();
+    let skip_prob = prob_of_zero((macroblocks.len() - skipped) as u64, skipped as u64);
+
+    let mut header = BoolEncoder::new();
+    header.put_literal(0, 1); // colour space
+    header.put_literal(0, 1); // clamping type
+    header.put_flag(false); // segmentation
+    header.put_literal(0, 1); // filter type
+    header.put_literal(0, 6); // loop filter level
+    header.put_literal(0, 3); // sharpness
+    header.put_flag(false); // loop filter adjustments
+    header.put_literal(0, 2); // one token partition
+    header.put_literal(q_index as u32, 7);
+    for _ in 0..5 {
+        header.put_flag(false); // quantizer deltas
+    }
+    header.put_flag(false); // refresh entropy probabilities
+    let mut probs = COEFF_PROBS;
+    for (plane, plane_probs) in probs.iter_mut().enumerate() {
+        for (band, band_probs) in plane_probs.iter_mut().enumerate() {
+            for (ctx, ctx_probs) in band_probs.iter_mut().enumerate() {
+                for (node, prob) in ctx_probs.iter_mut().enumerate() {
+                    let update_prob = COEFF_UPDATE_PROBS[plane][band][ctx][node];
+                    let [zeros, ones] = counter.counts[plane][band][ctx][node];
+                    let new_prob = prob_of_zero(zeros, ones);
+                    let saving = bit_cost(*prob, zeros, ones)
+                        - bit_cost(new_prob, zeros, ones)
+                        - bit_cost(update_prob, 0, 1)
+                        - 8.0
+                        + bit_cost(update_prob, 1, 0);
+                    if saving > 0.0 {
+                        header.put(update_prob, true);
+                        header.put_literal(new_prob.into(), 8);
+                        *prob = new_prob;
+                    } else {
+                        header.put(update_prob, false);
+                    }
+                }
+            }
+        }
+    }
+    header.put_flag(true); // per-macroblock skip flags
+    header.put_literal(skip_prob.into(), 8);
+    for mb in &macroblocks {
+        header.put(skip_prob, mb.is_empty());
+        header.put_tree(&KEYFRAME_YMODE_TREE, &KEYFRAME_YMODE_PROBS, mb.y_mode);
+        header.put_tree(&KEYFRAME_UV_MODE_TREE, &KEYFRAME_UV_MODE_PROBS, mb.uv_mode);
+    }
+    let header = header.finish();
+
+    let mut tokens = TokenWriter {
+        encoder: BoolEncoder::new(),
+        probs,
+    };
+    write_tokens(&mut tokens, &macroblocks, mb_width);
+    let tokens = tokens.encoder.finish();
+
+    let mut out = Vec::with_capacity(10 + header.len() + tokens.len());
+    // Key frame, version 0, shown.
+    let tag = (1 << 4) | ((header.len() as u32) << 5);
+    out.extend_from_slice(&tag.to_le_bytes()[..3]);
+    out.extend_from_slice(&[0x9d, 0x01, 0x2a]);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&tokens);
+    out
+}
+
+/// Probability of a zero bit, out of 256, that fits the given bit counts best.
+fn prob_of_zero(zeros: u64, ones: u64) -> u8 {
+    match zeros + ones {
+        0 => 128,
+        total => ((zeros * 256 + total / 2) / total).clamp(1, 255) as u8,
+    }
+}
+
+/// Size in bits of coding the given bits with `prob` as the probability of zero.
+fn bit_cost(prob: u8, zeros: u64, ones: u64) -> f64 {
+    let p = f64::from(prob) / 256.0;
+    -(zeros as f64) * p.log2() - (ones as f64) * (1.0 - p).log2()
+}
+
+/// Receives the coefficient tokens of the frame.
+trait TokenSink {
+    /// A branch of the token tree, taken with the probability at the given position.
+    fn branch(&mut self, plane: usize, band: usize, ctx: usize, node: usize, bit: bool);
+
+    /// A bit with a fixed probability.
+    fn put(&mut self, prob: u8, bit: bool);
+}
+
+struct TokenCounter {
+    counts: [[[[[u64; 2]; 11]; 3]; 8]; 4],
+}
+
+impl TokenSink for TokenCounter {
+    fn branch(&mut self, plane: usize, band: usize, ctx: usize, node: usize, bit: bool) {
+        self.counts[plane][band][ctx][node][usize::from(bit)] += 1;
+    }
+
+    fn put(&mut self, _prob: u8, _bit: bool) {}
+}
+
+struct TokenWriter {
+    encoder: BoolEncoder,
+    probs: TokenProbs,
+}
+
+impl TokenSink for TokenWriter {
+    fn branch(&mut self, plane: usize, band: usize, ctx: usize, node: usize, bit: bool) {
+        self.encoder.put(self.probs[plane][band][ctx][node], bit);
+    }
+
+    fn put(&mut self, prob: u8, bit: bool) {
+        self.encoder.put(prob, bit);
+    }
+}
+
+/// Finds the branches leading from `start` to `leaf`, as `(tree index, bit)` pairs.
+fn tree_path(tree: &[i8], start: usize, leaf: i8, path: &mut Vec<(usize, bool)>) -> bool {
+    for bit in [false, true] {
+        let index = start + usize::from(bit);
+        path.push((index, bit));
+        let next = tree[index];
+        let found = if next > 0 {
+            tree_path(tree, next as usize, leaf, path)
+        } else {
+            -next == leaf
+        };
+        if found {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+/// Writes the tokens of every macroblock that has coefficients, tracking which neighbouring
+/// blocks have coefficients the same way the decoder does.
+fn write_tokens<S: TokenSink>(sink: &mut S, macroblocks: &[Macroblock], mb_width: usize) {
+    // Per column: Y2, four luma columns, two U columns and two V columns.
+    let mut above = vec![[false; 9]; mb_width];
+    let mut left = [false; 9];
+    for (idx, mb) in macroblocks.iter().enumerate() {
+        let mbx = idx % mb_width;
+        if mbx == 0 {
+            left = [false; 9];
+        }
+        let above = &mut above[mbx];
+        if mb.is_empty() {
+            *above = [false; 9];
+            left = [false; 9];
+            continue;
+        }
+
+        let ctx = usize::from(above[0]) + usize::from(left[0]);
+        let has_coeffs = write_block(sink, PLANE_Y2, ctx, &mb.levels[24], 0);
+        above[0] = has_coeffs;
+        left[0] = has_coeffs;
+        for y in 0..4 {
+            for x in 0..4 {
+                let ctx = usize::from(above[1 + x]) + usize::from(left[1 + y]);
+                let levels = &mb.levels[y * 4 + x];
+                let has_coeffs = write_block(sink, PLANE_Y_AFTER_Y2, ctx, levels, 1);
+                above[1 + x] = has_coeffs;
+                left[1 + y] = has_coeffs;
+            }
+        }
+        for (plane_offset, block_offset) in [(5, 16), (7, 20)] {
+            for y in 0..2 {
+                for x in 0..2 {
+                    let ctx =
+                        usize::from(above[plane_offset + x]) + usize::from(left[plane_offset + y]);
+                    let levels = &mb.levels[block_offset + y * 2 + x];
+                    let has_coeffs = write_block(sink, PLANE_UV, ctx, levels, 0);
+                    above[plane_offset + x] = has_coeffs;
+                    left[plane_offset + y] = has_coeffs;
+                }
+            }
+        }
+    }
+}
+
+/// Writes the tokens of a block starting at scan position `first`, returning whether it had any
+/// coefficients.
+fn write_block<S: TokenSink>(
+    sink: &mut S,
+    plane: usize,
+    mut ctx: usize,
+    levels: &[i16; 16],
+    first: usize,
+) -> bool {
+    let last = (first..16).rev().find(|&i| levels[ZIGZAG[i]] != 0);
+    let last = match last {
+        Some(last) => last,
+        None => {
+            sink.branch(plane, COEFF_BANDS[first], ctx, 0, false);
+            return false;
+        }
+    };
+    let mut after_zero = false;
+    let mut path = Vec::new();
+    for i in first..16 {
+        let band = COEFF_BANDS[i];
+        if i > last {
+            sink.branch(plane, band, ctx, 0, false);
+            break;
+        }
+        let level = levels[ZIGZAG[i]];
+        let abs = level.unsigned_abs();
+        let token = match abs {
+            0..=4 => abs as i8,
+            _ => {
+                DCT_CAT1
+                    + DCT_CAT_BASE[1..]
+                        .iter()
+                        .filter(|&&base| abs >= base)
+                        .count() as i8
+            }
+        };
+        path.clear();
+        tree_path(
+            &DCT_TOKEN_TREE,
+            if after_zero { 2 } else { 0 },
+            token,
+            &mut path,
+        );
+        for &(index, bit) in &path {
+            sink.branch(plane, band, ctx, index >> 1, bit);
+        }
+        if abs == 0 {
+            after_zero = true;
+            ctx = 0;
+            continue;
+        }
+        if token >= DCT_CAT1 {
+            let cat = (token - DCT_CAT1) as usize;
+            let extra = abs - DCT_CAT_BASE[cat];
+            let probs = PROB_DCT_CAT[cat];
+            for (j, &prob) in probs.iter().enumerate() {
+                sink.put(prob, (extra >> (probs.len() - 1 - j)) & 1 != 0);
+            }
+        }
+        sink.put(128, level < 0);
+        after_zero = false;
+        ctx = if abs == 1 { 1 } else { 2 };
+    }
+    true
+}
+
+/// Boolean entropy coder of RFC 6386 section 7.
+struct BoolEncoder {
+    output: Vec<u8>,
+    range: u32,
+    bottom: u32,
+    bit_count: u32,
+}
+
+impl BoolEncoder {
+    fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            range: 255,
+            bottom: 0,
+            bit_count: 24,
+        }
+    }
+
+    fn add_one_to_output(&mut self) {
+        for byte in self.output.iter_mut().rev() {
+            if *byte == 255 {
+                *byte = 0;
+            } else {
+                *byte += 1;
+                break;
+            }
+        }
+    }
+
+    fn put(&mut self, prob: u8, bit: bool) {
+        let split = 1 + (((self.range - 1) * u32::from(prob)) >> 8);
+        if bit {
+            self.bottom = self.bottom.wrapping_add(split);
+            self.range -= split;
+        } else {
+            self.range = split;
+        }
+        while self.range < 128 {
+            self.range <<= 1;
+            if self.bottom & (1 << 31) != 0 {
+                self.add_one_to_output();
+            }
+            self.bottom <<= 1;
+            self.bit_count -= 1;
+            if self.bit_count == 0 {
+                self.output.push((self.bottom >> 24) as u8);
+                self.bottom &= (1 << 24) - 1;
+                self.bit_count = 8;
+            }
+        }
+    }
+
+    fn put_flag(&mut self, bit: bool) {
+        self.put(128, bit);
+    }
+
+    fn put_literal(&mut self, value: u32, bits: u32) {
+        for shift in (0..bits).rev() {
+            self.put(128, (value >> shift) & 1 != 0);
+        }
+    }
+
+    fn put_tree(&mut self, tree: &[i8], probs: &[u8], leaf: u8) {
+        let mut path = Vec::new();
+        tree_path(tree, 0, leaf as i8, &mut path);
+        for (index, bit) in path {
+            self.put(probs[index >> 1], bit);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let mut c = self.bit_count;
+        let mut v = self.bottom;
+        if v & (1 << (32 - c)) != 0 {
+            self.add_one_to_output();
+        }
+        v <<= c & 7;
+        c >>= 3;
+        for _ in 0..c {
+            v <<= 8;
+        }
+        for _ in 0..4 {
+            self.output.push((v >> 24) as u8);
+            v <<= 8;
+        }
+        self.output
+    }
+}
+
+// VP8L, used for the alpha plane
+
+/// Writes bits least significant first, as VP8L reads them.
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    used: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            output: Vec::new(),
+            buffer: 0,
+            used: 0,
+        }
+    }
+
+    fn put(&mut self, value: u32, bits: u32) {
+        self.buffer |= u64::from(value) << self.used;
+        self.used += bits;
+        while self.used >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.used -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is read one bit at a time from its most significant bit.
+    fn put_code(&mut self, code: (u32, u32)) {
+        let (bits, len) = code;
+        self.put(bits.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.used > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+/// Builds Huffman code lengths no longer than `max_len`, giving every used symbol a code.
+///
+/// At least two symbols always get a code, as decoders treat single-symbol codes specially.
+fn code_lengths(counts: &[u64], max_len: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    let used = counts.iter().filter(|&&c| c > 0).count();
+    for count in counts
+        .iter_mut()
+        .filter(|c| **c == 0)
+        .take(2usize.saturating_sub(used))
+    {
+        *count = 1;
+    }
+    loop {
+        let lengths = huffman_lengths(&counts);
+        if lengths.iter().all(|&len| len <= max_len) {
+            return lengths;
+        }
+        // Flatten the distribution until the tree is shallow enough.
+        for count in counts.iter_mut().filter(|c| **c > 0) {
+            *count = count.div_ceil(2);
+        }
+    }
+}
+
+fn huffman_lengths(counts: &[u64]) -> Vec<u8> {
+    // Nodes are (count, children); leaves come first, in symbol order.
+    let mut nodes = counts
+        .iter()
+        .map(|&count| (count, None))
+        .collect::<Vec<(u64, Option<(usize, usize)>)>>();
+    let mut queue = (0..counts.len())
+        .filter(|&i| counts[i] > 0)
+        .map(|i| std::cmp::Reverse((counts[i], i)))
+        .collect::<std::collections::BinaryHeap<_>>();
+    while queue.len() > 1 {
+        let std::cmp::Reverse((a_count, a)) = queue.pop().unwrap();
+        let std::cmp::Reverse((b_count, b)) = queue.pop().unwrap();
+        nodes.push((a_count + b_count, Some((a, b))));
+        queue.push(std::cmp::Reverse((a_count + b_count, nodes.len() - 1)));
+    }
+    let mut lengths = vec![0u8; counts.len()];
+    let mut stack = queue
+        .into_iter()
+        .map(|std::cmp::Reverse((_, root))| (root, 0u8))
+        .collect::<Vec<_>>();
+    while let Some((node, depth)) = stack.pop() {
+        match nodes[node].1 {
+            Some((a, b)) => {
+                stack.push((a, depth + 1));
+                stack.push((b, depth + 1));
+            }
+            None => lengths[node] = depth,
+        }
+    }
+    lengths
+}
+
+/// Assigns canonical codes to the given lengths, as `(code, length)` pairs.
+fn canonical_codes(lengths: &[u8]) -> Vec<(u32, u32)> {
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut count_per_len = vec![0u32; max_len + 1];
+    for &len in lengths.iter().filter(|&&len| len > 0) {
+        count_per_len[len as usize] += 1;
+    }
+    let mut next_code = vec![0u32; max_len + 1];
+    let mut code = 0;
+    for len in 1..=max_len {
+        code = (code + count_per_len[len - 1]) << 1;
+        next_code[len] = code;
+    }
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return (0, 0);
+            }
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            (code, u32::from(len))
+        })
+        .collect()
+}
+
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Writes a prefix code given by its code lengths, run-length coding runs of zeroes.
+fn write_code_lengths(writer: &mut BitWriter, lengths: &[u8]) {
+    // Symbols of the code-length code, with their extra bits.
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let run = lengths[i..]
+            .iter()
+            .take(138)
+            .take_while(|&&len| len == 0)
+            .count();
+        if run >= 11 {
+            symbols.push((18, (run - 11) as u32, 7));
+            i += run;
+        } else if run >= 3 {
+            symbols.push((17, (run - 3) as u32, 3));
+            i += run;
+        } else {
+            symbols.push((usize::from(lengths[i]), 0, 0));
+            i += 1;
+        }
+    }
+    let mut counts = [0u64; 19];
+    for &(symbol, _, _) in &symbols {
+        counts[symbol] += 1;
+    }
+    let code_length_lengths = code_lengths(&counts, 7);
+    let codes = canonical_codes(&code_length_lengths);
+
+    writer.put(0, 1); // normal code
+    let num_codes = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|&symbol| code_length_lengths[symbol] != 0)
+        .map_or(0, |pos| pos + 1)
+        .max(4);
+    writer.put((num_codes - 4) as u32, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..num_codes] {
+        writer.put(u32::from(code_length_lengths[symbol]), 3);
+    }
+    writer.put(0, 1); // codes cover the whole alphabet
+    for (symbol, extra, extra_bits) in symbols {
+        writer.put_code(codes[symbol]);
+        writer.put(extra, extra_bits);
+    }
+}
+
+/// Writes a prefix code with a single symbol, which takes no bits to code.
+fn write_single_symbol_code(writer: &mut BitWriter, symbol: u32) {
+    writer.put(1, 1); // simple code
+    writer.put(0, 1); // one symbol
+    if symbol < 2 {
+        writer.put(0, 1);
+        writer.put(symbol, 1);
+    } else {
+        writer.put(1, 1);
+        writer.put(symbol, 8);
+    }
+}
+
+/// Splits a length or distance into a prefix symbol and extra bits.
+fn prefix_encode(value: u32) -> (u32, u32, u32) {
+    let d = value - 1;
+    if d < 2 {
+        return (d, 0, 0);
+    }
+    let highest_bit = 31 - d.leading_zeros();
+    let second_bit = (d >> (highest_bit - 1)) & 1;
+    let extra_bits = highest_bit - 1;
+    (
+        2 * highest_bit + second_bit,
+        d & ((1 << extra_bits) - 1),
+        extra_bits,
+    )
+}
+
+const MAX_RUN: usize = 4096;
+const MIN_RUN: usize = 3;
+/// Distance codes above 120 are plain distances offset by 120.
+const PREVIOUS_PIXEL_DISTANCE_CODE: u32 = 121;
+
+/// Encodes the alpha channel as an `ALPH` chunk holding a headerless VP8L image.
+fn encode_alpha(rgba: &[u8]) -> Vec<u8> {
+    let alpha = rgba.chunks(4).map(|pixel| pixel[3]).collect::<Vec<_>>();
+
+    // Literal alphas, or runs repeating the previous pixel.
+    enum Symbol {
+        Literal(u8),
+        Run(usize),
+    }
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < alpha.len() {
+        let run = if i > 0 {
+            alpha[i..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&a| a == alpha[i - 1])
+                .count()
+        } else {
+            0
+        };
+        if run >= MIN_RUN {
+            symbols.push(Symbol::Run(run));
+            i += run;
+        } else {
+            symbols.push(Symbol::Literal(alpha[i]));
+            i += 1;
+        }
+    }
+
+    let mut green_counts = vec![0u64; 256 + 24];
+    for symbol in &symbols {
+        match *symbol {
+            Symbol::Literal(a) => green_counts[usize::from(a)] += 1,
+            Symbol::Run(len) => green_counts[256 + prefix_encode(len as u32).0 as usize] += 1,
+        }
+    }
+    let green_lengths = code_lengths(&green_counts, 15);
+    let green_codes = canonical_codes(&green_lengths);
+    let (distance_symbol, distance_extra, distance_extra_bits) =
+        prefix_encode(PREVIOUS_PIXEL_DISTANCE_CODE);
+
+    let mut writer = BitWriter::new();
+    writer.put(0, 1); // no transforms
+    writer.put(0, 1); // no colour cache
+    writer.put(0, 1); // no meta prefix codes
+    write_code_lengths(&mut writer, &green_lengths);
+    for _ in 0..3 {
+        write_single_symbol_code(&mut writer, 0); // red, blue and alpha
+    }
+    write_single_symbol_code(&mut writer, distance_symbol);
+    for symbol in symbols {
+        match symbol {
+            Symbol::Literal(a) => writer.put_code(green_codes[usize::from(a)]),
+            Symbol::Run(len) => {
+                let (prefix, extra, extra_bits) = prefix_encode(len as u32);
+                writer.put_code(green_codes[256 + prefix as usize]);
+                writer.put(extra, extra_bits);
+                writer.put(distance_extra, distance_extra_bits);
+            }
+        }
+    }
+
+    // Lossless compression, no filtering and no preprocessing.
+    let mut out = vec![1];
+    out.extend(writer.finish());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a WebP file into its chunks, checking the RIFF header and chunk sizes.
+    fn chunks(webp: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(&webp[..4], b"RIFF");
+        assert_eq!(&webp[8..12], b"WEBP");
+        let riff_size = u32::from_le_bytes([webp[4], webp[5], webp[6], webp[7]]);
+        assert_eq!(riff_size as usize, webp.len() - 8);
+
+        let mut chunks = Vec::new();
+        let mut rest = &webp[12..];
+        while !rest.is_empty() {
+            let fourcc = [rest[0], rest[1], rest[2], rest[3]];
+            let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            let padded = size + size % 2;
+            assert!(8 + padded <= rest.len(), "chunk overruns the file");
+            if size % 2 == 1 {
+                assert_eq!(rest[8 + size], 0);
+            }
+            chunks.push((fourcc, &rest[8..8 + size]));
+            rest = &rest[8 + padded..];
+        }
+        chunks
+    }
+
+    /// Boolean entropy decoder of RFC 6386 section 7.
+    struct BoolDecoder<'a> {
+        data: &'a [u8],
+        position: usize,
+        value: u32,
+        range: u32,
+        bit_count: u32,
+    }
+
+    impl<'a> BoolDecoder<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            Self {
+                data,
+                position: 2,
+                value: u32::from(data[0]) << 8 | u32::from(data[1]),
+                range: 255,
+                bit_count: 0,
+            }
+        }
+
+        fn get(&mut self, prob: u8) -> bool {
+            let split = 1 + (((self.range - 1) * u32::from(prob)) >> 8);
+            let bit = self.value >= split << 8;
+            if bit {
+                self.range -= split;
+                self.value -= split << 8;
+            } else {
+                self.range = split;
+            }
+            while self.range < 128 {
+                self.value <<= 1;
+                self.range <<= 1;
+                self.bit_count += 1;
+                if self.bit_count == 8 {
+                    self.bit_count = 0;
+                    self.value |= u32::from(self.data.get(self.position).copied().unwrap_or(0));
+                    self.position += 1;
+                }
+            }
+            bit
+        }
+
+        fn literal(&mut self, bits: u32) -> u32 {
+            (0..bits).fold(0, |value, _| value << 1 | u32::from(self.get(128)))
+        }
+
+        fn tree(&mut self, tree: &[i8], probs: &[u8], start: usize) -> i8 {
+            let mut index = start;
+            loop {
+                let next = tree[index + usize::from(self.get(probs[index >> 1]))];
+                if next <= 0 {
+                    return -next;
+                }
+                index = next as usize;
+            }
+        }
+    }
+
+    /// Reads the tokens of a block starting at scan position `first` into `levels`, returning
+    /// whether it had any coefficients.
+    fn read_block(
+        decoder: &mut BoolDecoder<'_>,
+        probs: &TokenProbs,
+        plane: usize,
+        mut ctx: usize,
+        levels: &mut [i16; 16],
+        first: usize,
+    ) -> bool {
+        let mut after_zero = false;
+        for i in first..16 {
+            let probs = &probs[plane][COEFF_BANDS[i]][ctx];
+            let token = decoder.tree(&DCT_TOKEN_TREE, probs, if after_zero { 2 } else { 0 });
+            if token == DCT_EOB {
+                return i > first;
+            }
+            if token == 0 {
+                after_zero = true;
+                ctx = 0;
+                continue;
+            }
+            let abs = if token >= DCT_CAT1 {
+                let cat = (token - DCT_CAT1) as usize;
+                let extra = PROB_DCT_CAT[cat]
+                    .iter()
+                    .fold(0, |extra, &prob| extra << 1 | u16::from(decoder.get(prob)));
+                DCT_CAT_BASE[cat] + extra
+            } else {
+                token as u16
+            };
+            let level = abs as i16;
+            levels[ZIGZAG[i]] = if decoder.get(128) { -level } else { level };
+            after_zero = false;
+            ctx = if abs == 1 { 1 } else { 2 };
+        }
+        true
+    }
+
+    /// Adds the dequantized residual of a `size`-square block to its prediction.
+    fn reconstruct(
+        recon: &mut Plane,
+        pred: &[u8; 256],
+        size: usize,
+        (mbx, mby): (usize, usize),
+        (dc_q, ac_q): (i32, i32),
+        levels: &[[i16; 16]],
+        dcs: Option<[i32; 16]>,
+    ) {
+        let blocks_per_row = size / 4;
+        for (b, levels) in levels.iter().enumerate() {
+            let mut block = [0i32; 16];
+            for k in 0..16 {
+                block[k] = i32::from(levels[k]) * if k == 0 { dc_q } else { ac_q };
+            }
+            if let Some(dcs) = dcs {
+                block[0] = dcs[b];
+            }
+            idct4x4(&mut block);
+            let (bx, by) = (b % blocks_per_row * 4, b / blocks_per_row * 4);
+            for j in 0..4 {
+                for i in 0..4 {
+                    let (x, y) = (mbx * size + bx + i, mby * size + by + j);
+                    let value = i32::from(pred[(by + j) * size + bx + i]) + block[j * 4 + i];
+                    recon.data[y * recon.stride + x] = value.clamp(0, 255) as u8;
+                }
+            }
+        }
+    }
+
+    /// Decodes a VP8 key frame of the kind `encode_vp8` writes: one token partition, no
+    /// segmentation or loop filter, and whole-macroblock prediction.
+    fn decode_vp8(vp8: &[u8], width: u32, height: u32) -> [Plane; 3] {
+        let tag = u32::from(vp8[0]) | u32::from(vp8[1]) << 8 | u32::from(vp8[2]) << 16;
+        assert_eq!(tag & 0x1f, 0x10, "not a shown version 0 key frame");
+        let first_partition_size = (tag >> 5) as usize;
+        assert!(10 + first_partition_size <= vp8.len());
+        assert_eq!(&vp8[3..6], [0x9d, 0x01, 0x2a]);
+        assert_eq!(u32::from(u16::from_le_bytes([vp8[6], vp8[7]])), width);
+        assert_eq!(u32::from(u16::from_le_bytes([vp8[8], vp8[9]])), height);
+
+        let mut header = BoolDecoder::new(&vp8[10..10 + first_partition_size]);
+        assert_eq!(
+            header.literal(3),
+            0,
+            "colour space, clamping or segmentation"
+        );
+        assert_eq!(header.literal(10), 0, "loop filter");
+        assert_eq!(header.literal(1), 0, "loop filter adjustments");
+        assert_eq!(header.literal(2), 0, "token partitions");
+        // Dequantization factors of RFC 6386 section 14.1, kept apart from the encoder's.
+        let q_index = header.literal(7) as usize;
+        let (dc_q, ac_q) = (DC_QUANT[q_index], AC_QUANT[q_index]);
+        let (y2_dc_q, y2_ac_q) = (dc_q * 2, (ac_q * 155 / 100).max(8));
+        let y_quant = (dc_q, ac_q);
+        let uv_quant = (dc_q.min(132), ac_q);
+        assert_eq!(header.literal(5), 0, "quantizer deltas");
+        header.literal(1);
+        let mut probs = COEFF_PROBS;
+        for (plane, plane_probs) in probs.iter_mut().enumerate() {
+            for (band, band_probs) in plane_probs.iter_mut().enumerate() {
+                for (ctx, ctx_probs) in band_probs.iter_mut().enumerate() {
+                    for (node, prob) in ctx_probs.iter_mut().enumerate() {
+                        if header.get(COEFF_UPDATE_PROBS[plane][band][ctx][node]) {
+                            *prob = header.literal(8) as u8;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(header.get(128), "no skip flags");
+        let skip_prob = header.literal(8) as u8;
+
+        let mb_width = width.div_ceil(16) as usize;
+        let mb_height = height.div_ceil(16) as usize;
+        let mut rec_y = Plane::new(mb_width * 16, mb_height * 16);
+        let mut rec_u = Plane::new(mb_width * 8, mb_height * 8);
+        let mut rec_v = Plane::new(mb_width * 8, mb_height * 8);
+        let mut tokens = BoolDecoder::new(&vp8[10 + first_partition_size..]);
+        let mut above = vec![[false; 9]; mb_width];
+        let mut left = [false; 9];
+        for mby in 0..mb_height {
+            left = [false; 9];
+            for mbx in 0..mb_width {
+                let skip = header.get(skip_prob);
+                let y_mode = header.tree(&KEYFRAME_YMODE_TREE, &KEYFRAME_YMODE_PROBS, 0) as u8;
+                assert_ne!(y_mode, B_PRED);
+                let uv_mode = header.tree(&KEYFRAME_UV_MODE_TREE, &KEYFRAME_UV_MODE_PROBS, 0) as u8;
+
+                let mut levels = [[0i16; 16]; 25];
+                let above = &mut above[mbx];
+                if skip {
+                    *above = [false; 9];
+                    left = [false; 9];
+                } else {
+                    let ctx = usize::from(above[0]) + usize::from(left[0]);
+                    let has_coeffs =
+                        read_block(&mut tokens, &probs, PLANE_Y2, ctx, &mut levels[24], 0);
+                    above[0] = has_coeffs;
+                    left[0] = has_coeffs;
+                    for b in 0..16 {
+                        let (x, y) = (b % 4, b / 4);
+                        let ctx = usize::from(above[1 + x]) + usize::from(left[1 + y]);
+                        let has_coeffs = read_block(
+                            &mut tokens,
+                            &probs,
+                            PLANE_Y_AFTER_Y2,
+                            ctx,
+                            &mut levels[b],
+                            1,
+                        );
+                        above[1 + x] = has_coeffs;
+                        left[1 + y] = has_coeffs;
+                    }
+                    for (plane_offset, block_offset) in [(5, 16), (7, 20)] {
+                        for b in 0..4 {
+                            let (x, y) = (b % 2, b / 2);
+                            let ctx = usize::from(above[plane_offset + x])
+                                + usize::from(left[plane_offset + y]);
+                            let levels = &mut levels[block_offset + b];
+                            let has_coeffs =
+                                read_block(&mut tokens, &probs, PLANE_UV, ctx, levels, 0);
+                            above[plane_offset + x] = has_coeffs;
+                            left[plane_offset + y] = has_coeffs;
+                        }
+                    }
+                }
+
+                let mut y2 = [0i32; 16];
+                for k in 0..16 {
+                    y2[k] = i32::from(levels[24][k]) * if k == 0 { y2_dc_q } else { y2_ac_q };
+                }
+                let pred = predict(y_mode, &rec_y, 16, mbx, mby);
+                let dcs = Some(iwht4x4(&y2));
+                reconstruct(
+                    &mut rec_y,
+                    &pred,
+                    16,
+                    (mbx, mby),
+                    y_quant,
+                    &levels[..16],
+                    dcs,
+                );
+                let pred = predict(uv_mode, &rec_u, 8, mbx, mby);
+                reconstruct(
+                    &mut rec_u,
+                    &pred,
+                    8,
+                    (mbx, mby),
+                    uv_quant,
+                    &levels[16..20],
+                    None,
+                );
+                let pred = predict(uv_mode, &rec_v, 8, mbx, mby);
+                reconstruct(
+                    &mut rec_v,
+                    &pred,
+                    8,
+                    (mbx, mby),
+                    uv_quant,
+                    &levels[20..24],
+                    None,
+                );
+            }
+        }
+        [rec_y, rec_u, rec_v]
+    }
+
+    /// Reads VP8L bits least significant first.
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn get(&mut self, bits: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..bits {
+                let byte = self.data[self.position / 8];
+                value |= u32::from(byte >> (self.position % 8) & 1) << i;
+                self.position += 1;
+            }
+            value
+        }
+    }
+
+    /// A prefix code, mapping `(code, length)` pairs to symbols.
+    struct PrefixCode(std::collections::HashMap<(u32, u32), u32>);
+
+    impl PrefixCode {
+        fn from_lengths(lengths: &[u8]) -> Self {
+            let codes = canonical_codes(lengths);
+            let symbols = codes
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, (_, len))| len > 0)
+                .map(|(symbol, code)| (code, symbol as u32));
+            Self(symbols.collect())
+        }
+
+        fn read(reader: &mut BitReader<'_>, alphabet_size: usize) -> Self {
+            if reader.get(1) == 1 {
+                let num_symbols = reader.get(1) + 1;
+                let first_bits = if reader.get(1) == 1 { 8 } else { 1 };
+                let mut symbols = vec![reader.get(first_bits)];
+                if num_symbols == 1 {
+                    return Self(std::iter::once(((0, 0), symbols[0])).collect());
+                }
+                symbols.push(reader.get(8));
+                let mut lengths = vec![0; alphabet_size];
+                for symbol in symbols {
+                    lengths[symbol as usize] = 1;
+                }
+                return Self::from_lengths(&lengths);
+            }
+
+            let num_codes = reader.get(4) as usize + 4;
+            let mut code_length_lengths = [0u8; 19];
+            for &symbol in &CODE_LENGTH_ORDER[..num_codes] {
+                code_length_lengths[symbol] = reader.get(3) as u8;
+            }
+            let code_length_code = Self::from_lengths(&code_length_lengths);
+            assert_eq!(reader.get(1), 0, "limited number of code lengths");
+            let mut lengths = Vec::new();
+            let mut previous = 8;
+            while lengths.len() < alphabet_size {
+                let (len, repeat) = match code_length_code.decode(reader) {
+                    len @ 0..=15 => (len as u8, 1),
+                    16 => (previous, 3 + reader.get(2)),
+                    17 => (0, 3 + reader.get(3)),
+                    _ => (0, 11 + reader.get(7)),
+                };
+                lengths.extend(std::iter::repeat(len).take(repeat as usize));
+                if len != 0 {
+                    previous = len;
+                }
+            }
+            assert_eq!(lengths.len(), alphabet_size);
+            Self::from_lengths(&lengths)
+        }
+
+        fn decode(&self, reader: &mut BitReader<'_>) -> u32 {
+            let (mut code, mut len) = (0, 0);
+            loop {
+                if let Some(&symbol) = self.0.get(&(code, len)) {
+                    return symbol;
+                }
+                assert!(len < 15, "invalid prefix code");
+                code = code << 1 | reader.get(1);
+                len += 1;
+            }
+        }
+    }
+
+    fn prefix_decode(reader: &mut BitReader<'_>, prefix: u32) -> usize {
+        if prefix < 4 {
+            return prefix as usize + 1;
+        }
+        let extra_bits = (prefix - 2) >> 1;
+        let offset = (2 + (prefix & 1)) << extra_bits;
+        (offset + reader.get(extra_bits)) as usize + 1
+    }
+
+    /// Decodes an `ALPH` chunk of the kind `encode_alpha` writes, with no VP8L transforms,
+    /// colour cache or meta prefix codes.
+    fn decode_alpha(alph: &[u8], pixels: usize) -> Vec<u8> {
+        assert_eq!(alph[0], 1, "not lossless, unfiltered alpha");
+        let mut reader = BitReader {
+            data: &alph[1..],
+            position: 0,
+        };
+        assert_eq!(
+            reader.get(3),
+            0,
+            "transforms, colour cache or meta prefix codes"
+        );
+        let green = PrefixCode::read(&mut reader, 256 + 24);
+        let others = [256; 3].map(|size| PrefixCode::read(&mut reader, size));
+        let distance = PrefixCode::read(&mut reader, 40);
+
+        let mut alpha = Vec::new();
+        while alpha.len() < pixels {
+            let symbol = green.decode(&mut reader);
+            if symbol < 256 {
+                for code in &others {
+                    code.decode(&mut reader);
+                }
+                alpha.push(symbol as u8);
+                continue;
+            }
+            let len = prefix_decode(&mut reader, symbol - 256);
+            let distance_symbol = distance.decode(&mut reader);
+            let distance = prefix_decode(&mut reader, distance_symbol);
+            assert!(distance > 120, "distance depends on the image width");
+            let start = alpha.len() - (distance - 120);
+            for i in 0..len {
+                alpha.push(alpha[start + i]);
+            }
+        }
+        assert_eq!(alpha.len(), pixels, "copy past the end of the image");
+        assert_eq!((reader.position + 7) / 8, alph.len() - 1, "trailing data");
+        alpha
+    }
+
+    /// A 37x21 image with smooth colour and alpha that has both runs and noise.
+    fn test_image() -> (u32, u32, Vec<u8>) {
+        let (width, height) = (37, 21);
+        let mut rgba = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let alpha = match y {
+                    0..=5 => 0xff,
+                    6..=13 => (x * 97 + y * 31) as u8,
+                    _ => 0,
+                };
+                rgba.extend_from_slice(&[(x * 6) as u8, (y * 11) as u8, 128, alpha]);
+            }
+        }
+        (width, height, rgba)
+    }
+
+    /// Largest difference between the decoded planes and the source, over the visible pixels.
+    fn max_error(width: u32, height: u32, rgba: &[u8], decoded: &[Plane; 3]) -> u8 {
+        let mb_width = width.div_ceil(16) as usize;
+        let mb_height = height.div_ceil(16) as usize;
+        let source = to_yuv(width, height, rgba, mb_width, mb_height);
+        let (width, height) = (width as usize, height as usize);
+        let mut error = 0;
+        for (plane, (source, decoded)) in source.iter().zip(decoded).enumerate() {
+            let scale = if plane == 0 { 1 } else { 2 };
+            for y in 0..height.div_ceil(scale) {
+                for x in 0..width.div_ceil(scale) {
+                    let diff = i16::from(source.at(x, y)) - i16::from(decoded.at(x, y));
+                    error = error.max(diff.unsigned_abs() as u8);
+                }
+            }
+        }
+        error
+    }
+
+    #[test]
+    fn opaque_image() {
+        let (width, height, mut rgba) = test_image();
+        for pixel in rgba.chunks_mut(4) {
+            pixel[3] = 0xff;
+        }
+        // Chroma is compared after subsampling, so every error comes from quantization.
+        for (quality, tolerance) in [(0.0, 32), (50.0, 24), (90.0, 8), (100.0, 1)] {
+            let webp = encode_rgba(width, height, &rgba, quality).unwrap();
+            let chunks = chunks(&webp);
+            assert_eq!(chunks.len(), 1);
+            assert_eq!(&chunks[0].0, b"VP8 ");
+            let decoded = decode_vp8(chunks[0].1, width, height);
+            assert!(max_error(width, height, &rgba, &decoded) <= tolerance);
+        }
+    }
+
+    #[test]
+    fn transparent_image() {
+        let (width, height, rgba) = test_image();
+        let webp = encode_rgba(width, height, &rgba, 90.0).unwrap();
+        let chunks = chunks(&webp);
+        let fourccs = chunks.iter().map(|chunk| &chunk.0).collect::<Vec<_>>();
+        assert_eq!(fourccs, [b"VP8X", b"ALPH", b"VP8 "]);
+
+        let vp8x = chunks[0].1;
+        assert_eq!(vp8x.len(), 10);
+        assert_eq!(&vp8x[..4], [0x10, 0, 0, 0], "only the alpha flag is set");
+        assert_eq!(&vp8x[4..7], [36, 0, 0]);
+        assert_eq!(&vp8x[7..10], [20, 0, 0]);
+
+        let alpha = rgba.chunks(4).map(|pixel| pixel[3]).collect::<Vec<_>>();
+        assert_eq!(decode_alpha(chunks[1].1, alpha.len()), alpha);
+        let decoded = decode_vp8(chunks[2].1, width, height);
+        assert!(max_error(width, height, &rgba, &decoded) <= 8);
+    }
+
+    #[test]
+    fn long_alpha_runs() {
+        // Runs longer than the longest copy, and a single alpha value taking no bits to code.
+        let (width, height) = (100, 50);
+        let rgba = [0, 0, 0, 0].repeat(width * height);
+        let webp = encode_rgba(width as u32, height as u32, &rgba, 50.0).unwrap();
+        let chunks = chunks(&webp);
+        assert_eq!(&chunks[1].0, b"ALPH");
+        assert_eq!(
+            decode_alpha(chunks[1].1, width * height),
+            vec![0; width * height]
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert!(encode_rgba(0, 1, &[], 80.0).is_err());
+        assert!(encode_rgba(MAX_DIMENSION + 1, 1, &[0; 4 * 16384], 80.0).is_err());
+        assert!(encode_rgba(2, 2, &[0; 12], 80.0).is_err());
+    }
+}
@@ -1,100 +1,359 @@
+mod convert;
+mod json;
+mod transform;
 mod type_tree;
 
 use crate::common_parser::read_string;
-use crate::util::align;
+use crate::util::{align, xxh64};
+use crate::{Platform, UnityFsError};
 use std::borrow::Cow;
 use std::collections::{btree_map, BTreeMap};
 
 use nom::{
+    bytes::complete as nom_bytes,
     i16, i32, i64,
     number::{complete as nom_number, Endianness},
-    u32, u64, IResult,
+    u16, u32, u64, IResult,
 };
 
-pub use type_tree::Data;
-use type_tree::TypeMetadata;
+pub use convert::DataError;
+pub use transform::TransformNode;
+pub use type_tree::{Bytes, Data, Fields, FieldsIter};
+pub(crate) use type_tree::{TypeMetadata, TypeTreeCache};
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct Asset<'b> {
     name: String,
-    metadata_size: u32,
-    file_size: u32,
-    format: u32,
-    data_offset: u32,
+    header: SerializedFileHeader,
     tree: TypeMetadata<'b>,
     objects: BTreeMap<i64, Object<'b>>,
     adds: Vec<(u64, i32)>,
-    refs: Vec<AssetRef<'b>>,
+    refs: Vec<ExternalRef<'b>>,
 }
 
 #[derive(Debug)]
 pub struct Object<'b> {
     pub path_id: i64,
+    /// Key of the object's type in the type table of its file: its position since format 17,
+    /// and its class ID before.
     pub type_id: i32,
+    /// Class ID of the object. Since format 17, `MonoBehaviour`s with a script type have a
+    /// negative ID here; [`Object::unity_class_id`] folds them back.
     pub class_id: i32,
     pub is_destroyed: bool,
+    pub(crate) is_stripped: bool,
     pub data: Data<'b>,
+    pub(crate) raw: Bytes<'b>,
 }
 
 impl<'b> Object<'b> {
+    /// Unity class ID of the object, such as 28 for `Texture2D` or 83 for `AudioClip`.
+    ///
+    /// It is known without reading the object, so filtering on it is cheap.
+    pub fn unity_class_id(&self) -> i32 {
+        if self.class_id < 0 {
+            type_tree::MONO_BEHAVIOUR_CLASS_ID
+        } else {
+            self.class_id
+        }
+    }
+
+    /// Whether the object table marks the object as stripped from the build, as files of format
+    /// 15 and 16 do. Its data is then left unread, as an empty struct of its type.
+    pub fn is_stripped(&self) -> bool {
+        self.is_stripped
+    }
+
+    /// Serialized bytes of the object, borrowed from the file it was read from.
+    ///
+    /// This is the way to get at large blobs without going through [`Data`].
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// XXH64 of the serialized bytes of the object, which stays the same across bundle builds as
+    /// long as the object is unchanged.
+    pub fn content_hash(&self) -> u64 {
+        xxh64(&self.raw)
+    }
+
     pub fn type_name(&self, asset: &'b Asset) -> Cow<'b, str> {
         match &self.data {
             Data::GenericStruct {
                 type_name, fields, ..
             } if type_name == "MonoBehaviour" => {
-                if let Some(Data::GenericStruct { fields, .. }) = fields.get("m_Script") {
-                    if let Some(Data::SInt64(path_id)) = fields.get("m_PathID") {
-                        if let Some(Object {
-                            data: Data::GenericStruct { fields, .. },
-                            ..
-                        }) = asset.get_object(path_id)
-                        {
-                            if let Some(Data::String(s)) = fields.get("m_ClassName") {
-                                return String::from_utf8_lossy(s);
-                            }
-                        }
+                if let Some(Object {
+                    data: Data::GenericStruct { fields, .. },
+                    ..
+                }) = fields.get("m_Script").and_then(|pptr| asset.resolve(pptr))
+                {
+                    if let Some(Data::String(s)) = fields.get("m_ClassName") {
+                        return String::from_utf8_lossy(s);
                     }
                 }
-                Cow::Owned(format!(
-                    "{}(Unknown: {})",
-                    type_name.to_owned(),
-                    self.path_id
-                ))
+                Cow::Owned(format!("{}(Unknown: {})", type_name, self.path_id))
             }
-            Data::GenericPrimitive { type_name, .. } => type_name.to_owned(),
-            Data::GenericStruct { type_name, .. } => type_name.to_owned(),
-            _ => unreachable!(),
+            Data::GenericPrimitive { type_name, .. } => type_name.clone(),
+            Data::GenericStruct { type_name, .. } => type_name.clone(),
+            // Only a corrupt type tree has anything else at its root.
+            _ => Cow::Owned(format!("(Unknown: {})", self.path_id)),
         }
     }
+
+    /// Name of the object, taken from its `GameObject` for components without an `m_Name`
+    /// of their own.
+    pub fn name(&self, asset: &Asset<'b>) -> Option<String> {
+        let name = self.data.field("m_Name").and_then(Data::as_bytes);
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            return Some(String::from_utf8_lossy(name).into_owned());
+        }
+        let game_object = asset.resolve(self.data.field("m_GameObject")?)?;
+        let name = game_object.data.field("m_Name").and_then(Data::as_bytes)?;
+        Some(String::from_utf8_lossy(name).into_owned())
+    }
+
+    /// Class of a `MonoBehaviour`, read from the `MonoScript` its `m_Script` points to.
+    ///
+    /// Returns `None` if the script lives in another file.
+    pub fn mono_class(&self, asset: &Asset<'b>) -> Option<MonoClass> {
+        let script = asset.resolve(self.data.field("m_Script")?)?;
+        let string = |name: &str| {
+            let s = script.data.field(name).and_then(Data::as_bytes)?;
+            Some(String::from_utf8_lossy(s).into_owned())
+        };
+        Some(MonoClass {
+            class_name: string("m_ClassName")?,
+            namespace: string("m_Namespace").unwrap_or_default(),
+            assembly_name: string("m_AssemblyName").unwrap_or_default(),
+        })
+    }
+
+    /// Components attached to a `GameObject`, such as its `Transform`, in `m_Component` order.
+    ///
+    /// Components in other files are skipped. Objects other than `GameObject`s have none.
+    pub fn components<'a>(&self, asset: &'a Asset<'b>) -> Vec<&'a Object<'b>> {
+        let components = match self.data.field("m_Component").and_then(Data::as_array) {
+            Some(components) => components,
+            None => return Vec::new(),
+        };
+        components
+            .iter()
+            .filter_map(|entry| {
+                // Before Unity 5.5 each entry pairs the class ID with the pointer.
+                let pptr = match entry {
+                    Data::Pair(_, pptr) => pptr,
+                    entry => entry.field("component")?,
+                };
+                asset.resolve(pptr)
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug)]
-pub struct AssetRef<'b> {
+/// The script class backing a `MonoBehaviour`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonoClass {
+    pub class_name: String,
+    /// Empty for classes in the global namespace.
+    pub namespace: String,
+    /// Assembly the class is compiled into, such as `Assembly-CSharp.dll`.
+    pub assembly_name: String,
+}
+
+impl MonoClass {
+    /// Class name qualified with its namespace, as in `UnityEngine.UI.Image`.
+    pub fn full_name(&self) -> String {
+        if self.namespace.is_empty() {
+            self.class_name.clone()
+        } else {
+            format!("{}.{}", self.namespace, self.class_name)
+        }
+    }
+}
+
+/// A file that the objects of an asset may point into.
+///
+/// A `PPtr` with `m_FileID` of `n` refers to the `n - 1`th entry of [`Asset::externals`].
+#[derive(Debug, Clone)]
+pub struct ExternalRef<'b> {
     asset_path: Cow<'b, str>,
-    guid: &'b [u8],
+    guid: [u8; 16],
     ty: u32,
-    file_path: Cow<'b, str>,
+    path: Cow<'b, str>,
 }
 
-impl<'b> Asset<'b> {
-    pub(crate) fn parse(name: String, input: &'b [u8], offset: u64) -> IResult<&[u8], Self> {
-        let base = input;
+impl ExternalRef<'_> {
+    /// Path of the referenced file, e.g. `archive:/CAB-.../CAB-...`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn guid(&self) -> [u8; 16] {
+        self.guid
+    }
+
+    pub fn ref_type(&self) -> u32 {
+        self.ty
+    }
+
+    pub fn asset_path(&self) -> &str {
+        &self.asset_path
+    }
+
+    pub(crate) fn into_owned(self) -> ExternalRef<'static> {
+        ExternalRef {
+            asset_path: self.asset_path.into_owned().into(),
+            guid: self.guid,
+            ty: self.ty,
+            path: self.path.into_owned().into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ObjectInfo {
+    pub(crate) path_id: i64,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) type_id: i32,
+    pub(crate) class_id: i32,
+    pub(crate) is_destroyed: bool,
+    pub(crate) is_stripped: bool,
+}
+
+impl ObjectInfo {
+    pub(crate) fn read_data<'b>(
+        &self,
+        tree: &TypeMetadata<'b>,
+        input: &'b [u8],
+        endianness: Endianness,
+    ) -> Result<Data<'b>, UnityFsError> {
+        let type_tree = tree
+            .type_tree_from_id(self.type_id, self.class_id)
+            .ok_or(UnityFsError::MissingTypeTree {
+                class_id: self.class_id,
+            })?;
+        if self.is_stripped {
+            return Ok(type_tree.empty());
+        }
+        let (_, data) = type_tree
+            .read(input, endianness, 0)
+            .map_err(|e| UnityFsError::from_nom(input, self.start, e))?;
+        Ok(data)
+    }
+}
+
+/// Fields of the header of a serialized file, as stored.
+///
+/// Since version 22, sizes and offsets are stored again after the endianness flag in wider
+/// fields, and the first ones are left zero; the wider ones are reported then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerializedFileHeader {
+    /// Size of the type metadata and object table that follow the header.
+    pub metadata_size: u32,
+    pub file_size: u64,
+    /// Format version of the file, which decides how the rest of it is laid out.
+    pub version: u32,
+    /// Offset of the object data from the start of the file.
+    pub data_offset: u64,
+    /// Whether the metadata and objects are big endian. The header itself always is.
+    ///
+    /// Files before version 9 have no endianness flag and are always big endian.
+    pub big_endian: bool,
+    /// The three bytes following the endianness flag, zero before version 9.
+    pub reserved: [u8; 3],
+}
+
+impl SerializedFileHeader {
+    /// Length of the header, the type metadata and the object table, which precede the object
+    /// data.
+    pub(crate) fn table_len(&self) -> u64 {
+        let header_len = if self.version >= 22 { 48 } else { 20 };
+        header_len + u64::from(self.metadata_size)
+    }
+}
+
+/// Everything in a serialized file that precedes the object data.
+#[derive(Debug)]
+pub(crate) struct AssetTable<'b> {
+    pub(crate) header: SerializedFileHeader,
+    pub(crate) endianness: Endianness,
+    pub(crate) tree: TypeMetadata<'b>,
+    pub(crate) objects: Vec<ObjectInfo>,
+    pub(crate) adds: Vec<(u64, i32)>,
+    pub(crate) refs: Vec<ExternalRef<'b>>,
+}
+
+impl<'b> AssetTable<'b> {
+    /// Length of the longest header preceding the type metadata, enough to read
+    /// `metadata_size` of any version.
+    pub(crate) const HEADER_SIZE: usize = 48;
+
+    /// Checks whether `input` starts with the header of a serialized file as large as `input`.
+    ///
+    /// Only the formats whose header is read by [`AssetTable::parse`] are recognized.
+    pub(crate) fn is_header(input: &[u8]) -> bool {
+        match Self::parse_header(input) {
+            Ok((_, header)) => {
+                (9..=22).contains(&header.version)
+                    && header.file_size == input.len() as u64
+                    && u64::from(header.metadata_size) < header.file_size
+                    && header.data_offset <= header.file_size
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Reads the header preceding the type metadata.
+    pub(crate) fn parse_header(input: &[u8]) -> IResult<&[u8], SerializedFileHeader> {
         let (input, metadata_size) = nom_number::be_u32(input)?;
         let (input, file_size) = nom_number::be_u32(input)?;
-        let (input, format) = nom_number::be_u32(input)?;
+        let (input, version) = nom_number::be_u32(input)?;
         let (input, data_offset) = nom_number::be_u32(input)?;
-        let (input, endianness) = if format >= 9 {
-            let (input, endianness) = nom_number::be_u32(input)?;
-            let endianness = if endianness == 0 {
-                Endianness::Little
-            } else {
-                Endianness::Big
-            };
-            (input, endianness)
+        let (input, big_endian, reserved) = if version >= 9 {
+            // A single flag byte followed by three reserved bytes.
+            let (input, endianness) = nom_number::u8(input)?;
+            let (input, reserved) = nom_bytes::take(3usize)(input)?;
+            (input, endianness != 0, [reserved[0], reserved[1], reserved[2]])
+        } else {
+            (input, true, [0; 3])
+        };
+        let (input, metadata_size, file_size, data_offset) = if version >= 22 {
+            let (input, metadata_size) = nom_number::be_u32(input)?;
+            let (input, file_size) = nom_number::be_u64(input)?;
+            let (input, data_offset) = nom_number::be_u64(input)?;
+            // Reserved.
+            let (input, _) = nom_bytes::take(8usize)(input)?;
+            (input, metadata_size, file_size, data_offset)
+        } else {
+            (input, metadata_size, file_size.into(), data_offset.into())
+        };
+        let header = SerializedFileHeader {
+            metadata_size,
+            file_size,
+            version,
+            data_offset,
+            big_endian,
+            reserved,
+        };
+        Ok((input, header))
+    }
+
+    pub(crate) fn parse(
+        input: &'b [u8],
+        offset: u64,
+        cache: &mut TypeTreeCache<'b>,
+    ) -> IResult<&'b [u8], Self> {
+        let base = input;
+        let (input, header) = Self::parse_header(input)?;
+        let format = header.version;
+        let endianness = if header.big_endian {
+            Endianness::Big
         } else {
-            (input, Endianness::Big)
+            Endianness::Little
         };
-        let (input, tree) = TypeMetadata::parse(input, endianness, format)?;
+        let (input, tree) = TypeMetadata::parse(input, endianness, format, cache)?;
         let (input, long_object_ids) = if (7..=13).contains(&format) {
             let (input, long_object_ids) = u32!(input, endianness)?;
             (input, long_object_ids != 0)
@@ -115,10 +374,15 @@ impl<'b> Asset<'b> {
                     let (input, id) = i32!(input, endianness)?;
                     (input, id.into())
                 };
-                let (input, object_data_offset) = u32!(input, endianness)?;
+                let (input, object_data_offset) = if format >= 22 {
+                    u64!(input, endianness)?
+                } else {
+                    let (input, offset) = u32!(input, endianness)?;
+                    (input, offset.into())
+                };
                 let (input, size) = u32!(input, endianness)?;
-                let start = data_offset + object_data_offset;
-                let end = start + size;
+                let start = header.data_offset.saturating_add(object_data_offset);
+                let end = start.saturating_add(size.into());
 
                 let (input, type_id, class_id) = if format < 17 {
                     let (input, type_id) = i32!(input, endianness)?;
@@ -126,46 +390,44 @@ impl<'b> Asset<'b> {
                     (input, type_id, class_id.into())
                 } else {
                     let (input, type_id) = u32!(input, endianness)?;
-                    let class_id = tree.class_id_from_idx(type_id as usize);
-                    (input, class_id, class_id)
-                };
-                let data = {
-                    let type_tree = tree.type_tree_from_id(type_id, class_id).unwrap();
-                    let (_, data) =
-                        type_tree.read(&base[start as usize..end as usize], endianness, 0)?;
-                    data
+                    let class_id = tree.class_id_from_idx(type_id as usize).ok_or_else(|| {
+                        nom::Err::Error(nom::error::Error::new(
+                            input,
+                            nom::error::ErrorKind::MapOpt,
+                        ))
+                    })?;
+                    (input, type_id as i32, class_id)
                 };
 
                 let (input, is_destroyed) = if format <= 10 {
-                    let val = input[0] != 0;
-                    (&input[1..], val)
+                    let (input, val) = u16!(input, endianness)?;
+                    (input, val != 0)
                 } else {
                     (input, false)
                 };
                 let input = if (11..=16).contains(&format) {
-                    &input[2..]
+                    nom_bytes::take(2usize)(input)?.0
                 } else {
                     input
                 };
-                let input = if (15..=16).contains(&format) {
-                    &input[1..]
+                let (input, is_stripped) = if (15..=16).contains(&format) {
+                    let (input, val) = nom_number::u8(input)?;
+                    (input, val != 0)
                 } else {
-                    input
+                    (input, false)
                 };
                 input_out = input;
-                Ok((
+                Ok(ObjectInfo {
                     path_id,
-                    Object {
-                        path_id,
-                        type_id,
-                        class_id,
-                        is_destroyed,
-                        data,
-                    },
-                ))
+                    start,
+                    end,
+                    type_id,
+                    class_id,
+                    is_destroyed,
+                    is_stripped,
+                })
             })
-            .collect::<Result<BTreeMap<_, _>, _>>()?;
-
+            .collect::<Result<Vec<_>, _>>()?;
         let (input, adds) = if format >= 11 {
             let (mut input_out, add_count) = u32!(input_out, endianness)?;
             let adds = (0..add_count)
@@ -191,41 +453,105 @@ impl<'b> Asset<'b> {
             (input_out, Vec::new())
         };
 
-        let (input, refs) = if format >= 16 {
-            let (mut input_out, refs_count) = u32!(input, endianness)?;
-            let refs = (0..refs_count)
-                .map(|_| {
-                    let (input, asset_path) = read_string(input_out, None)?;
-                    let (guid, input) = input.split_at(0x10);
+        let (mut input_out, refs_count) = u32!(input, endianness)?;
+        let refs = (0..refs_count)
+            .map(|_| {
+                let (input, asset_path) = if format >= 6 {
+                    read_string(input_out, None)?
+                } else {
+                    (input_out, Cow::Borrowed(""))
+                };
+                let (input, guid, ty) = if format >= 5 {
+                    let (input, guid_slice) = nom_bytes::take(16usize)(input)?;
+                    let mut guid = [0; 16];
+                    guid.copy_from_slice(guid_slice);
                     let (input, ty) = u32!(input, endianness)?;
-                    let (input, file_path) = read_string(input, None)?;
-                    input_out = input;
-                    Ok(AssetRef {
-                        asset_path,
-                        guid,
-                        ty,
-                        file_path,
-                    })
+                    (input, guid, ty)
+                } else {
+                    (input, [0; 16], 0)
+                };
+                let (input, path) = read_string(input, None)?;
+                input_out = input;
+                Ok(ExternalRef {
+                    asset_path,
+                    guid,
+                    ty,
+                    path,
                 })
-                .collect::<Result<Vec<_>, _>>()?;
-            (input_out, refs)
-        } else {
-            (input, Vec::new())
-        };
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let input = input_out;
         let (input, _) = read_string(input, None)?;
 
-        let asset = Asset {
-            name,
-            metadata_size,
-            file_size,
-            format,
-            data_offset,
+        let table = AssetTable {
+            header,
+            endianness,
             tree,
             objects,
             adds,
             refs,
         };
-        Ok((input, asset))
+        Ok((input, table))
+    }
+}
+
+impl<'b> Asset<'b> {
+    /// Parses a standalone serialized file, such as a loose `.assets` file, outside of a bundle.
+    pub fn parse(name: String, input: &'b [u8]) -> Result<Self, UnityFsError> {
+        Self::parse_at(name, input, 0, &mut TypeTreeCache::default())
+    }
+
+    /// Parses a serialized file found at `offset` in the data of a bundle, sharing the type
+    /// trees in `cache` with the other files of the bundle.
+    pub(crate) fn parse_at(
+        name: String,
+        input: &'b [u8],
+        offset: u64,
+        cache: &mut TypeTreeCache<'b>,
+    ) -> Result<Self, UnityFsError> {
+        let base = input;
+        let (_, table) = AssetTable::parse(input, offset, cache)
+            .map_err(|e| UnityFsError::from_nom(base, 0, e))?;
+        let AssetTable {
+            header,
+            endianness,
+            tree,
+            objects,
+            adds,
+            refs,
+        } = table;
+        let objects = objects
+            .into_iter()
+            .map(|info| {
+                let raw = base
+                    .get(info.start as usize..info.end as usize)
+                    .ok_or(UnityFsError::OutOfRange {
+                        start: info.start,
+                        end: info.end,
+                        len: base.len() as u64,
+                    })?;
+                let data = info.read_data(&tree, raw, endianness)?;
+                let object = Object {
+                    path_id: info.path_id,
+                    type_id: info.type_id,
+                    class_id: info.class_id,
+                    is_destroyed: info.is_destroyed,
+                    is_stripped: info.is_stripped,
+                    data,
+                    raw: raw.into(),
+                };
+                Ok((info.path_id, object))
+            })
+            .collect::<Result<BTreeMap<_, _>, UnityFsError>>()?;
+
+        Ok(Asset {
+            name,
+            header,
+            tree,
+            objects,
+            adds,
+            refs,
+        })
     }
 }
 
@@ -234,11 +560,135 @@ impl<'b> Asset<'b> {
         &self.name
     }
 
-    pub fn objects(&self) -> btree_map::Values<i64, Object> {
+    /// The header of the file, as read before the type metadata.
+    pub fn header(&self) -> SerializedFileHeader {
+        self.header
+    }
+
+    /// Version of the engine that wrote the file, such as `2019.4.40f1`.
+    pub fn unity_version(&self) -> &str {
+        self.tree.unity_version()
+    }
+
+    /// Platform the file was built for, which tells how some of its data is laid out, such as
+    /// the swizzled textures of [`Platform::Switch`].
+    pub fn target_platform(&self) -> Platform {
+        self.tree.target_platform()
+    }
+
+    /// Number of objects in the file.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn objects(&self) -> btree_map::Values<'_, i64, Object<'b>> {
         self.objects.values()
     }
 
-    pub fn get_object(&self, path_id: &i64) -> Option<&Object> {
+    /// Objects of the type `name`, such as `Texture2D` or `MonoBehaviour`, in path ID order.
+    ///
+    /// This is the type at the root of the type tree, so every `MonoBehaviour` matches whatever
+    /// its script; [`Object::mono_class`] tells them apart.
+    pub fn objects_of_type<'s>(
+        &'s self,
+        name: &'s str,
+    ) -> impl Iterator<Item = &'s Object<'b>> + 's {
+        self.objects().filter(move |object| match &object.data {
+            Data::GenericStruct { type_name, .. } | Data::GenericPrimitive { type_name, .. } => {
+                type_name == name
+            }
+            _ => false,
+        })
+    }
+
+    /// Objects of the Unity class `class_id`, as told by [`Object::unity_class_id`], in path ID
+    /// order. Unlike [`Asset::objects_of_type`], no type name is compared.
+    pub fn objects_of_class(&self, class_id: i32) -> impl Iterator<Item = &Object<'b>> + '_ {
+        self.objects()
+            .filter(move |object| object.unity_class_id() == class_id)
+    }
+
+    pub fn get_object(&self, path_id: &i64) -> Option<&Object<'b>> {
         self.objects.get(path_id)
     }
+
+    /// Looks up an object by its path ID without scanning the object list, like
+    /// [`Asset::get_object`] but taking the ID by value.
+    pub fn object_by_path_id(&self, path_id: i64) -> Option<&Object<'b>> {
+        self.get_object(&path_id)
+    }
+
+    /// Files referenced by this asset, in `m_FileID` order starting from 1.
+    pub fn externals(&self) -> std::slice::Iter<'_, ExternalRef<'b>> {
+        self.refs.iter()
+    }
+
+    /// Entries of the `m_Container` of the `AssetBundle` object, mapping the path each asset was
+    /// built from to its `(m_FileID, m_PathID)`.
+    ///
+    /// Empty for files that aren't the main file of a bundle.
+    pub fn container(&self) -> impl Iterator<Item = (String, (i32, i64))> + '_ {
+        self.container_entries().filter_map(|(name, info)| {
+            Some((
+                String::from_utf8_lossy(name).into_owned(),
+                info.field("asset")?.as_pptr()?,
+            ))
+        })
+    }
+
+    /// The `m_PreloadTable` of the `AssetBundle` object, listing the objects to load before each
+    /// container entry as `(m_FileID, m_PathID)`.
+    ///
+    /// Empty for files that aren't the main file of a bundle.
+    pub fn preload_table(&self) -> Vec<(i32, i64)> {
+        self.asset_bundles()
+            .filter_map(|object| object.data.field("m_PreloadTable")?.as_array())
+            .flatten()
+            .filter_map(Data::as_pptr)
+            .collect()
+    }
+
+    /// The part of the preload table that the first container entry with the given path refers
+    /// to through its `preloadIndex` and `preloadSize`.
+    ///
+    /// Returns `None` if there is no such entry or its range is out of the table.
+    pub fn preloads(&self, path: &str) -> Option<Vec<(i32, i64)>> {
+        let (_, info) = self
+            .container_entries()
+            .find(|(name, _)| *name == path.as_bytes())?;
+        let index = info.field("preloadIndex")?.as_i32()?;
+        let size = info.field("preloadSize")?.as_i32()?;
+        if index < 0 || size < 0 {
+            return None;
+        }
+        let (index, size) = (index as usize, size as usize);
+        let table = self.preload_table();
+        table.get(index..index.checked_add(size)?).map(<[_]>::to_vec)
+    }
+
+    fn asset_bundles(&self) -> impl Iterator<Item = &Object<'b>> + '_ {
+        const ASSET_BUNDLE_CLASS_ID: i32 = 142;
+        self.objects().filter(|object| object.class_id == ASSET_BUNDLE_CLASS_ID)
+    }
+
+    /// Paths and `AssetInfo`s of the `m_Container` entries.
+    fn container_entries(&self) -> impl Iterator<Item = (&[u8], &Data<'b>)> + '_ {
+        self.asset_bundles()
+            .filter_map(|object| object.data.field("m_Container")?.as_array())
+            .flatten()
+            .filter_map(|entry| match entry {
+                Data::Pair(name, info) => Some((name.as_bytes()?, &**info)),
+                _ => None,
+            })
+    }
+
+    /// Follows a `PPtr<T>` to the object it points to.
+    ///
+    /// Returns `None` for null pointers and for references into other files.
+    pub fn resolve(&self, pptr: &Data) -> Option<&Object<'b>> {
+        match pptr.as_pptr()? {
+            (0, path_id) => self.object_by_path_id(path_id),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,151 @@
+use std::convert::TryFrom;
+
+use super::Data;
+
+/// Error converting [`Data`] into a Rust type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataError {
+    /// The data is of another kind than the one converted to.
+    TypeMismatch {
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// A struct doesn't have the field that was asked for.
+    MissingField(String),
+}
+
+impl std::fmt::Display for DataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataError::TypeMismatch { expected, actual } => {
+                write!(f, "expected {}, found {}", expected, actual)
+            }
+            DataError::MissingField(name) => write!(f, "field {} not found", name),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl<'b> Data<'b> {
+    /// Name of the variant, as used in [`DataError`].
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Data::GenericPrimitive { .. } => "GenericPrimitive",
+            Data::GenericArray(_) => "GenericArray",
+            Data::GenericStruct { .. } => "GenericStruct",
+            Data::Bool(_) => "Bool",
+            Data::UInt8(_) => "UInt8",
+            Data::UInt16(_) => "UInt16",
+            Data::UInt32(_) => "UInt32",
+            Data::UInt64(_) => "UInt64",
+            Data::SInt8(_) => "SInt8",
+            Data::SInt16(_) => "SInt16",
+            Data::SInt32(_) => "SInt32",
+            Data::SInt64(_) => "SInt64",
+            Data::Float(_) => "Float",
+            Data::Double(_) => "Double",
+            Data::String(_) => "String",
+            Data::UInt8Array(_) => "UInt8Array",
+            Data::Pair(..) => "Pair",
+        }
+    }
+
+    /// Converts a field of a struct, such as `let width: i32 = data.get("m_Width")?`.
+    pub fn get<'a, T>(&'a self, name: &str) -> Result<T, DataError>
+    where
+        T: TryFrom<&'a Data<'b>, Error = DataError>,
+    {
+        if self.as_struct().is_none() {
+            return Err(DataError::TypeMismatch {
+                expected: "GenericStruct",
+                actual: self.kind(),
+            });
+        }
+        let field = self
+            .field(name)
+            .ok_or_else(|| DataError::MissingField(name.to_owned()))?;
+        T::try_from(field)
+    }
+}
+
+/// Implements `TryFrom<&Data>` through one of the `as_*` accessors.
+macro_rules! try_from_data {
+    ($($ty:ty => $accessor:ident, $expected:expr;)*) => {
+        $(
+            impl<'a, 'b> TryFrom<&'a Data<'b>> for $ty {
+                type Error = DataError;
+
+                fn try_from(data: &'a Data<'b>) -> Result<Self, DataError> {
+                    data.$accessor().map(Into::into).ok_or(DataError::TypeMismatch {
+                        expected: $expected,
+                        actual: data.kind(),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+// Integers convert from narrower ones of the same signedness, like the accessors.
+try_from_data! {
+    bool => as_bool, "Bool";
+    i32 => as_i32, "SInt32";
+    i64 => as_i64, "SInt64";
+    u32 => as_u32, "UInt32";
+    u64 => as_u64, "UInt64";
+    f32 => as_f32, "Float";
+    &'a [u8] => as_bytes, "String or UInt8Array";
+    Vec<u8> => as_bytes, "String or UInt8Array";
+    &'a str => as_str, "UTF-8 String";
+    (i32, i64) => as_pptr, "PPtr";
+}
+
+impl<'a, 'b> TryFrom<&'a Data<'b>> for f64 {
+    type Error = DataError;
+
+    fn try_from(data: &'a Data<'b>) -> Result<Self, DataError> {
+        match data {
+            Data::Double(v) => Ok(*v),
+            Data::Float(v) => Ok((*v).into()),
+            _ => Err(DataError::TypeMismatch {
+                expected: "Double",
+                actual: data.kind(),
+            }),
+        }
+    }
+}
+
+/// Strings that aren't valid UTF-8 are converted lossily.
+impl<'a, 'b> TryFrom<&'a Data<'b>> for String {
+    type Error = DataError;
+
+    fn try_from(data: &'a Data<'b>) -> Result<Self, DataError> {
+        match data {
+            Data::String(b) => Ok(String::from_utf8_lossy(b).into_owned()),
+            _ => Err(DataError::TypeMismatch {
+                expected: "String",
+                actual: data.kind(),
+            }),
+        }
+    }
+}
+
+/// Converts every element of an array.
+impl<'a, 'b, T> TryFrom<&'a Data<'b>> for Vec<T>
+where
+    T: TryFrom<&'a Data<'b>, Error = DataError>,
+{
+    type Error = DataError;
+
+    fn try_from(data: &'a Data<'b>) -> Result<Self, DataError> {
+        data.as_array()
+            .ok_or(DataError::TypeMismatch {
+                expected: "GenericArray",
+                actual: data.kind(),
+            })?
+            .iter()
+            .map(T::try_from)
+            .collect()
+    }
+}
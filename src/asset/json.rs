@@ -0,0 +1,91 @@
+use std::fmt::Write;
+
+use super::Data;
+use crate::util::base64_encode;
+
+impl<'b> Data<'b> {
+    /// Writes the data as JSON, in the shape of its `Serialize` impl.
+    ///
+    /// Unlike the `Serialize` impl, 64-bit integers are written as strings, as most JSON readers
+    /// parse numbers into doubles. Non-finite floats are written as `null`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write_json(self, &mut out);
+        out
+    }
+}
+
+fn write_json(data: &Data<'_>, out: &mut String) {
+    match data {
+        Data::GenericPrimitive { data, .. } => write_str(&base64_encode(data), out),
+        Data::GenericArray(arr) => {
+            out.push('[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(v, out);
+            }
+            out.push(']');
+        }
+        Data::GenericStruct { fields, .. } => {
+            out.push('{');
+            for (i, (k, v)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(k, out);
+                out.push(':');
+                write_json(v, out);
+            }
+            out.push('}');
+        }
+        Data::Bool(v) => out.push_str(if *v { "true" } else { "false" }),
+        Data::UInt8(v) => write!(out, "{}", v).unwrap(),
+        Data::UInt16(v) => write!(out, "{}", v).unwrap(),
+        Data::UInt32(v) => write!(out, "{}", v).unwrap(),
+        Data::UInt64(v) => write!(out, "\"{}\"", v).unwrap(),
+        Data::SInt8(v) => write!(out, "{}", v).unwrap(),
+        Data::SInt16(v) => write!(out, "{}", v).unwrap(),
+        Data::SInt32(v) => write!(out, "{}", v).unwrap(),
+        Data::SInt64(v) => write!(out, "\"{}\"", v).unwrap(),
+        Data::Float(v) => write_float(*v, v.is_finite(), out),
+        Data::Double(v) => write_float(*v, v.is_finite(), out),
+        Data::String(b) => match std::str::from_utf8(b) {
+            Ok(s) => write_str(s, out),
+            Err(_) => write_str(&base64_encode(b), out),
+        },
+        Data::UInt8Array(b) => write_str(&base64_encode(b), out),
+        Data::Pair(fst, snd) => {
+            out.push('[');
+            write_json(fst, out);
+            out.push(',');
+            write_json(snd, out);
+            out.push(']');
+        }
+    }
+}
+
+fn write_float(v: impl std::fmt::Display, is_finite: bool, out: &mut String) {
+    if is_finite {
+        write!(out, "{}", v).unwrap();
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn write_str(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
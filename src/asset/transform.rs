@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+use super::{Asset, Data, Object};
+
+const TRANSFORM_CLASS_ID: i32 = 4;
+const RECT_TRANSFORM_CLASS_ID: i32 = 224;
+
+/// A `Transform` and the ones parented to it, as built by [`Asset::transform_roots`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformNode {
+    /// Path ID of the `Transform` or `RectTransform`.
+    pub path_id: i64,
+    /// Name of its `GameObject`, empty if that isn't in the same file.
+    pub name: String,
+    pub local_position: [f32; 3],
+    /// Rotation as a quaternion, in `[x, y, z, w]` order.
+    pub local_rotation: [f32; 4],
+    pub local_scale: [f32; 3],
+    /// Children in `m_Children` order.
+    pub children: Vec<TransformNode>,
+}
+
+fn floats<const N: usize>(data: &Data<'_>, name: &str, keys: [&str; N]) -> Option<[f32; N]> {
+    let data = data.field(name)?;
+    let mut ret = [0.0; N];
+    for (value, key) in ret.iter_mut().zip(keys.iter()) {
+        *value = data.field(key)?.as_f32()?;
+    }
+    Some(ret)
+}
+
+fn is_transform(object: &Object<'_>) -> bool {
+    let class_id = object.unity_class_id();
+    class_id == TRANSFORM_CLASS_ID || class_id == RECT_TRANSFORM_CLASS_ID
+}
+
+impl<'b> Asset<'b> {
+    /// Rebuilds the hierarchy of the `Transform`s in the file, following `m_Children`.
+    ///
+    /// Roots are the transforms whose `m_Father` isn't a transform of this file, in path ID
+    /// order. Transforms reached a second time, through a cycle or a child listed twice, are
+    /// left out, as are children in other files.
+    pub fn transform_roots(&self) -> Vec<TransformNode> {
+        let mut visited = HashSet::new();
+        self.objects()
+            .filter(|object| is_transform(object))
+            .filter(|object| {
+                let father = object
+                    .data
+                    .field("m_Father")
+                    .and_then(|pptr| self.resolve(pptr));
+                !father.is_some_and(is_transform)
+            })
+            .filter_map(|object| self.transform_node(object, &mut visited))
+            .collect()
+    }
+
+    fn transform_node(
+        &self,
+        transform: &Object<'b>,
+        visited: &mut HashSet<i64>,
+    ) -> Option<TransformNode> {
+        if !visited.insert(transform.path_id) {
+            return None;
+        }
+        let data = &transform.data;
+        let children = data
+            .field("m_Children")
+            .and_then(Data::as_array)
+            .map(|children| {
+                children
+                    .iter()
+                    .filter_map(|pptr| self.resolve(pptr))
+                    .filter(|child| is_transform(child))
+                    .filter_map(|child| self.transform_node(child, visited))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(TransformNode {
+            path_id: transform.path_id,
+            name: transform.name(self).unwrap_or_default(),
+            local_position: floats(data, "m_LocalPosition", ["x", "y", "z"]).unwrap_or([0.0; 3]),
+            local_rotation: floats(data, "m_LocalRotation", ["x", "y", "z", "w"])
+                .unwrap_or([0.0, 0.0, 0.0, 1.0]),
+            local_scale: floats(data, "m_LocalScale", ["x", "y", "z"]).unwrap_or([1.0; 3]),
+            children,
+        })
+    }
+}
@@ -1,15 +1,20 @@
-use crate::common_parser::read_string;
-use crate::util::align;
+use crate::common_parser::{check_count, read_string};
+use crate::util::{align, base64_encode};
+use crate::Platform;
 use nom::{
+    bytes::complete as nom_bytes,
     i16, i32, i64,
     number::{complete as nom_number, Endianness},
     u16, u32, u64, IResult,
 };
-use serde::Serialize;
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct TypeTree<'a> {
     version: u32,
     is_array: bool,
@@ -21,7 +26,14 @@ pub struct TypeTree<'a> {
     children: Vec<TypeTree<'a>>,
 }
 
-fn parse_old(endianness: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], TypeTree> {
+/// Meta flag of fields that are followed by padding to a multiple of four bytes.
+const ALIGN_BYTES_FLAG: u32 = 0x4000;
+
+fn malformed(input: &[u8], kind: nom::error::ErrorKind) -> nom::Err<nom::error::Error<&[u8]>> {
+    nom::Err::Error(nom::error::Error::new(input, kind))
+}
+
+fn parse_old(endianness: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], TypeTree<'_>> {
     move |input| {
         let (input, type_name) = read_string(input, None)?;
         let (input, name) = read_string(input, None)?;
@@ -33,6 +45,8 @@ fn parse_old(endianness: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], TypeTre
         let (input, flags) = u32!(input, endianness)?;
 
         let (input, field_count) = u32!(input, endianness)?;
+        // Two empty strings and six integers.
+        check_count(input, field_count, 26)?;
         let (input, children) =
             nom::multi::count(parse_old(endianness), field_count as usize)(input)?;
         let ret = TypeTree {
@@ -49,19 +63,20 @@ fn parse_old(endianness: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], TypeTre
     }
 }
 
-const STRINGS_DAT: &'static [u8] = include_bytes!("strings.dat");
+const STRINGS_DAT: &[u8] = include_bytes!("strings.dat");
 
-fn parse_blob(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8], TypeTree> {
+fn parse_blob(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8], TypeTree<'_>> {
     let (input, node_count) = u32!(input, endianness)?;
     let (input, buffer_bytes) = u32!(input, endianness)?;
     let node_bytes = if format >= 19 { 32 } else { 24 };
-    let (mut node_data, input) = input.split_at((node_count as usize) * node_bytes);
-    let (data, input) = input.split_at(buffer_bytes as usize);
+    let (input, mut node_data) =
+        nom_bytes::take((node_count as usize).saturating_mul(node_bytes))(input)?;
+    let (input, data) = nom_bytes::take(buffer_bytes as usize)(input)?;
 
     let get_string = |offset: u32| -> IResult<&[u8], Cow<'_, str>> {
         let slice = if offset >= 0x80000000 {
             let offset = (offset & 0x7fffffff) as usize;
-            &STRINGS_DAT[offset..]
+            STRINGS_DAT.get(offset..).unwrap_or_default()
         } else if offset < data.len() as u32 {
             &data[(offset as usize)..]
         } else {
@@ -81,7 +96,10 @@ fn parse_blob(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8
         let (input, depth) = nom_number::be_u8(input)?;
         while tree_stack.len() > depth as usize {
             let node = tree_stack.pop().unwrap();
-            tree_stack.last_mut().unwrap().children.push(node);
+            match tree_stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => return Err(malformed(input, nom::error::ErrorKind::Verify)),
+            }
         }
         let (input, is_array) = nom_number::be_u8(input)?;
         let is_array = is_array != 0;
@@ -92,7 +110,7 @@ fn parse_blob(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8
         let (input, size) = u32!(input, endianness)?;
         let (input, index) = u32!(input, endianness)?;
         let (input, flags) = u32!(input, endianness)?;
-        let input = &input[node_bytes - 24..];
+        let (input, _) = nom_bytes::take(node_bytes - 24)(input)?;
         let node = TypeTree {
             version: version as u32,
             is_array,
@@ -106,7 +124,9 @@ fn parse_blob(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8
         tree_stack.push(node);
         node_data = input;
     }
-    let mut node = tree_stack.pop().unwrap();
+    let mut node = tree_stack
+        .pop()
+        .ok_or_else(|| malformed(input, nom::error::ErrorKind::Verify))?;
     while let Some(mut parent) = tree_stack.pop() {
         parent.children.push(node);
         node = parent;
@@ -114,8 +134,30 @@ fn parse_blob(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8
     Ok((input, node))
 }
 
+/// Length of the type tree at the start of `input` in the blob layout, which tells it from its
+/// header without parsing the nodes.
+fn blob_len(input: &[u8], endianness: Endianness, format: u32) -> IResult<&[u8], usize> {
+    let (rest, node_count) = u32!(input, endianness)?;
+    let (_, buffer_bytes) = u32!(rest, endianness)?;
+    let node_bytes = if format >= 19 { 32 } else { 24 };
+    let len = (node_count as usize)
+        .saturating_mul(node_bytes)
+        .saturating_add(buffer_bytes as usize)
+        .saturating_add(8);
+    Ok((input, len))
+}
+
+/// Type trees already parsed, by the bytes they were parsed from.
+///
+/// The serialized files of a bundle often embed the same trees, which are then parsed once and
+/// shared rather than once per file.
+#[derive(Default)]
+pub(crate) struct TypeTreeCache<'a> {
+    trees: HashMap<(bool, u32, &'a [u8]), Arc<TypeTree<'a>>>,
+}
+
 impl<'a> TypeTree<'a> {
-    fn parse(input: &'a [u8], endianness: Endianness, format: u32) -> IResult<&[u8], Self> {
+    fn parse(input: &'a [u8], endianness: Endianness, format: u32) -> IResult<&'a [u8], Self> {
         if format == 10 || format >= 12 {
             parse_blob(input, endianness, format)
         } else {
@@ -123,8 +165,61 @@ impl<'a> TypeTree<'a> {
         }
     }
 
+    /// Parses a tree, or takes it from `cache` if the same bytes were parsed before. Only trees
+    /// in the blob layout are cached, as the length of older ones isn't known until parsed.
+    fn parse_cached(
+        input: &'a [u8],
+        endianness: Endianness,
+        format: u32,
+        cache: &mut TypeTreeCache<'a>,
+    ) -> IResult<&'a [u8], Arc<Self>> {
+        if format != 10 && format < 12 {
+            let (input, tree) = Self::parse(input, endianness, format)?;
+            return Ok((input, Arc::new(tree)));
+        }
+        let (_, len) = blob_len(input, endianness, format)?;
+        let key = match input.get(..len) {
+            Some(bytes) => (endianness == Endianness::Big, format, bytes),
+            // Too short; let parsing report it.
+            None => {
+                let (input, tree) = Self::parse(input, endianness, format)?;
+                return Ok((input, Arc::new(tree)));
+            }
+        };
+        if let Some(tree) = cache.trees.get(&key) {
+            return Ok((&input[len..], Arc::clone(tree)));
+        }
+        let (rest, tree) = Self::parse(input, endianness, format)?;
+        let tree = Arc::new(tree);
+        cache.trees.insert(key, Arc::clone(&tree));
+        Ok((rest, tree))
+    }
+
+    fn clone_owned(&self) -> TypeTree<'static> {
+        TypeTree {
+            version: self.version,
+            is_array: self.is_array,
+            type_name: self.type_name.clone().into_owned().into(),
+            name: self.name.clone().into_owned().into(),
+            size: self.size,
+            index: self.index,
+            flags: self.flags,
+            children: self.children.iter().map(Self::clone_owned).collect(),
+        }
+    }
+
+    /// Whether the stream is aligned after reading this field. This holds for fields of any kind;
+    /// strings and vectors usually have the flag on their `Array` child instead.
     fn needs_align(&self) -> bool {
-        self.flags & 0x4000 != 0
+        self.flags & ALIGN_BYTES_FLAG != 0
+    }
+
+    /// A struct of this type without any field, for objects whose data was stripped.
+    pub(crate) fn empty(&self) -> Data<'a> {
+        Data::GenericStruct {
+            type_name: self.type_name.clone(),
+            fields: Fields::default(),
+        }
     }
 
     pub fn read(
@@ -136,25 +231,29 @@ impl<'a> TypeTree<'a> {
         let base = input;
         let mut needs_align = self.needs_align();
         let (input, data) = if self.type_name == "string" {
-            debug_assert_eq!(self.children.len(), 1);
-            needs_align |= self.children[0].needs_align();
+            needs_align |= self.children.first().is_some_and(TypeTree::needs_align);
             let (input, length) = u32!(input, endianness)?;
-            let (bytes, input) = input.split_at(length as usize);
+            let (input, bytes) = nom_bytes::take(length as usize)(input)?;
             (input, Data::String(bytes.into()))
         } else if self.type_name == "pair" {
-            debug_assert_eq!(self.children.len(), 2);
-            let (input, fst) = self.children[0].read(input, endianness, offset)?;
+            let (fst_type, snd_type) = match &self.children[..] {
+                [fst, snd] => (fst, snd),
+                _ => return Err(malformed(input, nom::error::ErrorKind::Verify)),
+            };
+            let (input, fst) = fst_type.read(input, endianness, offset)?;
             let offset = offset + (input.as_ptr() as usize - base.as_ptr() as usize) as u64;
-            let (input, snd) = self.children[1].read(input, endianness, offset)?;
+            let (input, snd) = snd_type.read(input, endianness, offset)?;
             (input, Data::Pair(Box::new(fst), Box::new(snd)))
-        } else if let Some(child) = self.children.get(0).filter(|child| child.is_array) {
+        } else if let Some(child) = self.children.first().filter(|child| child.is_array) {
             child.read(input, endianness, offset)?
         } else if self.is_array {
-            debug_assert_eq!(self.children.len(), 2);
-            let element_type = &self.children[1];
+            let element_type = self
+                .children
+                .get(1)
+                .ok_or_else(|| malformed(input, nom::error::ErrorKind::Verify))?;
             let (input, length) = u32!(input, endianness)?;
             if element_type.type_name == "UInt8" {
-                let (bytes, input) = input.split_at(length as usize);
+                let (input, bytes) = nom_bytes::take(length as usize)(input)?;
                 (input, Data::UInt8Array(bytes.into()))
             } else {
                 let mut input = input;
@@ -169,16 +268,16 @@ impl<'a> TypeTree<'a> {
                     .collect::<Result<Vec<_>, _>>()?;
                 (input, Data::GenericArray(v))
             }
-        } else if self.children.len() == 0 {
+        } else if self.children.is_empty() {
             let length = self.size;
             let input = if self.type_name == "float" || self.type_name == "double" {
                 align(offset as usize, base, input)
             } else {
                 input
             };
-            let (data, input) = input.split_at(length as usize);
+            let (input, data) = nom_bytes::take(length as usize)(input)?;
             let data = match self.type_name.as_ref() {
-                "bool" => Data::Bool(data[0] != 0),
+                "bool" => Data::Bool(nom_number::be_u8(data)?.1 != 0),
                 "UInt8" => Data::UInt8(nom_number::be_u8(data)?.1),
                 "UInt16" => Data::UInt16(u16!(data, endianness)?.1),
                 "UInt32" | "unsigned int" => Data::UInt32(u32!(data, endianness)?.1),
@@ -206,7 +305,7 @@ impl<'a> TypeTree<'a> {
                     input = left;
                     Ok((field_type.name.clone(), data))
                 })
-                .collect::<Result<HashMap<_, _>, _>>()?;
+                .collect::<Result<Fields<'_>, _>>()?;
             (
                 input,
                 Data::GenericStruct {
@@ -224,17 +323,200 @@ impl<'a> TypeTree<'a> {
     }
 }
 
-#[derive(Serialize)]
-#[serde(tag = "type", content = "data")]
+/// A byte buffer of [`Data`], either borrowed from the asset or shared between owned copies.
+///
+/// Cloning only bumps a reference count, so owned data can be cloned without copying its
+/// buffers. Code written against the `Cow<[u8]>` these buffers used to be can convert them with
+/// [`Bytes::into_cow`].
+#[derive(Clone)]
+pub struct Bytes<'b>(BytesRepr<'b>);
+
+#[derive(Clone)]
+enum BytesRepr<'b> {
+    Borrowed(&'b [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl Bytes<'_> {
+    /// Copies borrowed bytes into a shared buffer; shared ones are only reference counted.
+    pub fn clone_owned(&self) -> Bytes<'static> {
+        match &self.0 {
+            BytesRepr::Borrowed(b) => Bytes(BytesRepr::Shared((*b).into())),
+            BytesRepr::Shared(b) => Bytes(BytesRepr::Shared(b.clone())),
+        }
+    }
+
+    /// Whether the bytes are borrowed from the asset rather than owned.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.0, BytesRepr::Borrowed(_))
+    }
+
+    /// Copies the bytes into a vector.
+    pub fn into_owned(self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl<'b> Bytes<'b> {
+    /// Borrowed bytes stay borrowed; shared ones are copied.
+    pub fn into_cow(self) -> Cow<'b, [u8]> {
+        self.into()
+    }
+}
+
+impl<'b> From<Bytes<'b>> for Cow<'b, [u8]> {
+    fn from(b: Bytes<'b>) -> Self {
+        match b.0 {
+            BytesRepr::Borrowed(b) => Cow::Borrowed(b),
+            BytesRepr::Shared(b) => Cow::Owned(b.to_vec()),
+        }
+    }
+}
+
+impl std::ops::Deref for Bytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            BytesRepr::Borrowed(b) => b,
+            BytesRepr::Shared(b) => b,
+        }
+    }
+}
+
+impl AsRef<[u8]> for Bytes<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<'b> From<&'b [u8]> for Bytes<'b> {
+    fn from(b: &'b [u8]) -> Self {
+        Bytes(BytesRepr::Borrowed(b))
+    }
+}
+
+impl From<Vec<u8>> for Bytes<'static> {
+    fn from(b: Vec<u8>) -> Self {
+        Bytes(BytesRepr::Shared(b.into()))
+    }
+}
+
+impl std::fmt::Debug for Bytes<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        (**self).fmt(fmt)
+    }
+}
+
+impl PartialEq for Bytes<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Bytes<'_> {}
+
+/// Fields of a struct, in the order they are serialized.
+///
+/// Lookups by name scan the fields, which is fast enough for the few fields structs have.
+#[derive(Clone, Default)]
+pub struct Fields<'b>(Vec<(Cow<'b, str>, Data<'b>)>);
+
+impl<'b> Fields<'b> {
+    pub fn get(&self, name: &str) -> Option<&Data<'b>> {
+        self.0.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Names and values of the fields, in serialized order.
+    pub fn iter(&self) -> FieldsIter<'_, 'b> {
+        FieldsIter(self.0.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Cow<'b, str>> + '_ {
+        self.0.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Data<'b>> + '_ {
+        self.0.iter().map(|(_, v)| v)
+    }
+}
+
+impl std::fmt::Debug for Fields<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'b> std::iter::FromIterator<(Cow<'b, str>, Data<'b>)> for Fields<'b> {
+    fn from_iter<I: IntoIterator<Item = (Cow<'b, str>, Data<'b>)>>(iter: I) -> Self {
+        Fields(iter.into_iter().collect())
+    }
+}
+
+impl<'b> IntoIterator for Fields<'b> {
+    type Item = (Cow<'b, str>, Data<'b>);
+    type IntoIter = std::vec::IntoIter<(Cow<'b, str>, Data<'b>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'a Fields<'b> {
+    type Item = (&'a Cow<'b, str>, &'a Data<'b>);
+    type IntoIter = FieldsIter<'a, 'b>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the fields of a struct, returned by [`Fields::iter`].
+#[derive(Clone)]
+pub struct FieldsIter<'a, 'b>(std::slice::Iter<'a, (Cow<'b, str>, Data<'b>)>);
+
+impl<'a, 'b> Iterator for FieldsIter<'a, 'b> {
+    type Item = (&'a Cow<'b, str>, &'a Data<'b>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for FieldsIter<'_, '_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(k, v)| (k, v))
+    }
+}
+
+impl ExactSizeIterator for FieldsIter<'_, '_> {}
+
+#[derive(Clone)]
 pub enum Data<'b> {
     GenericPrimitive {
         type_name: Cow<'b, str>,
-        data: Cow<'b, [u8]>,
+        data: Bytes<'b>,
     },
     GenericArray(Vec<Data<'b>>),
     GenericStruct {
         type_name: Cow<'b, str>,
-        fields: HashMap<Cow<'b, str>, Data<'b>>,
+        fields: Fields<'b>,
     },
     Bool(bool),
     UInt8(u8),
@@ -247,8 +529,8 @@ pub enum Data<'b> {
     SInt64(i64),
     Float(f32),
     Double(f64),
-    String(Cow<'b, [u8]>),
-    UInt8Array(Cow<'b, [u8]>),
+    String(Bytes<'b>),
+    UInt8Array(Bytes<'b>),
     Pair(Box<Data<'b>>, Box<Data<'b>>),
 }
 
@@ -302,12 +584,252 @@ impl std::fmt::Debug for Data<'_> {
     }
 }
 
+/// Structs become maps keyed by field name, in serialized order, and byte buffers become base64
+/// strings. Strings that aren't valid UTF-8 are treated as byte buffers.
+impl Serialize for Data<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Data::GenericPrimitive { data, .. } => serializer.serialize_str(&base64_encode(data)),
+            Data::GenericArray(arr) => {
+                let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+                for v in arr {
+                    seq.serialize_element(v)?;
+                }
+                seq.end()
+            }
+            Data::GenericStruct { fields, .. } => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (k, v) in fields {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+            Data::Bool(v) => serializer.serialize_bool(*v),
+            Data::UInt8(v) => serializer.serialize_u8(*v),
+            Data::UInt16(v) => serializer.serialize_u16(*v),
+            Data::UInt32(v) => serializer.serialize_u32(*v),
+            Data::UInt64(v) => serializer.serialize_u64(*v),
+            Data::SInt8(v) => serializer.serialize_i8(*v),
+            Data::SInt16(v) => serializer.serialize_i16(*v),
+            Data::SInt32(v) => serializer.serialize_i32(*v),
+            Data::SInt64(v) => serializer.serialize_i64(*v),
+            Data::Float(v) => serializer.serialize_f32(*v),
+            Data::Double(v) => serializer.serialize_f64(*v),
+            Data::String(b) => match std::str::from_utf8(b) {
+                Ok(s) => serializer.serialize_str(s),
+                Err(_) => serializer.serialize_str(&base64_encode(b)),
+            },
+            Data::UInt8Array(b) => serializer.serialize_str(&base64_encode(b)),
+            Data::Pair(fst, snd) => {
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element(fst)?;
+                seq.serialize_element(snd)?;
+                seq.end()
+            }
+        }
+    }
+}
+
+/// Number of array elements and bytes shown by the `Display` implementation of [`Data`].
+const DISPLAY_ELEMENTS: usize = 4;
+const DISPLAY_BYTES: usize = 16;
+
+/// Writes the data as an indented tree: structs with a line per field in serialized order, arrays
+/// with their length and first few elements, and scalars inline.
+impl std::fmt::Display for Data<'_> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.write_tree(fmt, 0)
+    }
+}
+
+impl Data<'_> {
+    fn write_tree(&self, fmt: &mut std::fmt::Formatter, indent: usize) -> std::fmt::Result {
+        let child_indent = indent + 2;
+        let write_bytes = |fmt: &mut std::fmt::Formatter, name: &str, data: &[u8]| {
+            write!(fmt, "{}({} bytes)", name, data.len())?;
+            for b in data.iter().take(DISPLAY_BYTES) {
+                write!(fmt, " {:02x}", b)?;
+            }
+            if data.len() > DISPLAY_BYTES {
+                fmt.write_str(" ...")?;
+            }
+            Ok(())
+        };
+        match self {
+            Data::GenericPrimitive { type_name, data } => write_bytes(fmt, type_name, data),
+            Data::GenericArray(arr) => {
+                write!(fmt, "Array({})", arr.len())?;
+                for (idx, v) in arr.iter().take(DISPLAY_ELEMENTS).enumerate() {
+                    write!(fmt, "\n{:indent$}[{}]: ", "", idx, indent = child_indent)?;
+                    v.write_tree(fmt, child_indent)?;
+                }
+                if arr.len() > DISPLAY_ELEMENTS {
+                    write!(fmt, "\n{:indent$}...", "", indent = child_indent)?;
+                }
+                Ok(())
+            }
+            Data::GenericStruct { type_name, fields } => {
+                fmt.write_str(type_name)?;
+                for (k, v) in fields {
+                    write!(fmt, "\n{:indent$}{}: ", "", k, indent = child_indent)?;
+                    v.write_tree(fmt, child_indent)?;
+                }
+                Ok(())
+            }
+            Data::Bool(v) => write!(fmt, "{}", v),
+            Data::UInt8(v) => write!(fmt, "{}", v),
+            Data::UInt16(v) => write!(fmt, "{}", v),
+            Data::UInt32(v) => write!(fmt, "{}", v),
+            Data::UInt64(v) => write!(fmt, "{}", v),
+            Data::SInt8(v) => write!(fmt, "{}", v),
+            Data::SInt16(v) => write!(fmt, "{}", v),
+            Data::SInt32(v) => write!(fmt, "{}", v),
+            Data::SInt64(v) => write!(fmt, "{}", v),
+            Data::Float(v) => write!(fmt, "{}", v),
+            Data::Double(v) => write!(fmt, "{}", v),
+            Data::String(b) => write!(fmt, "{:?}", String::from_utf8_lossy(b)),
+            Data::UInt8Array(b) => write_bytes(fmt, "ByteArray", b),
+            Data::Pair(fst, snd) => {
+                fmt.write_str("pair")?;
+                write!(fmt, "\n{:indent$}first: ", "", indent = child_indent)?;
+                fst.write_tree(fmt, child_indent)?;
+                write!(fmt, "\n{:indent$}second: ", "", indent = child_indent)?;
+                snd.write_tree(fmt, child_indent)
+            }
+        }
+    }
+}
+
+/// Accessors that return `None` when the data is of another kind.
+///
+/// The integer accessors also accept narrower integer types of the same signedness.
+impl<'b> Data<'b> {
+    /// Looks up a field of a struct.
+    pub fn field(&self, name: &str) -> Option<&Data<'b>> {
+        self.as_struct()?.get(name)
+    }
+
+    /// Looks up a field of a struct that is itself a struct, such as `m_StreamData`, borrowing
+    /// its fields.
+    pub fn get_struct(&self, name: &str) -> Option<&Fields<'b>> {
+        self.field(name)?.as_struct()
+    }
+
+    /// Looks up a `PPtr<T>` field of a struct, such as `m_Script`, as `(m_FileID, m_PathID)`.
+    pub fn get_pptr(&self, name: &str) -> Option<(i32, i64)> {
+        self.field(name)?.as_pptr()
+    }
+
+    pub fn as_struct(&self) -> Option<&Fields<'b>> {
+        match self {
+            Data::GenericStruct { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Data<'b>]> {
+        match self {
+            Data::GenericArray(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a string or a byte array.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Data::String(b) | Data::UInt8Array(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Returns a string, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Data::String(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Data::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Data::SInt8(v) => Some((*v).into()),
+            Data::SInt16(v) => Some((*v).into()),
+            Data::SInt32(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Data::SInt64(v) => Some(*v),
+            v => v.as_i32().map(Into::into),
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Data::UInt8(v) => Some((*v).into()),
+            Data::UInt16(v) => Some((*v).into()),
+            Data::UInt32(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Data::UInt64(v) => Some(*v),
+            v => v.as_u32().map(Into::into),
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Data::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Data::Double(v) => Some(*v),
+            v => v.as_f32().map(Into::into),
+        }
+    }
+}
+
 impl Data<'_> {
+    /// Returns `(m_FileID, m_PathID)` if this is a `PPtr<T>` struct.
+    pub fn as_pptr(&self) -> Option<(i32, i64)> {
+        match self {
+            Data::GenericStruct { type_name, fields } if type_name.starts_with("PPtr<") => {
+                let file_id = match fields.get("m_FileID")? {
+                    Data::SInt32(v) => *v,
+                    _ => return None,
+                };
+                let path_id = match fields.get("m_PathID")? {
+                    Data::SInt64(v) => *v,
+                    Data::SInt32(v) => (*v).into(),
+                    _ => return None,
+                };
+                Some((file_id, path_id))
+            }
+            _ => None,
+        }
+    }
+
+    /// Detaches the data from the asset it was read from, copying only the borrowed buffers.
     pub fn clone_owned(&self) -> Data<'static> {
         match self {
             Data::Pair(f, s) => Data::Pair(Box::new(f.clone_owned()), Box::new(s.clone_owned())),
-            Data::UInt8Array(b) => Data::UInt8Array(b.clone().into_owned().into()),
-            Data::String(b) => Data::String(b.clone().into_owned().into()),
+            Data::UInt8Array(b) => Data::UInt8Array(b.clone_owned()),
+            Data::String(b) => Data::String(b.clone_owned()),
             Data::GenericArray(v) => Data::GenericArray(v.iter().map(Self::clone_owned).collect()),
             Data::GenericStruct { type_name, fields } => Data::GenericStruct {
                 type_name: type_name.clone().into_owned().into(),
@@ -318,7 +840,7 @@ impl Data<'_> {
             },
             Data::GenericPrimitive { type_name, data } => Data::GenericPrimitive {
                 type_name: type_name.clone().into_owned().into(),
-                data: data.clone().into_owned().into(),
+                data: data.clone_owned(),
             },
             Data::Bool(v) => Data::Bool(*v),
             Data::UInt8(v) => Data::UInt8(*v),
@@ -336,10 +858,14 @@ impl Data<'_> {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct TypeMetadataEntry<'a> {
     class_id: i32,
-    hash: Option<&'a [u8]>,
-    tree: Option<TypeTree<'a>>,
+    hash: Option<Cow<'a, [u8]>>,
+    /// Shared with the entries of other files of the bundle that embed the same tree.
+    tree: Option<Arc<TypeTree<'a>>>,
+    /// Positions of the types this one refers to, stored with the tree since format 21.
+    dependencies: Vec<i32>,
 }
 
 impl<'a> TypeMetadataEntry<'a> {
@@ -348,13 +874,14 @@ impl<'a> TypeMetadataEntry<'a> {
         endianness: Endianness,
         format: u32,
         has_type_trees: bool,
+        cache: &mut TypeTreeCache<'a>,
     ) -> IResult<&'a [u8], Self> {
         let (input, class_id) = i32!(input, endianness)?;
         let (input, class_id) = if format >= 17 {
-            let input = &input[1..];
+            let (input, _) = nom_bytes::take(1usize)(input)?;
             let (input, script_id) = i16!(input, endianness)?;
             let script_id: i32 = script_id.into();
-            let class_id = if class_id == 114 {
+            let class_id = if class_id == MONO_BEHAVIOUR_CLASS_ID {
                 if script_id >= 0 {
                     -2 - script_id
                 } else {
@@ -367,27 +894,40 @@ impl<'a> TypeMetadataEntry<'a> {
         } else {
             (input, class_id)
         };
-        let (hash, input) = if class_id < 0 {
-            input.split_at(0x20)
-        } else {
-            input.split_at(0x10)
-        };
+        let (input, hash) = nom_bytes::take(if class_id < 0 { 0x20usize } else { 0x10 })(input)?;
         let (input, tree) = if has_type_trees {
-            let (input, tree) = TypeTree::parse(input, endianness, format)?;
+            let (input, tree) = TypeTree::parse_cached(input, endianness, format, cache)?;
             (input, Some(tree))
         } else {
             (input, None)
         };
+        let (input, dependencies) = if has_type_trees && format >= 21 {
+            let (input, count) = u32!(input, endianness)?;
+            check_count(input, count, 4)?;
+            nom::multi::count(|input| i32!(input, endianness), count as usize)(input)?
+        } else {
+            (input, Vec::new())
+        };
         Ok((
             input,
             Self {
                 class_id,
-                hash: Some(hash),
+                hash: Some(hash.into()),
                 tree,
+                dependencies,
             },
         ))
     }
 
+    fn into_owned(self) -> TypeMetadataEntry<'static> {
+        TypeMetadataEntry {
+            class_id: self.class_id,
+            hash: self.hash.map(|hash| hash.into_owned().into()),
+            tree: self.tree.map(|tree| Arc::new(tree.clone_owned())),
+            dependencies: self.dependencies,
+        }
+    }
+
     fn parse_old(input: &'a [u8], endianness: Endianness, format: u32) -> IResult<&'a [u8], Self> {
         let (input, class_id) = i32!(input, endianness)?;
         let (input, tree) = TypeTree::parse(input, endianness, format)?;
@@ -396,34 +936,46 @@ impl<'a> TypeMetadataEntry<'a> {
             Self {
                 class_id,
                 hash: None,
-                tree: Some(tree),
+                tree: Some(Arc::new(tree)),
+                dependencies: Vec::new(),
             },
         ))
     }
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct TypeMetadata<'a> {
     generator_version: Cow<'a, str>,
     target_platform: u32,
-    class_ids: Vec<i32>,
-    entries: HashMap<i32, TypeMetadataEntry<'a>>,
+    /// Types in file order, each parsed once however many objects share it.
+    entries: Vec<TypeMetadataEntry<'a>>,
+    /// Position of each type by class ID, which objects refer to types by before format 17.
+    by_class_id: HashMap<i32, usize>,
+    /// Whether objects refer to types by position, as they do since format 17. Several types
+    /// can then share a class ID, such as `MonoBehaviour`s of scripts stripped of their index.
+    by_index: bool,
 }
 
 impl<'a> TypeMetadata<'a> {
-    pub fn parse(input: &'a [u8], endianness: Endianness, format: u32) -> IResult<&'a [u8], Self> {
+    pub fn parse(
+        input: &'a [u8],
+        endianness: Endianness,
+        format: u32,
+        cache: &mut TypeTreeCache<'a>,
+    ) -> IResult<&'a [u8], Self> {
         let (input, generator_version) = read_string(input, None)?;
         let (input, target_platform) = u32!(input, endianness)?;
 
         let (input, entries) = if format >= 13 {
-            let has_type_trees = input[0] != 0;
-            let input = &input[1..];
+            let (input, has_type_trees) = nom_number::u8(input)?;
+            let has_type_trees = has_type_trees != 0;
             let (mut input, num_types) = u32!(input, endianness)?;
 
             let entries = (0..num_types)
                 .map(|_| {
                     let (left, entry) =
-                        TypeMetadataEntry::parse(input, endianness, format, has_type_trees)?;
+                        TypeMetadataEntry::parse(input, endianness, format, has_type_trees, cache)?;
                     input = left;
                     Ok(entry)
                 })
@@ -440,10 +992,10 @@ impl<'a> TypeMetadata<'a> {
                 .collect::<Result<Vec<_>, _>>()?;
             (input, entries)
         };
-        let class_ids = entries.iter().map(|entry| entry.class_id).collect();
-        let entries = entries
-            .into_iter()
-            .map(|entry| (entry.class_id, entry))
+        let by_class_id = entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| (entry.class_id, idx))
             .collect();
 
         Ok((
@@ -451,28 +1003,80 @@ impl<'a> TypeMetadata<'a> {
             Self {
                 generator_version,
                 target_platform,
-                class_ids,
                 entries,
+                by_class_id,
+                by_index: format >= 17,
             },
         ))
     }
 
-    pub fn class_id_from_idx(&self, idx: usize) -> i32 {
-        self.class_ids[idx]
+    /// Version of the engine that wrote the file, such as `2019.4.40f1`.
+    pub fn unity_version(&self) -> &str {
+        &self.generator_version
     }
 
+    pub fn target_platform(&self) -> Platform {
+        Platform::from_u32(self.target_platform)
+    }
+
+    pub fn into_owned(self) -> TypeMetadata<'static> {
+        TypeMetadata {
+            generator_version: self.generator_version.into_owned().into(),
+            target_platform: self.target_platform,
+            entries: self
+                .entries
+                .into_iter()
+                .map(TypeMetadataEntry::into_owned)
+                .collect(),
+            by_class_id: self.by_class_id,
+            by_index: self.by_index,
+        }
+    }
+
+    pub fn class_id_from_idx(&self, idx: usize) -> Option<i32> {
+        self.entries.get(idx).map(|entry| entry.class_id)
+    }
+
+    fn entry_by_class_id(&self, class_id: i32) -> Option<&TypeMetadataEntry<'a>> {
+        self.by_class_id
+            .get(&class_id)
+            .and_then(|&idx| self.entries.get(idx))
+    }
+
+    /// Finds the type tree for an object, preferring the one embedded in the file.
+    ///
+    /// `type_id` is the position of the type since format 17, and its class ID before.
+    ///
+    /// Files built without type trees fall back to the built-in trees. Script types have no
+    /// built-in tree, so they are read as a plain `MonoBehaviour`, leaving out script fields.
     pub fn type_tree_from_id(&self, type_id: i32, class_id: i32) -> Option<&TypeTree<'a>> {
-        self.entries
-            .get(&type_id)
-            .or_else(|| DEFAULT_TYPES.entries.get(&class_id))
-            .and_then(|entry| entry.tree.as_ref())
+        let default_class_id = if class_id < 0 {
+            MONO_BEHAVIOUR_CLASS_ID
+        } else {
+            class_id
+        };
+        let entry = if self.by_index {
+            usize::try_from(type_id)
+                .ok()
+                .and_then(|idx| self.entries.get(idx))
+        } else {
+            self.entry_by_class_id(type_id)
+        };
+        entry.and_then(|entry| entry.tree.as_deref()).or_else(|| {
+            DEFAULT_TYPES
+                .entry_by_class_id(default_class_id)
+                .and_then(|entry| entry.tree.as_deref())
+        })
     }
 }
 
-const DEFAULT_STRUCTS: &'static [u8] = include_bytes!("structs.dat");
+pub(crate) const MONO_BEHAVIOUR_CLASS_ID: i32 = 114;
+
+const DEFAULT_STRUCTS: &[u8] = include_bytes!("structs.dat");
 
 lazy_static::lazy_static! {
     static ref DEFAULT_TYPES: TypeMetadata<'static> = {
-        TypeMetadata::parse(DEFAULT_STRUCTS, Endianness::Little, 15).unwrap().1
+        let mut cache = TypeTreeCache::default();
+        TypeMetadata::parse(DEFAULT_STRUCTS, Endianness::Little, 15, &mut cache).unwrap().1
     };
 }
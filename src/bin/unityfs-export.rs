@@ -0,0 +1,118 @@
+use unityfs::texture::{self, DecodeFormat};
+use unityfs::{Data, Platform, UnityFs};
+
+/// Decodes a `Texture2D` object of an asset built for `target_platform` into its name and PNG
+/// encoding.
+fn export_texture(
+    fs: &UnityFs<'_>,
+    target_platform: Platform,
+    data: &Data<'_>,
+) -> Result<(String, Vec<u8>), String> {
+    let name = data
+        .field("m_Name")
+        .and_then(Data::as_bytes)
+        .ok_or("m_Name not found")?;
+    let name = String::from_utf8_lossy(name).into_owned();
+    let width = data
+        .field("m_Width")
+        .and_then(Data::as_i32)
+        .ok_or("m_Width not found")? as u32;
+    let height = data
+        .field("m_Height")
+        .and_then(Data::as_i32)
+        .ok_or("m_Height not found")? as u32;
+    let format_id = data
+        .field("m_TextureFormat")
+        .and_then(Data::as_i32)
+        .ok_or("m_TextureFormat not found")?;
+    let format = DecodeFormat::from_texture_format(
+        format_id,
+        data.field("m_ForcedFallbackFormat").is_some(),
+    )
+    .ok_or_else(|| format!("{}: unsupported texture format {}", name, format_id))?;
+    let format = match data.field("m_PlatformBlob").and_then(Data::as_bytes) {
+        Some(blob) if target_platform == Platform::Switch => format.switch_swizzled(blob),
+        _ => format,
+    };
+
+    let stream_data = data.field("m_StreamData");
+    let stream_path = stream_data
+        .and_then(|info| info.field("path"))
+        .and_then(Data::as_bytes)
+        .unwrap_or_default();
+    let image_data = if stream_path.is_empty() {
+        data.field("image data")
+            .and_then(Data::as_bytes)
+            .ok_or_else(|| format!("{}: image data not found", name))?
+    } else {
+        let path = String::from_utf8_lossy(stream_path);
+        let range = stream_data.and_then(|info| {
+            Some((
+                info.field("offset")?.as_u64()?,
+                info.field("size")?.as_u64()?,
+            ))
+        });
+        range
+            .and_then(|(offset, size)| fs.stream_data(&path, offset, size))
+            .ok_or_else(|| format!("{}: streamed data not found at {}", name, path))?
+    };
+
+    let rgba = format
+        .decode(width, height, image_data)
+        .map_err(|e| format!("{}: failed to decode: {}", name, e))?;
+    let png = texture::encode_png(width, height, &rgba).map_err(|e| format!("{}: {}", name, e))?;
+    Ok((name, png))
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let filename = args.next().expect("Expected filename");
+    let out_dir = std::path::PathBuf::from(args.next().expect("Expected output directory"));
+    let buf = std::fs::read(filename).expect("Failed to read file");
+
+    let (_, meta) = match unityfs::UnityFsMeta::parse(&buf) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to parse: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let fs = match meta.read_unityfs() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to read: {}", e);
+            std::process::exit(1);
+        }
+    };
+    std::fs::create_dir_all(&out_dir).expect("Failed to create output directory");
+    // Textures often share a name, so later ones get their path ID appended, and a counter if
+    // that is taken too.
+    let mut used_names = std::collections::HashSet::new();
+    for (asset, object) in fs.all_objects() {
+        match &object.data {
+            Data::GenericStruct { type_name, .. } if type_name == "Texture2D" => {}
+            _ => continue,
+        }
+        match export_texture(&fs, asset.asset().target_platform(), &object.data) {
+            Ok((name, png)) => {
+                let base_name = name.replace(['/', '\\'], "_");
+                let mut file_name = base_name.clone();
+                let mut repeats = 0;
+                while !used_names.insert(file_name.clone()) {
+                    repeats += 1;
+                    file_name = match repeats {
+                        1 => format!("{}_{}", base_name, object.path_id),
+                        _ => format!("{}_{}_{}", base_name, object.path_id, repeats),
+                    };
+                }
+                let path = out_dir.join(format!("{}.png", file_name));
+                if let Err(e) = std::fs::write(&path, png) {
+                    eprintln!("Failed to write {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+                println!("{}", path.display());
+            }
+            Err(e) => eprintln!("Skipping texture: {}", e),
+        }
+    }
+}
@@ -4,7 +4,7 @@ fn main() {
     let buf = std::fs::read(filename).expect("Failed to read file");
 
     let (_, meta) = unityfs::UnityFsMeta::parse(&buf).unwrap();
-    let fs = meta.read_unityfs();
+    let fs = meta.read_unityfs().unwrap();
     println!("{}", fs.name());
     println!("{:#?}", fs.main_asset().objects());
 }
@@ -6,11 +6,11 @@ fn main() {
     let (_, meta) = match unityfs::UnityFsMeta::parse(&buf) {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("Failed to parse: {:?}", e);
+            eprintln!("Failed to parse: {}", e);
             std::process::exit(1);
         }
     };
-    let fs = meta.read_unityfs();
+    let fs = meta.read_unityfs().unwrap();
     let asset = fs.main_asset();
     for object in asset.objects() {
         match &object.data {
@@ -4,9 +4,8 @@ fn main() {
     let buf = std::fs::read(filename).expect("Failed to read file");
 
     let (_, meta) = unityfs::UnityFsMeta::parse(&buf).unwrap();
-    let fs = meta.read_unityfs();
-    let asset = fs.main_asset();
-    for object in asset.objects() {
-        println!("{}", object.type_name(&asset))
+    let fs = meta.read_unityfs().unwrap();
+    for (asset, object) in fs.all_objects() {
+        println!("{}", object.type_name(asset.asset()))
     }
 }
@@ -12,3 +12,15 @@ pub fn read_string(input: &[u8], length: Option<usize>) -> IResult<&[u8], Cow<'_
     }?;
     Ok((input, String::from_utf8_lossy(s)))
 }
+
+/// Rejects entry counts that can't fit in the remaining input, so that a corrupt count doesn't
+/// cause a huge allocation.
+pub fn check_count(input: &[u8], count: u32, min_entry_size: usize) -> IResult<&[u8], ()> {
+    if (count as usize).saturating_mul(min_entry_size) > input.len() {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Count,
+        )));
+    }
+    Ok((input, ()))
+}
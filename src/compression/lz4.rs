@@ -7,7 +7,7 @@ struct Sequences<'a> {
 }
 
 impl<'a> Sequences<'a> {
-    fn new(input: &'a [u8]) -> Sequences {
+    fn new(input: &'a [u8]) -> Sequences<'a> {
         Self {
             input,
             output_length: 0,
@@ -74,8 +74,11 @@ impl<'a> Iterator for Sequences<'a> {
             lit_len += 0xff * count + input[count] as usize;
             input = &input[1 + count..];
         }
-        let lit = &input[0..lit_len];
-        input = &input[lit_len..];
+        if input.len() < lit_len {
+            return Some(Err(Lz4Error::UnexpectedEnd));
+        }
+        let (lit, left) = input.split_at(lit_len);
+        input = left;
         self.output_length += lit_len;
         if input.is_empty() {
             self.input = input;
@@ -94,11 +97,17 @@ impl<'a> Iterator for Sequences<'a> {
         }
         input = &input[2..];
         if match_len == 0x0f {
-            let count = input.iter().position(|&b| b != 0xff)?;
+            let count = input.iter().position(|&b| b != 0xff);
+            let count = match count {
+                Some(count) => count,
+                None => return Some(Err(Lz4Error::UnexpectedEnd)),
+            };
             match_len += 0xff * count + input[count] as usize;
             input = &input[1 + count..];
         }
         self.input = input;
+        // LZ4HC emits the same sequences with lookbacks reaching the whole 64 KiB window, so
+        // offsets are only checked against what has been decoded so far.
         self.output_length += match_len + MIN_MATCH_LEN;
         Some(Ok(Sequence {
             literal: lit,
@@ -134,3 +143,69 @@ pub fn decode_block(input: &[u8]) -> Result<Vec<u8>, Lz4Error> {
     }
     Ok(out)
 }
+
+/// Number of bytes at the end of a block that are always literals.
+const LAST_LITERALS: usize = 5;
+/// Matches have to start at least this many bytes before the end of a block.
+const MATCH_FIND_LIMIT: usize = 12;
+const HASH_LOG: u32 = 16;
+
+fn read_u32(input: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([input[pos], input[pos + 1], input[pos + 2], input[pos + 3]])
+}
+
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 0xff {
+        out.push(0xff);
+        len -= 0xff;
+    }
+    out.push(len as u8);
+}
+
+fn write_sequence(out: &mut Vec<u8>, literal: &[u8], match_copy: Option<MatchCopyInfo>) {
+    let match_len = match_copy.map_or(0, |m| m.length - MIN_MATCH_LEN);
+    out.push(((std::cmp::min(literal.len(), 15) as u8) << 4) | std::cmp::min(match_len, 15) as u8);
+    if literal.len() >= 15 {
+        write_length(out, literal.len() - 15);
+    }
+    out.extend_from_slice(literal);
+    if let Some(match_copy) = match_copy {
+        out.extend_from_slice(&match_copy.offset.to_le_bytes());
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+/// Compresses `input` into one LZ4 block, finding matches greedily through a hash table of the
+/// last position each four bytes were seen at.
+pub fn encode_block(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() + input.len() / 0xff + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+    let match_end = input.len().saturating_sub(LAST_LITERALS);
+    while pos + MATCH_FIND_LIMIT < input.len() {
+        let seq = read_u32(input, pos);
+        let hash = (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize;
+        let candidate = std::mem::replace(&mut table[hash], pos);
+        if candidate == usize::MAX || pos - candidate > 0xffff || read_u32(input, candidate) != seq
+        {
+            pos += 1;
+            continue;
+        }
+        let mut length = MIN_MATCH_LEN;
+        while pos + length < match_end && input[candidate + length] == input[pos + length] {
+            length += 1;
+        }
+        let match_copy = MatchCopyInfo {
+            offset: (pos - candidate) as u16,
+            length,
+        };
+        write_sequence(&mut out, &input[anchor..pos], Some(match_copy));
+        pos += length;
+        anchor = pos;
+    }
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
@@ -0,0 +1,396 @@
+const NUM_BIT_MODEL_TOTAL_BITS: u32 = 11;
+const BIT_MODEL_TOTAL: u32 = 1 << NUM_BIT_MODEL_TOTAL_BITS;
+const NUM_MOVE_BITS: u32 = 5;
+const PROB_INIT: u16 = (BIT_MODEL_TOTAL / 2) as u16;
+const TOP_VALUE: u32 = 1 << 24;
+
+const NUM_STATES: usize = 12;
+const NUM_POS_BITS_MAX: usize = 4;
+const NUM_LEN_TO_POS_STATES: usize = 4;
+const NUM_ALIGN_BITS: u32 = 4;
+const START_POS_MODEL_INDEX: u32 = 4;
+const END_POS_MODEL_INDEX: u32 = 14;
+const NUM_FULL_DISTANCES: usize = 1 << (END_POS_MODEL_INDEX >> 1);
+const MATCH_MIN_LEN: usize = 2;
+/// Compression ratio assumed when reserving the output, which still grows past it if needed.
+const CAPACITY_RATIO: usize = 32;
+/// More than any stream can reach: a probability stops adapting at 31/2048, so a repeated
+/// 273-byte match costs at least 14 bits of about 0.022 bits each, which is about 7000 bytes out
+/// for every byte in.
+pub(crate) const MAX_RATIO: u64 = 8192;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LzmaError {
+    UnexpectedEnd,
+    InvalidProperties,
+    Corrupted,
+}
+
+impl std::fmt::Display for LzmaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            LzmaError::UnexpectedEnd => "unexpected end of input",
+            LzmaError::InvalidProperties => "invalid LZMA properties",
+            LzmaError::Corrupted => "corrupted LZMA stream",
+        };
+        f.write_str(s)
+    }
+}
+
+impl std::error::Error for LzmaError {}
+
+struct RangeDecoder<'a> {
+    input: &'a [u8],
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(input: &'a [u8]) -> Result<Self, LzmaError> {
+        if input.len() < 5 {
+            return Err(LzmaError::UnexpectedEnd);
+        }
+        let code = u32::from_be_bytes([input[1], input[2], input[3], input[4]]);
+        let range = 0xffffffff;
+        if input[0] != 0 || code == range {
+            return Err(LzmaError::Corrupted);
+        }
+        Ok(Self {
+            input: &input[5..],
+            range,
+            code,
+        })
+    }
+
+    fn normalize(&mut self) -> Result<(), LzmaError> {
+        if self.range < TOP_VALUE {
+            let (&b, input) = self.input.split_first().ok_or(LzmaError::UnexpectedEnd)?;
+            self.input = input;
+            self.range <<= 8;
+            self.code = (self.code << 8) | u32::from(b);
+        }
+        Ok(())
+    }
+
+    fn decode_direct_bits(&mut self, num_bits: u32) -> Result<u32, LzmaError> {
+        let mut res = 0u32;
+        for _ in 0..num_bits {
+            self.range >>= 1;
+            self.code = self.code.wrapping_sub(self.range);
+            let t = 0u32.wrapping_sub(self.code >> 31);
+            self.code = self.code.wrapping_add(self.range & t);
+            if self.code == self.range {
+                return Err(LzmaError::Corrupted);
+            }
+            self.normalize()?;
+            res = (res << 1).wrapping_add(t.wrapping_add(1));
+        }
+        Ok(res)
+    }
+
+    fn decode_bit(&mut self, prob: &mut u16) -> Result<u32, LzmaError> {
+        let v = u32::from(*prob);
+        let bound = (self.range >> NUM_BIT_MODEL_TOTAL_BITS) * v;
+        let symbol = if self.code < bound {
+            *prob = (v + ((BIT_MODEL_TOTAL - v) >> NUM_MOVE_BITS)) as u16;
+            self.range = bound;
+            0
+        } else {
+            *prob = (v - (v >> NUM_MOVE_BITS)) as u16;
+            self.code -= bound;
+            self.range -= bound;
+            1
+        };
+        self.normalize()?;
+        Ok(symbol)
+    }
+
+    fn decode_tree(&mut self, probs: &mut [u16], num_bits: u32) -> Result<u32, LzmaError> {
+        let mut m = 1usize;
+        for _ in 0..num_bits {
+            m = (m << 1) + self.decode_bit(&mut probs[m])? as usize;
+        }
+        Ok(m as u32 - (1 << num_bits))
+    }
+
+    fn decode_tree_reverse(&mut self, probs: &mut [u16], num_bits: u32) -> Result<u32, LzmaError> {
+        let mut m = 1usize;
+        let mut symbol = 0u32;
+        for i in 0..num_bits {
+            let bit = self.decode_bit(&mut probs[m])?;
+            m = (m << 1) + bit as usize;
+            symbol |= bit << i;
+        }
+        Ok(symbol)
+    }
+}
+
+struct LenDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 1 << 3]; 1 << NUM_POS_BITS_MAX],
+    mid: [[u16; 1 << 3]; 1 << NUM_POS_BITS_MAX],
+    high: [u16; 1 << 8],
+}
+
+impl LenDecoder {
+    fn new() -> Self {
+        Self {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; 1 << 3]; 1 << NUM_POS_BITS_MAX],
+            mid: [[PROB_INIT; 1 << 3]; 1 << NUM_POS_BITS_MAX],
+            high: [PROB_INIT; 1 << 8],
+        }
+    }
+
+    fn decode(&mut self, rc: &mut RangeDecoder<'_>, pos_state: usize) -> Result<usize, LzmaError> {
+        let len = if rc.decode_bit(&mut self.choice)? == 0 {
+            rc.decode_tree(&mut self.low[pos_state], 3)?
+        } else if rc.decode_bit(&mut self.choice2)? == 0 {
+            8 + rc.decode_tree(&mut self.mid[pos_state], 3)?
+        } else {
+            16 + rc.decode_tree(&mut self.high, 8)?
+        };
+        Ok(len as usize)
+    }
+}
+
+struct Decoder {
+    lc: u32,
+    lp: u32,
+    pb: u32,
+    literal: Vec<u16>,
+    pos_slot: [[u16; 1 << 6]; NUM_LEN_TO_POS_STATES],
+    pos: [u16; 1 + NUM_FULL_DISTANCES - END_POS_MODEL_INDEX as usize],
+    align: [u16; 1 << NUM_ALIGN_BITS],
+    is_match: [u16; NUM_STATES << NUM_POS_BITS_MAX],
+    is_rep: [u16; NUM_STATES],
+    is_rep_g0: [u16; NUM_STATES],
+    is_rep_g1: [u16; NUM_STATES],
+    is_rep_g2: [u16; NUM_STATES],
+    is_rep0_long: [u16; NUM_STATES << NUM_POS_BITS_MAX],
+    len: LenDecoder,
+    rep_len: LenDecoder,
+}
+
+impl Decoder {
+    fn from_properties(props: u8) -> Result<Self, LzmaError> {
+        let props = u32::from(props);
+        if props >= 9 * 5 * 5 {
+            return Err(LzmaError::InvalidProperties);
+        }
+        let lc = props % 9;
+        let lp = (props / 9) % 5;
+        let pb = props / 45;
+        Ok(Self {
+            lc,
+            lp,
+            pb,
+            literal: vec![PROB_INIT; 0x300 << (lc + lp)],
+            pos_slot: [[PROB_INIT; 1 << 6]; NUM_LEN_TO_POS_STATES],
+            pos: [PROB_INIT; 1 + NUM_FULL_DISTANCES - END_POS_MODEL_INDEX as usize],
+            align: [PROB_INIT; 1 << NUM_ALIGN_BITS],
+            is_match: [PROB_INIT; NUM_STATES << NUM_POS_BITS_MAX],
+            is_rep: [PROB_INIT; NUM_STATES],
+            is_rep_g0: [PROB_INIT; NUM_STATES],
+            is_rep_g1: [PROB_INIT; NUM_STATES],
+            is_rep_g2: [PROB_INIT; NUM_STATES],
+            is_rep0_long: [PROB_INIT; NUM_STATES << NUM_POS_BITS_MAX],
+            len: LenDecoder::new(),
+            rep_len: LenDecoder::new(),
+        })
+    }
+
+    fn decode_literal(
+        &mut self,
+        rc: &mut RangeDecoder<'_>,
+        out: &[u8],
+        state: usize,
+        rep0: usize,
+    ) -> Result<u8, LzmaError> {
+        let prev_byte = out.last().copied().unwrap_or(0) as usize;
+        let lit_state =
+            ((out.len() & ((1 << self.lp) - 1)) << self.lc) + (prev_byte >> (8 - self.lc));
+        let probs = &mut self.literal[0x300 * lit_state..][..0x300];
+        let mut symbol = 1usize;
+        if state >= 7 {
+            let mut match_byte = out[out.len() - rep0 - 1] as usize;
+            while symbol < 0x100 {
+                let match_bit = (match_byte >> 7) & 1;
+                match_byte <<= 1;
+                let bit = rc.decode_bit(&mut probs[((1 + match_bit) << 8) + symbol])? as usize;
+                symbol = (symbol << 1) | bit;
+                if match_bit != bit {
+                    break;
+                }
+            }
+        }
+        while symbol < 0x100 {
+            symbol = (symbol << 1) | rc.decode_bit(&mut probs[symbol])? as usize;
+        }
+        Ok((symbol - 0x100) as u8)
+    }
+
+    fn decode_distance(&mut self, rc: &mut RangeDecoder<'_>, len: usize) -> Result<u32, LzmaError> {
+        let len_state = std::cmp::min(len, NUM_LEN_TO_POS_STATES - 1);
+        let pos_slot = rc.decode_tree(&mut self.pos_slot[len_state], 6)?;
+        if pos_slot < START_POS_MODEL_INDEX {
+            return Ok(pos_slot);
+        }
+        let num_direct_bits = (pos_slot >> 1) - 1;
+        let mut dist = (2 | (pos_slot & 1)) << num_direct_bits;
+        if pos_slot < END_POS_MODEL_INDEX {
+            let probs = &mut self.pos[(dist - pos_slot) as usize..];
+            dist += rc.decode_tree_reverse(probs, num_direct_bits)?;
+        } else {
+            dist += rc.decode_direct_bits(num_direct_bits - NUM_ALIGN_BITS)? << NUM_ALIGN_BITS;
+            dist += rc.decode_tree_reverse(&mut self.align, NUM_ALIGN_BITS)?;
+        }
+        Ok(dist)
+    }
+}
+
+/// Decodes an LZMA block as stored in UnityFS bundles: five bytes of properties followed by the
+/// raw range-coded stream, without the uncompressed size field of the `.lzma` file format.
+pub fn decode_block(input: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, LzmaError> {
+    if input.len() < 5 {
+        return Err(LzmaError::UnexpectedEnd);
+    }
+    let mut decoder = Decoder::from_properties(input[0])?;
+    let mut rc = RangeDecoder::new(&input[5..])?;
+    let pos_mask = (1 << decoder.pb) - 1;
+
+    // The size comes from the block table, so a corrupt one mustn't decide the allocation alone.
+    let mut out =
+        Vec::with_capacity(uncompressed_size.min(input.len().saturating_mul(CAPACITY_RATIO)));
+    let mut state = 0usize;
+    let mut reps = [0usize; 4];
+    while out.len() < uncompressed_size {
+        let pos_state = out.len() & pos_mask;
+        if rc.decode_bit(&mut decoder.is_match[(state << NUM_POS_BITS_MAX) + pos_state])? == 0 {
+            let b = decoder.decode_literal(&mut rc, &out, state, reps[0])?;
+            out.push(b);
+            state = if state < 4 {
+                0
+            } else if state < 10 {
+                state - 3
+            } else {
+                state - 6
+            };
+            continue;
+        }
+
+        let len = if rc.decode_bit(&mut decoder.is_rep[state])? != 0 {
+            if out.is_empty() {
+                return Err(LzmaError::Corrupted);
+            }
+            if rc.decode_bit(&mut decoder.is_rep_g0[state])? == 0 {
+                let idx = (state << NUM_POS_BITS_MAX) + pos_state;
+                if rc.decode_bit(&mut decoder.is_rep0_long[idx])? == 0 {
+                    state = if state < 7 { 9 } else { 11 };
+                    out.push(out[out.len() - reps[0] - 1]);
+                    continue;
+                }
+            } else {
+                let dist = if rc.decode_bit(&mut decoder.is_rep_g1[state])? == 0 {
+                    reps[1]
+                } else {
+                    let dist = if rc.decode_bit(&mut decoder.is_rep_g2[state])? == 0 {
+                        reps[2]
+                    } else {
+                        let dist = reps[3];
+                        reps[3] = reps[2];
+                        dist
+                    };
+                    reps[2] = reps[1];
+                    dist
+                };
+                reps[1] = reps[0];
+                reps[0] = dist;
+            }
+            state = if state < 7 { 8 } else { 11 };
+            decoder.rep_len.decode(&mut rc, pos_state)?
+        } else {
+            reps[3] = reps[2];
+            reps[2] = reps[1];
+            reps[1] = reps[0];
+            let len = decoder.len.decode(&mut rc, pos_state)?;
+            state = if state < 7 { 7 } else { 10 };
+            let dist = decoder.decode_distance(&mut rc, len)?;
+            if dist == 0xffffffff {
+                // end marker
+                break;
+            }
+            reps[0] = dist as usize;
+            if reps[0] >= out.len() {
+                return Err(LzmaError::Corrupted);
+            }
+            len
+        };
+
+        let len = std::cmp::min(len + MATCH_MIN_LEN, uncompressed_size - out.len());
+        let start = out.len() - reps[0] - 1;
+        for i in start..(start + len) {
+            out.push(out[i]);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generated with Python's `lzma.compress(data, format=lzma.FORMAT_ALONE)`, leaving out the
+    // eight byte size field after the properties. The streams end with an end marker.
+    const LITERALS: &[u8] = &[
+        0x5d, 0x00, 0x00, 0x80, 0x00, 0x00, 0x2a, 0x9b, 0x89, 0x27, 0x78, 0x65, 0x1b, 0xaa, 0x3f,
+        0xff, 0xfd, 0x89, 0xb0, 0x00,
+    ];
+    const MATCHES: &[u8] = &[
+        0x5d, 0x00, 0x00, 0x80, 0x00, 0x00, 0x30, 0x98, 0x88, 0xaa, 0x02, 0xa6, 0x43, 0xeb, 0xff,
+        0xff, 0xb5, 0x80, 0x00, 0x00,
+    ];
+    const REP_MATCHES: &[u8] = &[
+        0x5d, 0x00, 0x00, 0x80, 0x00, 0x00, 0x18, 0x0c, 0x42, 0x92, 0x6a, 0x67, 0xbc, 0x0e, 0xd1,
+        0x33, 0x2c, 0xe8, 0x05, 0x22, 0x66, 0x71, 0xf4, 0x6b, 0x2c, 0x4d, 0x77, 0x30, 0xff, 0xff,
+        0xd6, 0xbd, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn literals() {
+        assert_eq!(decode_block(LITERALS, 5).unwrap(), b"Unity");
+    }
+
+    #[test]
+    fn matches() {
+        assert_eq!(decode_block(MATCHES, 18).unwrap(), b"abcabcabcabcabcabc");
+    }
+
+    #[test]
+    fn rep_matches() {
+        assert_eq!(
+            decode_block(REP_MATCHES, 44).unwrap(),
+            &b"0123456789a0123456789b0123456789c0123456789d"[..]
+        );
+    }
+
+    #[test]
+    fn end_marker() {
+        assert_eq!(decode_block(LITERALS, 100).unwrap(), b"Unity");
+        assert_eq!(
+            decode_block(MATCHES, usize::MAX).unwrap(),
+            b"abcabcabcabcabcabc"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(
+            decode_block(&MATCHES[..4], 18),
+            Err(LzmaError::UnexpectedEnd)
+        );
+        assert!(decode_block(&MATCHES[..12], 18).is_err());
+    }
+}
@@ -1,7 +1,12 @@
 mod lz4;
+mod lzma;
 
+use crate::UnityFsError;
 use std::cell::{Cell, UnsafeCell};
 
+pub use lz4::Lz4Error;
+pub use lzma::LzmaError;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum CompressionType {
     Lzma,
@@ -11,30 +16,73 @@ enum CompressionType {
 }
 
 impl CompressionType {
-    fn from_u32(val: u32) -> Option<Self> {
-        Some(match val {
-            0 => return None,
+    fn from_u32(val: u32) -> Result<Option<Self>, UnityFsError> {
+        Ok(Some(match val {
+            0 => return Ok(None),
             1 => CompressionType::Lzma,
             2 => CompressionType::Lz4,
             3 => CompressionType::Lz4Hc,
             4 => CompressionType::Lzham,
-            _ => return None,
-        })
+            _ => return Err(UnityFsError::UnsupportedCompression(val)),
+        }))
     }
 
-    fn decompress(self, data: &[u8]) -> Vec<u8> {
-        use CompressionType::*;
-
+    /// Most bytes `compressed_size` bytes can decompress to.
+    fn max_uncompressed_size(self, compressed_size: u32) -> u64 {
+        let compressed_size = u64::from(compressed_size);
         match self {
-            Lz4 | Lz4Hc => lz4::decode_block(data).unwrap(),
-            _ => unimplemented!(),
+            CompressionType::Lzma => compressed_size * lzma::MAX_RATIO,
+            // Past a sequence's token and offset, every byte adds at most 255 to its match.
+            CompressionType::Lz4 | CompressionType::Lz4Hc => compressed_size * 255,
+            // LZHAM codes matches much like LZMA does.
+            CompressionType::Lzham => compressed_size * lzma::MAX_RATIO,
         }
     }
+
+    fn decompress(self, data: &[u8], uncompressed_size: u32) -> Result<Vec<u8>, UnityFsError> {
+        use CompressionType::*;
+
+        Ok(match self {
+            Lzma => lzma::decode_block(data, uncompressed_size as usize)?,
+            Lz4 | Lz4Hc => lz4::decode_block(data)?,
+            Lzham => return Err(UnityFsError::UnsupportedCompression(4)),
+        })
+    }
+}
+
+/// Compresses a block with the compression type ID of a block directory. Only LZ4 can be written,
+/// and LZ4HC blocks are written with the plain LZ4 compressor.
+pub(crate) fn compress_block(
+    compression_type_id: u32,
+    data: &[u8],
+) -> Result<Vec<u8>, UnityFsError> {
+    match CompressionType::from_u32(compression_type_id)? {
+        None => Ok(data.to_vec()),
+        Some(CompressionType::Lz4) | Some(CompressionType::Lz4Hc) => Ok(lz4::encode_block(data)),
+        Some(_) => Err(UnityFsError::UnsupportedCompression(compression_type_id)),
+    }
+}
+
+/// Whether `compressed_size` bytes of a block could decompress to `uncompressed_size` bytes at
+/// all. Both sizes come from the block directory, so this keeps a forged one from deciding how
+/// much memory is reserved for the blocks.
+pub(crate) fn is_plausible_block(
+    compression_type_id: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+) -> bool {
+    let max = match CompressionType::from_u32(compression_type_id) {
+        Ok(None) => u64::from(compressed_size),
+        Ok(Some(compression)) => compression.max_uncompressed_size(compressed_size),
+        // Decompressing fails anyway, so only keep the size in bounds.
+        Err(_) => u64::from(compressed_size) * lzma::MAX_RATIO,
+    };
+    u64::from(uncompressed_size) <= max
 }
 
 pub struct CompressedBlock<'a> {
     u_size: u32,
-    compression: Option<CompressionType>,
+    compression_type_id: u32,
     block: &'a [u8],
 }
 
@@ -42,10 +90,15 @@ impl<'a> CompressedBlock<'a> {
     pub fn from_slice(uncompressed_size: u32, compression_type_id: u32, block: &'a [u8]) -> Self {
         Self {
             u_size: uncompressed_size,
-            compression: CompressionType::from_u32(compression_type_id),
+            compression_type_id,
             block,
         }
     }
+
+    /// The block as stored, before decompression.
+    pub fn compressed_data(&self) -> &'a [u8] {
+        self.block
+    }
 }
 
 impl CompressedBlock<'_> {
@@ -53,11 +106,18 @@ impl CompressedBlock<'_> {
         self.u_size
     }
 
-    pub fn decompress(&self) -> Vec<u8> {
-        match self.compression {
+    pub fn decompress(&self) -> Result<Vec<u8>, UnityFsError> {
+        let block = match CompressionType::from_u32(self.compression_type_id)? {
             None => self.block.to_vec(),
-            Some(compression) => compression.decompress(self.block),
+            Some(compression) => compression.decompress(self.block, self.u_size)?,
+        };
+        if block.len() != self.u_size as usize {
+            return Err(UnityFsError::BlockSizeMismatch {
+                expected: self.u_size,
+                actual: block.len(),
+            });
         }
+        Ok(block)
     }
 }
 
@@ -73,35 +133,61 @@ pub struct CompressedBlockStorage<'a> {
 }
 
 impl<'a> CompressedBlockStorage<'a> {
-    pub fn from_blocks(blocks: Vec<CompressedBlock<'a>>) -> Self {
+    /// Lays out the blocks, failing if any of them couldn't decompress to the size it claims.
+    pub fn from_blocks(blocks: Vec<CompressedBlock<'a>>) -> Result<Self, UnityFsError> {
         let mut total_len = 0u64;
         let blocks = blocks
             .into_iter()
-            .map(|b| {
+            .enumerate()
+            .map(|(idx, b)| {
+                if !is_plausible_block(b.compression_type_id, b.block.len() as u32, b.u_size) {
+                    return Err(UnityFsError::InvalidBlockInfo {
+                        offset: crate::metadata::block_info_offset(idx),
+                    });
+                }
                 let start_offset = total_len;
                 total_len += <_ as Into<u64>>::into(b.uncompressed_size());
-                BlockEntry {
+                Ok(BlockEntry {
                     offset: start_offset,
                     uncompressed: Cell::new(false),
                     data: b,
-                }
+                })
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
         let buf = UnsafeCell::new(vec![0; total_len as usize].into());
-        Self { blocks, buf }
+        Ok(Self { blocks, buf })
+    }
+}
+
+impl<'a> CompressedBlockStorage<'a> {
+    /// The blocks as stored, in order.
+    pub fn blocks(&self) -> impl Iterator<Item = &CompressedBlock<'a>> + '_ {
+        self.blocks.iter().map(|entry| &entry.data)
     }
 }
 
 impl CompressedBlockStorage<'_> {
+    #[allow(clippy::mut_from_ref)]
     unsafe fn get_buf_by_entry_mut(&self, entry: &BlockEntry) -> &mut [u8] {
         let start = entry.offset as usize;
         let len = entry.data.uncompressed_size() as usize;
-        let end = (start + len) as usize;
-        &mut (*self.buf.get())[start..end]
+        let end = start + len;
+        &mut (&mut *self.buf.get())[start..end]
     }
 
-    pub fn read_range(&self, range: std::ops::Range<u64>) -> &[u8] {
+    pub fn read_range(&self, range: std::ops::Range<u64>) -> Result<&[u8], UnityFsError> {
         let std::ops::Range { start, end } = range;
+        let len = self
+            .blocks
+            .last()
+            .map(|b| b.offset + u64::from(b.data.uncompressed_size()))
+            .unwrap_or(0);
+        if start > end || end > len {
+            return Err(UnityFsError::OutOfRange { start, end, len });
+        }
+        if start == end {
+            return Ok(&[]);
+        }
         let start_block_idx = self
             .blocks
             .binary_search_by_key(&start, |b| b.offset)
@@ -112,16 +198,15 @@ impl CompressedBlockStorage<'_> {
             .unwrap_or_else(|idx| idx)
             - 1;
         for entry in &self.blocks[start_block_idx..=end_block_idx] {
-            if entry.uncompressed.replace(true) {
+            if entry.uncompressed.get() {
                 continue;
             }
-            let block = entry.data.decompress();
+            let block = entry.data.decompress()?;
             let buf_area = unsafe { self.get_buf_by_entry_mut(entry) };
-            debug_assert_eq!(block.len(), buf_area.len());
-            let len = std::cmp::min(block.len(), buf_area.len());
-            buf_area[0..len].copy_from_slice(&block[0..len]);
+            buf_area.copy_from_slice(&block);
+            entry.uncompressed.set(true);
         }
 
-        unsafe { &(*self.buf.get())[start as usize..end as usize] }
+        unsafe { Ok(&(&*self.buf.get())[start as usize..end as usize]) }
     }
 }
@@ -0,0 +1,124 @@
+use crate::compression::{Lz4Error, LzmaError};
+
+/// Errors while reading a bundle.
+///
+/// Offsets count from the start of the bundle for its header, from the start of the
+/// decompressed block directory for the directory, and from the start of the serialized file
+/// for everything in one.
+#[derive(Debug)]
+pub enum UnityFsError {
+    /// The input doesn't have a `Unity` signature where one should start.
+    BadMagic { offset: u64 },
+    /// The input ended in the middle of a structure.
+    UnexpectedEof { offset: u64 },
+    /// The input couldn't be parsed.
+    Parse {
+        kind: nom::error::ErrorKind,
+        offset: u64,
+    },
+    /// A block uses a compression method that isn't supported.
+    UnsupportedCompression(u32),
+    Lz4(Lz4Error),
+    Lzma(LzmaError),
+    /// An entry of the block directory describes a block that can't be there, such as one that
+    /// runs past the end of the input.
+    InvalidBlockInfo { offset: u64 },
+    /// A block decompressed to a different size than the block info said.
+    BlockSizeMismatch { expected: u32, actual: usize },
+    /// A node or object points outside of the data that contains it.
+    OutOfRange { start: u64, end: u64, len: u64 },
+    /// The bundle doesn't contain any serialized file.
+    NoAssets,
+    /// An object's type has no type tree, neither in the file nor in the built-in defaults.
+    MissingTypeTree { class_id: i32 },
+}
+
+impl std::fmt::Display for UnityFsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnityFsError::BadMagic { offset } => {
+                write!(f, "not a Unity file: no signature at offset {}", offset)
+            }
+            UnityFsError::UnexpectedEof { offset } => {
+                write!(f, "unexpected end of input at offset {}", offset)
+            }
+            UnityFsError::Parse { kind, offset } => write!(
+                f,
+                "parse error at offset {}: {}",
+                offset,
+                kind.description()
+            ),
+            UnityFsError::UnsupportedCompression(ty) => {
+                write!(f, "unsupported compression type {}", ty)
+            }
+            UnityFsError::Lz4(e) => write!(f, "LZ4 error: {}", e),
+            UnityFsError::Lzma(e) => write!(f, "LZMA error: {}", e),
+            UnityFsError::InvalidBlockInfo { offset } => {
+                write!(f, "invalid block info at offset {} of the directory", offset)
+            }
+            UnityFsError::BlockSizeMismatch { expected, actual } => write!(
+                f,
+                "block decompressed to {} bytes, expected {}",
+                actual, expected
+            ),
+            UnityFsError::OutOfRange { start, end, len } => write!(
+                f,
+                "range {}..{} is out of bounds of {} bytes",
+                start, end, len
+            ),
+            UnityFsError::NoAssets => f.write_str("no serialized file in bundle"),
+            UnityFsError::MissingTypeTree { class_id } => {
+                write!(f, "no type tree for class {}", class_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UnityFsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UnityFsError::Lz4(e) => Some(e),
+            UnityFsError::Lzma(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Lz4Error> for UnityFsError {
+    fn from(e: Lz4Error) -> Self {
+        UnityFsError::Lz4(e)
+    }
+}
+
+impl From<LzmaError> for UnityFsError {
+    fn from(e: LzmaError) -> Self {
+        UnityFsError::Lzma(e)
+    }
+}
+
+impl UnityFsError {
+    /// Converts a parser error, locating it from where `base` starts at `base_offset`.
+    pub(crate) fn from_nom(
+        base: &[u8],
+        base_offset: u64,
+        e: nom::Err<nom::error::Error<&[u8]>>,
+    ) -> Self {
+        let (input, kind) = match e {
+            nom::Err::Incomplete(_) => (&base[base.len()..], nom::error::ErrorKind::Eof),
+            nom::Err::Error(e) | nom::Err::Failure(e) => (e.input, e.code),
+        };
+        // Some parsers fail on input of their own, so don't trust it to point into `base`.
+        let dist = (input.as_ptr() as usize).wrapping_sub(base.as_ptr() as usize);
+        let offset = base_offset + std::cmp::min(dist, base.len()) as u64;
+        match kind {
+            nom::error::ErrorKind::Eof => UnityFsError::UnexpectedEof { offset },
+            kind => UnityFsError::Parse { kind, offset },
+        }
+    }
+}
+
+impl From<UnityFsError> for std::io::Error {
+    fn from(e: UnityFsError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
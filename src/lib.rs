@@ -1,25 +1,78 @@
 mod asset;
 mod common_parser;
 mod compression;
+mod error;
 mod metadata;
+mod platform;
+mod reader;
+#[cfg(feature = "textures")]
+pub mod texture;
+mod texture_format;
 mod util;
+mod writer;
 
 use crate::common_parser::read_string;
-use nom::{number::complete as nom_number, IResult};
+use crate::util::align_to;
+use nom::{bytes::complete as nom_bytes, number::complete as nom_number};
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-pub use asset::{Asset, Data, Object};
-pub use compression::CompressedBlock;
-pub use metadata::Metadata;
+pub use asset::{
+    Asset, Bytes, Data, DataError, ExternalRef, Fields, FieldsIter, MonoClass, Object,
+    SerializedFileHeader, TransformNode,
+};
+pub use compression::{CompressedBlock, Lz4Error, LzmaError};
+pub use error::UnityFsError;
+pub use metadata::{BlockInfo, BundleInfo, Metadata, NodeInfo, RawBlock};
+pub use platform::Platform;
+pub use reader::{AssetReader, UnityFsReader, DEFAULT_BLOCK_CACHE_SIZE};
+pub use texture_format::TextureFormat;
+pub use writer::{UnityFsWriter, DEFAULT_BLOCK_SIZE};
+
+/// A standalone serialized file reads into the same [`Asset`] as one inside a bundle.
+pub type SerializedFile<'b> = Asset<'b>;
+
+/// What kind of file a buffer holds, as told by [`detect`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContainerKind {
+    /// A `UnityFS` bundle, read with [`UnityFsMeta::parse`].
+    UnityFs,
+    /// A serialized file outside of a bundle, read with [`SerializedFile::parse`].
+    SerializedFile,
+    Unknown,
+}
+
+/// Tells bundles from standalone serialized files by looking at the start of the file.
+///
+/// Serialized files have no signature, so they are recognized by a header that agrees with the
+/// size of `input`; pass the whole file.
+pub fn detect(input: &[u8]) -> ContainerKind {
+    if input.starts_with(b"UnityFS\0") {
+        ContainerKind::UnityFs
+    } else if asset::AssetTable::is_header(input) {
+        ContainerKind::SerializedFile
+    } else {
+        ContainerKind::Unknown
+    }
+}
+
+/// Archive flag of bundles whose block directory is at the end of the file instead of after the
+/// header, as written by streaming builds.
+pub(crate) const BLOCKS_INFO_AT_THE_END: u32 = 0x80;
+
+/// Archive flag set by Unity 2020.2 and later when the data blocks start on a 16-byte boundary.
+pub(crate) const BLOCKS_NEED_PADDING_AT_START: u32 = 0x200;
 
 pub struct UnityFsMeta<'a> {
     signature: Cow<'a, str>,
     format_version: u32,
     unity_version: Cow<'a, str>,
     generator_version: Cow<'a, str>,
+    header: metadata::BundleHeader,
     metadata: Metadata,
     storage: compression::CompressedBlockStorage<'a>,
+    /// Position of the first data block in the file.
+    data_offset: u64,
 }
 
 impl<'a> UnityFsMeta<'a> {
@@ -38,47 +91,109 @@ impl<'a> UnityFsMeta<'a> {
     pub fn generator_version(&self) -> &str {
         &self.generator_version
     }
+
+    pub fn info(&self) -> BundleInfo {
+        self.metadata.info(self.header)
+    }
+
+    /// The data blocks as stored in the file, with where they are, for repacking a bundle with
+    /// only some of its blocks changed.
+    pub fn raw_blocks(&self) -> impl Iterator<Item = RawBlock<'a>> + '_ {
+        let mut offset = self.data_offset;
+        let mut uncompressed_offset = 0;
+        self.metadata
+            .blocks
+            .iter()
+            .zip(self.storage.blocks())
+            .map(move |(block, data)| {
+                let raw = RawBlock {
+                    info: block.info(),
+                    offset,
+                    uncompressed_offset,
+                    data: data.compressed_data(),
+                };
+                offset += u64::from(block.c_size);
+                uncompressed_offset += u64::from(block.u_size);
+                raw
+            })
+    }
+
+    /// Entries of the block directory, in directory order.
+    pub fn entries(&self) -> std::slice::Iter<'_, NodeInfo> {
+        self.metadata.nodes.iter()
+    }
 }
 
 impl<'a> UnityFsMeta<'a> {
-    pub fn parse(input: &'a [u8]) -> IResult<&[u8], Self> {
-        nom::error::context(
-            "should start in 'Unity'",
-            nom::bytes::complete::tag(b"Unity"),
-        )(input)?;
-        let (input, signature) = read_string(input, None)?;
-        let (input, format_version) = nom_number::be_u32(input)?;
-        let (input, unity_version) = read_string(input, None)?;
-        let (input, generator_version) = read_string(input, None)?;
-        let (input, _file_size) = nom_number::be_u64(input)?;
-
-        let (input, c_block_size) = nom_number::be_u32(input)?;
-        let (input, u_block_size) = nom_number::be_u32(input)?;
-        let (input, flags) = nom_number::be_u32(input)?;
-        let (input, raw_metadata) = if flags & 0x80 == 0x80 {
-            input.split_at(input.len() - c_block_size as usize)
+    pub fn parse(input: &'a [u8]) -> Result<(&'a [u8], Self), UnityFsError> {
+        let base = input;
+        let err = |e| UnityFsError::from_nom(base, 0, e);
+        if !input.starts_with(b"Unity") {
+            return Err(UnityFsError::BadMagic { offset: 0 });
+        }
+        let (input, signature) = read_string(input, None).map_err(err)?;
+        let (input, format_version) = nom_number::be_u32(input).map_err(err)?;
+        let (input, unity_version) = read_string(input, None).map_err(err)?;
+        let (input, generator_version) = read_string(input, None).map_err(err)?;
+        let (input, file_size) = nom_number::be_u64(input).map_err(err)?;
+
+        let (input, c_block_size) = nom_number::be_u32(input).map_err(err)?;
+        let (input, u_block_size) = nom_number::be_u32(input).map_err(err)?;
+        let (input, flags) = nom_number::be_u32(input).map_err(err)?;
+        // Since format version 7 the block directory starts on a 16-byte boundary.
+        let input = if format_version >= 7 {
+            align_to(16, 0, base, input)
         } else {
-            let (a, b) = input.split_at(c_block_size as usize);
-            (b, a)
+            input
         };
-        let metadata = CompressedBlock::from_slice(u_block_size, flags & 0x3f, raw_metadata);
-        let metadata = metadata.decompress();
-        let metadata = match Metadata::parse(&metadata) {
-            Ok((_, metadata)) => metadata,
-            Err(e) => return Err(e.map(|e| nom::error::Error::new(input, e.code))),
+        let (input, raw_metadata) = if flags & BLOCKS_INFO_AT_THE_END != 0 {
+            // The end of the file is taken from the header, as the buffer may have some data
+            // appended to the bundle.
+            let end = std::cmp::min(file_size, base.len() as u64) as usize;
+            let header_len = base.len() - input.len();
+            let start = end
+                .checked_sub(c_block_size as usize)
+                .filter(|&start| start >= header_len)
+                .ok_or(UnityFsError::UnexpectedEof {
+                    offset: base.len() as u64,
+                })?;
+            (&input[..start - header_len], &base[start..end])
+        } else {
+            nom_bytes::take::<_, _, nom::error::Error<_>>(c_block_size as usize)(input)
+                .map_err(err)?
         };
+        let metadata = CompressedBlock::from_slice(u_block_size, flags & 0x3f, raw_metadata);
+        let metadata = metadata.decompress()?;
+        let (_, metadata) =
+            Metadata::parse(&metadata).map_err(|e| UnityFsError::from_nom(&metadata, 0, e))?;
 
+        let input = if flags & BLOCKS_NEED_PADDING_AT_START != 0 {
+            align_to(16, 0, base, input)
+        } else {
+            input
+        };
+        // The input stops short of a directory at the end, so measure where it starts instead.
+        let data_offset = (input.as_ptr() as usize - base.as_ptr() as usize) as u64;
         let mut left = input;
         let blocks = metadata
             .blocks
             .iter()
-            .map(|block| {
-                let (data, remainder) = left.split_at(block.c_size as usize);
+            .enumerate()
+            .map(|(idx, block)| {
+                let (remainder, data) =
+                    nom_bytes::take::<_, _, nom::error::Error<_>>(block.c_size as usize)(left)
+                        .map_err(|_| UnityFsError::InvalidBlockInfo {
+                            offset: metadata::block_info_offset(idx),
+                        })?;
                 left = remainder;
-                CompressedBlock::from_slice(block.u_size, (block.flags & 0x3f) as u32, data)
+                Ok(CompressedBlock::from_slice(
+                    block.u_size,
+                    (block.flags & 0x3f) as u32,
+                    data,
+                ))
             })
-            .collect();
-        let storage = compression::CompressedBlockStorage::from_blocks(blocks);
+            .collect::<Result<_, UnityFsError>>()?;
+        let storage = compression::CompressedBlockStorage::from_blocks(blocks)?;
 
         Ok((
             left,
@@ -87,65 +202,231 @@ impl<'a> UnityFsMeta<'a> {
                 format_version,
                 unity_version,
                 generator_version,
+                header: metadata::BundleHeader {
+                    flags,
+                    file_size,
+                    compressed_size: c_block_size,
+                    uncompressed_size: u_block_size,
+                },
                 metadata,
                 storage,
+                data_offset,
             },
         ))
     }
 
-    pub fn read_unityfs(&'a self) -> UnityFs<'a> {
-        let resources = self
+    /// Reads a bundle lazily from a seekable source, decompressing blocks only when objects or
+    /// resources backed by them are accessed.
+    ///
+    /// Sources that can't seek should be read into memory and passed to [`UnityFsMeta::parse`].
+    pub fn parse_reader<R: std::io::Read + std::io::Seek>(
+        reader: R,
+    ) -> std::io::Result<UnityFsReader<R>> {
+        UnityFsReader::parse(reader, DEFAULT_BLOCK_CACHE_SIZE)
+    }
+
+    /// Like [`UnityFsMeta::parse_reader`], keeping up to `cache_size` decompressed blocks in
+    /// memory so that reading many objects from the same few blocks doesn't decompress them
+    /// again.
+    pub fn parse_reader_with_cache<R: std::io::Read + std::io::Seek>(
+        reader: R,
+        cache_size: usize,
+    ) -> std::io::Result<UnityFsReader<R>> {
+        UnityFsReader::parse(reader, cache_size)
+    }
+
+    pub fn read_unityfs(&'a self) -> Result<UnityFs<'a>, UnityFsError> {
+        let mut resources = self
             .metadata
             .nodes
             .iter()
             .map(|node| {
-                let block = self
-                    .storage
-                    .read_range(node.offset..(node.offset + node.size));
-                (node.name.clone(), block)
+                let end = node.offset.checked_add(node.size).ok_or(UnityFsError::OutOfRange {
+                    start: node.offset,
+                    end: u64::MAX,
+                    len: 0,
+                })?;
+                let block = self.storage.read_range(node.offset..end)?;
+                Ok((node.name.clone(), block))
+            })
+            .collect::<Result<HashMap<_, _>, UnityFsError>>()?;
+        // Split resources are reassembled when their parts are stored back to back, which is
+        // how Unity writes them. Every node range was checked above.
+        for name in self.metadata.split_names() {
+            if resources.contains_key(name) {
+                continue;
+            }
+            let parts = match self.metadata.split_parts(name) {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let contiguous = parts
+                .windows(2)
+                .all(|pair| pair[0].offset + pair[0].size == pair[1].offset);
+            if contiguous {
+                let last = parts[parts.len() - 1];
+                let block = self.storage.read_range(parts[0].offset..last.offset + last.size)?;
+                resources.insert(name.to_owned(), block);
+            }
+        }
+        let mut type_trees = asset::TypeTreeCache::default();
+        let assets = self
+            .metadata
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(idx, node)| node.is_serialized_file(*idx))
+            .map(|(_, node)| {
+                let data = resources[&node.name];
+                Asset::parse_at(node.name.clone(), data, node.offset, &mut type_trees)
             })
-            .collect::<HashMap<_, _>>();
-        let metadata::NodeInfo {
-            name: main_asset_name,
-            offset: main_asset_offset,
-            ..
-        } = &self.metadata.nodes[0];
-        let main_asset_resource = *resources.get(main_asset_name).unwrap();
-        let (_, main_asset) = Asset::parse(
-            main_asset_name.into(),
-            main_asset_resource,
-            *main_asset_offset,
-        )
-        .unwrap();
-        UnityFs {
+            .collect::<Result<Vec<_>, _>>()?;
+        if assets.is_empty() {
+            return Err(UnityFsError::NoAssets);
+        }
+        let resource_names = self
+            .metadata
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(idx, node)| !node.is_serialized_file(*idx))
+            .map(|(_, node)| &*node.name)
+            .collect();
+        Ok(UnityFs {
+            unity_version: &self.unity_version,
+            generator_version: &self.generator_version,
             guid: self.metadata.guid,
-            main_asset,
+            assets,
             resources,
-        }
+            resource_names,
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct UnityFs<'a> {
+    unity_version: &'a str,
+    generator_version: &'a str,
     guid: [u8; 16],
-    main_asset: Asset<'a>,
+    assets: Vec<Asset<'a>>,
     resources: HashMap<String, &'a [u8]>,
+    /// Directory entries that aren't serialized files, in directory order.
+    resource_names: Vec<&'a str>,
 }
 
 impl<'a> UnityFs<'a> {
+    /// Version recorded in the bundle header. Unity writes `5.x.x` here for every release; the
+    /// engine version is reported by [`UnityFs::generator_version`].
+    pub fn unity_version(&self) -> &str {
+        self.unity_version
+    }
+
+    pub fn generator_version(&self) -> &str {
+        self.generator_version
+    }
+
     pub fn guid(&self) -> [u8; 16] {
         self.guid
     }
 
     pub fn name(&self) -> &str {
-        self.main_asset.name()
+        self.main_asset().name()
     }
 
+    /// The first serialized file in the bundle.
     pub fn main_asset(&self) -> &Asset<'a> {
-        &self.main_asset
+        &self.assets[0]
+    }
+
+    /// Every serialized file in the bundle, in directory order.
+    pub fn assets(&self) -> std::slice::Iter<'_, Asset<'a>> {
+        self.assets.iter()
     }
 
+    /// Looks up a resource by name.
+    ///
+    /// Resources stored as `<name>.split0`, `<name>.split1`, ... can also be looked up by `name`,
+    /// which returns the parts joined together.
     pub fn resource(&self, name: &str) -> Option<&'a [u8]> {
         self.resources.get(name).copied()
     }
+
+    /// Parses a resource that is a bundle of its own.
+    ///
+    /// The nested bundle borrows the data of this one; read its files with
+    /// [`UnityFsMeta::read_unityfs`]. Returns `Ok(None)` if there is no such resource or it isn't
+    /// a bundle.
+    pub fn nested_bundle(&self, name: &str) -> Result<Option<UnityFsMeta<'a>>, UnityFsError> {
+        match self.resource(name) {
+            Some(data) if detect(data) == ContainerKind::UnityFs => {
+                UnityFsMeta::parse(data).map(|(_, meta)| Some(meta))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Every entry of the bundle that isn't a serialized file, such as the `.resS` and
+    /// `.resource` files streamed data lives in.
+    pub fn resources(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + '_ {
+        self.resource_names
+            .iter()
+            .map(move |&name| (name, self.resources[name]))
+    }
+
+    /// Looks up the data a `StreamingInfo` or `StreamedResource` points to.
+    ///
+    /// Paths name a serialized file and then a resource, as in `archive:/CAB-<hash>/<resource>`.
+    /// The serialized file doesn't have to be the main one, and newer versions sometimes name a
+    /// file that isn't in the bundle at all. `CAB-<hash>` resources are unique to their bundle,
+    /// so they are found whatever the file is; other resources need the file to be in this
+    /// bundle.
+    ///
+    /// Returns `None` if the data lives in another bundle, or if the path or range doesn't match
+    /// anything in this one.
+    pub fn stream_data(&self, path: &str, offset: u64, size: u64) -> Option<&'a [u8]> {
+        let path = path.strip_prefix("archive:/")?;
+        let mut path_segments = path.split('/');
+        let (bundle_name, resource_name) = (path_segments.next()?, path_segments.next()?);
+        let in_bundle = self.assets.iter().any(|asset| asset.name() == bundle_name);
+        if !in_bundle && !resource_name.starts_with("CAB-") {
+            return None;
+        }
+        let resource = self.resource(resource_name)?;
+        match offset.checked_add(size) {
+            Some(end) if end <= resource.len() as u64 => {
+                Some(&resource[(offset as usize)..(end as usize)])
+            }
+            _ => None,
+        }
+    }
+
+    /// Every object in every serialized file of the bundle, along with the file it belongs to.
+    pub fn all_objects(&self) -> impl Iterator<Item = (AssetRef<'_, 'a>, &Object<'a>)> + '_ {
+        self.assets.iter().enumerate().flat_map(|(index, asset)| {
+            let asset_ref = AssetRef { index, asset };
+            asset.objects().map(move |object| (asset_ref, object))
+        })
+    }
+}
+
+/// A serialized file of a bundle, as yielded by [`UnityFs::all_objects`].
+#[derive(Debug, Clone, Copy)]
+pub struct AssetRef<'f, 'a> {
+    index: usize,
+    asset: &'f Asset<'a>,
+}
+
+impl<'f, 'a> AssetRef<'f, 'a> {
+    /// Position of the file in [`UnityFs::assets`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn name(&self) -> &'f str {
+        self.asset.name()
+    }
+
+    pub fn asset(&self) -> &'f Asset<'a> {
+        self.asset
+    }
 }
@@ -1,4 +1,4 @@
-use crate::common_parser::read_string;
+use crate::common_parser::{check_count, read_string};
 use nom::{self, bytes::complete as nom_bytes, number::complete as nom_number, IResult};
 
 #[derive(Debug)]
@@ -8,6 +8,7 @@ pub struct ArchiveBlockInfo {
     pub(crate) flags: u16,
 }
 
+/// A directory entry of a bundle: a serialized file or a resource.
 #[derive(Debug)]
 pub struct NodeInfo {
     pub(crate) offset: u64,
@@ -23,12 +24,225 @@ pub struct Metadata {
     pub(crate) nodes: Vec<NodeInfo>,
 }
 
+/// Layout of a bundle as described by its header, for inspection without reading any data.
+#[derive(Debug, Clone)]
+pub struct BundleInfo {
+    flags: u32,
+    file_size: u64,
+    header_compressed_size: u32,
+    header_uncompressed_size: u32,
+    blocks: Vec<BlockInfo>,
+    entry_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlockInfo {
+    compressed_size: u32,
+    uncompressed_size: u32,
+    flags: u16,
+}
+
+/// A data block exactly as stored in a bundle, so that it can be copied into another one
+/// without recompressing it.
+#[derive(Debug, Clone, Copy)]
+pub struct RawBlock<'a> {
+    pub(crate) info: BlockInfo,
+    pub(crate) offset: u64,
+    pub(crate) uncompressed_offset: u64,
+    pub(crate) data: &'a [u8],
+}
+
+impl BundleInfo {
+    /// Archive flags of the bundle header.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Compression type ID of the block directory: 0 for none, 1 for LZMA, 2 for LZ4 and 3 for
+    /// LZ4HC.
+    pub fn header_compression_type(&self) -> u32 {
+        self.flags & 0x3f
+    }
+
+    /// Size of the bundle file as recorded in its header.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn header_compressed_size(&self) -> u32 {
+        self.header_compressed_size
+    }
+
+    pub fn header_uncompressed_size(&self) -> u32 {
+        self.header_uncompressed_size
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn blocks(&self) -> std::slice::Iter<'_, BlockInfo> {
+        self.blocks.iter()
+    }
+
+    /// Total size of the data blocks as stored.
+    pub fn compressed_size(&self) -> u64 {
+        self.blocks
+            .iter()
+            .map(|block| u64::from(block.compressed_size))
+            .sum()
+    }
+
+    /// Total size of the data blocks once decompressed.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.blocks
+            .iter()
+            .map(|block| u64::from(block.uncompressed_size))
+            .sum()
+    }
+
+    /// Names of the directory entries, in directory order.
+    pub fn entry_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entry_names.iter().map(String::as_str)
+    }
+}
+
+impl BlockInfo {
+    pub fn compressed_size(&self) -> u32 {
+        self.compressed_size
+    }
+
+    pub fn uncompressed_size(&self) -> u32 {
+        self.uncompressed_size
+    }
+
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+
+    /// Compression type ID of the block, with the same values as
+    /// [`BundleInfo::header_compression_type`].
+    pub fn compression_type(&self) -> u32 {
+        (self.flags & 0x3f) as u32
+    }
+}
+
+impl<'a> RawBlock<'a> {
+    /// Sizes and compression of the block, as listed in the block directory.
+    pub fn info(&self) -> BlockInfo {
+        self.info
+    }
+
+    /// Position of the block in the bundle file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Position of the decompressed block in the data that directory entries point into.
+    pub fn uncompressed_offset(&self) -> u64 {
+        self.uncompressed_offset
+    }
+
+    /// The compressed bytes of the block.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl ArchiveBlockInfo {
+    pub(crate) fn info(&self) -> BlockInfo {
+        BlockInfo {
+            compressed_size: self.c_size,
+            uncompressed_size: self.u_size,
+            flags: self.flags,
+        }
+    }
+}
+
+/// Fields of the bundle header that are only needed for [`BundleInfo`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BundleHeader {
+    pub(crate) flags: u32,
+    pub(crate) file_size: u64,
+    pub(crate) compressed_size: u32,
+    pub(crate) uncompressed_size: u32,
+}
+
+impl NodeInfo {
+    /// Position of the entry in the decompressed data of the blocks.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Flags of the entry. Bit 2 is set for serialized files.
+    pub fn flags(&self) -> u32 {
+        self.status
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the node holds a serialized file rather than a resource.
+    ///
+    /// The first node is always treated as a serialized file.
+    pub(crate) fn is_serialized_file(&self, index: usize) -> bool {
+        index == 0 || self.status & 0x4 != 0
+    }
+}
+
+/// Offset of the `index`th block info in the decompressed block directory, after the GUID and
+/// the block count.
+pub(crate) fn block_info_offset(index: usize) -> u64 {
+    20 + 10 * index as u64
+}
+
 impl Metadata {
+    /// Base names of resources that are split into `<name>.split0`, `<name>.split1`, ...
+    pub(crate) fn split_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.nodes
+            .iter()
+            .filter_map(|node| node.name.strip_suffix(".split0"))
+    }
+
+    /// Consecutively numbered parts of a split resource, in order, or `None` if it isn't split.
+    pub(crate) fn split_parts(&self, name: &str) -> Option<Vec<&NodeInfo>> {
+        let mut parts = Vec::new();
+        loop {
+            let part_name = format!("{}.split{}", name, parts.len());
+            match self.nodes.iter().find(|node| node.name == part_name) {
+                Some(node) => parts.push(node),
+                None => break,
+            }
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts)
+        }
+    }
+
+    pub(crate) fn info(&self, header: BundleHeader) -> BundleInfo {
+        BundleInfo {
+            flags: header.flags,
+            file_size: header.file_size,
+            header_compressed_size: header.compressed_size,
+            header_uncompressed_size: header.uncompressed_size,
+            blocks: self.blocks.iter().map(ArchiveBlockInfo::info).collect(),
+            entry_names: self.nodes.iter().map(|node| node.name.clone()).collect(),
+        }
+    }
+
     pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, guid_slice) = nom_bytes::take(16usize)(input)?;
         let mut guid = [0; 16];
         guid.copy_from_slice(guid_slice);
         let (input, block_count) = nom_number::be_u32(input)?;
+        check_count(input, block_count, 10)?;
         let (input, blocks) = nom::multi::count(
             |input| {
                 let (input, u_size) = nom_number::be_u32(input)?;
@@ -44,6 +258,7 @@ impl Metadata {
             block_count as usize,
         )(input)?;
         let (input, node_count) = nom_number::be_u32(input)?;
+        check_count(input, node_count, 21)?;
         let (input, nodes) = nom::multi::count(
             |input| {
                 let (input, offset) = nom_number::be_u64(input)?;
@@ -0,0 +1,192 @@
+/// Values of the target platform in the header of serialized files, named after Unity's
+/// `BuildTarget`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Platform {
+    StandaloneOsx,
+    StandaloneOsxIntel,
+    StandaloneWindows,
+    WebPlayer,
+    WebPlayerStreamed,
+    Ios,
+    Ps3,
+    Xbox360,
+    Android,
+    StandaloneLinux,
+    StandaloneWindows64,
+    WebGl,
+    WsaPlayer,
+    StandaloneLinux64,
+    StandaloneLinuxUniversal,
+    Wp8Player,
+    StandaloneOsxIntel64,
+    BlackBerry,
+    Tizen,
+    Psp2,
+    Ps4,
+    Psm,
+    XboxOne,
+    SamsungTv,
+    N3ds,
+    WiiU,
+    TvOs,
+    Switch,
+    Lumin,
+    Stadia,
+    CloudRendering,
+    GameCoreXboxSeries,
+    GameCoreXboxOne,
+    Ps5,
+    EmbeddedLinux,
+    Qnx,
+    VisionOs,
+    /// Assets built for the editor rather than a player.
+    NoTarget,
+    /// A value with no name here, from a newer engine or a long-gone platform.
+    Other(u32),
+}
+
+/// `BuildTarget.NoTarget`, which is -2.
+const NO_TARGET: u32 = 0xffff_fffe;
+
+impl Platform {
+    pub fn from_u32(value: u32) -> Self {
+        use Platform::*;
+
+        match value {
+            2 => StandaloneOsx,
+            4 => StandaloneOsxIntel,
+            5 => StandaloneWindows,
+            6 => WebPlayer,
+            7 => WebPlayerStreamed,
+            9 => Ios,
+            10 => Ps3,
+            11 => Xbox360,
+            13 => Android,
+            17 => StandaloneLinux,
+            19 => StandaloneWindows64,
+            20 => WebGl,
+            21 => WsaPlayer,
+            24 => StandaloneLinux64,
+            25 => StandaloneLinuxUniversal,
+            26 => Wp8Player,
+            27 => StandaloneOsxIntel64,
+            28 => BlackBerry,
+            29 => Tizen,
+            30 => Psp2,
+            31 => Ps4,
+            32 => Psm,
+            33 => XboxOne,
+            34 => SamsungTv,
+            35 => N3ds,
+            36 => WiiU,
+            37 => TvOs,
+            38 => Switch,
+            39 => Lumin,
+            40 => Stadia,
+            41 => CloudRendering,
+            42 => GameCoreXboxSeries,
+            43 => GameCoreXboxOne,
+            44 => Ps5,
+            45 => EmbeddedLinux,
+            46 => Qnx,
+            47 => VisionOs,
+            NO_TARGET => NoTarget,
+            value => Other(value),
+        }
+    }
+
+    /// The value stored in the file.
+    pub fn to_u32(self) -> u32 {
+        use Platform::*;
+
+        match self {
+            StandaloneOsx => 2,
+            StandaloneOsxIntel => 4,
+            StandaloneWindows => 5,
+            WebPlayer => 6,
+            WebPlayerStreamed => 7,
+            Ios => 9,
+            Ps3 => 10,
+            Xbox360 => 11,
+            Android => 13,
+            StandaloneLinux => 17,
+            StandaloneWindows64 => 19,
+            WebGl => 20,
+            WsaPlayer => 21,
+            StandaloneLinux64 => 24,
+            StandaloneLinuxUniversal => 25,
+            Wp8Player => 26,
+            StandaloneOsxIntel64 => 27,
+            BlackBerry => 28,
+            Tizen => 29,
+            Psp2 => 30,
+            Ps4 => 31,
+            Psm => 32,
+            XboxOne => 33,
+            SamsungTv => 34,
+            N3ds => 35,
+            WiiU => 36,
+            TvOs => 37,
+            Switch => 38,
+            Lumin => 39,
+            Stadia => 40,
+            CloudRendering => 41,
+            GameCoreXboxSeries => 42,
+            GameCoreXboxOne => 43,
+            Ps5 => 44,
+            EmbeddedLinux => 45,
+            Qnx => 46,
+            VisionOs => 47,
+            NoTarget => NO_TARGET,
+            Other(value) => value,
+        }
+    }
+
+    /// The name Unity uses for the platform, such as `StandaloneWindows64`, or `None` for
+    /// [`Platform::Other`].
+    pub fn name(self) -> Option<&'static str> {
+        use Platform::*;
+
+        Some(match self {
+            StandaloneOsx => "StandaloneOSX",
+            StandaloneOsxIntel => "StandaloneOSXIntel",
+            StandaloneWindows => "StandaloneWindows",
+            WebPlayer => "WebPlayer",
+            WebPlayerStreamed => "WebPlayerStreamed",
+            Ios => "iOS",
+            Ps3 => "PS3",
+            Xbox360 => "XBOX360",
+            Android => "Android",
+            StandaloneLinux => "StandaloneLinux",
+            StandaloneWindows64 => "StandaloneWindows64",
+            WebGl => "WebGL",
+            WsaPlayer => "WSAPlayer",
+            StandaloneLinux64 => "StandaloneLinux64",
+            StandaloneLinuxUniversal => "StandaloneLinuxUniversal",
+            Wp8Player => "WP8Player",
+            StandaloneOsxIntel64 => "StandaloneOSXIntel64",
+            BlackBerry => "BlackBerry",
+            Tizen => "Tizen",
+            Psp2 => "PSP2",
+            Ps4 => "PS4",
+            Psm => "PSM",
+            XboxOne => "XboxOne",
+            SamsungTv => "SamsungTV",
+            N3ds => "N3DS",
+            WiiU => "WiiU",
+            TvOs => "tvOS",
+            Switch => "Switch",
+            Lumin => "Lumin",
+            Stadia => "Stadia",
+            CloudRendering => "CloudRendering",
+            GameCoreXboxSeries => "GameCoreXboxSeries",
+            GameCoreXboxOne => "GameCoreXboxOne",
+            Ps5 => "PS5",
+            EmbeddedLinux => "EmbeddedLinux",
+            Qnx => "QNX",
+            VisionOs => "VisionOS",
+            NoTarget => "NoTarget",
+            Other(_) => return None,
+        })
+    }
+}
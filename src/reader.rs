@@ -0,0 +1,472 @@
+use crate::asset::{
+    AssetTable, Data, ExternalRef, Object, ObjectInfo, SerializedFileHeader, TypeMetadata,
+    TypeTreeCache,
+};
+use crate::compression::{is_plausible_block, CompressedBlock};
+use crate::metadata::{block_info_offset, BundleHeader, BundleInfo, Metadata};
+use crate::{Platform, UnityFsError, BLOCKS_INFO_AT_THE_END, BLOCKS_NEED_PADDING_AT_START};
+use nom::number::Endianness;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Seek, SeekFrom};
+
+fn read_cstring(reader: &mut impl Read) -> io::Result<String> {
+    let mut buf = Vec::new();
+    loop {
+        let mut b = [0u8];
+        reader.read_exact(&mut b)?;
+        if b[0] == 0 {
+            break;
+        }
+        buf.push(b[0]);
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn read_be_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_be_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// Reads the `len` bytes at `position`. The buffer grows as bytes arrive rather than being
+/// allocated up front, so a corrupt length fails with an error instead of a huge allocation.
+fn read_at(reader: &mut (impl Read + Seek), position: u64, len: u32) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(position))?;
+    let mut buf = Vec::new();
+    reader.take(len.into()).read_to_end(&mut buf)?;
+    if buf.len() < len as usize {
+        return Err(UnityFsError::UnexpectedEof {
+            offset: position + buf.len() as u64,
+        }
+        .into());
+    }
+    Ok(buf)
+}
+
+/// Range of `size` bytes starting at `start`, failing instead of wrapping around.
+fn checked_range(start: u64, size: u64) -> io::Result<std::ops::Range<u64>> {
+    let end = start.checked_add(size).ok_or(UnityFsError::OutOfRange {
+        start,
+        end: u64::MAX,
+        len: 0,
+    })?;
+    Ok(start..end)
+}
+
+struct BlockEntry {
+    /// Position of the compressed block in the underlying reader.
+    position: u64,
+    /// Offset of the block in the uncompressed stream.
+    offset: u64,
+    c_size: u32,
+    u_size: u32,
+    flags: u16,
+}
+
+/// Number of decompressed blocks kept by [`UnityFsMeta::parse_reader`].
+///
+/// [`UnityFsMeta::parse_reader`]: crate::UnityFsMeta::parse_reader
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 4;
+
+/// A UnityFS bundle read on demand from a seekable source.
+///
+/// Only the block directory and the most recently used decompressed blocks are kept in memory.
+pub struct UnityFsReader<R> {
+    reader: RefCell<R>,
+    signature: String,
+    format_version: u32,
+    unity_version: String,
+    generator_version: String,
+    header: BundleHeader,
+    metadata: Metadata,
+    blocks: Vec<BlockEntry>,
+    /// Decompressed blocks by index, most recently used first.
+    cache: RefCell<VecDeque<(usize, Vec<u8>)>>,
+    cache_size: usize,
+}
+
+impl<R: Read + Seek> UnityFsReader<R> {
+    /// Parses the bundle header and directory, keeping up to `cache_size` decompressed blocks
+    /// around. At least one block is always kept.
+    pub(crate) fn parse(mut reader: R, cache_size: usize) -> io::Result<Self> {
+        let signature = read_cstring(&mut reader)?;
+        if !signature.starts_with("Unity") {
+            return Err(UnityFsError::BadMagic { offset: 0 }.into());
+        }
+        let format_version = read_be_u32(&mut reader)?;
+        let unity_version = read_cstring(&mut reader)?;
+        let generator_version = read_cstring(&mut reader)?;
+        let file_size = read_be_u64(&mut reader)?;
+
+        let c_block_size = read_be_u32(&mut reader)?;
+        let u_block_size = read_be_u32(&mut reader)?;
+        let flags = read_be_u32(&mut reader)?;
+        let mut header_end = reader.stream_position()?;
+        if format_version >= 7 {
+            header_end = header_end.next_multiple_of(16);
+        }
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        let (metadata_position, mut data_position, data_end) =
+            if flags & BLOCKS_INFO_AT_THE_END != 0 {
+                let end = stream_end.min(file_size);
+                let metadata_position = end
+                    .checked_sub(u64::from(c_block_size))
+                    .ok_or(UnityFsError::UnexpectedEof { offset: end })?;
+                (metadata_position, header_end, metadata_position)
+            } else {
+                (header_end, header_end + u64::from(c_block_size), stream_end)
+            };
+        if flags & BLOCKS_NEED_PADDING_AT_START != 0 {
+            data_position = data_position.next_multiple_of(16);
+        }
+
+        let raw_metadata = read_at(&mut reader, metadata_position, c_block_size)?;
+        let metadata = CompressedBlock::from_slice(u_block_size, flags & 0x3f, &raw_metadata);
+        let metadata = metadata.decompress()?;
+        let (_, metadata) =
+            Metadata::parse(&metadata).map_err(|e| UnityFsError::from_nom(&metadata, 0, e))?;
+
+        let mut position = data_position;
+        let mut offset = 0u64;
+        let blocks = metadata
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| {
+                let entry = BlockEntry {
+                    position,
+                    offset,
+                    c_size: block.c_size,
+                    u_size: block.u_size,
+                    flags: block.flags,
+                };
+                position = position.saturating_add(u64::from(block.c_size));
+                offset = offset.saturating_add(u64::from(block.u_size));
+                let compression_type_id = (block.flags & 0x3f) as u32;
+                if position > data_end
+                    || !is_plausible_block(compression_type_id, block.c_size, block.u_size)
+                {
+                    return Err(UnityFsError::InvalidBlockInfo {
+                        offset: block_info_offset(idx),
+                    });
+                }
+                Ok(entry)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            reader: RefCell::new(reader),
+            signature,
+            format_version,
+            unity_version,
+            generator_version,
+            header: BundleHeader {
+                flags,
+                file_size,
+                compressed_size: c_block_size,
+                uncompressed_size: u_block_size,
+            },
+            metadata,
+            blocks,
+            cache: RefCell::new(VecDeque::new()),
+            cache_size: cache_size.max(1),
+        })
+    }
+
+    fn with_block<T>(&self, idx: usize, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        let mut cache = self.cache.borrow_mut();
+        if let Some(pos) = cache.iter().position(|(cached_idx, _)| *cached_idx == idx) {
+            let hit = cache.remove(pos).unwrap();
+            cache.push_front(hit);
+            return Ok(f(&cache[0].1));
+        }
+
+        let entry = &self.blocks[idx];
+        let raw = read_at(&mut *self.reader.borrow_mut(), entry.position, entry.c_size)?;
+        let block = CompressedBlock::from_slice(entry.u_size, (entry.flags & 0x3f) as u32, &raw);
+        let block = block.decompress()?;
+        cache.truncate(self.cache_size - 1);
+        cache.push_front((idx, block));
+        Ok(f(&cache[0].1))
+    }
+
+    /// Reads a range of the uncompressed stream, decompressing only the blocks backing it.
+    pub fn read_range(&self, range: std::ops::Range<u64>) -> io::Result<Vec<u8>> {
+        let std::ops::Range { start, end } = range;
+        let len = self
+            .blocks
+            .last()
+            .map(|b| b.offset + u64::from(b.u_size))
+            .unwrap_or(0);
+        if start > end || end > len {
+            return Err(UnityFsError::OutOfRange { start, end, len }.into());
+        }
+        // The range comes from the directory, so the buffer grows with the blocks read rather
+        // than being reserved for it up front.
+        let mut out = Vec::new();
+        let mut pos = start;
+        while pos < end {
+            let idx = self
+                .blocks
+                .partition_point(|b| b.offset + u64::from(b.u_size) <= pos);
+            let entry = match self.blocks.get(idx) {
+                Some(entry) => entry,
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+            let block_start = (pos - entry.offset) as usize;
+            let block_end = std::cmp::min(end - entry.offset, u64::from(entry.u_size)) as usize;
+            self.with_block(idx, |block| {
+                out.extend_from_slice(&block[block_start..block_end]);
+            })?;
+            pos = entry.offset + block_end as u64;
+        }
+        Ok(out)
+    }
+
+    /// Reads a resource by name.
+    ///
+    /// Resources stored as `<name>.split0`, `<name>.split1`, ... can also be read by `name`,
+    /// which joins the parts together.
+    pub fn resource(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let node = match self.metadata.nodes.iter().find(|node| node.name == name) {
+            Some(node) => node,
+            None => return self.split_resource(name),
+        };
+        self.read_range(checked_range(node.offset, node.size)?)
+            .map(Some)
+    }
+
+    fn split_resource(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let parts = match self.metadata.split_parts(name) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        let mut out = Vec::new();
+        for part in parts {
+            out.extend(self.read_range(checked_range(part.offset, part.size)?)?);
+        }
+        Ok(Some(out))
+    }
+
+    /// Reads a resource that is a bundle of its own into memory, and parses it like a file.
+    ///
+    /// Returns `Ok(None)` if there is no such resource or it isn't a bundle.
+    pub fn nested_bundle(
+        &self,
+        name: &str,
+    ) -> io::Result<Option<UnityFsReader<io::Cursor<Vec<u8>>>>> {
+        match self.resource(name)? {
+            Some(data) if crate::detect(&data) == crate::ContainerKind::UnityFs => {
+                UnityFsReader::parse(io::Cursor::new(data), self.cache_size).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The first serialized file in the bundle.
+    pub fn main_asset(&self) -> io::Result<AssetReader<'_, R>> {
+        Self::read_asset(FsRef::Borrowed(self), 0)
+    }
+
+    /// Like [`UnityFsReader::main_asset`], handing the bundle over to the asset so that it can
+    /// be kept around without a borrow, such as by bindings to other languages.
+    pub fn into_main_asset(self) -> io::Result<AssetReader<'static, R>>
+    where
+        R: 'static,
+    {
+        Self::read_asset(FsRef::Owned(Box::new(self)), 0)
+    }
+
+    /// Every serialized file in the bundle, in directory order.
+    pub fn assets(&self) -> impl Iterator<Item = io::Result<AssetReader<'_, R>>> + '_ {
+        self.metadata
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(idx, node)| node.is_serialized_file(*idx))
+            .map(move |(idx, _)| Self::read_asset(FsRef::Borrowed(self), idx))
+    }
+
+    fn read_asset(fs: FsRef<'_, R>, idx: usize) -> io::Result<AssetReader<'_, R>> {
+        let node = fs.metadata.nodes.get(idx).ok_or(UnityFsError::NoAssets)?;
+        let name = node.name.clone();
+        let offset = node.offset;
+        let node_end = checked_range(node.offset, node.size)?.end;
+
+        let header = fs.read_range(offset..std::cmp::min(
+            offset.saturating_add(AssetTable::HEADER_SIZE as u64),
+            node_end,
+        ))?;
+        let (_, header) =
+            AssetTable::parse_header(&header).map_err(|e| UnityFsError::from_nom(&header, 0, e))?;
+        let table_end = std::cmp::min(offset.saturating_add(header.table_len()), node_end);
+        let raw_table = fs.read_range(offset..table_end)?;
+        let (_, table) = AssetTable::parse(&raw_table, offset, &mut TypeTreeCache::default())
+            .map_err(|e| UnityFsError::from_nom(&raw_table, 0, e))?;
+
+        Ok(AssetReader {
+            fs,
+            name,
+            offset,
+            header: table.header,
+            endianness: table.endianness,
+            tree: table.tree.into_owned(),
+            objects: table
+                .objects
+                .into_iter()
+                .map(|info| (info.path_id, info))
+                .collect(),
+            refs: table.refs.into_iter().map(ExternalRef::into_owned).collect(),
+        })
+    }
+}
+
+impl<R> UnityFsReader<R> {
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    pub fn unity_version(&self) -> &str {
+        &self.unity_version
+    }
+
+    pub fn generator_version(&self) -> &str {
+        &self.generator_version
+    }
+
+    pub fn info(&self) -> BundleInfo {
+        self.metadata.info(self.header)
+    }
+
+    pub fn guid(&self) -> [u8; 16] {
+        self.metadata.guid
+    }
+}
+
+/// The bundle an [`AssetReader`] reads from, which it either borrows or owns.
+enum FsRef<'r, R> {
+    Borrowed(&'r UnityFsReader<R>),
+    Owned(Box<UnityFsReader<R>>),
+}
+
+impl<R> std::ops::Deref for FsRef<'_, R> {
+    type Target = UnityFsReader<R>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            FsRef::Borrowed(fs) => fs,
+            FsRef::Owned(fs) => fs,
+        }
+    }
+}
+
+/// A serialized file inside a [`UnityFsReader`] whose objects are parsed when accessed.
+pub struct AssetReader<'r, R> {
+    fs: FsRef<'r, R>,
+    name: String,
+    offset: u64,
+    header: SerializedFileHeader,
+    endianness: Endianness,
+    tree: TypeMetadata<'static>,
+    objects: BTreeMap<i64, ObjectInfo>,
+    refs: Vec<ExternalRef<'static>>,
+}
+
+impl<R: Read + Seek> AssetReader<'_, R> {
+    fn read_raw(&self, info: &ObjectInfo) -> io::Result<Vec<u8>> {
+        let start = checked_range(self.offset, info.start)?.end;
+        let end = checked_range(self.offset, info.end)?.end;
+        self.fs.read_range(start..end)
+    }
+
+    fn read_object(&self, info: &ObjectInfo) -> io::Result<Object<'static>> {
+        let raw = self.read_raw(info)?;
+        let data = info.read_data(&self.tree, &raw, self.endianness)?.clone_owned();
+        Ok(Object {
+            path_id: info.path_id,
+            type_id: info.type_id,
+            class_id: info.class_id,
+            is_destroyed: info.is_destroyed,
+            is_stripped: info.is_stripped,
+            data,
+            raw: raw.into(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The header of the file, as read before the type metadata.
+    pub fn header(&self) -> SerializedFileHeader {
+        self.header
+    }
+
+    /// Version of the engine that wrote the file, such as `2019.4.40f1`.
+    pub fn unity_version(&self) -> &str {
+        self.tree.unity_version()
+    }
+
+    /// Platform the file was built for, which tells how some of its data is laid out, such as
+    /// the swizzled textures of [`Platform::Switch`].
+    pub fn target_platform(&self) -> Platform {
+        self.tree.target_platform()
+    }
+
+    /// Files referenced by this asset, in `m_FileID` order starting from 1.
+    pub fn externals(&self) -> std::slice::Iter<'_, ExternalRef<'static>> {
+        self.refs.iter()
+    }
+
+    /// Number of objects in the file, known from the object table without reading any of them.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = io::Result<Object<'static>>> + '_ {
+        self.objects.values().map(move |info| self.read_object(info))
+    }
+
+    /// Path IDs of the objects in the file, in the order [`AssetReader::objects`] reads them,
+    /// without reading any of them.
+    pub fn path_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.objects.keys().copied()
+    }
+
+    pub fn get_object(&self, path_id: &i64) -> io::Result<Option<Object<'static>>> {
+        self.objects
+            .get(path_id)
+            .map(|info| self.read_object(info))
+            .transpose()
+    }
+
+    /// Reads the serialized bytes of an object without parsing it, decompressing only the
+    /// blocks it lies in.
+    pub fn raw_bytes(&self, path_id: &i64) -> io::Result<Option<Vec<u8>>> {
+        self.objects
+            .get(path_id)
+            .map(|info| self.read_raw(info))
+            .transpose()
+    }
+
+    /// Follows a `PPtr<T>` to the object it points to.
+    ///
+    /// Returns `Ok(None)` for null pointers and for references into other files.
+    pub fn resolve(&self, pptr: &Data) -> io::Result<Option<Object<'static>>> {
+        match pptr.as_pptr() {
+            Some((0, path_id)) => self.get_object(&path_id),
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,1149 @@
+//! Decoding of `Texture2D` pixel data into RGBA8.
+//!
+//! Decoded images are laid out with the top row first. Unity stores rows bottom first, so every
+//! decoder flips the image while reading it, unless told not to with [`DecodeFormat::flip`].
+
+use crate::TextureFormat;
+use image::codecs::dxt;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// How the pixels of a texture are encoded.
+#[derive(Debug, Copy, Clone)]
+pub struct DecodeFormat {
+    format: Format,
+    /// Height in GOBs of the blocks of the block-linear layout, for textures of Switch builds.
+    gobs_per_block: Option<u32>,
+    /// Whether rows are reordered top first.
+    flip: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Format {
+    Etc(etcdec::DecodeFormat),
+    Dxt(dxt::DXTVariant),
+    Raw {
+        layout: ChannelLayout,
+        bit_depth: u8,
+    },
+    Astc {
+        block_width: u32,
+        block_height: u32,
+    },
+    Crunch(crndec::Variant),
+    Pvrtc {
+        bpp: u8,
+    },
+    Bc(bcdec::DecodeFormat),
+    Packed16(PackedLayout),
+    Alpha8,
+    /// Half or single precision floats, clamped to `[0, 1]`.
+    FloatChannels {
+        layout: ChannelLayout,
+        bit_depth: u8,
+    },
+}
+
+/// Order of the channels of an uncompressed pixel.
+#[derive(Debug, Copy, Clone)]
+enum ChannelLayout {
+    R,
+    Rg,
+    Rgb,
+    Rgba,
+    Argb,
+}
+
+impl ChannelLayout {
+    fn channels(self) -> usize {
+        match self {
+            ChannelLayout::R => 1,
+            ChannelLayout::Rg => 2,
+            ChannelLayout::Rgb => 3,
+            ChannelLayout::Rgba | ChannelLayout::Argb => 4,
+        }
+    }
+
+    fn to_rgba(self, pixel: &[u8]) -> [u8; 4] {
+        match self {
+            // Single channel data shows up as greyscale.
+            ChannelLayout::R => [pixel[0], pixel[0], pixel[0], 0xff],
+            ChannelLayout::Rg => [pixel[0], pixel[1], 0, 0xff],
+            ChannelLayout::Rgb => [pixel[0], pixel[1], pixel[2], 0xff],
+            ChannelLayout::Rgba => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            ChannelLayout::Argb => [pixel[1], pixel[2], pixel[3], pixel[0]],
+        }
+    }
+}
+
+/// Bit layout of a little-endian 16-bit pixel, from the most significant bits down.
+#[derive(Debug, Copy, Clone)]
+enum PackedLayout {
+    Rgb565,
+    Rgba4444,
+    Argb4444,
+}
+
+impl PackedLayout {
+    fn to_rgba(self, pixel: u16) -> [u8; 4] {
+        // Replicate the high bits into the low ones so that full intensity stays 0xff.
+        let expand4 = |v: u16| (v & 0xf) as u8 * 0x11;
+        let expand5 = |v: u16| {
+            let v = (v & 0x1f) as u8;
+            (v << 3) | (v >> 2)
+        };
+        let expand6 = |v: u16| {
+            let v = (v & 0x3f) as u8;
+            (v << 2) | (v >> 4)
+        };
+        match self {
+            PackedLayout::Rgb565 => [
+                expand5(pixel >> 11),
+                expand6(pixel >> 5),
+                expand5(pixel),
+                0xff,
+            ],
+            PackedLayout::Rgba4444 => [
+                expand4(pixel >> 12),
+                expand4(pixel >> 8),
+                expand4(pixel >> 4),
+                expand4(pixel),
+            ],
+            PackedLayout::Argb4444 => [
+                expand4(pixel >> 8),
+                expand4(pixel >> 4),
+                expand4(pixel),
+                expand4(pixel >> 12),
+            ],
+        }
+    }
+}
+
+impl DecodeFormat {
+    /// Maps a `m_TextureFormat` value to its encoding, or `None` if it can't be decoded.
+    ///
+    /// `unity_crunch` selects Unity's fork of crunch for crunched DXT textures. Unity switched
+    /// to it in 2017.3, the same release that added `m_ForcedFallbackFormat`, so the presence of
+    /// that field tells which one a texture uses.
+    pub fn from_texture_format(format: i32, unity_crunch: bool) -> Option<Self> {
+        use TextureFormat::*;
+
+        let format = match TextureFormat::from_i32(format)? {
+            EtcRgb4 => Format::Etc(etcdec::DecodeFormat::EtcRgb4),
+            Etc2Rgb => Format::Etc(etcdec::DecodeFormat::Etc2Rgb),
+            Etc2Rgba1 => Format::Etc(etcdec::DecodeFormat::Etc2Rgba1),
+            Etc2Rgba8 => Format::Etc(etcdec::DecodeFormat::Etc2Rgba8),
+            // Single and dual channel EAC are shown in red and green.
+            EacR => Format::Etc(etcdec::DecodeFormat::EacR),
+            EacRSigned => Format::Etc(etcdec::DecodeFormat::EacRSigned),
+            EacRg => Format::Etc(etcdec::DecodeFormat::EacRg),
+            EacRgSigned => Format::Etc(etcdec::DecodeFormat::EacRgSigned),
+            Alpha8 => Format::Alpha8,
+            Argb4444 => Format::Packed16(PackedLayout::Argb4444),
+            Rgb565 => Format::Packed16(PackedLayout::Rgb565),
+            Rgba4444 => Format::Packed16(PackedLayout::Rgba4444),
+            Rgb24 => Format::Raw {
+                layout: ChannelLayout::Rgb,
+                bit_depth: 8,
+            },
+            Rgba32 => Format::Raw {
+                layout: ChannelLayout::Rgba,
+                bit_depth: 8,
+            },
+            Argb32 => Format::Raw {
+                layout: ChannelLayout::Argb,
+                bit_depth: 8,
+            },
+            R8 => Format::Raw {
+                layout: ChannelLayout::R,
+                bit_depth: 8,
+            },
+            Rg16 => Format::Raw {
+                layout: ChannelLayout::Rg,
+                bit_depth: 8,
+            },
+            R16 => Format::Raw {
+                layout: ChannelLayout::R,
+                bit_depth: 16,
+            },
+            RHalf => Format::FloatChannels {
+                layout: ChannelLayout::R,
+                bit_depth: 16,
+            },
+            RgHalf => Format::FloatChannels {
+                layout: ChannelLayout::Rg,
+                bit_depth: 16,
+            },
+            RgbaHalf => Format::FloatChannels {
+                layout: ChannelLayout::Rgba,
+                bit_depth: 16,
+            },
+            RFloat => Format::FloatChannels {
+                layout: ChannelLayout::R,
+                bit_depth: 32,
+            },
+            RgFloat => Format::FloatChannels {
+                layout: ChannelLayout::Rg,
+                bit_depth: 32,
+            },
+            RgbaFloat => Format::FloatChannels {
+                layout: ChannelLayout::Rgba,
+                bit_depth: 32,
+            },
+            // RGB and RGBA variants are only told apart by the alpha of each block.
+            PvrtcRgb2 | PvrtcRgba2 => Format::Pvrtc { bpp: 2 },
+            PvrtcRgb4 | PvrtcRgba4 => Format::Pvrtc { bpp: 4 },
+            // RGB and RGBA variants share a block layout.
+            format @ (AstcRgb4x4 | AstcRgb5x5 | AstcRgb6x6 | AstcRgb8x8 | AstcRgb10x10
+            | AstcRgb12x12 | AstcRgba4x4 | AstcRgba5x5 | AstcRgba6x6 | AstcRgba8x8
+            | AstcRgba10x10 | AstcRgba12x12) => {
+                let (block_width, block_height) = format.block_size();
+                Format::Astc {
+                    block_width,
+                    block_height,
+                }
+            }
+            Dxt1 => Format::Dxt(dxt::DXTVariant::DXT1),
+            Dxt5 => Format::Dxt(dxt::DXTVariant::DXT5),
+            Dxt1Crunched | Dxt5Crunched if unity_crunch => Format::Crunch(crndec::Variant::Unity),
+            Dxt1Crunched | Dxt5Crunched => Format::Crunch(crndec::Variant::Crnlib),
+            EtcRgb4Crunched | Etc2Rgba8Crunched => Format::Crunch(crndec::Variant::Unity),
+            // HDR texels of BC6H are clamped to the displayable range.
+            Bc6h => Format::Bc(bcdec::DecodeFormat::Bc6hUnsigned),
+            Bc7 => Format::Bc(bcdec::DecodeFormat::Bc7),
+            _ => return None,
+        };
+        Some(DecodeFormat {
+            format,
+            gobs_per_block: None,
+            flip: true,
+        })
+    }
+
+    /// Maps a `GraphicsFormat` value, which `Texture2DArray` uses in place of `m_TextureFormat`,
+    /// to its encoding.
+    ///
+    /// sRGB and linear variants decode the same way.
+    pub fn from_graphics_format(format: i32) -> Option<Self> {
+        let texture_format = match format {
+            1 | 5 => 63,
+            2 | 6 => 62,
+            3 | 7 => 3,
+            4 | 8 => 4,
+            21 => 9,
+            45 => 15,
+            46 => 16,
+            48 => 17,
+            49 => 18,
+            50 => 19,
+            52 => 20,
+            96 | 97 => 10,
+            100 | 101 => 12,
+            106 => 24,
+            108 | 109 => 25,
+            118 => 34,
+            119 | 120 => 45,
+            121 | 122 => 46,
+            123 | 124 => 47,
+            // RGBA ASTC from 4x4 to 12x12, each size in sRGB and then linear.
+            id @ 128..=139 => 54 + (id - 128) / 2,
+            _ => return None,
+        };
+        Self::from_texture_format(texture_format, true)
+    }
+
+    /// Size in bytes of the full-resolution image, which comes first when the texture has mips.
+    ///
+    /// Crunched data carries its own mip table, so the whole buffer is needed.
+    pub fn level_size(self, width: u32, height: u32) -> Option<u64> {
+        let blocks = |block_width: u32, block_height: u32| {
+            u64::from(width.div_ceil(block_width)) * u64::from(height.div_ceil(block_height))
+        };
+        match self.format {
+            Format::Etc(format) => Some(blocks(4, 4) * format.block_size() as u64),
+            Format::Dxt(dxt::DXTVariant::DXT1) => Some(blocks(4, 4) * 8),
+            Format::Dxt(_) => Some(blocks(4, 4) * 16),
+            Format::Raw { layout, bit_depth } | Format::FloatChannels { layout, bit_depth } => {
+                Some(
+                    u64::from(width)
+                        * u64::from(height)
+                        * layout.channels() as u64
+                        * u64::from(bit_depth / 8),
+                )
+            }
+            Format::Astc {
+                block_width,
+                block_height,
+            } => Some(blocks(block_width, block_height) * 16),
+            Format::Crunch(_) => None,
+            Format::Pvrtc { bpp } => Some(pvrtc_format(bpp).image_size(width, height) as u64),
+            Format::Bc(_) => Some(blocks(4, 4) * 16),
+            Format::Packed16(_) => Some(u64::from(width) * u64::from(height) * 2),
+            Format::Alpha8 => Some(u64::from(width) * u64::from(height)),
+        }
+    }
+
+    /// Offset in bytes of the mip `level`, whose size is that of the full image halved `level`
+    /// times. Mips are stored from the largest down.
+    ///
+    /// Returns `None` where the offset can't be told from the size alone: for crunched data,
+    /// and for swizzled textures whose mips are padded.
+    pub fn mip_offset(self, width: u32, height: u32, level: u32) -> Option<u64> {
+        if self.gobs_per_block.is_some() {
+            return None;
+        }
+        (0..level).try_fold(0u64, |offset, level| {
+            let (width, height) = mip_size(width, height, level);
+            offset.checked_add(self.level_size(width, height)?)
+        })
+    }
+
+    /// Sets whether decoding flips the image so the top row comes first, which it does by
+    /// default. Without the flip, rows are left bottom first as Unity stores them, which saves
+    /// reordering them for consumers that handle the orientation themselves.
+    pub fn flip(self, flip: bool) -> Self {
+        Self { flip, ..self }
+    }
+
+    /// Decodes the full-resolution image into RGBA8 pixels, top row first unless
+    /// [`DecodeFormat::flip`] says otherwise.
+    pub fn decode(self, width: u32, height: u32, image_data: impl Read) -> io::Result<Vec<u8>> {
+        self.decode_with_progress(width, height, image_data, |_, _| true)
+    }
+
+    /// Like [`DecodeFormat::decode`], calling `progress` with the number of decoded rows and the
+    /// height as decoding goes.
+    ///
+    /// Block formats report after every row of blocks, others once they are done. Returning
+    /// `false` from `progress` stops decoding with an [`io::ErrorKind::Interrupted`] error.
+    ///
+    /// Images with a zero dimension decode to no pixels at all. Images whose pixels wouldn't fit
+    /// in memory fail with an [`io::ErrorKind::InvalidInput`] error before anything is read.
+    ///
+    /// Exactly the bytes of the first level, as told by [`DecodeFormat::level_size`], are read,
+    /// whatever follows them; `m_CompleteImageSize` and the length of `image data` cover all
+    /// mips and may include padding. Image data shorter than that fails with an
+    /// [`io::ErrorKind::UnexpectedEof`] error saying how many bytes were found, before any pixels
+    /// are allocated.
+    pub fn decode_with_progress(
+        self,
+        width: u32,
+        height: u32,
+        mut image_data: impl Read,
+        mut progress: impl FnMut(u32, u32) -> bool,
+    ) -> io::Result<Vec<u8>> {
+        if width == 0 || height == 0 {
+            return Ok(Vec::new());
+        }
+        let fits = width.checked_mul(4).is_some()
+            && usize::try_from(u64::from(width) * u64::from(height) * 4).is_ok();
+        if !fits {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("texture dimensions {}x{} are too large", width, height),
+            ));
+        }
+        if let Some(gobs_per_block) = self.gobs_per_block {
+            return self.decode_swizzled(
+                width,
+                height,
+                gobs_per_block,
+                &mut image_data,
+                &mut progress,
+            );
+        }
+        let mut progress = Progress {
+            callback: &mut progress,
+            height,
+            rows: 0,
+        };
+        let progress = &mut progress;
+        // Smaller mips, or the padding of some platforms, may follow the first level; leave them
+        // unread.
+        let level_size = self.level_size(width, height);
+        let mut level = Vec::new();
+        image_data
+            .take(level_size.unwrap_or(u64::MAX))
+            .read_to_end(&mut level)?;
+        if let Some(needed) = level_size {
+            if (level.len() as u64) < needed {
+                return Err(short_image_data(level.len() as u64, needed, width, height));
+            }
+        }
+        let mut image_data = level.as_slice();
+        let flip = self.flip;
+        let buf = match self.format {
+            Format::Etc(format) => read_etc(width, height, format, flip, &mut image_data, progress),
+            Format::Dxt(variant) => {
+                read_dxt(width, height, variant, flip, &mut image_data, progress)
+            }
+            Format::Raw { layout, bit_depth } => {
+                read_raw(width, height, layout, bit_depth, flip, &mut image_data)
+            }
+            Format::Astc {
+                block_width,
+                block_height,
+            } => read_astc(
+                width,
+                height,
+                block_width,
+                block_height,
+                flip,
+                &mut image_data,
+                progress,
+            ),
+            Format::Crunch(variant) => {
+                read_crunch(width, height, variant, flip, &mut image_data, progress)
+            }
+            Format::Pvrtc { bpp } => read_pvrtc(width, height, bpp, flip, &mut image_data),
+            Format::Bc(format) => read_bc(width, height, format, flip, &mut image_data, progress),
+            Format::Packed16(layout) => read_packed16(width, height, layout, flip, &mut image_data),
+            Format::Alpha8 => read_alpha8(width, height, flip, &mut image_data),
+            Format::FloatChannels { layout, bit_depth } => {
+                read_float(width, height, layout, bit_depth, flip, &mut image_data)
+            }
+        };
+        let buf = buf?;
+        if progress.rows < height {
+            progress.report(height)?;
+        }
+        Ok(buf)
+    }
+
+    /// Reads the image from the block-linear layout of the Nintendo Switch GPU, for textures of
+    /// builds whose target platform is [`Platform::Switch`](crate::Platform::Switch).
+    ///
+    /// The layout depends on the `m_PlatformBlob` of the texture. The format is returned as it
+    /// is if the blob is too short, or if the Switch has no such layout for it.
+    pub fn switch_swizzled(self, platform_blob: &[u8]) -> Self {
+        let block_height_log2 = match platform_blob.get(8..12) {
+            Some(bytes) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            None => return self,
+        };
+        if self.format.swizzle_unit().is_none() || block_height_log2 > 5 {
+            return self;
+        }
+        Self {
+            gobs_per_block: Some(1 << block_height_log2),
+            ..self
+        }
+    }
+
+    /// Deswizzles the padded image the Switch stores, decodes it and crops it back to `width`
+    /// by `height`.
+    fn decode_swizzled(
+        self,
+        width: u32,
+        height: u32,
+        gobs_per_block: u32,
+        image_data: &mut dyn Read,
+        progress: &mut dyn FnMut(u32, u32) -> bool,
+    ) -> io::Result<Vec<u8>> {
+        let (unit_width, unit_height) = self
+            .format
+            .swizzle_unit()
+            .expect("swizzled format has no unit");
+        let pad = |len: u32, to: u32| {
+            u32::try_from(u64::from(len.div_ceil(to)) * u64::from(to)).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("texture dimensions {}x{} are too large", width, height),
+                )
+            })
+        };
+        let padded_width = pad(width, unit_width * GOB_WIDTH)?;
+        let padded_height = pad(height, unit_height * GOB_HEIGHT * gobs_per_block)?;
+        let units_x = (padded_width / unit_width) as usize;
+        let units_y = (padded_height / unit_height) as usize;
+        let needed = units_x * units_y * SWIZZLE_UNIT_SIZE;
+        let mut swizzled = Vec::new();
+        image_data.take(needed as u64).read_to_end(&mut swizzled)?;
+        if swizzled.len() < needed {
+            return Err(short_image_data(
+                swizzled.len() as u64,
+                needed as u64,
+                width,
+                height,
+            ));
+        }
+        let linear = deswizzle(&swizzled, units_x, gobs_per_block as usize);
+
+        let format = Self {
+            gobs_per_block: None,
+            ..self
+        };
+        let padded = format.decode_with_progress(
+            padded_width,
+            padded_height,
+            &*linear,
+            |rows, padded_height| {
+                progress(
+                    (u64::from(rows) * u64::from(height) / u64::from(padded_height)) as u32,
+                    height,
+                )
+            },
+        )?;
+        // The image starts at the first stored row, which ends up at the bottom once flipped.
+        let row_len = width as usize * 4;
+        let padded_row_len = padded_width as usize * 4;
+        let first_row = if self.flip { padded_height - height } else { 0 };
+        let rows =
+            &padded[first_row as usize * padded_row_len..][..height as usize * padded_row_len];
+        Ok(rows
+            .chunks_exact(padded_row_len)
+            .flat_map(|row| &row[..row_len])
+            .copied()
+            .collect())
+    }
+}
+
+/// Size in bytes of the units a GOB is made of, which are 16 bytes wide and one row high.
+const SWIZZLE_UNIT_SIZE: usize = 16;
+/// Size in units of a GOB, the 512-byte tile of the block-linear layout.
+const GOB_WIDTH: u32 = 4;
+const GOB_HEIGHT: u32 = 8;
+
+impl Format {
+    /// Size in pixels of 16 bytes of image data in the Switch's block-linear layout, which is
+    /// a single block for 16-byte block formats, two blocks side by side for 8-byte ones, and a
+    /// run of pixels of a row otherwise.
+    fn swizzle_unit(self) -> Option<(u32, u32)> {
+        let run = |bytes_per_pixel: u32| match bytes_per_pixel {
+            1 | 2 | 4 | 8 | 16 => Some((16 / bytes_per_pixel, 1)),
+            _ => None,
+        };
+        match self {
+            Format::Etc(format) if format.block_size() == 16 => Some((4, 4)),
+            Format::Etc(_) => Some((8, 4)),
+            Format::Dxt(dxt::DXTVariant::DXT1) => Some((8, 4)),
+            Format::Dxt(_) => Some((4, 4)),
+            Format::Raw { layout, bit_depth } | Format::FloatChannels { layout, bit_depth } => {
+                run(layout.channels() as u32 * u32::from(bit_depth / 8))
+            }
+            Format::Astc {
+                block_width,
+                block_height,
+            } => Some((block_width, block_height)),
+            Format::Bc(_) => Some((4, 4)),
+            Format::Packed16(_) => run(2),
+            Format::Alpha8 => run(1),
+            Format::Crunch(_) | Format::Pvrtc { .. } => None,
+        }
+    }
+}
+
+/// Reorders the units of a block-linear image `units_x` units wide into rows.
+///
+/// The image is cut into blocks one GOB wide and `gobs_per_block` GOBs high, stored left to
+/// right and then top to bottom. The GOBs of a block are stored top to bottom, and the units of
+/// a GOB in an order that interleaves the bits of their coordinates.
+fn deswizzle(swizzled: &[u8], units_x: usize, gobs_per_block: usize) -> Vec<u8> {
+    let gob_width = GOB_WIDTH as usize;
+    let gob_height = GOB_HEIGHT as usize;
+    let gob_size = gob_width * gob_height * SWIZZLE_UNIT_SIZE;
+    let mut linear = vec![0; swizzled.len()];
+    let mut gobs = swizzled.chunks_exact(gob_size);
+    let block_rows = swizzled.len() / (units_x * SWIZZLE_UNIT_SIZE * gob_height * gobs_per_block);
+    for block_row in 0..block_rows {
+        for gob_x in 0..units_x / gob_width {
+            for gob_in_block in 0..gobs_per_block {
+                let gob_y = block_row * gobs_per_block + gob_in_block;
+                let gob = gobs.next().expect("image is a whole number of blocks");
+                for (i, unit) in gob.chunks_exact(SWIZZLE_UNIT_SIZE).enumerate() {
+                    let x = gob_x * gob_width + ((i >> 3) & 0b10 | (i >> 1) & 0b1);
+                    let y = gob_y * gob_height + ((i >> 1) & 0b110 | i & 0b1);
+                    let at = (y * units_x + x) * SWIZZLE_UNIT_SIZE;
+                    linear[at..at + SWIZZLE_UNIT_SIZE].copy_from_slice(unit);
+                }
+            }
+        }
+    }
+    linear
+}
+
+/// Size of the mip `level` of a `width` by `height` image. Sides are never halved below 1,
+/// unless the image is empty.
+pub fn mip_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    let halve = |len: u32| match len {
+        0 => 0,
+        len => len.checked_shr(level).unwrap_or(0).max(1),
+    };
+    (halve(width), halve(height))
+}
+
+/// The first of `mip_count` mips whose sides are both at most `max_size`, or the last mip if
+/// none is that small.
+pub fn mip_for_max_size(width: u32, height: u32, mip_count: u32, max_size: u32) -> u32 {
+    (0..mip_count.max(1))
+        .find(|&level| {
+            let (width, height) = mip_size(width, height, level);
+            width.max(height) <= max_size
+        })
+        .unwrap_or(mip_count.max(1) - 1)
+}
+
+/// The `scanline`-long rows of `buf` in the order they are stored, which is bottom first when
+/// flipping.
+fn stored_rows(
+    buf: &mut [u8],
+    scanline: usize,
+    flip: bool,
+) -> Box<dyn Iterator<Item = &mut [u8]> + '_> {
+    let rows = buf.chunks_mut(scanline);
+    if flip {
+        Box::new(rows.rev())
+    } else {
+        Box::new(rows)
+    }
+}
+
+/// The error for image data that ends after `len` of the `needed` bytes of a level.
+fn short_image_data(len: u64, needed: u64, width: u32, height: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!(
+            "image data is too short: {} bytes where a {}x{} image needs {}",
+            len, width, height, needed
+        ),
+    )
+}
+
+/// Passes the number of decoded rows to a callback, which may cancel decoding.
+struct Progress<'p> {
+    callback: &'p mut dyn FnMut(u32, u32) -> bool,
+    height: u32,
+    rows: u32,
+}
+
+impl Progress<'_> {
+    fn report(&mut self, rows: u32) -> io::Result<()> {
+        self.rows = std::cmp::min(rows, self.height);
+        if (self.callback)(self.rows, self.height) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "decoding was cancelled",
+            ))
+        }
+    }
+}
+
+fn read_etc(
+    width: u32,
+    height: u32,
+    format: etcdec::DecodeFormat,
+    flip: bool,
+    mut image_data: impl Read,
+    progress: &mut Progress<'_>,
+) -> io::Result<Vec<u8>> {
+    let block_width = width.div_ceil(4);
+    let block_height = height.div_ceil(4);
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for block_y in 0..block_height {
+        let y = block_y * 4;
+        for block_x in 0..block_width {
+            let x = block_x * 4;
+            let block = etcdec::decode_single_block(&mut image_data, format)?;
+            // Edge blocks may extend past the image; clip them. Rows are clipped by `zip`, which
+            // runs out of image rows in the last block row.
+            let copy_width = (std::cmp::min(4, width - x) * 4) as usize;
+            for (block_raw, target) in block.iter().zip(
+                stored_rows(&mut buf[(4 * x as usize)..], scanline, flip)
+                    .skip(y as usize)
+                    .take(4),
+            ) {
+                target[..copy_width].copy_from_slice(&block_raw[..copy_width]);
+            }
+        }
+        progress.report(y + 4)?;
+    }
+    Ok(buf)
+}
+
+fn pvrtc_format(bpp: u8) -> pvrtcdec::DecodeFormat {
+    if bpp == 2 {
+        pvrtcdec::DecodeFormat::Pvrtc2Bpp
+    } else {
+        pvrtcdec::DecodeFormat::Pvrtc4Bpp
+    }
+}
+
+fn read_pvrtc(
+    width: u32,
+    height: u32,
+    bpp: u8,
+    flip: bool,
+    mut image_data: impl Read,
+) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    image_data.read_to_end(&mut data)?;
+    let rgba = pvrtcdec::decode_image(&data, width, height, pvrtc_format(bpp))?;
+    if !flip {
+        return Ok(rgba);
+    }
+    let scanline = (width * 4) as usize;
+    Ok(rgba.chunks(scanline).rev().flatten().copied().collect())
+}
+
+fn read_astc(
+    width: u32,
+    height: u32,
+    block_width: u32,
+    block_height: u32,
+    flip: bool,
+    mut image_data: impl Read,
+    progress: &mut Progress<'_>,
+) -> io::Result<Vec<u8>> {
+    let blocks_x = width.div_ceil(block_width);
+    let blocks_y = height.div_ceil(block_height);
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for block_y in 0..blocks_y {
+        let y = block_y * block_height;
+        for block_x in 0..blocks_x {
+            let x = block_x * block_width;
+            let block = astcdec::decode_single_block(
+                &mut image_data,
+                block_width as usize,
+                block_height as usize,
+            )?;
+            // Edge blocks may extend past the image; clip them.
+            let copy_width = (std::cmp::min(block_width, width - x) * 4) as usize;
+            for (block_raw, target) in block.chunks(block_width as usize * 4).zip(
+                stored_rows(&mut buf[(4 * x as usize)..], scanline, flip)
+                    .skip(y as usize)
+                    .take(block_height as usize),
+            ) {
+                target[..copy_width].copy_from_slice(&block_raw[..copy_width]);
+            }
+        }
+        progress.report(y + block_height)?;
+    }
+    Ok(buf)
+}
+
+fn read_bc(
+    width: u32,
+    height: u32,
+    format: bcdec::DecodeFormat,
+    flip: bool,
+    mut image_data: impl Read,
+    progress: &mut Progress<'_>,
+) -> io::Result<Vec<u8>> {
+    let block_width = width.div_ceil(4);
+    let block_height = height.div_ceil(4);
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for block_y in 0..block_height {
+        let y = block_y * 4;
+        for block_x in 0..block_width {
+            let x = block_x * 4;
+            let block = bcdec::decode_single_block(&mut image_data, format)?;
+            let copy_width = (std::cmp::min(4, width - x) * 4) as usize;
+            for (block_raw, target) in block.iter().zip(
+                stored_rows(&mut buf[(4 * x as usize)..], scanline, flip)
+                    .skip(y as usize)
+                    .take(4),
+            ) {
+                target[..copy_width].copy_from_slice(&block_raw[..copy_width]);
+            }
+        }
+        progress.report(y + 4)?;
+    }
+    Ok(buf)
+}
+
+/// Decodes a DXT1 block into four rows of RGBA8 pixels, in the order they are stored.
+///
+/// The `image` decoder drops the alpha of blocks using punch-through transparency, so DXT1 is
+/// decoded here instead.
+fn decode_dxt1_block(block: [u8; 8]) -> [[u8; 16]; 4] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let p0 = PackedLayout::Rgb565.to_rgba(c0);
+    let p1 = PackedLayout::Rgb565.to_rgba(c1);
+    let mix = |w0: u16, w1: u16| {
+        let mut pixel = [0xff; 4];
+        for (channel, (&a, &b)) in pixel.iter_mut().zip(p0.iter().zip(&p1)).take(3) {
+            *channel = ((u16::from(a) * w0 + u16::from(b) * w1 + (w0 + w1) / 2) / (w0 + w1)) as u8;
+        }
+        pixel
+    };
+    let palette = if c0 > c1 {
+        [p0, p1, mix(2, 1), mix(1, 2)]
+    } else {
+        [p0, p1, mix(1, 1), [0; 4]]
+    };
+
+    let mut rows = [[0u8; 16]; 4];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (x, pixel) in row.chunks_mut(4).enumerate() {
+            let index = (indices >> (2 * (4 * y + x))) & 3;
+            pixel.copy_from_slice(&palette[index as usize]);
+        }
+    }
+    rows
+}
+
+fn read_dxt1(
+    width: u32,
+    height: u32,
+    flip: bool,
+    mut image_data: impl Read,
+    progress: &mut Progress<'_>,
+) -> io::Result<Vec<u8>> {
+    let block_width = width.div_ceil(4);
+    let block_height = height.div_ceil(4);
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for block_y in 0..block_height {
+        let y = block_y * 4;
+        for block_x in 0..block_width {
+            let x = block_x * 4;
+            let mut block = [0u8; 8];
+            image_data.read_exact(&mut block)?;
+            let block = decode_dxt1_block(block);
+            let copy_width = (std::cmp::min(4, width - x) * 4) as usize;
+            for (block_raw, target) in block.iter().zip(
+                stored_rows(&mut buf[(4 * x as usize)..], scanline, flip)
+                    .skip(y as usize)
+                    .take(4),
+            ) {
+                target[..copy_width].copy_from_slice(&block_raw[..copy_width]);
+            }
+        }
+        progress.report(y + 4)?;
+    }
+    Ok(buf)
+}
+
+fn read_dxt(
+    width: u32,
+    height: u32,
+    variant: dxt::DXTVariant,
+    flip: bool,
+    image_data: impl Read,
+    progress: &mut Progress<'_>,
+) -> io::Result<Vec<u8>> {
+    if let dxt::DXTVariant::DXT1 = variant {
+        return read_dxt1(width, height, flip, image_data, progress);
+    }
+    let dec = dxt::DxtDecoder::new(image_data, width, height, variant)
+        .map_err(|e| invalid_data(format!("failed to build decoder: {}", e)))?;
+    let image = image::DynamicImage::from_decoder(dec)
+        .map_err(|e| invalid_data(format!("failed to decode: {}", e)))?;
+    let image = if flip { image.flipv() } else { image };
+    Ok(image.into_rgba8().into_vec())
+}
+
+fn read_crunch(
+    width: u32,
+    height: u32,
+    variant: crndec::Variant,
+    flip: bool,
+    mut image_data: impl Read,
+    progress: &mut Progress<'_>,
+) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    image_data.read_to_end(&mut buf)?;
+    let texture = crndec::Texture::parse(&buf, variant)
+        .map_err(|e| invalid_data(format!("failed to parse crunch texture: {}", e)))?;
+    let blocks = texture
+        .unpack_level(0)
+        .map_err(|e| invalid_data(format!("failed to transcode crunch texture: {}", e)))?;
+    let blocks = io::Cursor::new(blocks);
+    match texture.format() {
+        crndec::Format::Dxt1 => {
+            read_dxt(width, height, dxt::DXTVariant::DXT1, flip, blocks, progress)
+        }
+        crndec::Format::Dxt5 => {
+            read_dxt(width, height, dxt::DXTVariant::DXT5, flip, blocks, progress)
+        }
+        crndec::Format::Etc1 => read_etc(
+            width,
+            height,
+            etcdec::DecodeFormat::EtcRgb4,
+            flip,
+            blocks,
+            progress,
+        ),
+        crndec::Format::Etc2a => read_etc(
+            width,
+            height,
+            etcdec::DecodeFormat::Etc2Rgba8,
+            flip,
+            blocks,
+            progress,
+        ),
+    }
+}
+
+fn read_raw(
+    width: u32,
+    height: u32,
+    layout: ChannelLayout,
+    bit_depth: u8,
+    flip: bool,
+    mut image_data: impl Read,
+) -> io::Result<Vec<u8>> {
+    let bytes_per_channel = (bit_depth / 8) as usize;
+    let mut row = vec![0u8; width as usize * layout.channels() * bytes_per_channel];
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for target in stored_rows(&mut buf, scanline, flip) {
+        image_data.read_exact(&mut row)?;
+        for (pixel, target) in row
+            .chunks(layout.channels() * bytes_per_channel)
+            .zip(target.chunks_mut(4))
+        {
+            // Keep the most significant byte of each little-endian channel.
+            let mut channels = [0u8; 4];
+            for (channel, src) in channels.iter_mut().zip(pixel.chunks(bytes_per_channel)) {
+                *channel = src[bytes_per_channel - 1];
+            }
+            target.copy_from_slice(&layout.to_rgba(&channels));
+        }
+    }
+    Ok(buf)
+}
+
+fn read_packed16(
+    width: u32,
+    height: u32,
+    layout: PackedLayout,
+    flip: bool,
+    mut image_data: impl Read,
+) -> io::Result<Vec<u8>> {
+    let mut row = vec![0u8; width as usize * 2];
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for target in stored_rows(&mut buf, scanline, flip) {
+        image_data.read_exact(&mut row)?;
+        for (pixel, target) in row.chunks(2).zip(target.chunks_mut(4)) {
+            let pixel = u16::from_le_bytes([pixel[0], pixel[1]]);
+            target.copy_from_slice(&layout.to_rgba(pixel));
+        }
+    }
+    Ok(buf)
+}
+
+fn read_alpha8(
+    width: u32,
+    height: u32,
+    flip: bool,
+    mut image_data: impl Read,
+) -> io::Result<Vec<u8>> {
+    let mut row = vec![0u8; width as usize];
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for target in stored_rows(&mut buf, scanline, flip) {
+        image_data.read_exact(&mut row)?;
+        for (&alpha, target) in row.iter().zip(target.chunks_mut(4)) {
+            target.copy_from_slice(&[0xff, 0xff, 0xff, alpha]);
+        }
+    }
+    Ok(buf)
+}
+
+/// Widens an IEEE 754 half precision float.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = u32::from(bits & 0x8000) << 16;
+    let exponent = u32::from((bits >> 10) & 0x1f);
+    let mantissa = u32::from(bits & 0x3ff);
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal; normalize the mantissa.
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((mantissa << shift) & 0x3ff) << 13
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+fn read_float(
+    width: u32,
+    height: u32,
+    layout: ChannelLayout,
+    bit_depth: u8,
+    flip: bool,
+    mut image_data: impl Read,
+) -> io::Result<Vec<u8>> {
+    let bytes_per_channel = (bit_depth / 8) as usize;
+    let mut row = vec![0u8; width as usize * layout.channels() * bytes_per_channel];
+    let scanline = (width * 4) as usize;
+    let mut buf = vec![0u8; scanline * height as usize];
+    for target in stored_rows(&mut buf, scanline, flip) {
+        image_data.read_exact(&mut row)?;
+        for (pixel, target) in row
+            .chunks(layout.channels() * bytes_per_channel)
+            .zip(target.chunks_mut(4))
+        {
+            let mut channels = [0u8; 4];
+            for (channel, src) in channels.iter_mut().zip(pixel.chunks(bytes_per_channel)) {
+                let value = match *src {
+                    [a, b] => f16_to_f32(u16::from_le_bytes([a, b])),
+                    [a, b, c, d] => f32::from_le_bytes([a, b, c, d]),
+                    _ => unreachable!(),
+                };
+                // NaN ends up as 0.
+                *channel = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+            target.copy_from_slice(&layout.to_rgba(&channels));
+        }
+    }
+    Ok(buf)
+}
+
+/// Rebuilds the normals of a normal map that keeps X in alpha and Y in green, as Unity does for
+/// DXT5nm and ASTC normal maps, into RGB with full alpha.
+pub fn unpack_normal_map(rgba: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(rgba.len());
+    for pixel in rgba.chunks_exact(4) {
+        let x = f32::from(pixel[3]) / 127.5 - 1.0;
+        let y = f32::from(pixel[1]) / 127.5 - 1.0;
+        let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+        buf.extend_from_slice(&[pixel[3], pixel[1], ((z + 1.0) * 127.5).round() as u8, 0xff]);
+    }
+    buf
+}
+
+/// Shows the alpha of `rgba` as gray with full alpha, for masks such as `Alpha8` textures.
+pub fn alpha_to_luminance(rgba: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(rgba.len());
+    for pixel in rgba.chunks_exact(4) {
+        buf.extend_from_slice(&[pixel[3], pixel[3], pixel[3], 0xff]);
+    }
+    buf
+}
+
+/// Replaces the alpha of `rgba` with the luminance of a separate alpha texture, as games that
+/// keep alpha out of compressed color textures do.
+///
+/// The alpha texture is scaled to the size of the color one, since it is often smaller.
+pub fn merge_alpha(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    alpha_width: u32,
+    alpha_height: u32,
+    alpha: &[u8],
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let (alpha_width, alpha_height) = (alpha_width as usize, alpha_height as usize);
+    let mut buf = rgba.to_vec();
+    if alpha_width == 0 || alpha_height == 0 || alpha.len() < alpha_width * alpha_height * 4 {
+        return buf;
+    }
+    for (index, pixel) in buf.chunks_exact_mut(4).take(width * height).enumerate() {
+        let x = (index % width) * alpha_width / width;
+        let y = (index / width) * alpha_height / height;
+        let source = &alpha[(y * alpha_width + x) * 4..][..3];
+        let luma =
+            299 * u32::from(source[0]) + 587 * u32::from(source[1]) + 114 * u32::from(source[2]);
+        pixel[3] = ((luma + 500) / 1000) as u8;
+    }
+    buf
+}
+
+/// PNG and WebP can't hold empty images, so images with a zero dimension are written as a
+/// single transparent pixel instead.
+fn non_empty(width: u32, height: u32, rgba: &[u8]) -> (u32, u32, &[u8]) {
+    if width == 0 || height == 0 {
+        (1, 1, &[0; 4])
+    } else {
+        (width, height, rgba)
+    }
+}
+
+/// How hard [`encode_png_with`] compresses the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngCompression {
+    /// Quick to encode, for previews.
+    Fast,
+    Default,
+    /// Smallest output, for archival.
+    Best,
+}
+
+/// The filter [`encode_png_with`] applies to every row before compressing it.
+///
+/// Filters that predict from the row above aren't offered: the PNG encoder in use predicts
+/// from that row after filtering it, which corrupts the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    Sub,
+}
+
+/// Settings of [`encode_png_with`]. The default is what [`encode_png`] uses: fast compression
+/// and the `Sub` filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            compression: PngCompression::Fast,
+            filter: PngFilter::Sub,
+        }
+    }
+}
+
+/// Encodes RGBA8 pixels, top row first, as PNG.
+///
+/// An image with a zero dimension is encoded as one transparent pixel.
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> io::Result<Vec<u8>> {
+    encode_png_with(width, height, rgba, PngOptions::default())
+}
+
+/// Like [`encode_png`], with the compression level and filter given by `options`.
+pub fn encode_png_with(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    options: PngOptions,
+) -> io::Result<Vec<u8>> {
+    let (width, height, rgba) = non_empty(width, height, rgba);
+    let mut buf = Vec::new();
+    let w = io::BufWriter::new(&mut buf);
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set_compression(match options.compression {
+        PngCompression::Fast => png::Compression::Fast,
+        PngCompression::Default => png::Compression::Default,
+        PngCompression::Best => png::Compression::Best,
+    });
+    encoder.set_filter(match options.filter {
+        PngFilter::None => png::FilterType::NoFilter,
+        PngFilter::Sub => png::FilterType::Sub,
+    });
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut w = encoder
+        .write_header()
+        .map_err(|e| io::Error::other(format!("error initializing encoder: {}", e)))?;
+    w.write_image_data(rgba)
+        .map_err(|e| io::Error::other(format!("error while encoding: {}", e)))?;
+    drop(w);
+    Ok(buf)
+}
+
+/// Encodes RGBA8 pixels, top row first, as lossy WebP with `quality` from 0 to 100.
+///
+/// An image with a zero dimension is encoded as one transparent pixel.
+pub fn encode_webp(width: u32, height: u32, rgba: &[u8], quality: f32) -> io::Result<Vec<u8>> {
+    let (width, height, rgba) = non_empty(width, height, rgba);
+    webpenc::encode_rgba(width, height, rgba, quality)
+}
@@ -0,0 +1,243 @@
+/// Values of the `m_TextureFormat` field of textures, named after Unity's `TextureFormat`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    Alpha8 = 1,
+    Argb4444 = 2,
+    Rgb24 = 3,
+    Rgba32 = 4,
+    Argb32 = 5,
+    Rgb565 = 7,
+    R16 = 9,
+    Dxt1 = 10,
+    Dxt5 = 12,
+    Rgba4444 = 13,
+    Bgra32 = 14,
+    RHalf = 15,
+    RgHalf = 16,
+    RgbaHalf = 17,
+    RFloat = 18,
+    RgFloat = 19,
+    RgbaFloat = 20,
+    Yuy2 = 21,
+    Rgb9e5Float = 22,
+    Bc6h = 24,
+    Bc7 = 25,
+    Bc4 = 26,
+    Bc5 = 27,
+    Dxt1Crunched = 28,
+    Dxt5Crunched = 29,
+    PvrtcRgb2 = 30,
+    PvrtcRgba2 = 31,
+    PvrtcRgb4 = 32,
+    PvrtcRgba4 = 33,
+    EtcRgb4 = 34,
+    EacR = 41,
+    EacRSigned = 42,
+    EacRg = 43,
+    EacRgSigned = 44,
+    Etc2Rgb = 45,
+    Etc2Rgba1 = 46,
+    Etc2Rgba8 = 47,
+    AstcRgb4x4 = 48,
+    AstcRgb5x5 = 49,
+    AstcRgb6x6 = 50,
+    AstcRgb8x8 = 51,
+    AstcRgb10x10 = 52,
+    AstcRgb12x12 = 53,
+    AstcRgba4x4 = 54,
+    AstcRgba5x5 = 55,
+    AstcRgba6x6 = 56,
+    AstcRgba8x8 = 57,
+    AstcRgba10x10 = 58,
+    AstcRgba12x12 = 59,
+    EtcRgb4For3ds = 60,
+    EtcRgba8For3ds = 61,
+    Rg16 = 62,
+    R8 = 63,
+    EtcRgb4Crunched = 64,
+    Etc2Rgba8Crunched = 65,
+    AstcHdr4x4 = 66,
+    AstcHdr5x5 = 67,
+    AstcHdr6x6 = 68,
+    AstcHdr8x8 = 69,
+    AstcHdr10x10 = 70,
+    AstcHdr12x12 = 71,
+    Rg32 = 72,
+    Rgb48 = 73,
+    Rgba64 = 74,
+}
+
+impl TextureFormat {
+    pub fn from_i32(value: i32) -> Option<Self> {
+        use TextureFormat::*;
+
+        Some(match value {
+            1 => Alpha8,
+            2 => Argb4444,
+            3 => Rgb24,
+            4 => Rgba32,
+            5 => Argb32,
+            7 => Rgb565,
+            9 => R16,
+            10 => Dxt1,
+            12 => Dxt5,
+            13 => Rgba4444,
+            14 => Bgra32,
+            15 => RHalf,
+            16 => RgHalf,
+            17 => RgbaHalf,
+            18 => RFloat,
+            19 => RgFloat,
+            20 => RgbaFloat,
+            21 => Yuy2,
+            22 => Rgb9e5Float,
+            24 => Bc6h,
+            25 => Bc7,
+            26 => Bc4,
+            27 => Bc5,
+            28 => Dxt1Crunched,
+            29 => Dxt5Crunched,
+            30 => PvrtcRgb2,
+            31 => PvrtcRgba2,
+            32 => PvrtcRgb4,
+            33 => PvrtcRgba4,
+            34 => EtcRgb4,
+            41 => EacR,
+            42 => EacRSigned,
+            43 => EacRg,
+            44 => EacRgSigned,
+            45 => Etc2Rgb,
+            46 => Etc2Rgba1,
+            47 => Etc2Rgba8,
+            48 => AstcRgb4x4,
+            49 => AstcRgb5x5,
+            50 => AstcRgb6x6,
+            51 => AstcRgb8x8,
+            52 => AstcRgb10x10,
+            53 => AstcRgb12x12,
+            54 => AstcRgba4x4,
+            55 => AstcRgba5x5,
+            56 => AstcRgba6x6,
+            57 => AstcRgba8x8,
+            58 => AstcRgba10x10,
+            59 => AstcRgba12x12,
+            60 => EtcRgb4For3ds,
+            61 => EtcRgba8For3ds,
+            62 => Rg16,
+            63 => R8,
+            64 => EtcRgb4Crunched,
+            65 => Etc2Rgba8Crunched,
+            66 => AstcHdr4x4,
+            67 => AstcHdr5x5,
+            68 => AstcHdr6x6,
+            69 => AstcHdr8x8,
+            70 => AstcHdr10x10,
+            71 => AstcHdr12x12,
+            72 => Rg32,
+            73 => Rgb48,
+            74 => Rgba64,
+            _ => return None,
+        })
+    }
+
+    /// The name Unity uses for the format, such as `ETC2_RGBA8`.
+    pub fn name(self) -> &'static str {
+        use TextureFormat::*;
+
+        match self {
+            Alpha8 => "Alpha8",
+            Argb4444 => "ARGB4444",
+            Rgb24 => "RGB24",
+            Rgba32 => "RGBA32",
+            Argb32 => "ARGB32",
+            Rgb565 => "RGB565",
+            R16 => "R16",
+            Dxt1 => "DXT1",
+            Dxt5 => "DXT5",
+            Rgba4444 => "RGBA4444",
+            Bgra32 => "BGRA32",
+            RHalf => "RHalf",
+            RgHalf => "RGHalf",
+            RgbaHalf => "RGBAHalf",
+            RFloat => "RFloat",
+            RgFloat => "RGFloat",
+            RgbaFloat => "RGBAFloat",
+            Yuy2 => "YUY2",
+            Rgb9e5Float => "RGB9e5Float",
+            Bc6h => "BC6H",
+            Bc7 => "BC7",
+            Bc4 => "BC4",
+            Bc5 => "BC5",
+            Dxt1Crunched => "DXT1Crunched",
+            Dxt5Crunched => "DXT5Crunched",
+            PvrtcRgb2 => "PVRTC_RGB2",
+            PvrtcRgba2 => "PVRTC_RGBA2",
+            PvrtcRgb4 => "PVRTC_RGB4",
+            PvrtcRgba4 => "PVRTC_RGBA4",
+            EtcRgb4 => "ETC_RGB4",
+            EacR => "EAC_R",
+            EacRSigned => "EAC_R_SIGNED",
+            EacRg => "EAC_RG",
+            EacRgSigned => "EAC_RG_SIGNED",
+            Etc2Rgb => "ETC2_RGB",
+            Etc2Rgba1 => "ETC2_RGBA1",
+            Etc2Rgba8 => "ETC2_RGBA8",
+            AstcRgb4x4 => "ASTC_RGB_4x4",
+            AstcRgb5x5 => "ASTC_RGB_5x5",
+            AstcRgb6x6 => "ASTC_RGB_6x6",
+            AstcRgb8x8 => "ASTC_RGB_8x8",
+            AstcRgb10x10 => "ASTC_RGB_10x10",
+            AstcRgb12x12 => "ASTC_RGB_12x12",
+            AstcRgba4x4 => "ASTC_RGBA_4x4",
+            AstcRgba5x5 => "ASTC_RGBA_5x5",
+            AstcRgba6x6 => "ASTC_RGBA_6x6",
+            AstcRgba8x8 => "ASTC_RGBA_8x8",
+            AstcRgba10x10 => "ASTC_RGBA_10x10",
+            AstcRgba12x12 => "ASTC_RGBA_12x12",
+            EtcRgb4For3ds => "ETC_RGB4_3DS",
+            EtcRgba8For3ds => "ETC_RGBA8_3DS",
+            Rg16 => "RG16",
+            R8 => "R8",
+            EtcRgb4Crunched => "ETC_RGB4Crunched",
+            Etc2Rgba8Crunched => "ETC2_RGBA8Crunched",
+            AstcHdr4x4 => "ASTC_HDR_4x4",
+            AstcHdr5x5 => "ASTC_HDR_5x5",
+            AstcHdr6x6 => "ASTC_HDR_6x6",
+            AstcHdr8x8 => "ASTC_HDR_8x8",
+            AstcHdr10x10 => "ASTC_HDR_10x10",
+            AstcHdr12x12 => "ASTC_HDR_12x12",
+            Rg32 => "RG32",
+            Rgb48 => "RGB48",
+            Rgba64 => "RGBA64",
+        }
+    }
+
+    /// Width and height in pixels of the blocks the format encodes together, `(1, 1)` for
+    /// formats that store each pixel on its own.
+    pub fn block_size(self) -> (u32, u32) {
+        use TextureFormat::*;
+
+        match self {
+            Dxt1 | Dxt5 | Bc6h | Bc7 | Bc4 | Bc5 | Dxt1Crunched | Dxt5Crunched | PvrtcRgb4
+            | PvrtcRgba4 | EtcRgb4 | EacR | EacRSigned | EacRg | EacRgSigned | Etc2Rgb
+            | Etc2Rgba1 | Etc2Rgba8 | AstcRgb4x4 | AstcRgba4x4 | EtcRgb4For3ds | EtcRgba8For3ds
+            | EtcRgb4Crunched | Etc2Rgba8Crunched | AstcHdr4x4 => (4, 4),
+            Yuy2 => (2, 1),
+            PvrtcRgb2 | PvrtcRgba2 => (8, 4),
+            AstcRgb5x5 | AstcRgba5x5 | AstcHdr5x5 => (5, 5),
+            AstcRgb6x6 | AstcRgba6x6 | AstcHdr6x6 => (6, 6),
+            AstcRgb8x8 | AstcRgba8x8 | AstcHdr8x8 => (8, 8),
+            AstcRgb10x10 | AstcRgba10x10 | AstcHdr10x10 => (10, 10),
+            AstcRgb12x12 | AstcRgba12x12 | AstcHdr12x12 => (12, 12),
+            _ => (1, 1),
+        }
+    }
+
+    /// Whether [`DecodeFormat`] can decode textures of this format.
+    ///
+    /// [`DecodeFormat`]: crate::texture::DecodeFormat
+    #[cfg(feature = "textures")]
+    pub fn is_supported(self) -> bool {
+        crate::texture::DecodeFormat::from_texture_format(self as i32, false).is_some()
+    }
+}
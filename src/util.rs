@@ -1,5 +1,112 @@
 pub fn align<'a>(offset: usize, base: &'a [u8], target: &'a [u8]) -> &'a [u8] {
+    align_to(4, offset, base, target)
+}
+
+/// Skips `target` forward to the next multiple of `alignment`, counting positions from `offset`
+/// bytes before `base`.
+pub fn align_to<'a>(alignment: usize, offset: usize, base: &'a [u8], target: &'a [u8]) -> &'a [u8] {
     let dist = (target.as_ptr() as usize) - (base.as_ptr() as usize);
-    let new = ((offset + dist + 3) & 0xfffffffc) - offset;
-    return &base[new..];
+    let new = (offset + dist).next_multiple_of(alignment) - offset;
+    &base[std::cmp::min(new, base.len())..]
+}
+
+pub fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+const XXH_PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const XXH_PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const XXH_PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+const XXH_PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const XXH_PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+fn xxh64_round(acc: u64, lane: u64) -> u64 {
+    acc.wrapping_add(lane.wrapping_mul(XXH_PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge(hash: u64, acc: u64) -> u64 {
+    (hash ^ xxh64_round(0, acc))
+        .wrapping_mul(XXH_PRIME64_1)
+        .wrapping_add(XXH_PRIME64_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buf)
+}
+
+/// XXH64 of `input` with a seed of 0.
+pub fn xxh64(input: &[u8]) -> u64 {
+    let mut rest = input;
+    let mut hash = if input.len() >= 32 {
+        let mut acc = [
+            XXH_PRIME64_1.wrapping_add(XXH_PRIME64_2),
+            XXH_PRIME64_2,
+            0,
+            0u64.wrapping_sub(XXH_PRIME64_1),
+        ];
+        while rest.len() >= 32 {
+            for (i, acc) in acc.iter_mut().enumerate() {
+                *acc = xxh64_round(*acc, read_u64(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let hash = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        acc.iter().fold(hash, |hash, acc| xxh64_merge(hash, *acc))
+    } else {
+        XXH_PRIME64_5
+    };
+    hash = hash.wrapping_add(input.len() as u64);
+
+    while rest.len() >= 8 {
+        hash ^= xxh64_round(0, read_u64(rest));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(XXH_PRIME64_1)
+            .wrapping_add(XXH_PRIME64_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let lane = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        hash ^= u64::from(lane).wrapping_mul(XXH_PRIME64_1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(XXH_PRIME64_2)
+            .wrapping_add(XXH_PRIME64_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash ^= u64::from(byte).wrapping_mul(XXH_PRIME64_5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH_PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH_PRIME64_3);
+    hash ^ (hash >> 32)
 }
@@ -0,0 +1,149 @@
+use crate::compression::compress_block;
+use crate::metadata::ArchiveBlockInfo;
+use crate::UnityFsError;
+
+/// Flag of the block directory telling it lists the directory entries as well.
+const BLOCKS_AND_DIRECTORY_INFO_COMBINED: u32 = 0x40;
+/// Flag of a directory entry holding a serialized file.
+const SERIALIZED_FILE_FLAG: u32 = 0x4;
+
+/// Size of the data blocks Unity uses for LZ4 compressed bundles.
+pub const DEFAULT_BLOCK_SIZE: u32 = 0x20000;
+
+struct Entry {
+    name: String,
+    data: Vec<u8>,
+    flags: u32,
+}
+
+/// Writes serialized files and resources into a new UnityFS bundle, as format version 6.
+///
+/// The files are laid out back to back in the order they are added, and the whole is cut into
+/// blocks of [`DEFAULT_BLOCK_SIZE`] unless told otherwise. Blocks and the block directory are
+/// compressed with the same compression type; only 0 for none and 2 or 3 for LZ4 can be
+/// written. Blocks that don't get smaller are stored as they are.
+pub struct UnityFsWriter {
+    unity_version: String,
+    generator_version: String,
+    compression_type: u32,
+    block_size: u32,
+    entries: Vec<Entry>,
+}
+
+impl UnityFsWriter {
+    /// Starts an empty LZ4 compressed bundle that says it was built by `generator_version`, such
+    /// as `2019.4.40f1`.
+    pub fn new(generator_version: &str) -> Self {
+        Self {
+            unity_version: "5.x.x".into(),
+            generator_version: generator_version.into(),
+            compression_type: 2,
+            block_size: DEFAULT_BLOCK_SIZE,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets the compression type ID of the blocks and the block directory, with the same
+    /// values as [`BundleInfo::header_compression_type`](crate::BundleInfo).
+    pub fn set_compression_type(&mut self, compression_type: u32) {
+        self.compression_type = compression_type;
+    }
+
+    /// Sets the size of the data before compression of each block but the last. A size of 0
+    /// keeps all the data in one block.
+    pub fn set_block_size(&mut self, block_size: u32) {
+        self.block_size = block_size;
+    }
+
+    /// Adds a serialized file. The first one added is the main asset of the bundle.
+    pub fn add_serialized_file(&mut self, name: &str, data: Vec<u8>) {
+        self.entries.push(Entry {
+            name: name.into(),
+            data,
+            flags: SERIALIZED_FILE_FLAG,
+        });
+    }
+
+    /// Adds a resource, such as a `.resS` file textures stream their pixels from.
+    pub fn add_resource(&mut self, name: &str, data: Vec<u8>) {
+        self.entries.push(Entry {
+            name: name.into(),
+            data,
+            flags: 0,
+        });
+    }
+
+    fn write_blocks(&self) -> Result<(Vec<u8>, Vec<ArchiveBlockInfo>), UnityFsError> {
+        let stream = self
+            .entries
+            .iter()
+            .flat_map(|entry| entry.data.iter().copied())
+            .collect::<Vec<_>>();
+        let block_size = match self.block_size {
+            0 => stream.len().max(1),
+            size => size as usize,
+        };
+        let mut data = Vec::new();
+        let mut blocks = Vec::new();
+        for chunk in stream.chunks(block_size) {
+            let compressed = compress_block(self.compression_type, chunk)?;
+            let (compressed, flags) = if compressed.len() < chunk.len() {
+                (&*compressed, self.compression_type as u16)
+            } else {
+                (chunk, 0)
+            };
+            blocks.push(ArchiveBlockInfo {
+                u_size: chunk.len() as u32,
+                c_size: compressed.len() as u32,
+                flags,
+            });
+            data.extend_from_slice(compressed);
+        }
+        Ok((data, blocks))
+    }
+
+    fn write_directory(&self, blocks: &[ArchiveBlockInfo]) -> Vec<u8> {
+        let mut out = vec![0; 16];
+        out.extend_from_slice(&(blocks.len() as u32).to_be_bytes());
+        for block in blocks {
+            out.extend_from_slice(&block.u_size.to_be_bytes());
+            out.extend_from_slice(&block.c_size.to_be_bytes());
+            out.extend_from_slice(&block.flags.to_be_bytes());
+        }
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        let mut offset = 0u64;
+        for entry in &self.entries {
+            out.extend_from_slice(&offset.to_be_bytes());
+            out.extend_from_slice(&(entry.data.len() as u64).to_be_bytes());
+            out.extend_from_slice(&entry.flags.to_be_bytes());
+            out.extend_from_slice(entry.name.as_bytes());
+            out.push(0);
+            offset += entry.data.len() as u64;
+        }
+        out
+    }
+
+    /// Writes the bundle, which can be read back with [`UnityFsMeta::parse`](crate::UnityFsMeta).
+    pub fn write(&self) -> Result<Vec<u8>, UnityFsError> {
+        let (data, blocks) = self.write_blocks()?;
+        let directory = self.write_directory(&blocks);
+        let compressed_directory = compress_block(self.compression_type, &directory)?;
+
+        let mut out = b"UnityFS\0".to_vec();
+        out.extend_from_slice(&6u32.to_be_bytes());
+        for s in &[&self.unity_version, &self.generator_version] {
+            out.extend_from_slice(s.as_bytes());
+            out.push(0);
+        }
+        let file_size = out.len() + 8 + 12 + compressed_directory.len() + data.len();
+        out.extend_from_slice(&(file_size as u64).to_be_bytes());
+        out.extend_from_slice(&(compressed_directory.len() as u32).to_be_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_be_bytes());
+        out.extend_from_slice(
+            &(self.compression_type | BLOCKS_AND_DIRECTORY_INFO_COMBINED).to_be_bytes(),
+        );
+        out.extend_from_slice(&compressed_directory);
+        out.extend_from_slice(&data);
+        Ok(out)
+    }
+}
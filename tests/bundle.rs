@@ -0,0 +1,115 @@
+mod common;
+
+use common::{named_object, SerializedFile};
+use unityfs::{UnityFsMeta, UnityFsWriter};
+
+#[test]
+fn lz4hc_round_trip() {
+    // A long name, so that the block compresses and keeps its LZ4HC flag.
+    let name = "Player".repeat(64);
+    let (tree, data) = named_object(false, &name, 42);
+    let file = SerializedFile::new(17, vec![(1, tree)]).object(1, 0, data);
+    let bundle = common::bundle(file.build(), 3);
+
+    let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+    assert!(meta.raw_blocks().count() > 0);
+    for block in meta.raw_blocks() {
+        assert_eq!(block.info().compression_type(), 3);
+        assert!(block.info().compressed_size() < block.info().uncompressed_size());
+    }
+    let fs = meta.read_unityfs().unwrap();
+    assert_eq!(fs.name(), "CAB-test");
+    let object = fs.main_asset().get_object(&1).unwrap();
+    assert_eq!(object.data.field("m_Name").unwrap().as_str(), Some(&*name));
+
+    let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).unwrap();
+    assert_eq!(reader.main_asset().unwrap().name(), "CAB-test");
+}
+
+#[test]
+fn directory_at_end() {
+    let (tree, data) = named_object(false, "Streamed", 7);
+    let file = SerializedFile::new(17, vec![(1, tree)]).object(1, 0, data);
+    let bundle = common::directory_at_end(&common::bundle(file.build(), 2));
+
+    let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+    assert_eq!(meta.info().flags() & 0x80, 0x80);
+    let fs = meta.read_unityfs().unwrap();
+    let object = fs.main_asset().get_object(&1).unwrap();
+    assert_eq!(
+        object.data.field("m_Name").unwrap().as_str(),
+        Some("Streamed")
+    );
+
+    // The directory is found from the file size in the header, whatever follows the bundle.
+    let mut padded = bundle.clone();
+    padded.extend_from_slice(&[0; 100]);
+    let (_, meta) = UnityFsMeta::parse(&padded).unwrap();
+    assert!(meta.read_unityfs().is_ok());
+    let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&padded)).unwrap();
+    let object = reader
+        .main_asset()
+        .unwrap()
+        .get_object(&1)
+        .unwrap()
+        .unwrap();
+    assert_eq!(object.data.field("m_Value").unwrap().as_i32(), Some(7));
+}
+
+#[test]
+fn format_7_alignment() {
+    let (tree, data) = named_object(false, "Player", 42);
+    let mut file = SerializedFile::new(22, vec![(1, tree)]).object(1, 0, data);
+    file.unity_version = "2021.3.16f1";
+    let mut writer = UnityFsWriter::new("2021.3.16f1");
+    writer.set_compression_type(2);
+    writer.add_serialized_file("CAB-test", file.build());
+    let bundle = writer.write().unwrap();
+    let header_len = common::bundle_header_len(&bundle);
+    let directory_len = u32::from_be_bytes([
+        bundle[header_len - 12],
+        bundle[header_len - 11],
+        bundle[header_len - 10],
+        bundle[header_len - 9],
+    ]);
+    // Otherwise the padding after the directory would be empty.
+    assert_ne!(directory_len % 16, 0);
+
+    for &at_end in &[false, true] {
+        for &padded in &[false, true] {
+            let bundle = if at_end {
+                common::directory_at_end(&bundle)
+            } else {
+                bundle.clone()
+            };
+            let bundle = common::format_7(&bundle, padded);
+            let context = format!("at end {}, padded {}", at_end, padded);
+
+            let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+            assert_eq!(meta.format_version(), 7, "{}", context);
+            assert_eq!(meta.info().flags() & 0x200 != 0, padded, "{}", context);
+            let fs = meta.read_unityfs().unwrap();
+            let object = fs.main_asset().get_object(&1).unwrap();
+            assert_eq!(
+                object.data.field("m_Name").unwrap().as_str(),
+                Some("Player"),
+                "{}",
+                context
+            );
+
+            let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).unwrap();
+            let object = reader
+                .main_asset()
+                .unwrap()
+                .get_object(&1)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                object.data.field("m_Value").unwrap().as_i32(),
+                Some(42),
+                "{}",
+                context
+            );
+        }
+    }
+}
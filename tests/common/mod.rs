@@ -0,0 +1,415 @@
+//! Builders for the serialized files and bundles the tests read back.
+
+#![allow(dead_code)]
+
+use std::convert::TryInto;
+use unityfs::UnityFsWriter;
+
+/// Meta flag of fields followed by padding to a multiple of four bytes.
+pub const ALIGN: u32 = 0x4000;
+
+/// A node of a type tree.
+pub struct Node {
+    type_name: &'static str,
+    name: &'static str,
+    size: i32,
+    is_array: bool,
+    flags: u32,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Sets the [`ALIGN`] flag, padding the stream after the field.
+    pub fn aligned(mut self) -> Self {
+        self.flags |= ALIGN;
+        self
+    }
+}
+
+pub fn primitive(type_name: &'static str, name: &'static str, size: i32) -> Node {
+    Node {
+        type_name,
+        name,
+        size,
+        is_array: false,
+        flags: 0,
+        children: Vec::new(),
+    }
+}
+
+pub fn int(name: &'static str) -> Node {
+    primitive("int", name, 4)
+}
+
+pub fn string(name: &'static str) -> Node {
+    let array = Node {
+        type_name: "Array",
+        name: "Array",
+        size: -1,
+        is_array: true,
+        flags: ALIGN,
+        children: vec![int("size"), primitive("char", "data", 1)],
+    };
+    strukt("string", name, vec![array])
+}
+
+pub fn strukt(type_name: &'static str, name: &'static str, children: Vec<Node>) -> Node {
+    Node {
+        type_name,
+        name,
+        size: -1,
+        is_array: false,
+        flags: 0,
+        children,
+    }
+}
+
+fn flatten<'n>(node: &'n Node, depth: u8, out: &mut Vec<(u8, &'n Node)>) {
+    out.push((depth, node));
+    for child in &node.children {
+        flatten(child, depth + 1, out);
+    }
+}
+
+/// Writes values in the byte order of a serialized file.
+pub struct Writer {
+    pub buf: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Writer {
+    pub fn new(big_endian: bool) -> Self {
+        Self {
+            buf: Vec::new(),
+            big_endian,
+        }
+    }
+
+    pub fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+
+    pub fn u16(&mut self, v: u16) -> &mut Self {
+        let bytes = if self.big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        };
+        self.buf.extend_from_slice(&bytes);
+        self
+    }
+
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        let bytes = if self.big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        };
+        self.buf.extend_from_slice(&bytes);
+        self
+    }
+
+    pub fn i32(&mut self, v: i32) -> &mut Self {
+        self.u32(v as u32)
+    }
+
+    pub fn u64(&mut self, v: u64) -> &mut Self {
+        let bytes = if self.big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        };
+        self.buf.extend_from_slice(&bytes);
+        self
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(v);
+        self
+    }
+
+    pub fn cstr(&mut self, v: &str) -> &mut Self {
+        self.bytes(v.as_bytes()).u8(0)
+    }
+
+    /// A string field: its length, its bytes and padding to four bytes.
+    pub fn string(&mut self, v: &str) -> &mut Self {
+        self.u32(v.len() as u32).bytes(v.as_bytes()).align(4)
+    }
+
+    pub fn align(&mut self, to: usize) -> &mut Self {
+        let len = self.buf.len().div_ceil(to) * to;
+        self.buf.resize(len, 0);
+        self
+    }
+}
+
+fn write_tree(w: &mut Writer, root: &Node, format: u32) {
+    if format == 10 || format >= 12 {
+        let mut nodes = Vec::new();
+        flatten(root, 0, &mut nodes);
+        let mut strings = Vec::new();
+        let mut offset_of = |s: &str| {
+            let offset = strings.len() as u32;
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let offsets = nodes
+            .iter()
+            .map(|(_, node)| (offset_of(node.type_name), offset_of(node.name)))
+            .collect::<Vec<_>>();
+        w.u32(nodes.len() as u32).u32(strings.len() as u32);
+        for (index, ((depth, node), (type_name, name))) in nodes.iter().zip(offsets).enumerate() {
+            w.u16(1)
+                .u8(*depth)
+                .u8(node.is_array as u8)
+                .u32(type_name)
+                .u32(name)
+                .i32(node.size)
+                .u32(index as u32)
+                .u32(node.flags);
+            if format >= 19 {
+                w.u64(0);
+            }
+        }
+        w.bytes(&strings);
+    } else {
+        fn write_old(w: &mut Writer, node: &Node, index: &mut u32) {
+            w.cstr(node.type_name)
+                .cstr(node.name)
+                .i32(node.size)
+                .u32(*index)
+                .u32(node.is_array as u32)
+                .u32(1)
+                .u32(node.flags)
+                .u32(node.children.len() as u32);
+            *index += 1;
+            for child in &node.children {
+                write_old(w, child, index);
+            }
+        }
+        write_old(w, root, &mut 0);
+    }
+}
+
+/// A serialized file of format 9 or later.
+pub struct SerializedFile {
+    pub format: u32,
+    pub big_endian: bool,
+    pub unity_version: &'static str,
+    pub platform: u32,
+    /// Class ID and tree of each type, with the positions of the types it depends on.
+    pub types: Vec<(i32, Node, Vec<i32>)>,
+    /// Path ID, position of the type and data of each object.
+    pub objects: Vec<(i64, usize, Vec<u8>)>,
+    /// Path IDs of the objects flagged as destroyed, which formats up to 10 record.
+    pub destroyed: Vec<i64>,
+}
+
+impl SerializedFile {
+    pub fn new(format: u32, types: Vec<(i32, Node)>) -> Self {
+        Self {
+            format,
+            big_endian: false,
+            unity_version: "2019.4.40f1",
+            platform: 5,
+            types: types
+                .into_iter()
+                .map(|(class_id, tree)| (class_id, tree, Vec::new()))
+                .collect(),
+            objects: Vec::new(),
+            destroyed: Vec::new(),
+        }
+    }
+
+    pub fn object(mut self, path_id: i64, type_index: usize, data: Vec<u8>) -> Self {
+        self.objects.push((path_id, type_index, data));
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let format = self.format;
+        let header_len = if format >= 22 { 48 } else { 20 };
+        let mut w = Writer::new(self.big_endian);
+        w.buf.resize(header_len, 0);
+        w.cstr(self.unity_version).u32(self.platform);
+        if format >= 13 {
+            w.u8(1).u32(self.types.len() as u32);
+        } else {
+            w.u32(self.types.len() as u32);
+        }
+        for (class_id, tree, dependencies) in &self.types {
+            w.i32(*class_id);
+            if format >= 13 {
+                if format >= 17 {
+                    w.u8(0).u16(0xffff);
+                }
+                // Script types have the hash of their script first.
+                let is_script = *class_id < 0 || (format >= 17 && *class_id == 114);
+                w.bytes(&[0; 16]);
+                if is_script {
+                    w.bytes(&[0; 16]);
+                }
+            }
+            write_tree(&mut w, tree, format);
+            if format >= 21 {
+                w.u32(dependencies.len() as u32);
+                for &dependency in dependencies {
+                    w.i32(dependency);
+                }
+            }
+        }
+        if (7..=13).contains(&format) {
+            w.u32(1);
+        }
+
+        let mut data = Writer::new(self.big_endian);
+        let mut entries = Vec::new();
+        for (_, _, bytes) in &self.objects {
+            data.align(8);
+            entries.push((data.buf.len(), bytes.len()));
+            data.bytes(bytes);
+        }
+        w.u32(self.objects.len() as u32);
+        for ((path_id, type_index, _), (offset, size)) in self.objects.iter().zip(entries) {
+            if format >= 14 {
+                w.align(4);
+            }
+            w.u64(*path_id as u64);
+            if format >= 22 {
+                w.u64(offset as u64);
+            } else {
+                w.u32(offset as u32);
+            }
+            w.u32(size as u32);
+            if format >= 17 {
+                w.u32(*type_index as u32);
+            } else {
+                let class_id = self.types[*type_index].0;
+                w.i32(class_id).u16(class_id as u16);
+            }
+            if format <= 10 {
+                w.u16(self.destroyed.contains(path_id) as u16);
+            }
+            if (11..=16).contains(&format) {
+                w.u16(0xffff);
+            }
+            if (15..=16).contains(&format) {
+                w.u8(0);
+            }
+        }
+        if format >= 11 {
+            w.u32(0);
+        }
+        // No externals, and no reference types either since format 20.
+        w.u32(0);
+        if format >= 20 {
+            w.u32(0);
+        }
+        w.u8(0);
+
+        let metadata_size = (w.buf.len() - header_len) as u32;
+        w.align(16);
+        let data_offset = w.buf.len() as u64;
+        w.bytes(&data.buf);
+        let file_size = w.buf.len() as u64;
+
+        // Since format 22 the sizes follow the endianness flag instead.
+        let (legacy_metadata_size, legacy_file_size, legacy_data_offset) = if format >= 22 {
+            (0, 0, 0)
+        } else {
+            (metadata_size, file_size as u32, data_offset as u32)
+        };
+        let mut header = Vec::new();
+        header.extend_from_slice(&legacy_metadata_size.to_be_bytes());
+        header.extend_from_slice(&legacy_file_size.to_be_bytes());
+        header.extend_from_slice(&format.to_be_bytes());
+        header.extend_from_slice(&legacy_data_offset.to_be_bytes());
+        header.extend_from_slice(&[self.big_endian as u8, 0, 0, 0]);
+        if format >= 22 {
+            header.extend_from_slice(&metadata_size.to_be_bytes());
+            header.extend_from_slice(&file_size.to_be_bytes());
+            header.extend_from_slice(&data_offset.to_be_bytes());
+            header.extend_from_slice(&[0; 8]);
+        }
+        w.buf[..header_len].copy_from_slice(&header);
+        w.buf
+    }
+}
+
+/// A bundle holding the serialized file `data` as `CAB-test`, compressed with
+/// `compression_type`.
+pub fn bundle(data: Vec<u8>, compression_type: u32) -> Vec<u8> {
+    let mut writer = UnityFsWriter::new("2019.4.40f1");
+    writer.set_compression_type(compression_type);
+    writer.add_serialized_file("CAB-test", data);
+    writer.write().unwrap()
+}
+
+/// A `GameObject` like type with a name and an int, and an object of it.
+pub fn named_object(big_endian: bool, name: &str, value: i32) -> (Node, Vec<u8>) {
+    let tree = strukt("GameObject", "Base", vec![string("m_Name"), int("m_Value")]);
+    let mut w = Writer::new(big_endian);
+    w.string(name).i32(value);
+    (tree, w.buf)
+}
+
+/// Length of the header of a bundle written by [`bundle`], which its directory follows.
+pub fn bundle_header_len(bundle: &[u8]) -> usize {
+    // The signature and format version, then the two version strings.
+    let mut len = 12;
+    for _ in 0..2 {
+        len += bundle[len..].iter().position(|&b| b == 0).unwrap() + 1;
+    }
+    // The file size, the directory sizes and the flags.
+    len + 20
+}
+
+/// Moves the block directory of a bundle written by [`bundle`] after the data, as streaming
+/// builds place it, and sets the archive flag saying so.
+pub fn directory_at_end(bundle: &[u8]) -> Vec<u8> {
+    let header_len = bundle_header_len(bundle);
+    let field = |at: usize| u32::from_be_bytes(bundle[at..at + 4].try_into().unwrap());
+    let directory_len = field(header_len - 12) as usize;
+    let flags = field(header_len - 4) | 0x80;
+
+    let mut out = bundle[..header_len - 4].to_vec();
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&bundle[header_len + directory_len..]);
+    out.extend_from_slice(&bundle[header_len..header_len + directory_len]);
+    out
+}
+
+/// Rewrites a bundle written by [`bundle`], and maybe moved by [`directory_at_end`], as format 7
+/// like Unity 2020.2 and later write it: the directory starts on a 16-byte boundary, and with
+/// `padded` the data blocks do too, which the archive flag 0x200 says.
+pub fn format_7(bundle: &[u8], padded: bool) -> Vec<u8> {
+    let header_len = bundle_header_len(bundle);
+    let field = |at: usize| u32::from_be_bytes(bundle[at..at + 4].try_into().unwrap());
+    let directory_len = field(header_len - 12) as usize;
+    let mut flags = field(header_len - 4);
+    let at_end = flags & 0x80 != 0;
+    if padded {
+        flags |= 0x200;
+    }
+
+    let mut out = Writer::new(true);
+    out.bytes(&bundle[..header_len]);
+    out.buf[8..12].copy_from_slice(&7u32.to_be_bytes());
+    out.buf[header_len - 4..header_len].copy_from_slice(&flags.to_be_bytes());
+    out.align(16);
+    if at_end {
+        out.bytes(&bundle[header_len..]);
+    } else {
+        out.bytes(&bundle[header_len..header_len + directory_len]);
+        if padded {
+            out.align(16);
+        }
+        out.bytes(&bundle[header_len + directory_len..]);
+    }
+    let file_size = out.buf.len() as u64;
+    out.buf[header_len - 20..header_len - 12].copy_from_slice(&file_size.to_be_bytes());
+    out.buf
+}
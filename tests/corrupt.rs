@@ -0,0 +1,322 @@
+//! Truncated and garbled input must come back as errors rather than panics.
+
+mod common;
+
+use common::{named_object, SerializedFile};
+use std::convert::TryInto;
+use std::io::Cursor;
+use unityfs::{CompressedBlock, UnityFsError, UnityFsMeta, UnityFsWriter};
+
+const RESOURCE: &str = "CAB-test.resS";
+
+fn sample_bundle(compression_type: u32) -> Vec<u8> {
+    let (tree, data) = named_object(false, &"Texture".repeat(8), 1);
+    let (_, other_data) = named_object(false, "Other", 2);
+    let file = SerializedFile::new(17, vec![(28, tree)])
+        .object(1, 0, data)
+        .object(2, 0, other_data);
+    let mut writer = UnityFsWriter::new("2019.4.40f1");
+    writer.set_compression_type(compression_type);
+    writer.add_serialized_file("CAB-test", file.build());
+    writer.add_resource(RESOURCE, (0..100).collect());
+    writer.write().unwrap()
+}
+
+/// Reads every object of the bundle at once.
+fn read_eagerly(bundle: &[u8]) -> Result<usize, String> {
+    let (_, meta) = UnityFsMeta::parse(bundle).map_err(|e| e.to_string())?;
+    let fs = meta.read_unityfs().map_err(|e| e.to_string())?;
+    let objects = fs.all_objects().count();
+    fs.resource(RESOURCE).ok_or("no resource")?;
+    Ok(objects)
+}
+
+/// Reads every object of the bundle through the on-demand reader.
+fn read_lazily(bundle: &[u8]) -> Result<usize, String> {
+    let reader = UnityFsMeta::parse_reader(Cursor::new(bundle)).map_err(|e| e.to_string())?;
+    let mut objects = 0;
+    for asset in reader.assets() {
+        let asset = asset.map_err(|e| e.to_string())?;
+        for object in asset.objects() {
+            object.map_err(|e| e.to_string())?;
+            objects += 1;
+        }
+    }
+    reader
+        .resource(RESOURCE)
+        .map_err(|e| e.to_string())?
+        .ok_or("no resource")?;
+    Ok(objects)
+}
+
+/// Checks that both readers fail to parse `bundle` with an error that `check` accepts.
+fn assert_parse_error(bundle: &[u8], check: impl Fn(&UnityFsError) -> bool) {
+    match UnityFsMeta::parse(bundle) {
+        Err(e) => assert!(check(&e), "eager read: {:?}", e),
+        Ok(_) => panic!("eager read succeeded"),
+    }
+    match UnityFsMeta::parse_reader(Cursor::new(bundle)) {
+        Err(e) => {
+            let inner = e.get_ref().and_then(|e| e.downcast_ref::<UnityFsError>());
+            assert!(inner.is_some_and(&check), "lazy read: {:?}", e);
+        }
+        Ok(_) => panic!("lazy read succeeded"),
+    }
+}
+
+/// A xorshift generator, so that failures can be reproduced.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[test]
+fn truncated_bundles() {
+    for &compression_type in &[0, 2, 3] {
+        let bundle = sample_bundle(compression_type);
+        assert_eq!(read_eagerly(&bundle), Ok(2));
+        assert_eq!(read_lazily(&bundle), Ok(2));
+        for len in 0..bundle.len() {
+            let truncated = &bundle[..len];
+            assert!(
+                read_eagerly(truncated).is_err(),
+                "eager read of {} bytes",
+                len
+            );
+            assert!(
+                read_lazily(truncated).is_err(),
+                "lazy read of {} bytes",
+                len
+            );
+        }
+    }
+}
+
+#[test]
+fn garbled_bundles() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for &compression_type in &[0, 2] {
+        let bundle = sample_bundle(compression_type);
+        let header_len = common::bundle_header_len(&bundle);
+        for _ in 0..3000 {
+            let mut garbled = bundle.clone();
+            // Mostly the directory and the blocks, past the signature and version strings.
+            for _ in 0..=rng.below(4) {
+                let at = header_len - 20 + rng.below(bundle.len() - header_len + 20);
+                garbled[at] = rng.next() as u8;
+            }
+            let _ = read_eagerly(&garbled);
+            let _ = read_lazily(&garbled);
+        }
+    }
+}
+
+#[test]
+fn oversized_block_table() {
+    let bundle = sample_bundle(0);
+    let header_len = common::bundle_header_len(&bundle);
+    // The block count, then the uncompressed and compressed sizes of the first block.
+    for &(at, value) in &[
+        (16, 0xffff_ffffu32),
+        (16, 2),
+        (20, 0xffff_fff0),
+        (20, 1),
+        (24, 0xffff_fff0),
+    ] {
+        let mut garbled = bundle.clone();
+        let at = header_len + at;
+        garbled[at..at + 4].copy_from_slice(&value.to_be_bytes());
+        assert!(read_eagerly(&garbled).is_err(), "{:#x} at {}", value, at);
+        assert!(read_lazily(&garbled).is_err(), "{:#x} at {}", value, at);
+    }
+}
+
+/// Appends `(uncompressed size, compressed size)` block infos to the directory of an
+/// uncompressed bundle, which has no data for them.
+fn with_extra_blocks(bundle: &[u8], blocks: &[(u32, u32)]) -> Vec<u8> {
+    let header_len = common::bundle_header_len(bundle);
+    let field = |at: usize| u32::from_be_bytes(bundle[at..at + 4].try_into().unwrap());
+    let directory_len = field(header_len - 12) as usize;
+    let directory = &bundle[header_len..header_len + directory_len];
+    let block_count = u32::from_be_bytes(directory[16..20].try_into().unwrap());
+    let blocks_end = 20 + 10 * block_count as usize;
+
+    let mut new_directory = directory[..16].to_vec();
+    new_directory.extend_from_slice(&(block_count + blocks.len() as u32).to_be_bytes());
+    new_directory.extend_from_slice(&directory[20..blocks_end]);
+    for &(u_size, c_size) in blocks {
+        new_directory.extend_from_slice(&u_size.to_be_bytes());
+        new_directory.extend_from_slice(&c_size.to_be_bytes());
+        new_directory.extend_from_slice(&0u16.to_be_bytes());
+    }
+    new_directory.extend_from_slice(&directory[blocks_end..]);
+
+    let mut out = bundle[..header_len - 20].to_vec();
+    let file_size = bundle.len() + new_directory.len() - directory_len;
+    out.extend_from_slice(&(file_size as u64).to_be_bytes());
+    out.extend_from_slice(&(new_directory.len() as u32).to_be_bytes());
+    out.extend_from_slice(&(new_directory.len() as u32).to_be_bytes());
+    out.extend_from_slice(&bundle[header_len - 4..header_len]);
+    out.extend_from_slice(&new_directory);
+    out.extend_from_slice(&bundle[header_len + directory_len..]);
+    out
+}
+
+#[test]
+fn implausible_block_sizes() {
+    let bundle = sample_bundle(0);
+    assert_eq!(read_eagerly(&with_extra_blocks(&bundle, &[(0, 0)])), Ok(2));
+    // 16 TiB claimed by a 40 KB directory, which mustn't be reserved before reading a block.
+    let garbled = with_extra_blocks(&bundle, &[(u32::MAX, 0); 4000]);
+    assert!(garbled.len() < 48 * 1024);
+    assert_parse_error(&garbled, |e| {
+        matches!(e, UnityFsError::InvalidBlockInfo { offset: 30 })
+    });
+    // An uncompressed block can't grow either.
+    let garbled = with_extra_blocks(&bundle, &[(1, 0)]);
+    assert_parse_error(&garbled, |e| {
+        matches!(e, UnityFsError::InvalidBlockInfo { offset: 30 })
+    });
+}
+
+#[test]
+fn forged_directories() {
+    let bundle = sample_bundle(0);
+    let header_len = common::bundle_header_len(&bundle);
+
+    // A block count that the directory is far too short for.
+    let mut garbled = bundle.clone();
+    garbled[header_len + 16..header_len + 20].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_parse_error(&garbled, |e| {
+        matches!(e, UnityFsError::Parse { offset: 20, .. })
+    });
+
+    // An uncompressed directory claiming to decompress to 4 GiB.
+    let mut garbled = bundle.clone();
+    garbled[header_len - 8..header_len - 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_parse_error(&garbled, |e| {
+        matches!(
+            e,
+            UnityFsError::BlockSizeMismatch {
+                expected: u32::MAX,
+                ..
+            }
+        )
+    });
+
+    // A directory running past the end of the bundle.
+    let mut garbled = bundle.clone();
+    garbled[header_len - 12..header_len - 8].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_parse_error(&garbled, |e| {
+        matches!(e, UnityFsError::UnexpectedEof { .. })
+    });
+
+    // One more block after those holding all of the data.
+    let garbled = with_extra_blocks(&bundle, &[(1, 1)]);
+    assert_parse_error(&garbled, |e| {
+        matches!(e, UnityFsError::InvalidBlockInfo { offset: 30 })
+    });
+}
+
+#[test]
+fn truncated_lz4_blocks() {
+    let bundle = sample_bundle(2);
+    let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+    let block = meta.raw_blocks().next().unwrap();
+    let info = block.info();
+    assert_eq!(info.compression_type(), 2);
+    let data = &bundle[block.offset() as usize..][..info.compressed_size() as usize];
+    let u_size = info.uncompressed_size();
+    assert!(CompressedBlock::from_slice(u_size, 2, data)
+        .decompress()
+        .is_ok());
+    for len in 0..data.len() {
+        let block = CompressedBlock::from_slice(u_size, 2, &data[..len]);
+        assert!(block.decompress().is_err(), "{} bytes", len);
+    }
+}
+
+#[test]
+fn garbled_lzma_blocks() {
+    // `abcabcabcabcabcabc`, from Python's `lzma` module as in the decoder's own tests.
+    const MATCHES: &[u8] = &[
+        0x5d, 0x00, 0x00, 0x80, 0x00, 0x00, 0x30, 0x98, 0x88, 0xaa, 0x02, 0xa6, 0x43, 0xeb, 0xff,
+        0xff, 0xb5, 0x80, 0x00, 0x00,
+    ];
+    let block = CompressedBlock::from_slice(18, 1, MATCHES);
+    assert_eq!(block.decompress().unwrap(), b"abcabcabcabcabcabc");
+    // The range coder needs five bytes after the properties to start, and the first literal
+    // takes more.
+    for len in 0..10 {
+        let block = CompressedBlock::from_slice(18, 1, &MATCHES[..len]);
+        assert!(block.decompress().is_err(), "{} bytes", len);
+    }
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..3000 {
+        let mut garbled = MATCHES.to_vec();
+        garbled.truncate(rng.below(MATCHES.len() + 1));
+        for byte in garbled.iter_mut().skip(1) {
+            if rng.below(4) == 0 {
+                *byte = rng.next() as u8;
+            }
+        }
+        let _ = CompressedBlock::from_slice(rng.below(1 << 16) as u32, 1, &garbled).decompress();
+    }
+    // Invalid properties.
+    let mut garbled = MATCHES.to_vec();
+    garbled[0] = 225;
+    assert!(CompressedBlock::from_slice(18, 1, &garbled)
+        .decompress()
+        .is_err());
+}
+
+#[test]
+fn out_of_range_streaming_info() {
+    let bundle = sample_bundle(2);
+    let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+    let fs = meta.read_unityfs().unwrap();
+    let path = "archive:/CAB-test/CAB-test.resS";
+    assert_eq!(fs.stream_data(path, 10, 5), Some(&[10, 11, 12, 13, 14][..]));
+    assert_eq!(fs.stream_data(path, 0, 100).map(<[u8]>::len), Some(100));
+    for &(offset, size) in &[
+        (0, 101),
+        (100, 1),
+        (50, 60),
+        (u64::MAX, 1),
+        (1, u64::MAX),
+        (u64::MAX, u64::MAX),
+    ] {
+        assert_eq!(
+            fs.stream_data(path, offset, size),
+            None,
+            "{}+{}",
+            offset,
+            size
+        );
+    }
+    for &path in &[
+        "",
+        "archive:/",
+        "archive://",
+        "archive:/CAB-test",
+        "archive:/CAB-test/",
+        "archive:/CAB-test/missing.resS",
+        "CAB-test/CAB-test.resS",
+    ] {
+        assert_eq!(fs.stream_data(path, 0, 1), None, "{:?}", path);
+    }
+
+    let reader = UnityFsMeta::parse_reader(Cursor::new(&bundle)).unwrap();
+    assert!(reader.read_range(u64::MAX - 1..u64::MAX).is_err());
+    assert!(reader.read_range(0..1 << 20).is_err());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{named_object, SerializedFile};
+use unityfs::{UnityFsError, UnityFsMeta, UnityFsWriter};
+
+fn uncompressed_bundle() -> Vec<u8> {
+    let (tree, data) = named_object(false, "Player", 42);
+    common::bundle(
+        SerializedFile::new(17, vec![(1, tree)])
+            .object(1, 0, data)
+            .build(),
+        0,
+    )
+}
+
+#[test]
+fn oversized_directory_is_an_error() {
+    let mut bundle = uncompressed_bundle();
+    let header_len = common::bundle_header_len(&bundle);
+    // The compressed size of the directory.
+    bundle[header_len - 12..header_len - 8].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+    assert!(UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).is_err());
+}
+
+#[test]
+fn oversized_block_is_an_error() {
+    let mut bundle = uncompressed_bundle();
+    let header_len = common::bundle_header_len(&bundle);
+    // The compressed size of the first block, after the directory hash, the block count and the
+    // uncompressed size.
+    let c_size = header_len + 24;
+    assert_eq!(bundle[c_size - 4..c_size], bundle[c_size..c_size + 4]);
+    bundle[c_size..c_size + 4].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+    // The block info is the first entry after the 16-byte hash and the 4-byte block count.
+    match UnityFsMeta::parse(&bundle) {
+        Err(UnityFsError::InvalidBlockInfo { offset: 20 }) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("parsed a block past the end of the bundle"),
+    }
+    let err = match UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)) {
+        Err(e) => e,
+        Ok(_) => panic!("parsed a block past the end of the bundle"),
+    };
+    match err.get_ref().and_then(|e| e.downcast_ref::<UnityFsError>()) {
+        Some(UnityFsError::InvalidBlockInfo { offset: 20 }) => {}
+        _ => panic!("unexpected error {}", err),
+    }
+}
+
+#[test]
+fn bad_magic_has_an_offset() {
+    let mut bundle = uncompressed_bundle();
+    bundle[..5].copy_from_slice(b"Unite");
+    match UnityFsMeta::parse(&bundle) {
+        Err(UnityFsError::BadMagic { offset: 0 }) => {}
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("parsed a bundle without a signature"),
+    }
+    let err = match UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)) {
+        Err(e) => e,
+        Ok(_) => panic!("parsed a bundle without a signature"),
+    };
+    assert_eq!(
+        err.to_string(),
+        "not a Unity file: no signature at offset 0"
+    );
+}
+
+#[test]
+fn node_larger_than_its_blocks() {
+    let (tree, data) = named_object(false, "Player", 42);
+    let mut writer = UnityFsWriter::new("2019.4.40f1");
+    writer.set_compression_type(0);
+    writer.add_serialized_file(
+        "CAB-test",
+        SerializedFile::new(17, vec![(1, tree)])
+            .object(1, 0, data)
+            .build(),
+    );
+    writer.add_resource("CAB-test.resS", vec![7; 100]);
+    let mut bundle = writer.write().unwrap();
+    let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).unwrap();
+    assert_eq!(
+        reader.resource("CAB-test.resS").unwrap(),
+        Some(vec![7; 100])
+    );
+
+    // The size of the resource node comes before its flags and name.
+    let name = bundle
+        .windows(14)
+        .position(|w| w == b"CAB-test.resS\0")
+        .unwrap();
+    bundle[name - 12..name - 4].copy_from_slice(&(4u64 << 40).to_be_bytes());
+    let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).unwrap();
+    let err = reader.resource("CAB-test.resS").unwrap_err();
+    match err.get_ref().and_then(|e| e.downcast_ref::<UnityFsError>()) {
+        Some(UnityFsError::OutOfRange { end, .. }) => assert!(*end > 4 << 40),
+        _ => panic!("unexpected error {}", err),
+    }
+    assert_eq!(
+        reader
+            .main_asset()
+            .unwrap()
+            .get_object(&1)
+            .unwrap()
+            .unwrap()
+            .data
+            .field("m_Value")
+            .unwrap()
+            .as_i32(),
+        Some(42)
+    );
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use common::{named_object, SerializedFile};
+use std::borrow::Cow;
+use unityfs::{Asset, ContainerKind, Data, UnityFsMeta};
+
+/// A file as written by Unity 2021.3: format 22 with type dependencies after each tree.
+fn unity_2021_3() -> Vec<u8> {
+    let (tree, data) = named_object(false, "Player", 42);
+    let (other_tree, other_data) = named_object(false, "Camera", -7);
+    let mut file = SerializedFile::new(22, vec![(1, tree), (4, other_tree)])
+        .object(1, 0, data)
+        .object(2, 1, other_data);
+    file.unity_version = "2021.3.16f1";
+    file.types[0].2 = vec![1];
+    file.types[1].2 = vec![0, 1];
+    file.build()
+}
+
+fn check_objects(asset: &Asset) {
+    let player = asset.get_object(&1).unwrap();
+    assert_eq!(
+        player.data.field("m_Name").unwrap().as_str(),
+        Some("Player")
+    );
+    assert_eq!(player.data.field("m_Value").unwrap().as_i32(), Some(42));
+    let camera = asset.get_object(&2).unwrap();
+    assert_eq!(camera.class_id, 4);
+    assert_eq!(
+        camera.data.field("m_Name").unwrap().as_str(),
+        Some("Camera")
+    );
+    assert_eq!(camera.data.field("m_Value").unwrap().as_i32(), Some(-7));
+}
+
+#[test]
+fn format_22_standalone() {
+    let file = unity_2021_3();
+    assert_eq!(unityfs::detect(&file), ContainerKind::SerializedFile);
+    let asset = Asset::parse("test".to_owned(), &file).unwrap();
+    let header = asset.header();
+    assert_eq!(header.version, 22);
+    assert_eq!(header.file_size, file.len() as u64);
+    assert_eq!(header.data_offset % 16, 0);
+    assert_eq!(asset.unity_version(), "2021.3.16f1");
+    check_objects(&asset);
+}
+
+#[test]
+fn format_22_in_bundle() {
+    let bundle = common::bundle(unity_2021_3(), 2);
+
+    let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+    let fs = meta.read_unityfs().unwrap();
+    assert_eq!(fs.main_asset().header().version, 22);
+    check_objects(fs.main_asset());
+
+    let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).unwrap();
+    let asset = reader.main_asset().unwrap();
+    assert_eq!(asset.header().version, 22);
+    let player = asset.get_object(&1).unwrap().unwrap();
+    assert_eq!(
+        player.data.field("m_Name").unwrap().as_str(),
+        Some("Player")
+    );
+    let camera = asset.get_object(&2).unwrap().unwrap();
+    assert_eq!(camera.data.field("m_Value").unwrap().as_i32(), Some(-7));
+}
+
+#[test]
+fn format_21_dependencies() {
+    let (tree, data) = named_object(false, "Light", 3);
+    let mut file = SerializedFile::new(21, vec![(1, tree)]).object(5, 0, data);
+    file.unity_version = "2020.3.48f1";
+    file.types[0].2 = vec![0, 0, 0];
+    let file = file.build();
+    assert_eq!(unityfs::detect(&file), ContainerKind::SerializedFile);
+    let asset = Asset::parse("test".to_owned(), &file).unwrap();
+    let light = asset.get_object(&5).unwrap();
+    assert_eq!(light.data.field("m_Name").unwrap().as_str(), Some("Light"));
+    assert_eq!(light.data.field("m_Value").unwrap().as_i32(), Some(3));
+}
+
+#[test]
+fn format_10_destroyed_flag() {
+    for &big_endian in &[false, true] {
+        let (tree, data) = named_object(big_endian, "Old", 1);
+        let (_, other_data) = named_object(big_endian, "Kept", 2);
+        let mut file = SerializedFile::new(10, vec![(1, tree)])
+            .object(1, 0, data)
+            .object(2, 0, other_data);
+        file.big_endian = big_endian;
+        file.unity_version = "4.7.2f1";
+        file.destroyed = vec![1];
+        let file = file.build();
+        let asset = Asset::parse("test".to_owned(), &file).unwrap();
+        let old = asset.get_object(&1).unwrap();
+        assert!(old.is_destroyed);
+        let kept = asset.get_object(&2).unwrap();
+        assert!(!kept.is_destroyed);
+        assert_eq!(kept.data.field("m_Name").unwrap().as_str(), Some("Kept"));
+        assert_eq!(kept.data.field("m_Value").unwrap().as_i32(), Some(2));
+    }
+}
+
+#[test]
+fn big_endian_round_trip() {
+    let tree = common::strukt(
+        "MonoBehaviour",
+        "Base",
+        vec![
+            common::string("m_Name"),
+            common::primitive("SInt32", "m_Count", 4),
+        ],
+    );
+    let mut data = common::Writer::new(true);
+    data.string("Counter").i32(0x1234_5678);
+    let mut file = SerializedFile::new(17, vec![(114, tree)]).object(3, 0, data.buf);
+    file.big_endian = true;
+    let bundle = common::bundle(file.build(), 2);
+
+    let (_, meta) = UnityFsMeta::parse(&bundle).unwrap();
+    let fs = meta.read_unityfs().unwrap();
+    let asset = fs.main_asset();
+    assert!(asset.header().big_endian);
+    let object = asset.get_object(&3).unwrap();
+    assert_eq!(
+        object.data.field("m_Name").unwrap().as_str(),
+        Some("Counter")
+    );
+    match object.data.field("m_Count") {
+        Some(Data::SInt32(count)) => assert_eq!(*count, 0x1234_5678),
+        other => panic!("expected an SInt32, got {:?}", other),
+    }
+
+    let reader = UnityFsMeta::parse_reader(std::io::Cursor::new(&bundle)).unwrap();
+    let object = reader
+        .main_asset()
+        .unwrap()
+        .get_object(&3)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        object.data.field("m_Count").unwrap().as_i32(),
+        Some(0x1234_5678)
+    );
+}
+
+#[test]
+fn string_followed_by_int() {
+    for name in &["a", "ab", "abc", "abcd", "abcde"] {
+        let (tree, data) = named_object(false, name, 0x0102_0304);
+        let file = SerializedFile::new(17, vec![(1, tree)]).object(1, 0, data);
+        let file = file.build();
+        let asset = Asset::parse("test".to_owned(), &file).unwrap();
+        let object = asset.get_object(&1).unwrap();
+        assert_eq!(object.data.field("m_Name").unwrap().as_str(), Some(*name));
+        assert_eq!(
+            object.data.field("m_Value").unwrap().as_i32(),
+            Some(0x0102_0304)
+        );
+    }
+}
+
+#[test]
+fn aligned_bool_followed_by_int() {
+    let tree = common::strukt(
+        "Behaviour",
+        "Base",
+        vec![
+            common::primitive("UInt8", "m_Enabled", 1).aligned(),
+            common::primitive("bool", "m_Unaligned", 1),
+            common::primitive("bool", "m_Aligned", 1).aligned(),
+            common::int("m_Value"),
+        ],
+    );
+    let mut data = common::Writer::new(false);
+    data.u8(1).align(4).u8(1).u8(0).align(4).i32(-2);
+    let file = SerializedFile::new(17, vec![(8, tree)]).object(1, 0, data.buf);
+    let file = file.build();
+    let asset = Asset::parse("test".to_owned(), &file).unwrap();
+    let object = asset.get_object(&1).unwrap();
+    assert_eq!(object.data.field("m_Enabled").unwrap().as_u32(), Some(1));
+    assert_eq!(
+        object.data.field("m_Unaligned").unwrap().as_bool(),
+        Some(true)
+    );
+    assert_eq!(
+        object.data.field("m_Aligned").unwrap().as_bool(),
+        Some(false)
+    );
+    assert_eq!(object.data.field("m_Value").unwrap().as_i32(), Some(-2));
+}
+
+#[test]
+fn owned_data_shares_buffers() {
+    let (tree, data) = named_object(false, "Shared", 1);
+    let file = SerializedFile::new(17, vec![(1, tree)])
+        .object(1, 0, data)
+        .build();
+    let asset = Asset::parse("test".to_owned(), &file).unwrap();
+    let object = asset.get_object(&1).unwrap();
+    let name = match object.data.field("m_Name") {
+        Some(Data::String(name)) => name.clone(),
+        other => panic!("expected a string, got {:?}", other),
+    };
+    assert!(name.is_borrowed());
+    assert!(matches!(name.clone().into_cow(), Cow::Borrowed(b"Shared")));
+
+    let owned = object.data.clone_owned();
+    let copy = owned.clone();
+    let buffer = |data: &Data<'static>| match data.field("m_Name") {
+        Some(Data::String(name)) => name.clone(),
+        other => panic!("expected a string, got {:?}", other),
+    };
+    let (owned_name, copied_name) = (buffer(&owned), buffer(&copy));
+    assert!(!owned_name.is_borrowed());
+    assert_eq!(&*owned_name, b"Shared");
+    // Cloning owned data shares its buffers instead of copying them.
+    assert_eq!(owned_name.as_ptr(), copied_name.as_ptr());
+    assert_eq!(copied_name.into_owned(), b"Shared");
+}
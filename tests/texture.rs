@@ -0,0 +1,520 @@
+#![cfg(feature = "textures")]
+
+use std::io;
+use unityfs::texture::DecodeFormat;
+
+const RGBA32: i32 = 4;
+const RGB565: i32 = 7;
+const DXT1: i32 = 10;
+const DXT5: i32 = 12;
+const BC6H: i32 = 24;
+const BC7: i32 = 25;
+const DXT1_CRUNCHED: i32 = 28;
+const DXT5_CRUNCHED: i32 = 29;
+const ETC2_RGB: i32 = 45;
+const ETC2_RGBA8_CRUNCHED: i32 = 65;
+
+fn format(id: i32) -> DecodeFormat {
+    DecodeFormat::from_texture_format(id, true).unwrap()
+}
+
+#[test]
+fn short_image_data() {
+    let format = format(DXT5);
+    let err = format.decode(8, 8, &[0u8; 63][..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(
+        err.to_string(),
+        "image data is too short: 63 bytes where a 8x8 image needs 64"
+    );
+    assert!(format.decode(8, 8, &[0u8; 64][..]).is_ok());
+}
+
+#[test]
+fn short_image_data_of_a_huge_texture() {
+    // The pixels would take 4 GiB, which mustn't be allocated for 16 bytes of input.
+    let err = format(RGBA32)
+        .decode(32768, 32768, &[0u8; 16][..])
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn short_swizzled_image_data() {
+    let mut blob = [0u8; 12];
+    blob[8] = 4;
+    let format = format(RGBA32).switch_swizzled(&blob);
+    let err = format.decode(4, 4, &[0u8; 64][..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn dxt5_mips() {
+    let format = format(DXT5);
+    let offsets = (0..=9)
+        .map(|level| format.mip_offset(256, 256, level).unwrap())
+        .collect::<Vec<_>>();
+    // Levels from 256x256 down to 1x1, the last three taking a block each.
+    assert_eq!(
+        offsets,
+        [0, 65536, 81920, 86016, 87040, 87296, 87360, 87376, 87392, 87408]
+    );
+    assert_eq!(format.level_size(256, 256), Some(65536));
+
+    // Black opaque blocks for the first level and garbage for the mips after it.
+    let block = [0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut image_data = block.repeat(4096);
+    image_data.resize(87408, 0xa5);
+    let mut rest = &image_data[..];
+    let rgba = format.decode(256, 256, &mut rest).unwrap();
+    assert_eq!(rest.len(), 87408 - 65536);
+    assert_eq!(rgba.len(), 256 * 256 * 4);
+    assert!(rgba.chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+}
+
+#[test]
+fn rgb565_samples() {
+    // Rows are stored bottom first: red and green, then blue and a mid grey.
+    let image_data = [0xf800u16, 0x07e0, 0x001f, 0x8410]
+        .iter()
+        .flat_map(|pixel| pixel.to_le_bytes())
+        .collect::<Vec<_>>();
+    let red = [0xff, 0, 0, 0xff];
+    let green = [0, 0xff, 0, 0xff];
+    let blue = [0, 0, 0xff, 0xff];
+    // Five and six bit channels of 0b10000 and 0b100000 scale to 0x84 and 0x82, not 0x80.
+    let grey = [0x84, 0x82, 0x84, 0xff];
+
+    let rgba = format(RGB565).decode(2, 2, &image_data[..]).unwrap();
+    assert_eq!(rgba, [blue, grey, red, green].concat());
+    let rgba = format(RGB565)
+        .flip(false)
+        .decode(2, 2, &image_data[..])
+        .unwrap();
+    assert_eq!(rgba, [red, green, blue, grey].concat());
+}
+
+/// Nibbles of the solid colour of the ETC block at `x`, `y`, unique to each block.
+fn etc_nibbles(x: usize, y: usize) -> [u8; 3] {
+    [
+        (x & 15) as u8,
+        (y & 15) as u8,
+        ((x >> 4) | (y >> 4) << 1) as u8,
+    ]
+}
+
+#[test]
+fn etc2_partial_blocks() {
+    // 17 by 17 blocks, of which the last row and column only show two pixels across.
+    let (width, height) = (66, 66);
+    let blocks = usize::div_ceil(width, 4);
+    let mut image_data = Vec::new();
+    for y in 0..blocks {
+        for x in 0..blocks {
+            // Individual mode with the same colour in both halves, the first modifier table and
+            // every pixel adding its smallest modifier, 2.
+            let [r, g, b] = etc_nibbles(x, y);
+            image_data.extend_from_slice(&[r << 4 | r, g << 4 | g, b << 4 | b, 0, 0, 0, 0, 0]);
+        }
+    }
+    let colour = |x: usize, y: usize| {
+        let nibbles = etc_nibbles(x / 4, y / 4);
+        let channel = |nibble: u8| (u32::from(nibble) * 17 + 2).min(255) as u8;
+        [
+            channel(nibbles[0]),
+            channel(nibbles[1]),
+            channel(nibbles[2]),
+            0xff,
+        ]
+    };
+
+    for &flip in &[true, false] {
+        let rgba = format(ETC2_RGB)
+            .flip(flip)
+            .decode(width as u32, height as u32, &image_data[..])
+            .unwrap();
+        assert_eq!(rgba.len(), width * height * 4);
+        for (row, pixels) in rgba.chunks(width * 4).enumerate() {
+            let stored_row = if flip { height - 1 - row } else { row };
+            for (x, pixel) in pixels.chunks(4).enumerate() {
+                assert_eq!(
+                    pixel,
+                    colour(x, stored_row),
+                    "flip {} at {}, {}",
+                    flip,
+                    x,
+                    row
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn astc_partial_blocks() {
+    // ASTC_RGB_4x4, ASTC_RGB_6x6 and ASTC_RGBA_8x8, each two blocks across and down with the last
+    // ones cut short.
+    for &(id, block_size) in &[(48, 4), (50, 6), (57, 8)] {
+        let (width, height) = (2 * block_size - 1, 2 * block_size - 3);
+        let mut image_data = Vec::new();
+        for block in 0..4u8 {
+            // Void-extent blocks, whose UNORM16 colour gives the block index in its red channel.
+            image_data.extend_from_slice(&[0xfc, 0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+            image_data.extend_from_slice(&[0, block, 0, 0x80, 0, 0, 0xff, 0xff]);
+        }
+        for &flip in &[true, false] {
+            let rgba = format(id)
+                .flip(flip)
+                .decode(width as u32, height as u32, &image_data[..])
+                .unwrap();
+            assert_eq!(rgba.len(), width * height * 4);
+            for (row, pixels) in rgba.chunks(width * 4).enumerate() {
+                let stored_row = if flip { height - 1 - row } else { row };
+                for (x, pixel) in pixels.chunks(4).enumerate() {
+                    let block = (stored_row / block_size * 2 + x / block_size) as u8;
+                    assert_eq!(pixel, [block, 0x80, 0, 0xff], "{} at {}, {}", id, x, row);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn bc7_blocks() {
+    // 2 by 2 blocks of BC7 mode 6, with equal endpoints whose 7-bit red goes at bits 7 and 14,
+    // and green 0x81, blue 0x21 and alpha 0xff from the rest and the set p-bits.
+    let mut image_data = Vec::new();
+    for block in 0..4u128 {
+        let red = (block * 80) >> 1;
+        let bits = 0x1_fffe_4084_0800_0040 | red << 7 | red << 14;
+        image_data.extend_from_slice(&bits.to_le_bytes());
+    }
+    for &flip in &[true, false] {
+        let rgba = format(BC7)
+            .flip(flip)
+            .decode(8, 8, &image_data[..])
+            .unwrap();
+        for (row, pixels) in rgba.chunks(8 * 4).enumerate() {
+            let stored_row = if flip { 7 - row } else { row };
+            for (x, pixel) in pixels.chunks(4).enumerate() {
+                let block = stored_row / 4 * 2 + x / 4;
+                let red = block as u8 * 80 + 1;
+                assert_eq!(
+                    pixel,
+                    [red, 0x81, 0x21, 0xff],
+                    "flip {} at {}, {}",
+                    flip,
+                    x,
+                    row
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn bc6h_blocks() {
+    // Mode 11 with both endpoints at (495, 0, 462) of 1023, which unquantize to 1.0, 0 and a
+    // little over 0.5.
+    let block = 0xe700_0f7b_9c00_3de3u128.to_le_bytes();
+    let rgba = format(BC6H).decode(4, 4, &block[..]).unwrap();
+    assert_eq!(rgba, [0xff, 0, 0x80, 0xff].repeat(16));
+}
+
+/// Writes bits most significant first, as crunch streams are read.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, count: usize) -> &mut Self {
+        for i in (0..count).rev() {
+            let shift = 7 - self.len % 8;
+            if shift == 7 {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i) as u8 & 1;
+            *self.bytes.last_mut().unwrap() |= bit << shift;
+            self.len += 1;
+        }
+        self
+    }
+
+    /// A Huffman model giving each of the 256 byte values an 8-bit code, so that symbols are
+    /// written as plain bytes afterwards.
+    fn flat_model(&mut self) -> &mut Self {
+        // 256 symbols, with code length codes for 17, 18, 19, 20, 0 and 8: one bit each for 8
+        // and 20, which repeats the previous length.
+        self.put(256, 14).put(6, 5);
+        for &size in &[0, 0, 0, 1, 0, 1] {
+            self.put(size, 3);
+        }
+        self.put(0, 1);
+        for &run in &[70, 70, 70, 45] {
+            self.put(1, 1).put(run - 7, 6);
+        }
+        self
+    }
+
+    fn symbols(&mut self, symbols: &[u32]) -> &mut Self {
+        for &symbol in symbols {
+            self.put(symbol, 8);
+        }
+        self
+    }
+}
+
+/// Builds a crunch file of one level and face from its tables, its four palettes (color
+/// endpoints, color selectors, alpha endpoints and alpha selectors) of `counts` entries, and the
+/// level.
+fn crunch(
+    width: usize,
+    height: usize,
+    crn_format: usize,
+    streams: &[Vec<u8>; 6],
+    counts: [usize; 4],
+) -> Vec<u8> {
+    let header_len = 74;
+    let mut offsets = Vec::new();
+    let mut len = header_len;
+    for stream in streams {
+        offsets.push(len);
+        len += stream.len();
+    }
+    let mut crn = vec![0u8; header_len];
+    let mut put = |at: usize, value: usize, size: usize| {
+        for i in 0..size {
+            crn[at + i] = (value >> (8 * (size - 1 - i))) as u8;
+        }
+    };
+    put(0, 0x4878, 2);
+    put(2, header_len, 2);
+    put(6, len, 4);
+    put(12, width, 2);
+    put(14, height, 2);
+    put(16, 1, 1);
+    put(17, 1, 1);
+    put(18, crn_format, 1);
+    for (i, at) in [33, 41, 49, 57].iter().enumerate() {
+        put(*at, offsets[i + 1], 3);
+        put(at + 3, streams[i + 1].len(), 3);
+        put(at + 6, counts[i], 2);
+    }
+    put(65, streams[0].len(), 2);
+    put(67, offsets[0], 3);
+    put(70, offsets[5], 4);
+    crn.extend(streams.concat());
+    crn
+}
+
+#[test]
+fn etc2a_crunched() {
+    // Two blocks sharing one entry of each palette, the first with its flip bit set and the
+    // second without, which transposes its selectors.
+    let (width, height) = (8, 4);
+    // Linear alpha selector of each pixel in row-major order, two to a symbol.
+    let linear = |p: usize| (p & 7) as u8;
+    let streams = [
+        // The chunk encoding model, then the endpoint and selector delta models of color and
+        // alpha.
+        BitWriter::default()
+            .flat_model()
+            .flat_model()
+            .flat_model()
+            .flat_model()
+            .flat_model()
+            .bytes
+            .clone(),
+        // Red 16, green 8 and blue 24 in five bits, and the first modifier table.
+        BitWriter::default()
+            .flat_model()
+            .symbols(&[16, 8, 24, 0])
+            .bytes
+            .clone(),
+        // Every pixel taking the lowest selector.
+        BitWriter::default()
+            .flat_model()
+            .symbols(&[0; 8])
+            .bytes
+            .clone(),
+        // A base of 128, a multiplier of 1 and the first modifier table.
+        BitWriter::default()
+            .flat_model()
+            .symbols(&[128, 0x10])
+            .bytes
+            .clone(),
+        BitWriter::default()
+            .flat_model()
+            .symbols(
+                &(0..8)
+                    .map(|i| u32::from(linear(2 * i) | linear(2 * i + 1) << 3))
+                    .collect::<Vec<_>>(),
+            )
+            .bytes
+            .clone(),
+        // Per block: the reference group of its pair of rows, the color and alpha endpoint
+        // deltas, and the color and alpha selectors. The second group clears the flip bit of
+        // the second block. The row below the blocks pads them to pairs.
+        BitWriter::default()
+            .symbols(&[0, 0, 0, 0, 0, 0x20, 0, 0, 0, 0])
+            .symbols(&[0; 8])
+            .bytes
+            .clone(),
+    ];
+
+    let crn = crunch(width, height, 12, &streams, [1; 4]);
+
+    let rgba = format(ETC2_RGBA8_CRUNCHED)
+        .flip(false)
+        .decode(width as u32, height as u32, &crn[..])
+        .unwrap();
+    assert_eq!(rgba.len(), width * height * 4);
+    // The lowest selector subtracts 8 from each channel.
+    let colour = [
+        (16 << 3 | 16 >> 2) - 8,
+        (8 << 3 | 8 >> 2) - 8,
+        (24 << 3 | 24 >> 2) - 8,
+    ];
+    let modifiers = [-15, -9, -6, -3, 2, 5, 8, 14];
+    for (y, pixels) in rgba.chunks(width * 4).enumerate() {
+        for (x, pixel) in pixels.chunks(4).enumerate() {
+            let p = if x < 4 { 4 * y + x } else { 4 * (x - 4) + y };
+            let alpha = (128 + modifiers[linear(p) as usize]) as u8;
+            assert_eq!(
+                pixel,
+                [colour[0], colour[1], colour[2], alpha],
+                "at {}, {}",
+                x,
+                y
+            );
+        }
+    }
+}
+
+/// Linear color selector of each pixel of the second selector entry: a ramp along each row.
+fn colour_ramp(p: usize) -> u32 {
+    (p & 3) as u32
+}
+
+/// Linear alpha selector of each pixel of the second alpha selector entry.
+fn alpha_ramp(p: usize) -> u32 {
+    (p & 7) as u32
+}
+
+/// crnlib's selector palette of one entry of zeros and one of `ramp`, coded two pixels to a
+/// symbol as their differences less one from the previous entry.
+fn crnlib_selectors(ramp: fn(usize) -> u32, levels: u32) -> Vec<u32> {
+    let mut symbols = vec![(levels - 1) * 2 * levels; 8];
+    let delta = |p: usize| (ramp(p) + levels - 1) % levels;
+    symbols.extend((0..8).map(|j| delta(2 * j) + (2 * levels - 1) * delta(2 * j + 1)));
+    symbols
+}
+
+/// An 8x8 crunched DXT1 or DXT5 texture of two blocks across and down, with two entries in each
+/// palette. Returns the texture and the DXT blocks it transcodes to.
+fn dxt_crunched(alpha: bool, unity: bool) -> (Vec<u8>, Vec<u8>) {
+    let mut tables = BitWriter::default();
+    for _ in 0..if alpha { 5 } else { 3 } {
+        tables.flat_model();
+    }
+    // Red to black, then white to blue, as deltas of each 5:6:5 channel from the last entry.
+    let color_endpoints = BitWriter::default()
+        .flat_model()
+        .flat_model()
+        .symbols(&[31, 0, 0, 0, 0, 0, 0, 63, 31, 0, 0, 31])
+        .bytes
+        .clone();
+    // 255 to 0, which gives eight alpha levels, then 64 to 200, which gives six.
+    let alpha_endpoints = BitWriter::default()
+        .flat_model()
+        .symbols(&[255, 0, 65, 200])
+        .bytes
+        .clone();
+    // The first entries select the first endpoint everywhere, and the second ones the ramps.
+    let (color_selectors, alpha_selectors) = if unity {
+        // Linear selectors XORed with the previous entry, two pixels to a symbol.
+        let colour = (0..8).map(|j| colour_ramp(2 * j) | colour_ramp(2 * j + 1) << 2);
+        let alpha = (0..8).map(|j| alpha_ramp(2 * j) | alpha_ramp(2 * j + 1) << 3);
+        (
+            [vec![0; 8], colour.collect()].concat(),
+            [vec![0; 8], alpha.collect()].concat(),
+        )
+    } else {
+        (
+            crnlib_selectors(colour_ramp, 4),
+            crnlib_selectors(alpha_ramp, 8),
+        )
+    };
+    let color_selectors = BitWriter::default()
+        .flat_model()
+        .symbols(&color_selectors)
+        .bytes
+        .clone();
+    let alpha_selectors = BitWriter::default()
+        .flat_model()
+        .symbols(&alpha_selectors)
+        .bytes
+        .clone();
+
+    // Endpoint and selector entries of each block.
+    let (level, blocks): (&[u32], _) = match (unity, alpha) {
+        // Chunk encoding 1, giving each row of blocks an endpoint tile, then the endpoint
+        // deltas of the tiles and the selector deltas of the blocks, which wrap around the
+        // palette.
+        (false, false) => (&[1, 0, 1, 0, 1, 1, 1], [(0, 0), (0, 1), (1, 0), (1, 1)]),
+        (false, true) => (
+            &[1, 0, 1, 0, 1, 0, 0, 1, 1, 1, 1, 1, 1],
+            [(0, 0), (0, 1), (1, 0), (1, 1)],
+        ),
+        // A reference group for new endpoints in the left column, the endpoints of the block to
+        // the left for the top right one, and those of the block above for the bottom right one.
+        // Each new endpoint comes with its delta, and each block with its selector.
+        (true, false) => (&[0x90, 0, 0, 1, 1, 0, 1], [(0, 0), (0, 1), (1, 0), (0, 1)]),
+        (true, true) => (
+            &[0x90, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 1, 1],
+            [(0, 0), (0, 1), (1, 0), (0, 1)],
+        ),
+    };
+    let streams = [
+        tables.bytes.clone(),
+        color_endpoints,
+        color_selectors,
+        alpha_endpoints,
+        alpha_selectors,
+        BitWriter::default().symbols(level).bytes.clone(),
+    ];
+    let crn = crunch(8, 8, if alpha { 2 } else { 0 }, &streams, [2; 4]);
+
+    let from_linear = [0, 2, 3, 4, 5, 6, 7, 1];
+    let alpha_selectors = [
+        0,
+        (0..16).fold(0u64, |acc, p| acc | from_linear[p & 7] << (3 * p)),
+    ];
+    let mut dxt = Vec::new();
+    for &(endpoint, selector) in &blocks {
+        if alpha {
+            dxt.extend_from_slice(&[[255, 0], [64, 200]][endpoint]);
+            dxt.extend_from_slice(&alpha_selectors[selector].to_le_bytes()[..6]);
+        }
+        dxt.extend_from_slice(&[0x0000_f800u32, 0x001f_ffff][endpoint].to_le_bytes());
+        dxt.extend_from_slice(&[0u32, 0x7878_7878][selector].to_le_bytes());
+    }
+    (crn, dxt)
+}
+
+#[test]
+fn dxt_crunched_variants() {
+    for &(id, plain, alpha) in &[(DXT1_CRUNCHED, DXT1, false), (DXT5_CRUNCHED, DXT5, true)] {
+        for &unity in &[false, true] {
+            let (crn, dxt) = dxt_crunched(alpha, unity);
+            let rgba = DecodeFormat::from_texture_format(id, unity)
+                .unwrap()
+                .decode(8, 8, &crn[..])
+                .unwrap();
+            let expected = format(plain).decode(8, 8, &dxt[..]).unwrap();
+            assert_eq!(rgba, expected, "format {}, Unity {}", id, unity);
+        }
+    }
+}